@@ -1,17 +1,31 @@
 //! Main application state and logic
 
 use crate::signal::manager::{IncomingMessage, MessageContent};
-use crate::signal::messages::{Content, Message, MessageDirection, MessageStatus};
+use crate::signal::messages::{
+    expiration_timer_text, AttachmentPreview, Content, Message, MessageDirection, MessageStatus, Quote,
+    DELETED_MESSAGE_TEXT, REMOTE_DELETE_RECEIVE_SECS,
+};
+use crate::signal::requests;
 use crate::signal::{ConnectionState as SignalConnectionState, SignalEvent, SignalManager};
+use crate::services::downloads::DownloadQueue;
+use crate::services::expiry::ExpiryReaper;
+use crate::services::sync::{SyncProgress, SyncService};
+use crate::services::{ServiceHealth, ServiceManager, SHUTDOWN_DEADLINE};
+use crate::services::typing::TypingIndicators;
+use crate::storage::attachments::{AttachmentRepository, StoredAttachment};
 use crate::storage::contacts::ContactRepository;
 use crate::storage::conversations::{Conversation, ConversationType, ConversationRepository};
-use crate::storage::messages::MessageRepository;
+use crate::storage::groups::GroupRepository;
+use crate::storage::messages::{MessageRepository, ReceiptType};
+use crate::storage::settings::SettingsRepository;
 use crate::storage::Storage;
 use crate::ui::avatar_cache::AvatarCache;
 use crate::ui::{theme::SignalTheme, views::ViewState};
-use chrono::{TimeZone, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use parking_lot::RwLock;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc;
 
@@ -81,6 +95,17 @@ pub struct SignalApp {
     event_tx: mpsc::UnboundedSender<SignalEvent>,
     selected_conversation_id: Option<String>,
     avatar_cache: AvatarCache,
+    /// Outgoing attachment messages whose upload has started, by message id
+    uploading: HashSet<String>,
+    /// Supervisor of the background workers below
+    services: ServiceManager,
+    download_queue: DownloadQueue,
+    expiry_reaper: ExpiryReaper,
+    sync_service: SyncService,
+    /// Ask the primary device for its data once the first receive finished
+    initial_sync_pending: bool,
+    /// Contacts currently typing, by conversation
+    typing: TypingIndicators,
 }
 
 /// Connection status to Signal servers
@@ -89,7 +114,8 @@ pub enum ConnectionStatus {
     Disconnected,
     Connecting,
     Connected,
-    Reconnecting,
+    /// Waiting to reconnect after the connection dropped
+    Reconnecting { attempt: u32, retry_at: DateTime<Utc> },
     Error(String),
 }
 
@@ -115,62 +141,68 @@ fn incoming_to_message(incoming: &IncomingMessage) -> Message {
             body: text.clone(),
             mentions: Vec::new(),
         },
-        MessageContent::Attachment { content_type, filename, size, attachment_id } => {
+        MessageContent::Attachment { metadata, caption } => {
+            let attachment_id = metadata.id.clone();
+            let content_type = metadata.content_type.clone();
             if content_type.starts_with("image/") {
                 Content::Image {
-                    attachment_id: attachment_id.clone(),
-                    content_type: content_type.clone(),
-                    width: 0,
-                    height: 0,
-                    size: *size,
-                    caption: None,
-                    blurhash: None,
+                    attachment_id,
+                    content_type,
+                    width: metadata.width.unwrap_or(0),
+                    height: metadata.height.unwrap_or(0),
+                    size: metadata.size,
+                    caption: caption.clone(),
+                    blurhash: metadata.blurhash.clone(),
                 }
             } else if content_type.starts_with("video/") {
                 Content::Video {
-                    attachment_id: attachment_id.clone(),
-                    content_type: content_type.clone(),
-                    width: 0,
-                    height: 0,
+                    attachment_id,
+                    content_type,
+                    width: metadata.width.unwrap_or(0),
+                    height: metadata.height.unwrap_or(0),
                     duration_ms: 0,
-                    size: *size,
-                    caption: None,
+                    size: metadata.size,
+                    caption: caption.clone(),
                     thumbnail_id: None,
                 }
             } else if content_type.starts_with("audio/") {
                 Content::Audio {
-                    attachment_id: attachment_id.clone(),
-                    content_type: content_type.clone(),
+                    attachment_id,
+                    content_type,
                     duration_ms: 0,
-                    size: *size,
+                    size: metadata.size,
                     waveform: None,
                 }
             } else {
                 Content::File {
-                    attachment_id: attachment_id.clone(),
-                    content_type: content_type.clone(),
-                    filename: filename.clone().unwrap_or_else(|| "attachment".to_string()),
-                    size: *size,
+                    attachment_id,
+                    content_type,
+                    filename: metadata.filename.clone().unwrap_or_else(|| "attachment".to_string()),
+                    size: metadata.size,
                 }
             }
         }
-        MessageContent::Sticker { pack_id, sticker_id } => Content::Sticker {
+        MessageContent::Sticker { pack_id, pack_key, sticker_id, emoji } => Content::Sticker {
             pack_id: pack_id.clone(),
-            pack_key: String::new(),
+            pack_key: pack_key.clone(),
             sticker_id: *sticker_id,
-            emoji: None,
+            emoji: emoji.clone(),
         },
-        MessageContent::Reaction { emoji, target_message_id: _, remove: _ } => {
-            // Reactions are usually handled separately, but store as text for now
+        MessageContent::Reaction { emoji, .. } => {
+            // Reactions are applied to their target in handle_incoming_reaction
             Content::Text {
                 body: format!("Reacted with {} to message", emoji),
                 mentions: Vec::new(),
             }
         }
-        MessageContent::Quote { quoted_message_id: _, text } => Content::Text {
+        MessageContent::Edit { target_timestamp: _, text } => Content::Text {
             body: text.clone(),
             mentions: Vec::new(),
         },
+        MessageContent::Delete { .. } => Content::Deleted,
+        MessageContent::ExpirationTimerUpdate { seconds } => Content::ExpirationTimerUpdate {
+            expires_in_seconds: *seconds,
+        },
     };
 
     let sent_at = Utc.timestamp_opt(incoming.timestamp / 1000, 0)
@@ -178,7 +210,7 @@ fn incoming_to_message(incoming: &IncomingMessage) -> Message {
         .unwrap_or_else(Utc::now);
     let server_timestamp = Utc.timestamp_opt(incoming.server_timestamp / 1000, 0).single();
 
-    Message {
+    let mut message = Message {
         id: incoming.id.clone(),
         conversation_id: incoming.conversation_id.clone(),
         sender: incoming.sender.clone(),
@@ -191,9 +223,18 @@ fn incoming_to_message(incoming: &IncomingMessage) -> Message {
         read_at: None,
         quote: None,
         reactions: Vec::new(),
-        expires_in_seconds: None,
+        expires_in_seconds: incoming.expire_timer.filter(|t| *t > 0),
         expires_at: None,
+        signal_timestamp: Some(incoming.timestamp as u64),
+        edited_at: None,
+    };
+
+    // Our messages from another device count down from when they were sent,
+    // everything else from when it's read
+    if incoming.sender == "self" {
+        message.start_expiry(sent_at);
     }
+    message
 }
 
 impl SignalApp {
@@ -232,6 +273,12 @@ impl SignalApp {
         
         let _ = EGUI_CTX.set(cc.egui_ctx.clone());
 
+        let services = ServiceManager::new(runtime.clone());
+        let download_queue = DownloadQueue::start(&services, storage.clone(), event_tx.clone());
+        let expiry_reaper = ExpiryReaper::start(&services, storage.clone(), event_tx.clone());
+        let sync_service = SyncService::start(&services, storage.clone(), event_tx.clone());
+        crate::services::profiles::start(&services, storage.clone());
+
         let mut app = Self {
             runtime,
             signal_manager: Arc::new(RwLock::new(None)),
@@ -246,6 +293,13 @@ impl SignalApp {
             event_tx,
             selected_conversation_id: None,
             avatar_cache: AvatarCache::new(),
+            uploading: HashSet::new(),
+            services,
+            download_queue,
+            expiry_reaper,
+            sync_service,
+            initial_sync_pending: false,
+            typing: TypingIndicators::new(),
         };
 
         if has_account && !needs_password {
@@ -293,6 +347,7 @@ impl SignalApp {
                 self.linking_state = LinkingState::Success;
                 self.view_state = ViewState::ChatList;
                 self.connection_status = ConnectionStatus::Connected;
+                self.initial_sync_pending = true;
                 // Reload the signal manager
                 self.initialize_signal_manager();
             }
@@ -304,7 +359,9 @@ impl SignalApp {
                 self.connection_status = match state {
                     SignalConnectionState::Connected => ConnectionStatus::Connected,
                     SignalConnectionState::Connecting => ConnectionStatus::Connecting,
-                    SignalConnectionState::Reconnecting => ConnectionStatus::Reconnecting,
+                    SignalConnectionState::Reconnecting { attempt, retry_at } => {
+                        ConnectionStatus::Reconnecting { attempt, retry_at }
+                    }
                     SignalConnectionState::Disconnected => ConnectionStatus::Disconnected,
                 };
             }
@@ -312,14 +369,103 @@ impl SignalApp {
                 self.error_message = Some(error);
             }
             SignalEvent::MessageReceived(incoming) => {
+                // A message from someone ends their typing indicator
+                self.typing.stopped(&incoming.conversation_id, &incoming.sender);
                 self.handle_incoming_message(&incoming);
+                self.expiry_reaper.wake();
+                crate::ui::views::chat_list::invalidate_conversations_cache();
+                crate::ui::views::chat_view::invalidate_messages_cache();
+            }
+            SignalEvent::MessagesExpired { conversation_ids } => {
+                tracing::debug!("Messages expired in {} conversations", conversation_ids.len());
                 crate::ui::views::chat_list::invalidate_conversations_cache();
                 crate::ui::views::chat_view::invalidate_messages_cache();
             }
+            SignalEvent::AttachmentDownloaded { message_id, attachment_id } => {
+                tracing::debug!("Attachment {} for message {} downloaded", attachment_id, message_id);
+                crate::ui::views::chat_view::invalidate_messages_cache();
+            }
+            SignalEvent::AttachmentDownloadFailed { message_id, attachment_id, error } => {
+                tracing::warn!(
+                    "Attachment {} for message {} could not be downloaded: {}",
+                    attachment_id,
+                    message_id,
+                    error
+                );
+                crate::ui::views::chat_view::invalidate_messages_cache();
+            }
+            SignalEvent::MessageSent { message_id } => {
+                self.uploading.remove(&message_id);
+                self.mark_message_sent(&message_id);
+                self.expiry_reaper.wake();
+            }
+            SignalEvent::DeliveryReceipt { recipient, timestamps, at } => {
+                self.handle_receipt(&recipient, &timestamps, at, ReceiptType::Delivery);
+            }
+            SignalEvent::ReadReceipt { recipient, timestamps, at } => {
+                self.handle_receipt(&recipient, &timestamps, at, ReceiptType::Read);
+            }
+            SignalEvent::ReadSync { reads } => {
+                self.handle_read_sync(&reads);
+                self.expiry_reaper.wake();
+            }
+            SignalEvent::TypingStarted { conversation_id, sender } => {
+                if self.typing_indicators_enabled() {
+                    self.typing.started(&conversation_id, &sender, Instant::now());
+                }
+            }
+            SignalEvent::TypingStopped { conversation_id, sender } => {
+                self.typing.stopped(&conversation_id, &sender);
+            }
+            SignalEvent::AttachmentUploading { message_id } => {
+                self.uploading.insert(message_id);
+            }
+            SignalEvent::MessageSendFailed { message_id, error } => {
+                self.uploading.remove(&message_id);
+                self.update_message_status(&message_id, MessageStatus::Failed);
+                self.error_message = Some(format!("Failed to send message: {}", error));
+            }
+            SignalEvent::SyncCompleted => {
+                if std::mem::take(&mut self.initial_sync_pending) {
+                    tracing::info!("Requesting initial sync from primary device");
+                    self.sync_service.request_sync();
+                }
+            }
+            SignalEvent::SyncResponse(kind) => {
+                tracing::info!("Primary device sent {}", kind.label());
+                self.sync_service.response_received(kind);
+                crate::ui::views::chat_list::invalidate_conversations_cache();
+                crate::ui::views::chat_list::invalidate_contacts_cache();
+            }
             SignalEvent::ContactUpdated { contact_id } => {
                 tracing::info!("Contact updated: {}, invalidating caches", contact_id);
+                if contact_id == "avatars" {
+                    self.avatar_cache.clear();
+                }
                 crate::ui::views::chat_list::invalidate_conversations_cache();
                 crate::ui::views::chat_list::invalidate_contacts_cache();
+                crate::ui::views::safety_number::invalidate_cache();
+            }
+            SignalEvent::ConversationUpdated { conversation_id } => {
+                tracing::info!("Conversation {} changed", conversation_id);
+                let deleted = self
+                    .storage
+                    .database()
+                    .is_some_and(|db| ConversationRepository::new(&*db).get(&conversation_id).is_none());
+                if deleted && self.selected_conversation_id.as_deref() == Some(conversation_id.as_str()) {
+                    self.selected_conversation_id = None;
+                }
+                crate::ui::views::chat_list::invalidate_conversations_cache();
+                crate::ui::views::chat_view::invalidate_messages_cache();
+            }
+            SignalEvent::GroupUpdated { group_id } => {
+                tracing::info!("Group updated: {}, invalidating caches", group_id);
+                crate::ui::views::chat_list::invalidate_conversations_cache();
+                crate::ui::views::chat_view::invalidate_messages_cache();
+                crate::ui::views::group_details::invalidate_group_cache();
+            }
+            SignalEvent::StickerPacksChanged => {
+                crate::ui::widgets::sticker::invalidate_packs_cache();
             }
             _ => {
                 tracing::debug!("Received event: {:?}", event);
@@ -328,29 +474,77 @@ impl SignalApp {
     }
 
     fn handle_incoming_message(&self, incoming: &IncomingMessage) {
+        if let MessageContent::Edit { target_timestamp, text } = &incoming.content {
+            self.handle_incoming_edit(incoming, *target_timestamp, text);
+            return;
+        }
+        if let MessageContent::Delete { target_timestamp } = &incoming.content {
+            self.handle_incoming_delete(incoming, *target_timestamp);
+            return;
+        }
+        if let MessageContent::Reaction { emoji, target_author, target_timestamp, remove } = &incoming.content {
+            self.handle_incoming_reaction(incoming, emoji, target_author, *target_timestamp, *remove);
+            return;
+        }
+
         let Some(db) = self.storage.database() else {
             tracing::warn!("No database available, cannot save message");
             return;
         };
 
-        let message = incoming_to_message(incoming);
+        let mut message = incoming_to_message(incoming);
         let message_repo = MessageRepository::new(&*db);
         let conv_repo = ConversationRepository::new(&*db);
 
+        if let Some(quote) = &incoming.quote {
+            message.quote = Some(Quote {
+                message_id: message_repo
+                    .find_by_timestamp(&quote.author, quote.timestamp)
+                    .filter(|quoted| quoted.conversation_id == incoming.conversation_id)
+                    .map(|quoted| quoted.id)
+                    .unwrap_or_default(),
+                timestamp: Some(quote.timestamp),
+                author: quote.author.clone(),
+                text: quote.text.clone(),
+                attachment_preview: quote.content_type.as_ref().map(|content_type| AttachmentPreview {
+                    content_type: content_type.clone(),
+                    filename: quote.filename.clone(),
+                    thumbnail_id: quote.thumbnail.as_ref().map(|t| t.id.clone()),
+                }),
+            });
+        }
+
         let text_preview = match &incoming.content {
             MessageContent::Text(t) => t.clone(),
-            MessageContent::Attachment { .. } => "[Attachment]".to_string(),
+            MessageContent::Attachment { metadata, caption } => {
+                if let Some(caption) = caption {
+                    caption.clone()
+                } else if metadata.content_type.starts_with("image/") {
+                    "[Image]".to_string()
+                } else if metadata.content_type.starts_with("audio/") {
+                    "[Voice message]".to_string()
+                } else {
+                    "[Attachment]".to_string()
+                }
+            }
             MessageContent::Sticker { .. } => "[Sticker]".to_string(),
             MessageContent::Reaction { emoji, .. } => format!("Reacted {}", emoji),
-            MessageContent::Quote { text, .. } => text.clone(),
+            MessageContent::Edit { text, .. } => text.clone(),
+            MessageContent::Delete { .. } => DELETED_MESSAGE_TEXT.to_string(),
+            MessageContent::ExpirationTimerUpdate { seconds } => expiration_timer_text(*seconds),
         };
 
         if conv_repo.get(&incoming.conversation_id).is_none() {
             let is_group = incoming.conversation_id != incoming.sender 
                 && incoming.sender != "self";
             
-            let conv = if is_group {
-                Conversation::new_group(&incoming.conversation_id, "Group")
+            let mut conv = if is_group {
+                let name = GroupRepository::new(&*db)
+                    .get(&incoming.conversation_id)
+                    .map(|g| g.name)
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or_else(|| "Group".to_string());
+                Conversation::new_group(&incoming.conversation_id, &name)
             } else {
                 let contact_repo = ContactRepository::new(&*db);
                 
@@ -378,6 +572,8 @@ impl SignalApp {
                 
                 conv
             };
+            // Someone we don't know starts a message request
+            conv.is_accepted = !requests::is_message_request(&db, &incoming.sender);
             
             if let Err(e) = conv_repo.save(&conv) {
                 tracing::error!("Failed to create conversation: {}", e);
@@ -385,11 +581,58 @@ impl SignalApp {
             }
         }
 
+        // Timer changes become a notice. Group messages that carry the
+        // group's timer also pick up a change we missed; one without the
+        // field says nothing about it.
+        let timer_change = match &incoming.content {
+            MessageContent::ExpirationTimerUpdate { seconds } => Some(*seconds),
+            _ => incoming.expire_timer.filter(|seconds| {
+                conv_repo.get(&incoming.conversation_id).is_some_and(|c| {
+                    c.conversation_type == ConversationType::Group
+                        && c.disappearing_messages_timer != *seconds
+                })
+            }),
+        };
+        if let Some(seconds) = timer_change {
+            if let Err(e) = SignalManager::apply_expiration_timer(
+                &db,
+                &incoming.conversation_id,
+                &incoming.sender,
+                seconds,
+                incoming.timestamp as u64,
+            ) {
+                tracing::error!("Failed to update disappearing message timer: {}", e);
+            }
+        }
+        if matches!(incoming.content, MessageContent::ExpirationTimerUpdate { .. }) {
+            return;
+        }
+
         if let Err(e) = message_repo.save(&message) {
             tracing::error!("Failed to save message: {}", e);
             return;
         }
 
+        if let MessageContent::Attachment { metadata, .. } = &incoming.content {
+            let attachment_repo = AttachmentRepository::new(&*db);
+            let stored = StoredAttachment::from_metadata(&incoming.id, metadata);
+            if let Err(e) = attachment_repo.save(&stored) {
+                tracing::error!("Failed to save attachment pointer: {}", e);
+            } else if let Err(e) = self.download_queue.enqueue(&stored) {
+                tracing::error!("Failed to queue attachment download: {}", e);
+            }
+        }
+
+        // Thumbnails of quoted media are small, fetch them like any attachment
+        if let Some(thumbnail) = incoming.quote.as_ref().and_then(|q| q.thumbnail.as_ref()) {
+            let stored = StoredAttachment::from_metadata(&incoming.id, thumbnail);
+            if let Err(e) = AttachmentRepository::new(&*db).save(&stored) {
+                tracing::error!("Failed to save quote thumbnail: {}", e);
+            } else if let Err(e) = self.download_queue.enqueue(&stored) {
+                tracing::error!("Failed to queue quote thumbnail download: {}", e);
+            }
+        }
+
         if let Some(mut conv) = conv_repo.get(&incoming.conversation_id) {
             conv.update_last_message(&text_preview, message.sent_at);
             conv.increment_unread();
@@ -401,6 +644,206 @@ impl SignalApp {
         tracing::info!("Saved message {} from {}", incoming.id, incoming.sender);
     }
 
+    /// Apply an edit to the message its author sent at `target_timestamp`
+    fn handle_incoming_edit(&self, incoming: &IncomingMessage, target_timestamp: u64, text: &str) {
+        let Some(db) = self.storage.database() else {
+            return;
+        };
+        let message_repo = MessageRepository::new(&*db);
+
+        // Of a message split into attachments, this is the row holding the
+        // body or caption, the only one an edit can change
+        let Some(original) = message_repo.find_by_timestamp(&incoming.sender, target_timestamp) else {
+            tracing::warn!(
+                "Ignoring edit from {} for unknown message sent at {}",
+                incoming.sender,
+                target_timestamp
+            );
+            return;
+        };
+        if original.conversation_id != incoming.conversation_id {
+            tracing::warn!("Ignoring edit of message {} from another conversation", original.id);
+            return;
+        }
+
+        let edited_at = Utc
+            .timestamp_millis_opt(incoming.timestamp)
+            .single()
+            .unwrap_or_else(Utc::now);
+        match message_repo.apply_edit(&original.id, text, edited_at) {
+            Ok(Some(_)) => {
+                tracing::info!("Applied edit to message {}", original.id);
+                let conv_repo = ConversationRepository::new(&*db);
+                let is_latest = message_repo
+                    .get_latest(&original.conversation_id)
+                    .is_some_and(|latest| latest.id == original.id);
+                if let (true, Some(mut conv)) = (is_latest, conv_repo.get(&original.conversation_id)) {
+                    conv.update_last_message(text, original.sent_at);
+                    let _ = conv_repo.save(&conv);
+                }
+            }
+            Ok(None) => tracing::warn!("Message {} has no editable text", original.id),
+            Err(e) => tracing::error!("Failed to apply edit to {}: {}", original.id, e),
+        }
+    }
+
+    /// Apply a delete for everyone to the message its author sent at
+    /// `target_timestamp`, including every attachment it was split into
+    fn handle_incoming_delete(&self, incoming: &IncomingMessage, target_timestamp: u64) {
+        let Some(db) = self.storage.database() else {
+            return;
+        };
+
+        let targets = MessageRepository::new(&*db).find_all_by_timestamp(&incoming.sender, target_timestamp);
+        let Some(first) = targets.first() else {
+            tracing::warn!(
+                "Ignoring delete from {} for unknown message sent at {}",
+                incoming.sender,
+                target_timestamp
+            );
+            return;
+        };
+        if first.conversation_id != incoming.conversation_id {
+            tracing::warn!("Ignoring delete of message {} from another conversation", first.id);
+            return;
+        }
+
+        let age_secs = (incoming.timestamp - target_timestamp as i64) / 1000;
+        if age_secs > REMOTE_DELETE_RECEIVE_SECS {
+            tracing::warn!(
+                "Ignoring delete of message {} sent {}s after the message",
+                first.id,
+                age_secs
+            );
+            return;
+        }
+
+        for target in targets.iter().filter(|t| t.conversation_id == incoming.conversation_id) {
+            if let Err(e) = SignalManager::tombstone_message(&db, self.storage.attachments_dir(), &target.id) {
+                tracing::error!("Failed to delete message {}: {}", target.id, e);
+            }
+        }
+    }
+
+    /// Apply a reaction (or its removal) from `incoming.sender` to the target message
+    fn handle_incoming_reaction(
+        &self,
+        incoming: &IncomingMessage,
+        emoji: &str,
+        target_author: &str,
+        target_timestamp: u64,
+        remove: bool,
+    ) {
+        let Some(db) = self.storage.database() else {
+            return;
+        };
+        let message_repo = MessageRepository::new(&*db);
+
+        // A message split into attachments keeps its reactions on one row,
+        // the same one `find_by_timestamp` returns for every lookup
+        let Some(target) = message_repo.find_by_timestamp(target_author, target_timestamp) else {
+            tracing::warn!(
+                "Ignoring reaction for unknown message from {} sent at {}",
+                target_author,
+                target_timestamp
+            );
+            return;
+        };
+        if target.conversation_id != incoming.conversation_id {
+            tracing::warn!("Ignoring reaction to message {} from another conversation", target.id);
+            return;
+        }
+        if target.is_deleted() {
+            return;
+        }
+
+        if let Err(e) = message_repo.apply_reaction(&target.id, emoji, &incoming.sender, remove) {
+            tracing::error!("Failed to apply reaction to {}: {}", target.id, e);
+        }
+    }
+
+    /// Record a receipt from `recipient` for each of our messages sent at `timestamps`
+    fn handle_receipt(&self, recipient: &str, timestamps: &[u64], at: i64, receipt: ReceiptType) {
+        let Some(db) = self.storage.database() else {
+            return;
+        };
+        let message_repo = MessageRepository::new(&*db);
+        let conv_repo = ConversationRepository::new(&*db);
+        let at = Utc.timestamp_millis_opt(at).single().unwrap_or_else(Utc::now);
+
+        for &timestamp in timestamps {
+            // Every row of a message split into attachments gets the receipt
+            let messages = message_repo.find_all_by_timestamp("self", timestamp);
+            let Some(first) = messages.first() else {
+                tracing::debug!("{:?} receipt from {} for unknown message sent at {}", receipt, recipient, timestamp);
+                continue;
+            };
+
+            // In a 1:1 chat only the other person can confirm our messages
+            let is_group = conv_repo
+                .get(&first.conversation_id)
+                .is_some_and(|c| c.conversation_type == ConversationType::Group);
+            if !is_group && first.conversation_id != recipient {
+                tracing::warn!("Ignoring receipt from {} for message {} in another chat", recipient, first.id);
+                continue;
+            }
+
+            for message in messages.iter().filter(|m| m.conversation_id == first.conversation_id) {
+                if let Err(e) = message_repo.record_receipt(&message.id, recipient, receipt, at) {
+                    tracing::error!("Failed to record receipt for {}: {}", message.id, e);
+                }
+            }
+        }
+
+        crate::ui::views::chat_view::invalidate_messages_cache();
+    }
+
+    /// Apply read state from another of our devices. Reading a message there
+    /// also reads everything before it in the same conversation.
+    fn handle_read_sync(&self, reads: &[(String, u64)]) {
+        let Some(db) = self.storage.database() else {
+            return;
+        };
+        let message_repo = MessageRepository::new(&*db);
+        let conv_repo = ConversationRepository::new(&*db);
+
+        let mut conversations: Vec<String> = Vec::new();
+        for (sender, timestamp) in reads {
+            let Some(message) = message_repo.find_by_timestamp(sender, *timestamp) else {
+                tracing::debug!("Read sync for unknown message from {} sent at {}", sender, timestamp);
+                continue;
+            };
+            if let Err(e) = message_repo.mark_read(&message.conversation_id, message.sent_at) {
+                tracing::error!("Failed to apply read sync to {}: {}", message.conversation_id, e);
+                continue;
+            }
+            if !conversations.contains(&message.conversation_id) {
+                conversations.push(message.conversation_id);
+            }
+        }
+
+        for conversation_id in &conversations {
+            let unread = message_repo.get_unread(conversation_id).len() as u32;
+            if let Err(e) = conv_repo.update_unread(conversation_id, unread) {
+                tracing::error!("Failed to update unread count of {}: {}", conversation_id, e);
+            }
+        }
+
+        if !conversations.is_empty() {
+            crate::ui::views::chat_list::invalidate_conversations_cache();
+            crate::ui::views::chat_view::invalidate_messages_cache();
+        }
+    }
+
+    /// Queue a skipped or failed attachment for download at the user's request
+    pub fn download_attachment(&mut self, attachment_id: &str) {
+        if let Err(e) = self.download_queue.download_now(attachment_id) {
+            tracing::error!("Failed to queue attachment {}: {}", attachment_id, e);
+            self.error_message = Some(format!("Could not download attachment: {}", e));
+        }
+        crate::ui::views::chat_view::invalidate_messages_cache();
+    }
+
     /// Start the device linking process
     pub fn start_linking(&mut self) {
         // Only start if not already started (don't auto-retry on error)
@@ -482,6 +925,21 @@ impl SignalApp {
         &self.runtime
     }
 
+    /// Health of the background services, for the diagnostics view
+    pub fn service_health(&self) -> Vec<ServiceHealth> {
+        self.services.health()
+    }
+
+    /// Sync now and ask the primary device for its contacts, blocked list,
+    /// configuration and keys
+    pub fn request_sync(&self) {
+        self.sync_service.request_sync();
+    }
+
+    pub fn sync_progress(&self) -> SyncProgress {
+        self.sync_service.progress()
+    }
+
     /// Get storage reference
     pub fn storage(&self) -> &Arc<Storage> {
         &self.storage
@@ -502,7 +960,15 @@ impl SignalApp {
         self.selected_conversation_id = id;
     }
 
+    /// Open a conversation scrolled to a specific message, e.g. a search hit
+    pub fn open_message(&mut self, conversation_id: &str, message_id: &str) {
+        crate::ui::views::chat_view::focus_message(conversation_id, message_id);
+        self.select_conversation(Some(conversation_id.to_string()));
+    }
+
     /// Reset unread count and mark incoming messages as read for a conversation.
+    ///
+    /// Newly read messages are reported to their senders and our other devices.
     pub fn mark_conversation_read(&self, conversation_id: &str) {
         let Some(db) = self.storage.database() else {
             return;
@@ -510,19 +976,66 @@ impl SignalApp {
         let conv_repo = ConversationRepository::new(&*db);
         let msg_repo = MessageRepository::new(&*db);
 
+        let unread = msg_repo.get_unread(conversation_id);
         if let Err(e) = conv_repo.update_unread(conversation_id, 0) {
             tracing::error!("Failed to reset unread count: {}", e);
         }
         if let Err(e) = msg_repo.mark_read(conversation_id, Utc::now()) {
             tracing::error!("Failed to mark messages as read: {}", e);
         }
+        self.expiry_reaper.wake();
         crate::ui::views::chat_list::invalidate_conversations_cache();
+
+        if !unread.is_empty() {
+            let storage = self.storage.clone();
+            self.runtime.spawn(async move {
+                if let Err(e) = SignalManager::mark_read(&storage, &unread).await {
+                    tracing::warn!("Failed to send read receipts: {}", e);
+                }
+            });
+        }
     }
 
     pub fn avatar_cache(&self) -> &AvatarCache {
         &self.avatar_cache
     }
 
+    /// Senders typing in a conversation
+    pub fn typing_senders(&self, conversation_id: &str) -> Vec<&str> {
+        self.typing.senders(conversation_id)
+    }
+
+    fn typing_indicators_enabled(&self) -> bool {
+        self.storage
+            .database()
+            .is_some_and(|db| SettingsRepository::new(&db).get().typing_indicators)
+    }
+
+    /// Whether an outgoing attachment message is uploading
+    pub fn is_uploading(&self, message_id: &str) -> bool {
+        self.uploading.contains(message_id)
+    }
+
+    fn mark_message_sent(&self, message_id: &str) {
+        let Some(db) = self.storage.database() else {
+            return;
+        };
+        if let Err(e) = MessageRepository::new(&*db).mark_sent(message_id) {
+            tracing::error!("Failed to mark message {} as sent: {}", message_id, e);
+        }
+        crate::ui::views::chat_view::invalidate_messages_cache();
+    }
+
+    fn update_message_status(&self, message_id: &str, status: MessageStatus) {
+        let Some(db) = self.storage.database() else {
+            return;
+        };
+        if let Err(e) = MessageRepository::new(&*db).update_status(message_id, status) {
+            tracing::error!("Failed to update status of message {}: {}", message_id, e);
+        }
+        crate::ui::views::chat_view::invalidate_messages_cache();
+    }
+
     pub fn on_database_unlocked(&mut self) {
         self.view_state = ViewState::ChatList;
         self.initialize_signal_manager();
@@ -622,6 +1135,12 @@ impl eframe::App for SignalApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.process_events(ctx);
 
+        let now = Instant::now();
+        self.typing.prune(now);
+        if let Some(expiry) = self.typing.next_expiry(now) {
+            ctx.request_repaint_after(expiry);
+        }
+
         // Show error toast if present
         let mut dismiss_error = false;
         if let Some(ref error) = self.error_message {
@@ -645,13 +1164,25 @@ impl eframe::App for SignalApp {
             .show(ctx, |ui| {
                 ui.horizontal(|ui| {
                     let (color, text) = match &self.connection_status {
-                        ConnectionStatus::Connected => (egui::Color32::GREEN, "Connected"),
-                        ConnectionStatus::Connecting => (egui::Color32::YELLOW, "Connecting..."),
-                        ConnectionStatus::Reconnecting => (egui::Color32::YELLOW, "Reconnecting..."),
-                        ConnectionStatus::Disconnected => (egui::Color32::GRAY, "Disconnected"),
-                        ConnectionStatus::Error(e) => (egui::Color32::RED, e.as_str()),
+                        ConnectionStatus::Connected => (egui::Color32::GREEN, "Connected".to_string()),
+                        ConnectionStatus::Connecting => (egui::Color32::YELLOW, "Connecting...".to_string()),
+                        ConnectionStatus::Reconnecting { attempt, retry_at } => {
+                            // Count down to the next attempt
+                            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+                            let seconds = (*retry_at - Utc::now()).num_seconds().max(0);
+                            let text = format!("Offline, reconnecting in {}s (attempt {})", seconds, attempt);
+                            (egui::Color32::YELLOW, text)
+                        }
+                        ConnectionStatus::Disconnected => (egui::Color32::GRAY, "Disconnected".to_string()),
+                        ConnectionStatus::Error(e) => (egui::Color32::RED, e.clone()),
                     };
                     ui.colored_label(color, format!("● {}", text));
+
+                    if matches!(self.connection_status, ConnectionStatus::Reconnecting { .. })
+                        && ui.small_button("Retry now").clicked()
+                    {
+                        SignalManager::reconnect_now();
+                    }
                 });
             });
 
@@ -677,4 +1208,8 @@ impl eframe::App for SignalApp {
     fn save(&mut self, _storage: &mut dyn eframe::Storage) {
         // Save application state if needed
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.services.shutdown_blocking(SHUTDOWN_DEADLINE);
+    }
 }
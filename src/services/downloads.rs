@@ -0,0 +1,363 @@
+//! Automatic attachment download queue
+//!
+//! Incoming attachment pointers are checked against the media auto-download
+//! policy. Accepted ones become jobs in `download_jobs` and are fetched by a
+//! background worker with bounded concurrency and exponential backoff.
+//! Skipped attachments can still be queued manually from the chat view.
+
+use crate::services::{ServiceContext, ServiceManager};
+use crate::signal::attachments::{voice, AttachmentManager};
+use crate::signal::SignalEvent;
+use crate::storage::attachments::{
+    AttachmentRepository, DownloadJob, DownloadJobRepository, DownloadStatus, StoredAttachment,
+};
+use crate::storage::messages::MessageRepository;
+use crate::storage::settings::{MediaSettings, SettingsRepository};
+use crate::storage::Storage;
+use chrono::Utc;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::{interval, Duration};
+
+/// Maximum number of attachments downloaded at the same time
+const MAX_CONCURRENT_DOWNLOADS: usize = 3;
+
+/// Attempts before a job is marked failed and left for a manual retry
+const MAX_ATTEMPTS: u32 = 5;
+
+const BASE_BACKOFF_SECS: i64 = 10;
+const MAX_BACKOFF_SECS: i64 = 30 * 60;
+
+/// How often the worker looks for jobs whose backoff has elapsed
+const POLL_INTERVAL_SECS: u64 = 5;
+
+/// Which attachments may be fetched without the user asking
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadPolicy {
+    pub images: bool,
+    pub videos: bool,
+    pub files: bool,
+    /// Size cap in bytes (0 = no limit)
+    pub max_size: u64,
+}
+
+impl DownloadPolicy {
+    /// Pick the WiFi or mobile flags depending on whether the connection is metered
+    pub fn from_settings(media: &MediaSettings) -> Self {
+        let metered = media.metered_connection;
+        Self {
+            images: if metered { media.auto_download_images_mobile } else { media.auto_download_images_wifi },
+            videos: if metered { media.auto_download_videos_mobile } else { media.auto_download_videos_wifi },
+            files: if metered { media.auto_download_files_mobile } else { media.auto_download_files_wifi },
+            max_size: media.auto_download_max_size_mb as u64 * 1024 * 1024,
+        }
+    }
+
+    /// Whether an attachment should be downloaded automatically.
+    ///
+    /// Voice notes are always fetched, like on the official clients.
+    pub fn allows(&self, content_type: &str, size: u64, voice_note: bool) -> bool {
+        if voice_note {
+            return true;
+        }
+        if self.max_size > 0 && size > self.max_size {
+            return false;
+        }
+
+        if content_type.starts_with("image/") {
+            self.images
+        } else if content_type.starts_with("video/") {
+            self.videos
+        } else if content_type.starts_with("audio/") {
+            true
+        } else {
+            self.files
+        }
+    }
+}
+
+/// Delay before the next attempt after `attempts` failures
+pub fn backoff_secs(attempts: u32) -> i64 {
+    (BASE_BACKOFF_SECS << attempts.saturating_sub(1).min(16)).min(MAX_BACKOFF_SECS)
+}
+
+/// Handle to the background download worker
+#[derive(Clone)]
+pub struct DownloadQueue {
+    storage: Arc<Storage>,
+    wake_tx: mpsc::UnboundedSender<()>,
+}
+
+impl DownloadQueue {
+    /// Start the worker as a supervised service. Jobs persisted by a previous
+    /// session are resumed once the database is available.
+    pub fn start(
+        services: &ServiceManager,
+        storage: Arc<Storage>,
+        event_tx: mpsc::UnboundedSender<SignalEvent>,
+    ) -> Self {
+        let (wake_tx, wake_rx) = mpsc::unbounded_channel();
+        // Shared so a restarted worker keeps listening on the same channel
+        let wake_rx = Arc::new(Mutex::new(wake_rx));
+        let queue = Self { storage, wake_tx };
+        let worker = queue.clone();
+        services.spawn("Attachment downloads", move |ctx| {
+            worker.clone().run(ctx, event_tx.clone(), wake_rx.clone())
+        });
+        queue
+    }
+
+    /// Apply the auto-download policy to a newly received attachment.
+    ///
+    /// Returns `true` if a download job was queued.
+    pub fn enqueue(&self, attachment: &StoredAttachment) -> anyhow::Result<bool> {
+        let db = self
+            .storage
+            .database()
+            .ok_or_else(|| anyhow::anyhow!("Database not available"))?;
+
+        let policy = DownloadPolicy::from_settings(&SettingsRepository::new(&*db).get().media);
+        if !policy.allows(&attachment.content_type, attachment.size, attachment.voice_note) {
+            tracing::info!(
+                "Skipping auto-download of {} ({}, {} bytes)",
+                attachment.id,
+                attachment.content_type,
+                attachment.size
+            );
+            AttachmentRepository::new(&*db).set_status(&attachment.id, DownloadStatus::Skipped)?;
+            return Ok(false);
+        }
+
+        DownloadJobRepository::new(&*db).enqueue(&attachment.id, &attachment.message_id)?;
+        drop(db);
+        let _ = self.wake_tx.send(());
+        Ok(true)
+    }
+
+    /// Queue an attachment regardless of policy, e.g. when the user clicks download
+    pub fn download_now(&self, attachment_id: &str) -> anyhow::Result<()> {
+        let db = self
+            .storage
+            .database()
+            .ok_or_else(|| anyhow::anyhow!("Database not available"))?;
+
+        let attachments = AttachmentRepository::new(&*db);
+        let attachment = attachments
+            .get(attachment_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown attachment {}", attachment_id))?;
+
+        DownloadJobRepository::new(&*db).enqueue(&attachment.id, &attachment.message_id)?;
+        attachments.set_status(&attachment.id, DownloadStatus::Pending)?;
+        drop(db);
+        let _ = self.wake_tx.send(());
+        Ok(())
+    }
+
+    async fn run(
+        self,
+        mut ctx: ServiceContext,
+        event_tx: mpsc::UnboundedSender<SignalEvent>,
+        wake_rx: Arc<Mutex<mpsc::UnboundedReceiver<()>>>,
+    ) -> anyhow::Result<()> {
+        let mut wake_rx = wake_rx.lock().await;
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_DOWNLOADS));
+        let mut poll = interval(Duration::from_secs(POLL_INTERVAL_SECS));
+        let mut recovered = false;
+
+        loop {
+            tokio::select! {
+                woke = wake_rx.recv() => {
+                    if woke.is_none() {
+                        break;
+                    }
+                }
+                _ = poll.tick() => {}
+                _ = ctx.shutdown_requested() => break,
+            }
+
+            let available = semaphore.available_permits();
+            if available == 0 {
+                continue;
+            }
+
+            let due = {
+                let Some(db) = self.storage.database() else {
+                    continue;
+                };
+                let jobs = DownloadJobRepository::new(&*db);
+
+                if !recovered {
+                    match jobs.requeue_running() {
+                        Ok(n) if n > 0 => tracing::info!("Resuming {} interrupted attachment downloads", n),
+                        Ok(_) => {}
+                        Err(e) => tracing::warn!("Failed to resume attachment downloads: {}", e),
+                    }
+                    recovered = true;
+                }
+
+                match jobs.take_due(Utc::now().timestamp(), available) {
+                    Ok(due) => due,
+                    Err(e) => {
+                        ctx.report(Err(e.context("Failed to load download jobs")));
+                        continue;
+                    }
+                }
+            };
+            ctx.report(Ok(format!("Started {} downloads", due.len())));
+
+            for job in due {
+                let Ok(permit) = semaphore.clone().acquire_owned().await else {
+                    break;
+                };
+                let queue = self.clone();
+                let event_tx = event_tx.clone();
+                tokio::spawn(async move {
+                    queue.process(job, &event_tx, permit).await;
+                });
+            }
+        }
+
+        tracing::info!("Download queue shutting down");
+        Ok(())
+    }
+
+    async fn process(
+        &self,
+        job: DownloadJob,
+        event_tx: &mpsc::UnboundedSender<SignalEvent>,
+        permit: OwnedSemaphorePermit,
+    ) {
+        let attachment = {
+            let Some(db) = self.storage.database() else {
+                return;
+            };
+            let attachments = AttachmentRepository::new(&*db);
+            match attachments.get(&job.attachment_id) {
+                Some(attachment) => {
+                    let _ = attachments.set_status(&attachment.id, DownloadStatus::Downloading);
+                    attachment
+                }
+                None => {
+                    // Message was deleted in the meantime
+                    let _ = DownloadJobRepository::new(&*db).delete(&job.attachment_id);
+                    return;
+                }
+            }
+        };
+
+        let manager = AttachmentManager::new(self.storage.attachments_dir().clone());
+        let result = manager.download(&attachment.to_metadata()).await;
+        drop(permit);
+
+        // Voice notes arrive without a usable duration or waveform on some
+        // clients, so measure them ourselves
+        let audio_details = match &result {
+            Ok(path) if attachment.content_type.starts_with("audio/") => {
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || std::fs::read(path).ok().and_then(|data| voice::analyze(&data)))
+                    .await
+                    .ok()
+                    .flatten()
+            }
+            _ => None,
+        };
+
+        let Some(db) = self.storage.database() else {
+            return;
+        };
+        let attachments = AttachmentRepository::new(&*db);
+        let jobs = DownloadJobRepository::new(&*db);
+
+        match result {
+            Ok(_) if attachments.get(&attachment.id).is_none() => {
+                // Message was deleted for everyone while downloading
+                manager.remove_local(&[attachment.id.as_str()]);
+            }
+            Ok(_) => {
+                let _ = jobs.delete(&attachment.id);
+                let _ = attachments.set_status(&attachment.id, DownloadStatus::Downloaded);
+                if let Some((duration_ms, waveform)) = audio_details {
+                    let _ = MessageRepository::new(&*db).set_audio_details(&attachment.message_id, duration_ms, waveform);
+                }
+                let _ = event_tx.send(SignalEvent::AttachmentDownloaded {
+                    message_id: attachment.message_id.clone(),
+                    attachment_id: attachment.id.clone(),
+                });
+            }
+            Err(e) => {
+                let attempts = job.attempts + 1;
+                let error = e.to_string();
+                if attempts >= MAX_ATTEMPTS {
+                    tracing::error!(
+                        "Giving up on attachment {} after {} attempts: {}",
+                        attachment.id,
+                        attempts,
+                        error
+                    );
+                    let _ = jobs.mark_failed(&attachment.id, attempts, &error);
+                    let _ = attachments.set_status(&attachment.id, DownloadStatus::Failed);
+                    let _ = event_tx.send(SignalEvent::AttachmentDownloadFailed {
+                        message_id: attachment.message_id.clone(),
+                        attachment_id: attachment.id.clone(),
+                        error,
+                    });
+                } else {
+                    let delay = backoff_secs(attempts);
+                    tracing::warn!(
+                        "Attachment {} download failed (attempt {}), retrying in {}s: {}",
+                        attachment.id,
+                        attempts,
+                        delay,
+                        error
+                    );
+                    let _ = jobs.reschedule(&attachment.id, attempts, Utc::now().timestamp() + delay, &error);
+                    let _ = attachments.set_status(&attachment.id, DownloadStatus::Pending);
+                }
+            }
+        }
+
+        drop(db);
+        crate::app::request_repaint();
+        let _ = self.wake_tx.send(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_uses_mobile_flags_when_metered() {
+        let mut media = MediaSettings::default();
+        media.auto_download_videos_wifi = true;
+        media.auto_download_videos_mobile = false;
+
+        assert!(DownloadPolicy::from_settings(&media).allows("video/mp4", 1024, false));
+
+        media.metered_connection = true;
+        assert!(!DownloadPolicy::from_settings(&media).allows("video/mp4", 1024, false));
+    }
+
+    #[test]
+    fn test_policy_size_cap() {
+        let mut media = MediaSettings::default();
+        media.auto_download_max_size_mb = 1;
+        let policy = DownloadPolicy::from_settings(&media);
+
+        assert!(policy.allows("image/jpeg", 1024 * 1024, false));
+        assert!(!policy.allows("image/jpeg", 1024 * 1024 + 1, false));
+        // Voice notes ignore both the type flags and the cap
+        assert!(policy.allows("audio/aac", 10 * 1024 * 1024, true));
+
+        media.auto_download_max_size_mb = 0;
+        assert!(DownloadPolicy::from_settings(&media).allows("image/jpeg", u64::MAX, false));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        assert_eq!(backoff_secs(1), BASE_BACKOFF_SECS);
+        assert_eq!(backoff_secs(2), BASE_BACKOFF_SECS * 2);
+        assert_eq!(backoff_secs(3), BASE_BACKOFF_SECS * 4);
+        assert_eq!(backoff_secs(30), MAX_BACKOFF_SECS);
+    }
+}
@@ -0,0 +1,141 @@
+//! Disappearing message reaper
+//!
+//! A message with a timer gets its `expires_at` once the countdown starts:
+//! when it is read for incoming messages, when it is sent for our own. The
+//! reaper sleeps until the next message is due, then deletes everything that
+//! expired along with the downloaded attachment files.
+
+use crate::services::{ServiceContext, ServiceManager};
+use crate::signal::attachments::AttachmentManager;
+use crate::signal::messages::Message;
+use crate::signal::SignalEvent;
+use crate::storage::conversations::ConversationRepository;
+use crate::storage::messages::MessageRepository;
+use crate::storage::Storage;
+use chrono::Utc;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::{sleep, Duration};
+
+/// Longest the reaper sleeps, so countdowns started by other threads are
+/// picked up without an explicit wake
+const MAX_SLEEP: Duration = Duration::from_secs(5);
+
+/// Handle to the background reaper task
+#[derive(Clone)]
+pub struct ExpiryReaper {
+    wake_tx: mpsc::UnboundedSender<()>,
+}
+
+impl ExpiryReaper {
+    /// Start the reaper as a supervised service
+    pub fn start(
+        services: &ServiceManager,
+        storage: Arc<Storage>,
+        event_tx: mpsc::UnboundedSender<SignalEvent>,
+    ) -> Self {
+        let (wake_tx, wake_rx) = mpsc::unbounded_channel();
+        // Shared so a restarted worker keeps listening on the same channel
+        let wake_rx = Arc::new(Mutex::new(wake_rx));
+        services.spawn("Expiry reaper", move |ctx| {
+            run(ctx, storage.clone(), event_tx.clone(), wake_rx.clone())
+        });
+        Self { wake_tx }
+    }
+
+    /// Look at the next expiry again, e.g. after messages were read
+    pub fn wake(&self) {
+        let _ = self.wake_tx.send(());
+    }
+}
+
+async fn run(
+    mut ctx: ServiceContext,
+    storage: Arc<Storage>,
+    event_tx: mpsc::UnboundedSender<SignalEvent>,
+    wake_rx: Arc<Mutex<mpsc::UnboundedReceiver<()>>>,
+) -> anyhow::Result<()> {
+    let mut wake_rx = wake_rx.lock().await;
+
+    loop {
+        // Nothing to do until the database is unlocked
+        if storage.database().is_some() {
+            let result = reap_expired(&storage);
+            if let Ok(expired) = &result {
+                notify_expired(&event_tx, expired);
+            }
+            ctx.report(result.map(|expired| format!("Deleted {} expired messages", expired.len())));
+        }
+
+        let delay = storage
+            .database()
+            .and_then(|db| MessageRepository::new(&db).next_expiry())
+            .map(|at| (at - Utc::now()).num_milliseconds().max(0) as u64)
+            .map_or(MAX_SLEEP, |ms| Duration::from_millis(ms).min(MAX_SLEEP));
+
+        tokio::select! {
+            woke = wake_rx.recv() => {
+                if woke.is_none() {
+                    break;
+                }
+            }
+            _ = sleep(delay) => {}
+            _ = ctx.shutdown_requested() => break,
+        }
+    }
+
+    tracing::info!("Expiry reaper shutting down");
+    Ok(())
+}
+
+/// Tell the UI which conversations lost messages, if any
+pub fn notify_expired(event_tx: &mpsc::UnboundedSender<SignalEvent>, expired: &[Message]) {
+    if expired.is_empty() {
+        return;
+    }
+    let mut conversation_ids: Vec<String> = expired.iter().map(|m| m.conversation_id.clone()).collect();
+    conversation_ids.sort_unstable();
+    conversation_ids.dedup();
+    let _ = event_tx.send(SignalEvent::MessagesExpired { conversation_ids });
+    crate::app::request_repaint();
+}
+
+/// Delete all expired messages and their attachment files, and refresh the
+/// preview of conversations whose latest message disappeared. Returns the
+/// deleted messages.
+pub fn reap_expired(storage: &Storage) -> anyhow::Result<Vec<Message>> {
+    let db = storage
+        .database()
+        .ok_or_else(|| anyhow::anyhow!("Database not available"))?;
+    let message_repo = MessageRepository::new(&db);
+
+    let expired = message_repo.delete_expired(Utc::now())?;
+    if expired.is_empty() {
+        return Ok(expired);
+    }
+    tracing::info!("Deleted {} expired messages", expired.len());
+
+    let attachments = AttachmentManager::new(storage.attachments_dir().clone());
+    for message in &expired {
+        attachments.remove_local(&message.attachment_ids());
+    }
+
+    // Conversations are ordered by `updated_at`, which is left alone so a
+    // conversation doesn't jump to the top when its messages disappear
+    let conv_repo = ConversationRepository::new(&db);
+    for mut conv in conv_repo.list() {
+        let preview_expired = expired
+            .iter()
+            .any(|m| m.conversation_id == conv.id && Some(m.sent_at) >= conv.last_message_at);
+        if !preview_expired {
+            continue;
+        }
+
+        let latest = message_repo.get_latest(&conv.id);
+        conv.last_message = latest.as_ref().map(Message::preview_text);
+        conv.last_message_at = latest.map(|m| m.sent_at);
+        conv_repo.save(&conv)?;
+    }
+
+    Ok(expired)
+}
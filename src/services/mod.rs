@@ -1,55 +1,363 @@
 //! Background services and utilities
+//!
+//! Long-running workers are supervised by the `ServiceManager`. Each one runs
+//! as a tokio task on the app runtime and reports the outcome of every pass.
+//! A worker that panics or returns an error is restarted with a backoff, and
+//! given up on after too many crashes in a row. Shutdown is cooperative:
+//! workers watch the shutdown signal and are aborted if they miss the deadline.
 
+pub mod downloads;
+pub mod expiry;
 pub mod notifications;
+pub mod profiles;
 pub mod sync;
+pub mod typing;
 pub mod updates;
 
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::runtime::Runtime;
+use tokio::sync::watch;
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio::time::{sleep, timeout, Duration, Instant};
 
-/// Service manager for background tasks
-pub struct ServiceManager {
-    /// Shutdown signal sender
-    shutdown_tx: mpsc::Sender<()>,
+/// How long `shutdown` waits for workers to stop before aborting them
+pub const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(3);
+
+/// Crashes in a row, without a successful pass in between, before a worker
+/// is no longer restarted
+const MAX_CONSECUTIVE_CRASHES: u32 = 5;
+
+const BASE_RESTART_DELAY: Duration = Duration::from_millis(500);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// Delay before restarting a worker after `crashes` crashes in a row
+pub fn restart_delay(crashes: u32) -> Duration {
+    BASE_RESTART_DELAY
+        .saturating_mul(1 << crashes.saturating_sub(1).min(16))
+        .min(MAX_RESTART_DELAY)
+}
+
+/// Lifecycle of a supervised worker
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceStatus {
+    Running,
+    /// Crashed and waiting to be started again
+    Restarting,
+    Stopped,
+    /// Crashed too often and was given up on
+    Failed(String),
+}
+
+/// Health of a supervised worker, as shown in the diagnostics view
+#[derive(Debug, Clone)]
+pub struct ServiceHealth {
+    pub name: &'static str,
+    pub status: ServiceStatus,
+    /// When the current (or last) instance was started
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the worker last finished a pass
+    pub last_run: Option<DateTime<Utc>>,
+    /// Summary of the last pass, or its error
+    pub last_result: Option<Result<String, String>>,
+    /// Total number of restarts after a crash
+    pub restarts: u32,
+    /// Panic message or error of the last crash
+    pub last_crash: Option<String>,
+    consecutive_crashes: u32,
+}
+
+impl ServiceHealth {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            status: ServiceStatus::Running,
+            started_at: None,
+            last_run: None,
+            last_result: None,
+            restarts: 0,
+            last_crash: None,
+            consecutive_crashes: 0,
+        }
+    }
+}
+
+/// Handle given to a worker to report results and watch for shutdown
+#[derive(Clone)]
+pub struct ServiceContext {
+    health: Arc<Mutex<ServiceHealth>>,
+    shutdown_rx: watch::Receiver<bool>,
+}
+
+impl ServiceContext {
+    /// Record the outcome of a pass
+    pub fn report(&self, result: anyhow::Result<String>) {
+        let mut health = self.health.lock();
+        health.last_run = Some(Utc::now());
+        match result {
+            Ok(summary) => {
+                health.consecutive_crashes = 0;
+                health.last_result = Some(Ok(summary));
+            }
+            Err(e) => {
+                tracing::warn!("{} service: {:#}", health.name, e);
+                health.last_result = Some(Err(format!("{:#}", e)));
+            }
+        }
+    }
 
-    /// Shutdown signal receiver
-    shutdown_rx: Option<mpsc::Receiver<()>>,
+    /// Resolves once shutdown was requested
+    pub async fn shutdown_requested(&mut self) {
+        let _ = self.shutdown_rx.wait_for(|stop| *stop).await;
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown_rx.borrow()
+    }
+}
+
+type Worker = Arc<dyn Fn(ServiceContext) -> BoxFuture<'static, anyhow::Result<()>> + Send + Sync>;
+
+struct Service {
+    health: Arc<Mutex<ServiceHealth>>,
+    supervisor: JoinHandle<()>,
+    /// Running worker instance, aborted if it misses the shutdown deadline
+    task: Arc<Mutex<Option<AbortHandle>>>,
+}
+
+/// Supervisor for the background workers
+pub struct ServiceManager {
+    runtime: Arc<Runtime>,
+    shutdown_tx: watch::Sender<bool>,
+    services: Mutex<Vec<Service>>,
 }
 
 impl ServiceManager {
-    /// Create a new service manager
-    pub fn new() -> Self {
-        let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+    /// Create a service manager that runs its workers on `runtime`
+    pub fn new(runtime: Arc<Runtime>) -> Self {
+        let (shutdown_tx, _) = watch::channel(false);
         Self {
+            runtime,
             shutdown_tx,
-            shutdown_rx: Some(shutdown_rx),
+            services: Mutex::new(Vec::new()),
         }
     }
 
-    /// Start all background services
-    pub async fn start(&mut self) -> anyhow::Result<()> {
-        tracing::info!("Starting background services...");
+    /// Start a supervised worker. `worker` is called again to restart it
+    /// after a crash, so any state it needs across restarts must be shared.
+    pub fn spawn<F, Fut>(&self, name: &'static str, worker: F)
+    where
+        F: Fn(ServiceContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let worker: Worker = Arc::new(move |ctx| -> BoxFuture<'static, anyhow::Result<()>> {
+            Box::pin(worker(ctx))
+        });
+        let health = Arc::new(Mutex::new(ServiceHealth::new(name)));
+        let task = Arc::new(Mutex::new(None));
+
+        let supervisor = self.runtime.spawn(supervise(
+            worker,
+            health.clone(),
+            task.clone(),
+            self.shutdown_tx.subscribe(),
+        ));
 
-        // TODO: Start notification service
-        // TODO: Start sync service
-        // TODO: Start update checker
+        tracing::info!("Started {} service", name);
+        self.services.lock().push(Service { health, supervisor, task });
+    }
 
-        Ok(())
+    /// Snapshot of every service's health, in start order
+    pub fn health(&self) -> Vec<ServiceHealth> {
+        self.services.lock().iter().map(|s| s.health.lock().clone()).collect()
     }
 
-    /// Shutdown all services
-    pub async fn shutdown(&self) -> anyhow::Result<()> {
+    /// Ask all workers to stop and wait for them until `deadline` has
+    /// passed. Workers still running after that are aborted.
+    pub async fn shutdown(&self, deadline: Duration) {
         tracing::info!("Shutting down background services...");
+        let _ = self.shutdown_tx.send(true);
 
-        // Send shutdown signal
-        let _ = self.shutdown_tx.send(()).await;
+        let services = std::mem::take(&mut *self.services.lock());
+        let until = Instant::now() + deadline;
 
-        Ok(())
+        for mut service in services {
+            let remaining = until.saturating_duration_since(Instant::now());
+            if timeout(remaining, &mut service.supervisor).await.is_err() {
+                let mut health = service.health.lock();
+                tracing::warn!("{} service did not stop in time, aborting", health.name);
+                if let Some(task) = service.task.lock().take() {
+                    task.abort();
+                }
+                service.supervisor.abort();
+                health.status = ServiceStatus::Stopped;
+            }
+        }
+
+        tracing::info!("Background services stopped");
+    }
+
+    /// Blocking `shutdown` for callers outside the runtime, e.g. on exit
+    pub fn shutdown_blocking(&self, deadline: Duration) {
+        let runtime = self.runtime.clone();
+        runtime.block_on(self.shutdown(deadline));
+    }
+}
+
+/// Run a worker, restarting it after a panic or an error until shutdown
+async fn supervise(
+    worker: Worker,
+    health: Arc<Mutex<ServiceHealth>>,
+    task: Arc<Mutex<Option<AbortHandle>>>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    let name = health.lock().name;
+
+    loop {
+        {
+            let mut health = health.lock();
+            health.status = ServiceStatus::Running;
+            health.started_at = Some(Utc::now());
+        }
+
+        let ctx = ServiceContext {
+            health: health.clone(),
+            shutdown_rx: shutdown_rx.clone(),
+        };
+        let handle = tokio::spawn(worker(ctx));
+        *task.lock() = Some(handle.abort_handle());
+        let outcome = handle.await;
+        task.lock().take();
+
+        let crash = match outcome {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("{:#}", e)),
+            Err(e) if e.is_panic() => Some(panic_message(e.into_panic())),
+            Err(_) => None,
+        };
+
+        let stopping = *shutdown_rx.borrow();
+        let Some(crash) = crash.filter(|_| !stopping) else {
+            health.lock().status = ServiceStatus::Stopped;
+            tracing::info!("{} service stopped", name);
+            return;
+        };
+
+        let crashes = {
+            let mut health = health.lock();
+            health.consecutive_crashes += 1;
+            health.last_crash = Some(crash.clone());
+            if health.consecutive_crashes >= MAX_CONSECUTIVE_CRASHES {
+                tracing::error!("{} service crashed {} times in a row, giving up: {}", name, health.consecutive_crashes, crash);
+                health.status = ServiceStatus::Failed(crash);
+                return;
+            }
+            health.status = ServiceStatus::Restarting;
+            health.restarts += 1;
+            health.consecutive_crashes
+        };
+
+        let delay = restart_delay(crashes);
+        tracing::error!("{} service crashed, restarting in {:?}: {}", name, delay, crash);
+        tokio::select! {
+            _ = sleep(delay) => {}
+            _ = shutdown_rx.wait_for(|stop| *stop) => {
+                health.lock().status = ServiceStatus::Stopped;
+                return;
+            }
+        }
     }
 }
 
-impl Default for ServiceManager {
-    fn default() -> Self {
-        Self::new()
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => format!("panicked: {}", message),
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => format!("panicked: {}", message),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn runtime() -> Arc<Runtime> {
+        Arc::new(
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .enable_all()
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_restart_delay_grows_and_caps() {
+        assert_eq!(restart_delay(1), BASE_RESTART_DELAY);
+        assert_eq!(restart_delay(2), BASE_RESTART_DELAY * 2);
+        assert_eq!(restart_delay(3), BASE_RESTART_DELAY * 4);
+        assert_eq!(restart_delay(40), MAX_RESTART_DELAY);
+    }
+
+    #[test]
+    fn test_worker_restarted_after_panic() {
+        let services = ServiceManager::new(runtime());
+        let starts = Arc::new(AtomicU32::new(0));
+
+        // The restarted worker says when it is up, so the test doesn't
+        // depend on how long the restart takes
+        let (restarted_tx, restarted_rx) = std::sync::mpsc::channel();
+        let counter = starts.clone();
+        services.spawn("flaky", move |mut ctx| {
+            let start = counter.fetch_add(1, Ordering::SeqCst);
+            let restarted_tx = restarted_tx.clone();
+            async move {
+                if start == 0 {
+                    panic!("first start fails");
+                }
+                ctx.report(Ok("ok".to_string()));
+                let _ = restarted_tx.send(());
+                ctx.shutdown_requested().await;
+                Ok(())
+            }
+        });
+
+        restarted_rx
+            .recv_timeout(Duration::from_secs(30))
+            .expect("worker was not restarted");
+        let health = &services.health()[0];
+        assert_eq!(health.status, ServiceStatus::Running);
+        assert_eq!(health.restarts, 1);
+        assert_eq!(health.last_crash.as_deref(), Some("panicked: first start fails"));
+        assert_eq!(health.last_result, Some(Ok("ok".to_string())));
+
+        services.shutdown_blocking(SHUTDOWN_DEADLINE);
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_shutdown_aborts_workers_past_deadline() {
+        let services = ServiceManager::new(runtime());
+        services.spawn("stubborn", |_ctx| async {
+            sleep(Duration::from_secs(60)).await;
+            Ok(())
+        });
+        services.spawn("polite", |mut ctx| async move {
+            ctx.shutdown_requested().await;
+            Ok(())
+        });
+        let health = services.services.lock().iter().map(|s| s.health.clone()).collect::<Vec<_>>();
+
+        let started = std::time::Instant::now();
+        services.shutdown_blocking(Duration::from_millis(200));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(services.health().is_empty());
+        assert!(health.iter().all(|h| h.lock().status == ServiceStatus::Stopped));
     }
 }
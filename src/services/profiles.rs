@@ -0,0 +1,109 @@
+//! Periodic profile refresh
+//!
+//! Avatars are fetched once a contact's profile key is known, and change
+//! without us being told. Every hour the refresher picks contacts that haven't
+//! been looked at for a day and asks the receive loop to fetch them again.
+
+use crate::services::{ServiceContext, ServiceManager};
+use crate::signal::SignalManager;
+use crate::storage::contacts::{ContactRepository, StoredContact};
+use crate::storage::Storage;
+use chrono::Utc;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Profiles fetched per run, to keep the receive loop responsive
+const BATCH_SIZE: usize = 20;
+
+/// Start the profile refresher as a supervised service
+pub fn start(services: &ServiceManager, storage: Arc<Storage>) {
+    services.spawn("Profile refresh", move |ctx| run(ctx, storage.clone()));
+}
+
+async fn run(mut ctx: ServiceContext, storage: Arc<Storage>) -> anyhow::Result<()> {
+    let mut refresh = interval(REFRESH_INTERVAL);
+    refresh.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = refresh.tick() => {}
+            _ = ctx.shutdown_requested() => break,
+        }
+        ctx.report(refresh_stale(&storage).await);
+    }
+
+    tracing::info!("Profile refresher shutting down");
+    Ok(())
+}
+
+async fn refresh_stale(storage: &Arc<Storage>) -> anyhow::Result<String> {
+    if !SignalManager::receive_loop_running() {
+        return Ok("Waiting for connection".to_string());
+    }
+
+    let uuids = {
+        let Some(db) = storage.database() else {
+            return Ok("Waiting for database".to_string());
+        };
+        stale_contacts(&ContactRepository::new(&db).list(), Utc::now().timestamp())
+    };
+    if uuids.is_empty() {
+        return Ok("All profiles up to date".to_string());
+    }
+
+    let count = uuids.len();
+    SignalManager::refresh_profiles(uuids).await?;
+    Ok(format!("Refreshed {} profiles", count))
+}
+
+/// Contacts with a profile key that weren't refreshed for a day or whose
+/// avatar file is gone, oldest first, at most `BATCH_SIZE`
+pub fn stale_contacts(contacts: &[StoredContact], now: i64) -> Vec<String> {
+    let mut stale: Vec<&StoredContact> = contacts
+        .iter()
+        .filter(|c| c.profile_key.as_ref().is_some_and(|k| k.len() == 32))
+        .filter(|c| {
+            let file_missing = c.avatar_path.as_deref().is_some_and(|p| !Path::new(p).exists());
+            file_missing || c.needs_profile_refresh(now)
+        })
+        .collect();
+    stale.sort_by_key(|c| c.profile_fetched_at.unwrap_or(0));
+    stale.into_iter().take(BATCH_SIZE).map(|c| c.uuid.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::contacts::PROFILE_REFRESH_SECS;
+
+    #[test]
+    fn test_stale_contacts() {
+        let now = Utc::now().timestamp();
+        let contact = |uuid: &str, key: bool, age: i64| {
+            let mut c = StoredContact::new(uuid, uuid);
+            c.profile_key = key.then(|| vec![0; 32]);
+            c.avatar_path = Some(std::env::temp_dir().to_string_lossy().to_string());
+            c.profile_fetched_at = Some(now - age);
+            c
+        };
+
+        let contacts = vec![
+            contact("fresh", true, 60),
+            contact("old", true, PROFILE_REFRESH_SECS + 1),
+            contact("older", true, PROFILE_REFRESH_SECS * 2),
+            contact("no-key", false, PROFILE_REFRESH_SECS * 2),
+        ];
+        assert_eq!(stale_contacts(&contacts, now), vec!["older", "old"]);
+
+        let mut deleted = contact("deleted", true, 60);
+        deleted.avatar_path = Some("/nonexistent/avatar.jpg".to_string());
+        assert_eq!(stale_contacts(&[deleted], now), vec!["deleted"]);
+
+        let mut never_fetched = contact("new", true, 0);
+        never_fetched.profile_fetched_at = None;
+        assert_eq!(stale_contacts(&[never_fetched], now), vec!["new"]);
+    }
+}
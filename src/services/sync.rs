@@ -1,68 +1,185 @@
 //! Sync service for data synchronization
+//!
+//! Local upkeep runs every few minutes. Asking the primary device for its
+//! data only happens on demand: after linking and from the "Sync now" button.
+
+use crate::services::{ServiceContext, ServiceManager};
+use crate::signal::sync::SyncRequestKind;
+use crate::signal::{SignalEvent, SignalManager};
+use crate::storage::Storage;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+/// How long to wait for the primary to answer before showing it as missing
+const RESPONSE_TIMEOUT_SECS: i64 = 60;
+
+/// Progress of the last sync request to the primary device
+#[derive(Debug, Clone, Default)]
+pub struct SyncProgress {
+    pub requested_at: Option<DateTime<Utc>>,
+    /// Requested data the primary hasn't sent yet
+    pub pending: Vec<SyncRequestKind>,
+    pub received: Vec<SyncRequestKind>,
+    /// The request couldn't be sent
+    pub error: Option<String>,
+}
+
+impl SyncProgress {
+    fn start(&mut self, kinds: &[SyncRequestKind], now: DateTime<Utc>) {
+        *self = Self {
+            requested_at: Some(now),
+            pending: kinds.to_vec(),
+            ..Default::default()
+        };
+    }
+
+    fn response_received(&mut self, kind: SyncRequestKind) {
+        self.pending.retain(|k| *k != kind);
+        if !self.received.contains(&kind) {
+            self.received.push(kind);
+        }
+    }
 
-use tokio::time::{interval, Duration};
+    fn failed(&mut self, error: String) {
+        self.pending.clear();
+        self.error = Some(error);
+    }
+
+    /// Waiting for answers that may still come
+    pub fn is_running(&self, now: DateTime<Utc>) -> bool {
+        !self.pending.is_empty() && !self.timed_out(now)
+    }
+
+    /// The primary didn't answer everything in time
+    pub fn timed_out(&self, now: DateTime<Utc>) -> bool {
+        !self.pending.is_empty()
+            && self
+                .requested_at
+                .is_some_and(|at| (now - at).num_seconds() > RESPONSE_TIMEOUT_SECS)
+    }
+}
 
 /// Sync service for periodic synchronization tasks
+#[derive(Clone)]
 pub struct SyncService {
+    storage: Arc<Storage>,
+    event_tx: mpsc::UnboundedSender<SignalEvent>,
+    wake_tx: mpsc::UnboundedSender<()>,
+    progress: Arc<parking_lot::Mutex<SyncProgress>>,
     /// Sync interval
     interval_secs: u64,
 }
 
 impl SyncService {
-    /// Create a new sync service
-    pub fn new() -> Self {
-        Self {
+    /// Start the sync service as a supervised worker
+    pub fn start(
+        services: &ServiceManager,
+        storage: Arc<Storage>,
+        event_tx: mpsc::UnboundedSender<SignalEvent>,
+    ) -> Self {
+        let (wake_tx, wake_rx) = mpsc::unbounded_channel();
+        // Shared so a restarted worker keeps listening on the same channel
+        let wake_rx = Arc::new(Mutex::new(wake_rx));
+        let service = Self {
+            storage,
+            event_tx,
+            wake_tx,
+            progress: Arc::new(parking_lot::Mutex::new(SyncProgress::default())),
             interval_secs: 300, // 5 minutes
-        }
+        };
+        let worker = service.clone();
+        services.spawn("Sync", move |ctx| worker.clone().run(ctx, wake_rx.clone()));
+        service
     }
 
-    /// Start the sync service
-    pub async fn start(&self, mut shutdown_rx: tokio::sync::mpsc::Receiver<()>) {
+    async fn run(
+        self,
+        mut ctx: ServiceContext,
+        wake_rx: Arc<Mutex<mpsc::UnboundedReceiver<()>>>,
+    ) -> anyhow::Result<()> {
+        let mut wake_rx = wake_rx.lock().await;
         let mut sync_interval = interval(Duration::from_secs(self.interval_secs));
+        sync_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         loop {
-            tokio::select! {
-                _ = sync_interval.tick() => {
-                    self.perform_sync().await;
-                }
-                _ = shutdown_rx.recv() => {
-                    tracing::info!("Sync service shutting down");
-                    break;
+            // Woken by `request_sync`, which also asks the primary device
+            let request_primary = tokio::select! {
+                _ = sync_interval.tick() => false,
+                woke = wake_rx.recv() => {
+                    if woke.is_none() {
+                        break;
+                    }
+                    true
                 }
+                _ = ctx.shutdown_requested() => break,
+            };
+
+            // Nothing to sync until the database is unlocked
+            if self.storage.database().is_some() {
+                ctx.report(self.perform_sync(request_primary).await);
             }
         }
+
+        tracing::info!("Sync service shutting down");
+        Ok(())
     }
 
     /// Perform synchronization
-    async fn perform_sync(&self) {
+    async fn perform_sync(&self, request_primary: bool) -> anyhow::Result<String> {
         tracing::debug!("Running periodic sync...");
 
-        // TODO: Sync contacts
-        // TODO: Sync groups
-        // TODO: Cleanup expired messages
-        // TODO: Refresh stale profiles
+        if request_primary {
+            if let Err(e) = SignalManager::request_sync(&SyncRequestKind::ALL).await {
+                self.progress.lock().failed(e.to_string());
+                return Err(e.into());
+            }
+        }
+
+        let contacts = self.sync_contacts().await?;
+        let groups = self.sync_groups().await?;
+        let expired = self.cleanup_expired_messages().await?;
+
+        Ok(format!(
+            "{} contacts, {} groups updated, {} expired messages removed",
+            contacts, groups, expired
+        ))
     }
 
-    /// Request immediate sync
-    pub async fn request_sync(&self) {
+    /// Request immediate sync, including everything the primary device
+    /// can send us
+    pub fn request_sync(&self) {
         tracing::info!("Immediate sync requested");
-        self.perform_sync().await;
+        self.progress.lock().start(&SyncRequestKind::ALL, Utc::now());
+        let _ = self.wake_tx.send(());
+    }
+
+    /// The primary device answered part of a sync request
+    pub fn response_received(&self, kind: SyncRequestKind) {
+        self.progress.lock().response_received(kind);
+    }
+
+    pub fn progress(&self) -> SyncProgress {
+        self.progress.lock().clone()
     }
 
     /// Sync contacts from primary device
     pub async fn sync_contacts(&self) -> anyhow::Result<usize> {
-        tracing::info!("Syncing contacts...");
+        tracing::debug!("Syncing contacts...");
 
-        // TODO: Request contact sync from primary device
-        // TODO: Process sync response
-        // TODO: Update local contact storage
-
-        Ok(0)
+        // Pick up names and avatars of contacts stored since the last run
+        let updated = crate::signal::profiles::update_conversations_from_contacts(&self.storage)?;
+        if updated > 0 {
+            let _ = self.event_tx.send(SignalEvent::ContactUpdated { contact_id: "all".to_string() });
+            crate::app::request_repaint();
+        }
+        Ok(updated)
     }
 
     /// Sync groups from primary device
     pub async fn sync_groups(&self) -> anyhow::Result<usize> {
-        tracing::info!("Syncing groups...");
+        tracing::debug!("Syncing groups...");
 
         // TODO: Request group sync from primary device
         // TODO: Process group updates
@@ -89,16 +206,39 @@ impl SyncService {
     pub async fn cleanup_expired_messages(&self) -> anyhow::Result<usize> {
         tracing::debug!("Cleaning up expired messages...");
 
-        // TODO: Query for expired messages
-        // TODO: Delete expired messages
-        // TODO: Delete associated attachments
-
-        Ok(0)
+        let expired = crate::services::expiry::reap_expired(&self.storage)?;
+        crate::services::expiry::notify_expired(&self.event_tx, &expired);
+        Ok(expired.len())
     }
 }
 
-impl Default for SyncService {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_progress() {
+        let now = Utc::now();
+        let mut progress = SyncProgress::default();
+        assert!(!progress.is_running(now));
+
+        progress.start(&SyncRequestKind::ALL, now);
+        progress.response_received(SyncRequestKind::Contacts);
+        progress.response_received(SyncRequestKind::Blocked);
+        assert!(progress.is_running(now));
+        assert_eq!(progress.received, vec![SyncRequestKind::Contacts, SyncRequestKind::Blocked]);
+
+        let later = now + chrono::Duration::seconds(RESPONSE_TIMEOUT_SECS + 1);
+        assert!(progress.timed_out(later));
+        assert!(!progress.is_running(later));
+
+        progress.response_received(SyncRequestKind::Configuration);
+        progress.response_received(SyncRequestKind::Keys);
+        assert!(!progress.timed_out(later));
+
+        progress.start(&SyncRequestKind::ALL, now);
+        progress.failed("Not connected".to_string());
+        assert!(!progress.is_running(now));
+        assert!(progress.received.is_empty());
     }
 }
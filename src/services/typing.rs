@@ -0,0 +1,227 @@
+//! Typing indicator state
+//!
+//! Incoming indicators are kept per conversation and expire if the sender
+//! never says they stopped. Outgoing ones are debounced: a "started" message is
+//! sent when the user begins typing and refreshed while they keep going, and a
+//! "stopped" message follows a few seconds of inactivity.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long an incoming indicator lasts without a refresh
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often "started" is repeated while the user keeps typing
+pub const TYPING_REFRESH: Duration = Duration::from_secs(10);
+
+/// Inactivity after which we send "stopped"
+pub const TYPING_IDLE: Duration = Duration::from_secs(3);
+
+/// Who is typing in which conversation
+#[derive(Debug, Default)]
+pub struct TypingIndicators {
+    /// conversation ID -> sender -> when the indicator expires
+    active: HashMap<String, HashMap<String, Instant>>,
+}
+
+impl TypingIndicators {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn started(&mut self, conversation_id: &str, sender: &str, now: Instant) {
+        self.active
+            .entry(conversation_id.to_string())
+            .or_default()
+            .insert(sender.to_string(), now + TYPING_TIMEOUT);
+    }
+
+    /// Clear an indicator, either on "stopped" or when the sender's message arrives
+    pub fn stopped(&mut self, conversation_id: &str, sender: &str) -> bool {
+        let Some(senders) = self.active.get_mut(conversation_id) else {
+            return false;
+        };
+        let removed = senders.remove(sender).is_some();
+        if senders.is_empty() {
+            self.active.remove(conversation_id);
+        }
+        removed
+    }
+
+    /// Drop expired indicators. Returns true if any were removed.
+    pub fn prune(&mut self, now: Instant) -> bool {
+        let mut changed = false;
+        self.active.retain(|_, senders| {
+            let before = senders.len();
+            senders.retain(|_, expires| *expires > now);
+            changed |= senders.len() != before;
+            !senders.is_empty()
+        });
+        changed
+    }
+
+    /// Senders currently typing in a conversation, in a stable order
+    pub fn senders(&self, conversation_id: &str) -> Vec<&str> {
+        let mut senders: Vec<&str> = self
+            .active
+            .get(conversation_id)
+            .map(|s| s.keys().map(String::as_str).collect())
+            .unwrap_or_default();
+        senders.sort_unstable();
+        senders
+    }
+
+    /// Time until the next indicator expires, to schedule a repaint
+    pub fn next_expiry(&self, now: Instant) -> Option<Duration> {
+        self.active
+            .values()
+            .flat_map(|senders| senders.values())
+            .min()
+            .map(|expires| expires.saturating_duration_since(now))
+    }
+}
+
+/// Typing message the composer should send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypingUpdate {
+    pub conversation_id: String,
+    pub started: bool,
+}
+
+/// Debounces our own typing indicators
+#[derive(Debug)]
+pub struct OutgoingTyping {
+    /// (conversation ID, last "started" sent, last keystroke)
+    current: Option<(String, Instant, Instant)>,
+}
+
+impl OutgoingTyping {
+    pub const fn new() -> Self {
+        Self { current: None }
+    }
+
+    /// The input text changed. Switching conversations stops the previous
+    /// indicator first.
+    pub fn input_changed(&mut self, conversation_id: &str, now: Instant) -> Vec<TypingUpdate> {
+        let mut updates = Vec::new();
+
+        if let Some((current, _, _)) = &self.current {
+            if current != conversation_id {
+                updates.extend(self.stop());
+            }
+        }
+
+        match &mut self.current {
+            Some((_, last_started, last_input)) => {
+                *last_input = now;
+                if now.duration_since(*last_started) >= TYPING_REFRESH {
+                    *last_started = now;
+                    updates.push(TypingUpdate {
+                        conversation_id: conversation_id.to_string(),
+                        started: true,
+                    });
+                }
+            }
+            None => {
+                self.current = Some((conversation_id.to_string(), now, now));
+                updates.push(TypingUpdate {
+                    conversation_id: conversation_id.to_string(),
+                    started: true,
+                });
+            }
+        }
+
+        updates
+    }
+
+    /// Stop after `TYPING_IDLE` without a keystroke
+    pub fn tick(&mut self, now: Instant) -> Option<TypingUpdate> {
+        let (_, _, last_input) = self.current.as_ref()?;
+        if now.duration_since(*last_input) >= TYPING_IDLE {
+            self.stop()
+        } else {
+            None
+        }
+    }
+
+    /// Stop right away, e.g. when the input is cleared
+    pub fn stop(&mut self) -> Option<TypingUpdate> {
+        self.current.take().map(|(conversation_id, _, _)| TypingUpdate {
+            conversation_id,
+            started: false,
+        })
+    }
+
+    /// Stop without telling anyone, when a sent message ends the indicator anyway
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+
+    /// Time until `tick` would stop the indicator
+    pub fn idle_remaining(&self, now: Instant) -> Option<Duration> {
+        let (_, _, last_input) = self.current.as_ref()?;
+        Some(TYPING_IDLE.saturating_sub(now.duration_since(*last_input)))
+    }
+
+    pub fn conversation_id(&self) -> Option<&str> {
+        self.current.as_ref().map(|(id, _, _)| id.as_str())
+    }
+}
+
+impl Default for OutgoingTyping {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incoming_indicators_expire() {
+        let now = Instant::now();
+        let mut typing = TypingIndicators::new();
+        typing.started("group", "alice", now);
+        typing.started("group", "bob", now + Duration::from_secs(5));
+        assert_eq!(typing.senders("group"), vec!["alice", "bob"]);
+
+        assert!(!typing.prune(now + Duration::from_secs(10)));
+        assert!(typing.prune(now + TYPING_TIMEOUT));
+        assert_eq!(typing.senders("group"), vec!["bob"]);
+
+        assert!(typing.stopped("group", "bob"));
+        assert!(typing.senders("group").is_empty());
+        assert!(typing.next_expiry(now).is_none());
+    }
+
+    #[test]
+    fn test_outgoing_typing_is_debounced() {
+        let now = Instant::now();
+        let mut typing = OutgoingTyping::new();
+        let started = |id: &str| TypingUpdate { conversation_id: id.to_string(), started: true };
+        let stopped = |id: &str| TypingUpdate { conversation_id: id.to_string(), started: false };
+
+        assert_eq!(typing.input_changed("alice", now), vec![started("alice")]);
+        // Keystrokes within the refresh interval send nothing
+        assert!(typing.input_changed("alice", now + Duration::from_secs(2)).is_empty());
+        assert!(typing.tick(now + Duration::from_secs(4)).is_none());
+        assert_eq!(
+            typing.input_changed("alice", now + TYPING_REFRESH),
+            vec![started("alice")]
+        );
+
+        assert_eq!(
+            typing.tick(now + TYPING_REFRESH + TYPING_IDLE),
+            Some(stopped("alice"))
+        );
+        assert!(typing.tick(now + TYPING_REFRESH * 2).is_none());
+
+        // Typing elsewhere stops the previous conversation first
+        typing.input_changed("alice", now);
+        assert_eq!(
+            typing.input_changed("bob", now),
+            vec![stopped("alice"), started("bob")]
+        );
+    }
+}
@@ -1,9 +1,17 @@
 //! Attachment handling
 
 use crate::signal::SignalError;
+use presage::libsignal_service::proto::AttachmentPointer;
+use presage::libsignal_service::sender::AttachmentSpec;
+use presage::manager::Registered;
+use presage::Manager;
+use presage_store_sqlite::SqliteStore;
 use std::path::{Path, PathBuf};
 use tokio::fs;
 
+/// Longest side of the thumbnail sent with a quoted image, in pixels
+const QUOTE_THUMBNAIL_SIZE: u32 = 150;
+
 /// Attachment metadata
 #[derive(Debug, Clone)]
 pub struct AttachmentMetadata {
@@ -45,6 +53,152 @@ pub struct AttachmentMetadata {
 
     /// Encryption digest
     pub digest: Option<Vec<u8>>,
+
+    /// Whether the attachment was recorded as a voice note
+    pub voice_note: bool,
+}
+
+impl AttachmentMetadata {
+    /// Build metadata from an incoming pointer.
+    ///
+    /// A fresh local ID (with an extension derived from the content type) is
+    /// assigned. Returns `None` for pointers without a key or CDN locator.
+    pub fn from_pointer(pointer: &AttachmentPointer) -> Option<Self> {
+        use presage::libsignal_service::proto::attachment_pointer::{AttachmentIdentifier, Flags};
+
+        let cdn_key = match pointer.attachment_identifier.as_ref()? {
+            AttachmentIdentifier::CdnId(id) => id.to_string(),
+            AttachmentIdentifier::CdnKey(key) => key.clone(),
+        };
+        let key = pointer.key.clone()?;
+
+        let content_type = pointer
+            .content_type
+            .clone()
+            .filter(|c| !c.is_empty())
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        let filename = pointer.file_name.clone().filter(|f| !f.is_empty());
+
+        let extension = filename
+            .as_deref()
+            .and_then(|f| Path::new(f).extension())
+            .and_then(|e| e.to_str())
+            .map(str::to_string)
+            .or_else(|| {
+                mime_guess::get_mime_extensions_str(&content_type)
+                    .and_then(|exts| exts.first())
+                    .map(|e| e.to_string())
+            });
+        let uuid = uuid::Uuid::new_v4().to_string();
+        let id = match extension {
+            Some(ext) => format!("{}.{}", uuid, ext),
+            None => uuid,
+        };
+
+        Some(Self {
+            id,
+            content_type,
+            filename,
+            size: pointer.size.unwrap_or(0) as u64,
+            width: pointer.width.filter(|w| *w > 0),
+            height: pointer.height.filter(|h| *h > 0),
+            duration_ms: None,
+            blurhash: pointer.blur_hash.clone(),
+            waveform: None,
+            cdn_number: Some(pointer.cdn_number.unwrap_or(0)),
+            cdn_key: Some(cdn_key),
+            key: Some(key),
+            digest: pointer.digest.clone(),
+            voice_note: pointer.flags.unwrap_or(0) & Flags::VoiceMessage as u32 != 0,
+        })
+    }
+}
+
+/// Build the CDN download URL for an attachment
+fn cdn_url(metadata: &AttachmentMetadata) -> Result<String, SignalError> {
+    let cdn_key = metadata
+        .cdn_key
+        .as_deref()
+        .ok_or_else(|| SignalError::AttachmentError("Attachment has no CDN key".into()))?;
+
+    match metadata.cdn_number.unwrap_or(0) {
+        0 => Ok(format!("https://cdn.signal.org/attachments/{}", cdn_key)),
+        n @ (2 | 3) => Ok(format!(
+            "https://cdn{}.signal.org/attachments/{}",
+            n,
+            urlencoding::encode(cdn_key)
+        )),
+        n => Err(SignalError::AttachmentError(format!("Unknown CDN number: {}", n))),
+    }
+}
+
+/// Verify and decrypt a downloaded attachment blob.
+///
+/// The blob is `IV || AES-256-CBC(plaintext) || HMAC-SHA256(IV || ciphertext)`,
+/// keyed by the 64-byte pointer key (AES key followed by HMAC key). The digest,
+/// when present, is SHA-256 over the whole blob. Signal pads plaintext before
+/// encrypting, so the result is truncated to `size` when known.
+pub fn decrypt_attachment(
+    blob: &[u8],
+    key: &[u8],
+    digest: Option<&[u8]>,
+    size: Option<u64>,
+) -> Result<Vec<u8>, SignalError> {
+    use aes::cipher::{BlockDecryptMut, KeyIvInit};
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    const IV_LEN: usize = 16;
+    const MAC_LEN: usize = 32;
+
+    if key.len() != 64 {
+        return Err(SignalError::CryptoError(format!(
+            "Invalid attachment key length: {}",
+            key.len()
+        )));
+    }
+    if blob.len() < IV_LEN + MAC_LEN {
+        return Err(SignalError::CryptoError("Attachment data too short".into()));
+    }
+
+    if let Some(expected) = digest {
+        let actual = Sha256::digest(blob);
+        if actual.as_slice() != expected {
+            return Err(SignalError::CryptoError("Attachment digest mismatch".into()));
+        }
+    }
+
+    let (aes_key, hmac_key) = key.split_at(32);
+    let (iv, rest) = blob.split_at(IV_LEN);
+    let (ciphertext, mac) = rest.split_at(rest.len() - MAC_LEN);
+
+    let mut hmac = Hmac::<Sha256>::new_from_slice(hmac_key)
+        .map_err(|_| SignalError::CryptoError("Invalid HMAC key length".into()))?;
+    hmac.update(iv);
+    hmac.update(ciphertext);
+    hmac.verify_slice(mac)
+        .map_err(|_| SignalError::CryptoError("Attachment MAC verification failed".into()))?;
+
+    let mut buffer = ciphertext.to_vec();
+    let decryptor = cbc::Decryptor::<aes::Aes256>::new_from_slices(aes_key, iv)
+        .map_err(|_| SignalError::CryptoError("Invalid AES key/IV".into()))?;
+    let decrypted = decryptor
+        .decrypt_padded_mut::<aes::cipher::block_padding::Pkcs7>(&mut buffer)
+        .map_err(|_| SignalError::CryptoError("Attachment decryption failed".into()))?;
+
+    let mut plaintext = decrypted.to_vec();
+    if let Some(size) = size {
+        if (size as usize) > plaintext.len() {
+            return Err(SignalError::CryptoError(format!(
+                "Attachment shorter than declared size ({} < {})",
+                plaintext.len(),
+                size
+            )));
+        }
+        plaintext.truncate(size as usize);
+    }
+
+    Ok(plaintext)
 }
 
 /// Attachment manager for uploading/downloading attachments
@@ -69,7 +223,7 @@ impl AttachmentManager {
         self.attachment_path(id).exists()
     }
 
-    /// Download an attachment from Signal servers
+    /// Download an attachment from the Signal CDN, verify and decrypt it
     pub async fn download(&self, metadata: &AttachmentMetadata) -> Result<PathBuf, SignalError> {
         let path = self.attachment_path(&metadata.id);
 
@@ -78,6 +232,12 @@ impl AttachmentManager {
             return Ok(path);
         }
 
+        let key = metadata
+            .key
+            .as_deref()
+            .ok_or_else(|| SignalError::AttachmentError("Attachment has no key".into()))?;
+        let url = cdn_url(metadata)?;
+
         // Ensure directory exists
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)
@@ -85,20 +245,62 @@ impl AttachmentManager {
                 .map_err(|e| SignalError::AttachmentError(e.to_string()))?;
         }
 
-        // TODO: Implement actual download
-        // 1. Get download URL from CDN
-        // 2. Download encrypted data
-        // 3. Decrypt with attachment key
-        // 4. Verify digest
-        // 5. Write to file
+        tracing::info!(
+            "Downloading attachment {} from CDN {}",
+            metadata.id,
+            metadata.cdn_number.unwrap_or(0)
+        );
+
+        let client = crate::signal::backup::api::build_signal_client()?;
+        let response = client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| SignalError::NetworkError(format!("Failed to download attachment: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(SignalError::NetworkError(format!(
+                "Attachment download failed with status {}",
+                response.status()
+            )));
+        }
+
+        let blob = response
+            .bytes()
+            .await
+            .map_err(|e| SignalError::NetworkError(format!("Failed to read attachment bytes: {}", e)))?;
+
+        let plaintext = decrypt_attachment(
+            &blob,
+            key,
+            metadata.digest.as_deref(),
+            Some(metadata.size).filter(|s| *s > 0),
+        )?;
+
+        // Write to a temp file first so a partial write never looks downloaded
+        let tmp_path = path.with_extension("part");
+        fs::write(&tmp_path, &plaintext)
+            .await
+            .map_err(|e| SignalError::AttachmentError(e.to_string()))?;
+        fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|e| SignalError::AttachmentError(e.to_string()))?;
 
-        tracing::info!("Downloading attachment: {}", metadata.id);
+        tracing::info!("Downloaded attachment {} ({} bytes)", metadata.id, plaintext.len());
 
         Ok(path)
     }
 
-    /// Upload an attachment to Signal servers
-    pub async fn upload(&self, file_path: &Path) -> Result<AttachmentMetadata, SignalError> {
+    /// Encrypt and upload an attachment to the Signal CDN via presage
+    ///
+    /// Returns the pointer that must be attached to the outgoing `DataMessage`.
+    pub async fn upload(
+        &self,
+        manager: &Manager<SqliteStore, Registered>,
+        file_path: &Path,
+        caption: Option<String>,
+        voice_note: bool,
+    ) -> Result<AttachmentPointer, SignalError> {
         // Read file
         let data = fs::read(file_path)
             .await
@@ -114,36 +316,121 @@ impl AttachmentManager {
             .first_or_octet_stream()
             .to_string();
 
-        let size = data.len() as u64;
+        let (width, height) = if content_type.starts_with("image/") {
+            image_utils::get_dimensions(&data)
+                .map(|(w, h)| (Some(w), Some(h)))
+                .unwrap_or((None, None))
+        } else {
+            (None, None)
+        };
 
-        // Generate attachment ID
-        let id = uuid::Uuid::new_v4().to_string();
+        tracing::info!(
+            "Uploading attachment: {:?} ({}, {} bytes)",
+            filename,
+            content_type,
+            data.len()
+        );
 
-        // TODO: Implement actual upload
-        // 1. Generate encryption key
-        // 2. Encrypt data
-        // 3. Calculate digest
-        // 4. Get upload URL
-        // 5. Upload encrypted data
-        // 6. Return metadata with CDN info
+        let spec = AttachmentSpec {
+            content_type,
+            length: data.len(),
+            file_name: filename,
+            preview: None,
+            voice_note: Some(voice_note),
+            borderless: Some(false),
+            width,
+            height,
+            caption,
+            blur_hash: None,
+        };
+
+        Self::upload_data(manager, spec, data).await
+    }
+
+    /// Upload a small JPEG thumbnail of a local image, as quoted replies carry
+    pub async fn upload_thumbnail(
+        &self,
+        manager: &Manager<SqliteStore, Registered>,
+        image_path: &Path,
+    ) -> Result<AttachmentPointer, SignalError> {
+        let data = fs::read(image_path)
+            .await
+            .map_err(|e| SignalError::AttachmentError(e.to_string()))?;
+        let (thumbnail, width, height) = image_utils::thumbnail_jpeg(&data, QUOTE_THUMBNAIL_SIZE)
+            .ok_or_else(|| SignalError::AttachmentError("Cannot decode image for thumbnail".to_string()))?;
+
+        let spec = AttachmentSpec {
+            content_type: "image/jpeg".to_string(),
+            length: thumbnail.len(),
+            file_name: None,
+            preview: None,
+            voice_note: Some(false),
+            borderless: Some(false),
+            width: Some(width),
+            height: Some(height),
+            caption: None,
+            blur_hash: None,
+        };
+
+        Self::upload_data(manager, spec, thumbnail).await
+    }
 
-        tracing::info!("Uploading attachment: {} ({} bytes)", id, size);
+    /// Upload a cached sticker image, to be sent along with its pack ID and key
+    pub async fn upload_sticker(
+        &self,
+        manager: &Manager<SqliteStore, Registered>,
+        sticker_path: &Path,
+        content_type: Option<String>,
+    ) -> Result<AttachmentPointer, SignalError> {
+        let data = fs::read(sticker_path)
+            .await
+            .map_err(|e| SignalError::AttachmentError(e.to_string()))?;
 
-        Ok(AttachmentMetadata {
-            id,
+        // Cached stickers have no extension, so sniff the format instead
+        let content_type = content_type.unwrap_or_else(|| {
+            image::guess_format(&data)
+                .map(|format| format.to_mime_type().to_string())
+                .unwrap_or_else(|_| "image/webp".to_string())
+        });
+        let (width, height) = image_utils::get_dimensions(&data)
+            .map(|(w, h)| (Some(w), Some(h)))
+            .unwrap_or((None, None));
+
+        let spec = AttachmentSpec {
             content_type,
-            filename,
-            size,
-            width: None,
-            height: None,
-            duration_ms: None,
-            blurhash: None,
-            waveform: None,
-            cdn_number: None,
-            cdn_key: None,
-            key: None,
-            digest: None,
-        })
+            length: data.len(),
+            file_name: None,
+            preview: None,
+            voice_note: Some(false),
+            borderless: Some(true),
+            width,
+            height,
+            caption: None,
+            blur_hash: None,
+        };
+
+        Self::upload_data(manager, spec, data).await
+    }
+
+    async fn upload_data(
+        manager: &Manager<SqliteStore, Registered>,
+        spec: AttachmentSpec,
+        data: Vec<u8>,
+    ) -> Result<AttachmentPointer, SignalError> {
+        // presage pads, encrypts and computes the digest before uploading
+        let mut results = manager
+            .upload_attachments(vec![(spec, data)])
+            .await
+            .map_err(|e| SignalError::AttachmentError(format!("Upload failed: {:?}", e)))?;
+
+        let pointer = results
+            .pop()
+            .ok_or_else(|| SignalError::AttachmentError("Upload returned no pointer".to_string()))?
+            .map_err(|e| SignalError::AttachmentError(format!("Upload failed: {:?}", e)))?;
+
+        tracing::info!("Uploaded attachment to CDN {}", pointer.cdn_number());
+
+        Ok(pointer)
     }
 
     /// Delete a local attachment
@@ -159,6 +446,21 @@ impl AttachmentManager {
         Ok(())
     }
 
+    /// Remove local attachments and their thumbnails, skipping ones that were
+    /// never downloaded. Blocking, for use outside the runtime.
+    pub fn remove_local(&self, ids: &[&str]) {
+        for id in ids {
+            let thumb_path = self.attachments_dir.join("thumbnails").join(id);
+            for path in [self.attachment_path(id), thumb_path] {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        tracing::warn!("Failed to remove {}: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+    }
+
     /// Get thumbnail for an image/video attachment
     pub async fn get_thumbnail(&self, id: &str) -> Option<PathBuf> {
         let thumb_path = self.attachments_dir.join("thumbnails").join(id);
@@ -237,25 +539,214 @@ impl AttachmentManager {
     }
 }
 
-/// Voice note recording utilities
+/// Voice note decoding: duration, waveform and samples for playback
+///
+/// WAV (what we record) and AAC in MP4 (what the phones send) are decoded by
+/// symphonia. Desktop clients send Opus in Ogg, which symphonia can demux but
+/// not decode, so those packets go through libopus.
 pub mod voice {
-    use super::*;
+    use audiopus::coder::Decoder as OpusDecoder;
+    use audiopus::{Channels, SampleRate};
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::{CodecParameters, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+    use symphonia::core::errors::Error as DecodeError;
+    use symphonia::core::formats::{FormatOptions, FormatReader};
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    /// Bars in a voice note waveform
+    pub const WAVEFORM_POINTS: usize = 64;
+    const OPUS_SAMPLE_RATE: u32 = 48_000;
+    /// Longest Opus frame, 120 ms at 48 kHz
+    const MAX_OPUS_FRAME: usize = 5_760;
+
+    /// Decoded audio, mixed down to mono
+    pub struct DecodedAudio {
+        pub samples: Vec<f32>,
+        pub sample_rate: u32,
+    }
+
+    impl DecodedAudio {
+        pub fn duration_ms(&self) -> u64 {
+            self.samples.len() as u64 * 1000 / self.sample_rate.max(1) as u64
+        }
+    }
+
+    /// Decode a WAV, AAC or Opus voice note
+    pub fn decode(audio_data: &[u8]) -> Option<DecodedAudio> {
+        let source = MediaSourceStream::new(Box::new(std::io::Cursor::new(audio_data.to_vec())), Default::default());
+        let probed = symphonia::default::get_probe()
+            .format(&Hint::new(), source, &FormatOptions::default(), &MetadataOptions::default())
+            .ok()?;
+        let mut format = probed.format;
+        let track = format.tracks().iter().find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
+        let track_id = track.id;
+        let params = track.codec_params.clone();
+
+        if params.codec == CODEC_TYPE_OPUS {
+            return decode_opus(format.as_mut(), track_id, &params);
+        }
+
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&params, &DecoderOptions::default())
+            .ok()?;
+        let mut sample_rate = params.sample_rate;
+        let mut samples = Vec::new();
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                // End of stream, or a stream that's cut off
+                Err(_) => break,
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+            match decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    sample_rate.get_or_insert(spec.rate);
+                    let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                    buffer.copy_interleaved_ref(decoded);
+                    push_mono(&mut samples, buffer.samples(), spec.channels.count());
+                }
+                // A corrupt packet; the rest may still be fine
+                Err(DecodeError::DecodeError(e)) => tracing::debug!("Skipping undecodable audio packet: {}", e),
+                Err(_) => break,
+            }
+        }
+
+        Some(DecodedAudio {
+            samples,
+            sample_rate: sample_rate?,
+        })
+    }
+
+    fn decode_opus(format: &mut dyn FormatReader, track_id: u32, params: &CodecParameters) -> Option<DecodedAudio> {
+        let channels = params.channels.map_or(1, |c| c.count()).clamp(1, 2);
+        let mut decoder = OpusDecoder::new(
+            SampleRate::Hz48000,
+            if channels == 2 { Channels::Stereo } else { Channels::Mono },
+        )
+        .ok()?;
+
+        let mut pcm = vec![0f32; MAX_OPUS_FRAME * channels];
+        let mut samples = Vec::new();
+        while let Ok(packet) = format.next_packet() {
+            if packet.track_id() != track_id {
+                continue;
+            }
+            match decoder.decode_float(Some(packet.buf()), &mut pcm[..], false) {
+                Ok(frames) => push_mono(&mut samples, &pcm[..frames * channels], channels),
+                Err(e) => tracing::debug!("Skipping undecodable Opus packet: {}", e),
+            }
+        }
+
+        // The encoder's lookahead, which isn't part of the recording
+        let pre_skip = (params.delay.unwrap_or(0) as usize).min(samples.len());
+        samples.drain(..pre_skip);
+        Some(DecodedAudio {
+            samples,
+            sample_rate: OPUS_SAMPLE_RATE,
+        })
+    }
+
+    fn push_mono(samples: &mut Vec<f32>, interleaved: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        samples.extend(
+            interleaved
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32),
+        );
+    }
+
+    /// Peak amplitude of each of `points` equal slices of the samples,
+    /// scaled so the loudest slice is 255
+    pub fn waveform(samples: &[f32], points: usize) -> Vec<u8> {
+        if samples.is_empty() || points == 0 {
+            return Vec::new();
+        }
+
+        let peaks: Vec<f32> = (0..points)
+            .map(|i| {
+                let start = i * samples.len() / points;
+                let end = ((i + 1) * samples.len() / points).max(start + 1).min(samples.len());
+                samples[start..end].iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+            })
+            .collect();
+        let loudest = peaks.iter().cloned().fold(0.0f32, f32::max);
+        if loudest <= f32::EPSILON {
+            return vec![0; points];
+        }
+        peaks.iter().map(|peak| (peak / loudest * 255.0).round() as u8).collect()
+    }
+
+    /// Duration (ms) and waveform of a voice note
+    pub fn analyze(audio_data: &[u8]) -> Option<(u64, Vec<u8>)> {
+        let audio = decode(audio_data)?;
+        Some((audio.duration_ms(), waveform(&audio.samples, WAVEFORM_POINTS)))
+    }
 
     /// Generate waveform data from audio
     pub fn generate_waveform(audio_data: &[u8]) -> Vec<u8> {
-        // TODO: Implement waveform generation
-        // 1. Decode audio
-        // 2. Sample amplitude at regular intervals
-        // 3. Normalize to 0-255 range
-
-        Vec::new()
+        decode(audio_data)
+            .map(|audio| waveform(&audio.samples, WAVEFORM_POINTS))
+            .unwrap_or_default()
     }
 
     /// Get duration of audio file in milliseconds
     pub fn get_duration_ms(audio_data: &[u8]) -> Option<u64> {
-        // TODO: Implement duration detection
+        decode(audio_data).map(|audio| audio.duration_ms())
+    }
 
-        None
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn wav(samples: &[i16], sample_rate: u32, channels: u16) -> Vec<u8> {
+            let spec = hound::WavSpec {
+                channels,
+                sample_rate,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let mut data = std::io::Cursor::new(Vec::new());
+            let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+            for &sample in samples {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize().unwrap();
+            data.into_inner()
+        }
+
+        #[test]
+        fn test_wav_duration_and_waveform() {
+            // 1.5 s of stereo: silent first half, loud second half
+            let mut samples = vec![0i16; 8_000 * 2 * 3 / 4];
+            samples.extend(std::iter::repeat(i16::MAX / 2).take(8_000 * 2 * 3 / 4));
+            let data = wav(&samples, 8_000, 2);
+
+            assert_eq!(get_duration_ms(&data), Some(1_500));
+            let waveform = generate_waveform(&data);
+            assert_eq!(waveform.len(), WAVEFORM_POINTS);
+            assert_eq!(waveform[0], 0);
+            assert_eq!(waveform[WAVEFORM_POINTS - 1], 255);
+        }
+
+        #[test]
+        fn test_waveform_scales_to_loudest_slice() {
+            assert!(waveform(&[], 10).is_empty());
+            assert_eq!(waveform(&[0.0; 4], 2), vec![0, 0]);
+            assert_eq!(waveform(&[0.1, -0.2, 0.4, 0.0], 2), vec![128, 255]);
+            // Fewer samples than points still gives every point a value
+            assert_eq!(waveform(&[0.5, 1.0], 4).len(), 4);
+        }
+
+        #[test]
+        fn test_garbage_is_not_audio() {
+            assert!(decode(b"definitely not audio").is_none());
+            assert!(generate_waveform(b"").is_empty());
+        }
     }
 }
 
@@ -265,9 +756,24 @@ pub mod image_utils {
 
     /// Get image dimensions
     pub fn get_dimensions(image_data: &[u8]) -> Option<(u32, u32)> {
-        // TODO: Implement dimension detection
+        image::ImageReader::new(std::io::Cursor::new(image_data))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()
+    }
 
-        None
+    /// Downscale an image to fit in `max_size` and encode it as JPEG.
+    ///
+    /// Returns the JPEG data with its width and height.
+    pub fn thumbnail_jpeg(image_data: &[u8], max_size: u32) -> Option<(Vec<u8>, u32, u32)> {
+        let image = image::load_from_memory(image_data).ok()?.thumbnail(max_size, max_size);
+        let mut jpeg = Vec::new();
+        image
+            .to_rgb8()
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .ok()?;
+        Some((jpeg, image.width(), image.height()))
     }
 
     /// Check if image needs rotation based on EXIF
@@ -284,3 +790,62 @@ pub mod image_utils {
         Err(SignalError::AttachmentError("HEIC conversion not implemented".to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::{BlockEncryptMut, KeyIvInit};
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    fn encrypt_attachment(plaintext: &[u8], key: &[u8], iv: &[u8; 16]) -> Vec<u8> {
+        let mut buffer = plaintext.to_vec();
+        buffer.resize(plaintext.len() + 16, 0);
+        let ciphertext = cbc::Encryptor::<aes::Aes256>::new_from_slices(&key[..32], iv)
+            .unwrap()
+            .encrypt_padded_mut::<aes::cipher::block_padding::Pkcs7>(&mut buffer, plaintext.len())
+            .unwrap()
+            .to_vec();
+
+        let mut hmac = Hmac::<Sha256>::new_from_slice(&key[32..]).unwrap();
+        hmac.update(iv);
+        hmac.update(&ciphertext);
+
+        let mut blob = iv.to_vec();
+        blob.extend_from_slice(&ciphertext);
+        blob.extend_from_slice(&hmac.finalize().into_bytes());
+        blob
+    }
+
+    #[test]
+    fn test_decrypt_attachment_roundtrip() {
+        let key = [42u8; 64];
+        // Trailing zeros stand in for Signal's padding
+        let mut padded = b"hello attachment".to_vec();
+        padded.extend_from_slice(&[0u8; 10]);
+        let blob = encrypt_attachment(&padded, &key, &[9u8; 16]);
+        let digest = Sha256::digest(&blob);
+
+        let plaintext = decrypt_attachment(&blob, &key, Some(digest.as_slice()), Some(16)).unwrap();
+        assert_eq!(plaintext, b"hello attachment");
+    }
+
+    #[test]
+    fn test_decrypt_attachment_rejects_bad_digest() {
+        let key = [42u8; 64];
+        let blob = encrypt_attachment(b"data", &key, &[1u8; 16]);
+
+        let result = decrypt_attachment(&blob, &key, Some(&[0u8; 32]), None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_decrypt_attachment_rejects_tampered_mac() {
+        let key = [42u8; 64];
+        let mut blob = encrypt_attachment(b"data", &key, &[1u8; 16]);
+        let last = blob.len() - 1;
+        blob[last] ^= 0xff;
+
+        assert!(decrypt_attachment(&blob, &key, None, None).is_err());
+    }
+}
@@ -21,7 +21,7 @@ pub enum TransferArchiveResponse {
     Error { error: String },
 }
 
-fn build_signal_client() -> Result<reqwest::Client, SignalError> {
+pub(crate) fn build_signal_client() -> Result<reqwest::Client, SignalError> {
     let signal_ca = Certificate::from_pem(SIGNAL_CA_CERT)
         .map_err(|e| SignalError::NetworkError(format!("Invalid Signal CA certificate: {}", e)))?;
     
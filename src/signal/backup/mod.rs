@@ -1,4 +1,4 @@
-mod api;
+pub(crate) mod api;
 mod crypto;
 
 pub use api::{TransferArchiveInfo, fetch_transfer_archive, download_backup};
@@ -185,6 +185,7 @@ fn convert_backup_conversation(backup: &BackupConversation) -> Conversation {
         muted_until: None,
         is_archived: false,
         is_blocked: false,
+        is_accepted: true,
         disappearing_messages_timer: 0,
         draft: None,
         created_at: now,
@@ -245,6 +246,8 @@ fn convert_backup_message(
         reactions: Vec::new(),
         expires_in_seconds: None,
         expires_at: None,
+        signal_timestamp: Some(sent_at.timestamp_millis() as u64),
+        edited_at: None,
     }
 }
 
@@ -0,0 +1,61 @@
+//! Connection supervision for the receive loop
+//!
+//! The websocket can die without closing, e.g. after a network change or
+//! suspend, so an idle connection is probed with a keepalive request and
+//! dropped when that goes unanswered. A dropped connection is reconnected
+//! with exponential backoff; the delay is jittered so that many clients cut
+//! off at once don't all come back at the same moment.
+
+use crate::signal::SignalError;
+use std::time::Duration;
+
+/// Idle time after which the connection is probed
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a keepalive may take before the connection counts as dead
+pub const KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(15);
+/// A connection that lasted this long starts the backoff over
+pub const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+const FIRST_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Delay before reconnect `attempt` (1-based). Half of the backoff is fixed
+/// and the other half scaled by `jitter`, a random number in `0.0..1.0`.
+pub fn reconnect_delay(attempt: u32, jitter: f64) -> Duration {
+    let backoff = FIRST_RECONNECT_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_RECONNECT_DELAY);
+    backoff / 2 + (backoff / 2).mul_f64(jitter.clamp(0.0, 1.0))
+}
+
+/// Whether connecting again may help. Anything else, like a store that
+/// doesn't open or an unregistered account, needs the user.
+pub fn is_retryable(error: &SignalError) -> bool {
+    matches!(error, SignalError::ConnectionFailed(_) | SignalError::NetworkError(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_delay_backs_off_with_jitter() {
+        assert_eq!(reconnect_delay(1, 0.0), Duration::from_millis(500));
+        assert_eq!(reconnect_delay(1, 1.0), FIRST_RECONNECT_DELAY);
+        assert_eq!(reconnect_delay(3, 0.5), Duration::from_secs(3));
+        for attempt in 1..40 {
+            let delay = reconnect_delay(attempt, 0.99);
+            assert!(delay <= MAX_RECONNECT_DELAY);
+            assert!(delay >= reconnect_delay(attempt, 0.0));
+        }
+        assert_eq!(reconnect_delay(40, 0.0), MAX_RECONNECT_DELAY / 2);
+    }
+
+    #[test]
+    fn test_only_network_errors_are_retried() {
+        assert!(is_retryable(&SignalError::ConnectionFailed("closed".to_string())));
+        assert!(is_retryable(&SignalError::NetworkError("timeout".to_string())));
+        assert!(!is_retryable(&SignalError::NotRegistered));
+        assert!(!is_retryable(&SignalError::StorageError("locked".to_string())));
+    }
+}
@@ -1,6 +1,23 @@
 //! Group management
-
-use chrono::{DateTime, Utc};
+//!
+//! Group state is kept by presage, which fetches and decrypts it from the
+//! master key whenever a message shows a newer revision. It is copied into
+//! `app.db` (see `storage::groups`) so the UI can show members and roles.
+//!
+//! The actions in an incoming group change aren't applied one by one: the
+//! refetched state is diffed against the stored copy instead (`diff_group`),
+//! so missed revisions are covered too. Presage has no API for submitting
+//! group changes, so groups can't be changed from this client yet.
+
+use crate::signal::messages::{format_expiration_timer, Content, GroupUpdateType, Message, MessageDirection, MessageStatus};
+use crate::storage::contacts::ContactRepository;
+use crate::storage::conversations::ConversationRepository;
+use crate::storage::database::Database;
+use crate::storage::groups::GroupRepository;
+use crate::storage::messages::MessageRepository;
+use chrono::{DateTime, TimeZone, Utc};
+use presage::libsignal_service::proto::access_control::AccessRequired;
+use presage::libsignal_service::proto::member::Role;
 use serde::{Deserialize, Serialize};
 
 /// Group access control
@@ -20,6 +37,32 @@ impl Default for AccessControl {
     }
 }
 
+impl AccessControl {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Any => "any",
+            Self::Administrator => "administrator",
+            Self::Unsatisfiable => "unsatisfiable",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Self {
+        match s {
+            "administrator" => Self::Administrator,
+            "unsatisfiable" => Self::Unsatisfiable,
+            _ => Self::Any,
+        }
+    }
+
+    fn from_required(required: AccessRequired) -> Self {
+        match required {
+            AccessRequired::Administrator => Self::Administrator,
+            AccessRequired::Unsatisfiable => Self::Unsatisfiable,
+            _ => Self::Any,
+        }
+    }
+}
+
 /// Group member role
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum MemberRole {
@@ -33,6 +76,22 @@ impl Default for MemberRole {
     }
 }
 
+impl MemberRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Administrator => "administrator",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Self {
+        match s {
+            "administrator" => Self::Administrator,
+            _ => Self::Default,
+        }
+    }
+}
+
 /// A group member
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GroupMember {
@@ -62,7 +121,7 @@ pub struct PendingMember {
 /// A Signal group
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Group {
-    /// Group ID (V2 groups use a base64-encoded master key derivative)
+    /// Group ID, the base64 master key (same as the conversation ID)
     pub id: String,
 
     /// Group master key (for V2 groups)
@@ -203,52 +262,438 @@ impl Group {
             self.revision += 1;
         }
     }
+
+    /// Whether `uuid` is invited but hasn't joined yet
+    pub fn is_pending(&self, uuid: &str) -> bool {
+        self.pending_members.iter().any(|m| m.uuid == uuid)
+    }
+
+    /// Build the group from the state presage fetched for `master_key`.
+    /// `existing` is the stored copy, whose local-only fields (join times,
+    /// avatar, block flag) are kept. `self_aci` decides whether we left.
+    pub fn from_presage(
+        master_key: &[u8],
+        state: &presage::model::groups::Group,
+        existing: Option<&Group>,
+        self_aci: &str,
+    ) -> Self {
+        let id = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, master_key);
+        let now = Utc::now();
+        let joined_at = |uuid: &str| {
+            existing
+                .and_then(|g| g.members.iter().find(|m| m.uuid == uuid))
+                .map(|m| m.joined_at)
+                .unwrap_or(now)
+        };
+
+        let members: Vec<GroupMember> = state
+            .members
+            .iter()
+            .map(|m| {
+                let uuid = m.aci.service_id_string();
+                GroupMember {
+                    joined_at: joined_at(&uuid),
+                    role: if m.role == Role::Administrator {
+                        MemberRole::Administrator
+                    } else {
+                        MemberRole::Default
+                    },
+                    uuid,
+                }
+            })
+            .collect();
+
+        let pending_members = state
+            .pending_members
+            .iter()
+            .map(|m| PendingMember {
+                uuid: m.address.service_id_string(),
+                added_by: m.added_by_aci.service_id_string(),
+                invited_at: DateTime::from_timestamp_millis(m.timestamp as i64).unwrap_or(now),
+            })
+            .collect::<Vec<_>>();
+
+        let (attributes_access, members_access, link_enabled) = match &state.access_control {
+            Some(access) => (
+                AccessControl::from_required(access.attributes),
+                AccessControl::from_required(access.members),
+                !matches!(
+                    access.add_from_invite_link,
+                    AccessRequired::Unknown | AccessRequired::Unsatisfiable
+                ),
+            ),
+            None => (AccessControl::Any, AccessControl::Any, false),
+        };
+
+        let left = !members.iter().any(|m| m.uuid == self_aci)
+            && !pending_members.iter().any(|m| m.uuid == self_aci);
+
+        Self {
+            id,
+            master_key: Some(master_key.to_vec()),
+            name: state.title.clone(),
+            description: state.description.clone().filter(|d| !d.is_empty()),
+            avatar_path: existing.and_then(|g| g.avatar_path.clone()),
+            members,
+            pending_members,
+            attributes_access,
+            members_access,
+            link_enabled,
+            invite_link: existing.and_then(|g| g.invite_link.clone()),
+            disappearing_messages_timer: state
+                .disappearing_messages_timer
+                .as_ref()
+                .map(|t| t.duration)
+                .unwrap_or(0),
+            blocked: existing.is_some_and(|g| g.blocked),
+            left,
+            revision: state.revision,
+            created_at: existing.map(|g| g.created_at).unwrap_or(now),
+            updated_at: now,
+        }
+    }
 }
 
-/// Group repository for storage operations
-pub struct GroupRepository {
-    // TODO: Add storage backend
+/// Store the group state presage has for `master_key` and name the group's
+/// conversation after it. Returns the stored group and the copy it replaced.
+pub fn store_group_state(
+    db: &Database,
+    master_key: &[u8],
+    state: &presage::model::groups::Group,
+    self_aci: &str,
+) -> anyhow::Result<(Group, Option<Group>)> {
+    let repo = GroupRepository::new(db);
+    let id = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, master_key);
+    let existing = repo.get(&id);
+    let mut group = Group::from_presage(master_key, state, existing.as_ref(), self_aci);
+
+    // The disappearing timer is left to the message path, which turns a
+    // change into a notice
+    let conv_repo = ConversationRepository::new(db);
+    let conv = conv_repo.get(&id);
+    if existing.is_none() {
+        // The block may have arrived before the group state did
+        group.blocked = conv.as_ref().is_some_and(|c| c.is_blocked);
+    }
+    repo.save(&group)?;
+
+    if let Some(mut conv) = conv {
+        if !group.name.is_empty() && conv.name != group.name {
+            conv.name = group.name.clone();
+            conv_repo.save(&conv)?;
+        }
+    }
+
+    tracing::debug!("Stored group {} at revision {}", group.name, group.revision);
+    Ok((group, existing))
 }
 
-impl GroupRepository {
-    /// Create a new group repository
-    pub fn new() -> Self {
-        Self {}
+/// One change between two revisions of a group
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupUpdate {
+    Created,
+    NameChanged(String),
+    DescriptionChanged,
+    MembersAdded(Vec<String>),
+    MembersInvited(Vec<String>),
+    MembersRemoved(Vec<String>),
+    /// Joined by link or by accepting an invitation
+    MemberJoined(String),
+    MemberLeft(String),
+    AdminChanged { member: String, admin: bool },
+    DisappearingMessagesChanged(u32),
+}
+
+impl GroupUpdate {
+    pub fn update_type(&self) -> GroupUpdateType {
+        match self {
+            Self::Created => GroupUpdateType::Created,
+            Self::NameChanged(_) => GroupUpdateType::NameChanged,
+            Self::DescriptionChanged => GroupUpdateType::DescriptionChanged,
+            Self::MembersAdded(_) | Self::MembersInvited(_) => GroupUpdateType::MembersAdded,
+            Self::MembersRemoved(_) => GroupUpdateType::MembersRemoved,
+            Self::MemberJoined(_) => GroupUpdateType::MemberJoined,
+            Self::MemberLeft(_) => GroupUpdateType::MemberLeft,
+            Self::AdminChanged { .. } => GroupUpdateType::AdminsChanged,
+            Self::DisappearingMessagesChanged(_) => GroupUpdateType::DisappearingMessagesChanged,
+        }
     }
 
-    /// Get a group by ID
-    pub async fn get(&self, id: &str) -> Option<Group> {
-        // TODO: Implement storage lookup
-        None
+    /// Human-readable text, e.g. "Alice added Bob". `name` resolves a UUID
+    /// to a display name ("You" for ourselves); without an editor the text
+    /// is passive.
+    pub fn describe(&self, editor: Option<&str>, name: &dyn Fn(&str) -> String) -> String {
+        let editor = editor.map(name);
+        let names = |uuids: &[String]| join_names(&uuids.iter().map(|u| name(u)).collect::<Vec<_>>());
+        let was = |uuids: &[String]| if uuids.len() == 1 && name(&uuids[0]) != "You" { "was" } else { "were" };
+
+        match (self, editor) {
+            (Self::Created, Some(e)) => format!("{} created the group", e),
+            (Self::Created, None) => "The group was created".to_string(),
+            (Self::NameChanged(title), Some(e)) => format!("{} changed the group name to \u{201c}{}\u{201d}", e, title),
+            (Self::NameChanged(title), None) => format!("The group name changed to \u{201c}{}\u{201d}", title),
+            (Self::DescriptionChanged, Some(e)) => format!("{} changed the group description", e),
+            (Self::DescriptionChanged, None) => "The group description changed".to_string(),
+            (Self::MembersAdded(m), Some(e)) => format!("{} added {}", e, names(m)),
+            (Self::MembersAdded(m), None) => format!("{} {} added to the group", names(m), was(m)),
+            (Self::MembersInvited(m), Some(e)) => format!("{} invited {}", e, names(m)),
+            (Self::MembersInvited(m), None) => format!("{} {} invited to the group", names(m), was(m)),
+            (Self::MembersRemoved(m), Some(e)) => format!("{} removed {}", e, names(m)),
+            (Self::MembersRemoved(m), None) => format!("{} {} removed from the group", names(m), was(m)),
+            (Self::MemberJoined(m), _) => format!("{} joined the group", name(m)),
+            (Self::MemberLeft(m), _) => format!("{} left the group", name(m)),
+            (Self::AdminChanged { member, admin: true }, Some(e)) => format!("{} made {} an admin", e, name(member)),
+            (Self::AdminChanged { member, admin: false }, Some(e)) => {
+                format!("{} revoked admin privileges from {}", e, name(member))
+            }
+            (Self::AdminChanged { member, admin }, None) => {
+                let member = name(member);
+                let is = if member == "You" { "are" } else { "is" };
+                if *admin {
+                    format!("{} {} now an admin", member, is)
+                } else {
+                    format!("{} {} no longer an admin", member, is)
+                }
+            }
+            (Self::DisappearingMessagesChanged(0), Some(e)) => format!("{} turned off disappearing messages", e),
+            (Self::DisappearingMessagesChanged(0), None) => "Disappearing messages were turned off".to_string(),
+            (Self::DisappearingMessagesChanged(secs), Some(e)) => {
+                format!("{} set the disappearing message time to {}", e, format_expiration_timer(*secs))
+            }
+            (Self::DisappearingMessagesChanged(secs), None) => {
+                format!("Disappearing message time set to {}", format_expiration_timer(*secs))
+            }
+        }
     }
+}
 
-    /// Save a group
-    pub async fn save(&self, group: &Group) -> anyhow::Result<()> {
-        // TODO: Implement storage save
-        Ok(())
+/// "Alice", "Alice and Bob", "Alice, Bob and Carol"
+fn join_names(names: &[String]) -> String {
+    match names {
+        [] => String::new(),
+        [one] => one.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
     }
+}
 
-    /// Get all groups
-    pub async fn list(&self) -> Vec<Group> {
-        // TODO: Implement storage list
-        Vec::new()
+/// What changed from `old` to `new`, in the order Signal shows it. `editor`
+/// is whoever made the change, if known; members that add or remove
+/// themselves joined or left. A group seen for the first time only yields
+/// its creation, or nothing if we were added later.
+pub fn diff_group(old: Option<&Group>, new: &Group, editor: Option<&str>) -> Vec<GroupUpdate> {
+    let Some(old) = old else {
+        return if new.revision == 0 { vec![GroupUpdate::Created] } else { Vec::new() };
+    };
+
+    let mut updates = Vec::new();
+    if old.name != new.name && !new.name.is_empty() {
+        updates.push(GroupUpdate::NameChanged(new.name.clone()));
+    }
+    if old.description != new.description {
+        updates.push(GroupUpdate::DescriptionChanged);
     }
 
-    /// Get active groups (not left, not blocked)
-    pub async fn list_active(&self) -> Vec<Group> {
-        // TODO: Implement storage list with filter
-        Vec::new()
+    let mut added = Vec::new();
+    for member in new.members.iter().filter(|m| !old.is_member(&m.uuid)) {
+        if old.is_pending(&member.uuid) || editor == Some(member.uuid.as_str()) {
+            updates.push(GroupUpdate::MemberJoined(member.uuid.clone()));
+        } else {
+            added.push(member.uuid.clone());
+        }
+    }
+    if !added.is_empty() {
+        updates.push(GroupUpdate::MembersAdded(added));
     }
 
-    /// Delete a group
-    pub async fn delete(&self, id: &str) -> anyhow::Result<()> {
-        // TODO: Implement delete
-        Ok(())
+    let invited: Vec<String> = new
+        .pending_members
+        .iter()
+        .filter(|m| !old.is_pending(&m.uuid) && !old.is_member(&m.uuid))
+        .map(|m| m.uuid.clone())
+        .collect();
+    if !invited.is_empty() {
+        updates.push(GroupUpdate::MembersInvited(invited));
+    }
+
+    let mut removed = Vec::new();
+    for member in old.members.iter().filter(|m| !new.is_member(&m.uuid)) {
+        if editor == Some(member.uuid.as_str()) {
+            updates.push(GroupUpdate::MemberLeft(member.uuid.clone()));
+        } else {
+            removed.push(member.uuid.clone());
+        }
+    }
+    if !removed.is_empty() {
+        updates.push(GroupUpdate::MembersRemoved(removed));
+    }
+
+    for member in &new.members {
+        let was_admin = old.is_admin(&member.uuid);
+        let is_admin = member.role == MemberRole::Administrator;
+        if old.is_member(&member.uuid) && was_admin != is_admin {
+            updates.push(GroupUpdate::AdminChanged {
+                member: member.uuid.clone(),
+                admin: is_admin,
+            });
+        }
     }
+
+    if old.disappearing_messages_timer != new.disappearing_messages_timer {
+        updates.push(GroupUpdate::DisappearingMessagesChanged(new.disappearing_messages_timer));
+    }
+    updates
 }
 
-impl Default for GroupRepository {
-    fn default() -> Self {
-        Self::new()
+/// Add group updates to the group's timeline as system rows, sent at
+/// `timestamp` (ms). A timer change also sets the conversation's timer.
+/// Returns the number of rows added.
+pub fn record_group_updates(
+    db: &Database,
+    group: &Group,
+    updates: &[GroupUpdate],
+    editor: Option<&str>,
+    self_aci: &str,
+    timestamp: u64,
+) -> anyhow::Result<usize> {
+    let conv_repo = ConversationRepository::new(db);
+    let Some(mut conv) = conv_repo.get(&group.id) else {
+        return Ok(0);
+    };
+    if updates.is_empty() {
+        return Ok(0);
+    }
+
+    let contacts = ContactRepository::new(db);
+    let name = |uuid: &str| {
+        if uuid == self_aci {
+            "You".to_string()
+        } else {
+            contacts
+                .get_by_uuid(uuid)
+                .map(|c| c.display_name().to_string())
+                .unwrap_or_else(|| "Unknown".to_string())
+        }
+    };
+
+    let now = Utc::now();
+    let sent_at = Utc.timestamp_millis_opt(timestamp as i64).single().unwrap_or(now);
+    let ours = editor == Some(self_aci);
+    let message_repo = MessageRepository::new(db);
+
+    let mut details = String::new();
+    for update in updates {
+        details = update.describe(editor, &name);
+        let message = Message {
+            id: uuid::Uuid::new_v4().to_string(),
+            conversation_id: group.id.clone(),
+            sender: if ours { "self".to_string() } else { editor.unwrap_or_default().to_string() },
+            direction: if ours { MessageDirection::Outgoing } else { MessageDirection::Incoming },
+            status: if ours { MessageStatus::Sent } else { MessageStatus::Read },
+            content: Content::GroupUpdate {
+                update_type: update.update_type(),
+                details: details.clone(),
+            },
+            sent_at,
+            server_timestamp: None,
+            delivered_at: None,
+            // System rows never count as unread
+            read_at: Some(now),
+            quote: None,
+            reactions: Vec::new(),
+            expires_in_seconds: None,
+            expires_at: None,
+            // Not addressable, so they never match a quote or an edit
+            signal_timestamp: None,
+            edited_at: None,
+        };
+        message_repo.save(&message)?;
+
+        if let GroupUpdate::DisappearingMessagesChanged(seconds) = update {
+            conv.disappearing_messages_timer = *seconds;
+        }
+    }
+
+    conv.update_last_message(&details, sent_at);
+    conv_repo.save(&conv)?;
+    Ok(updates.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group() -> Group {
+        let mut group = Group::new("group", "Climbing");
+        group.add_member("admin", MemberRole::Administrator);
+        group.add_member("alice", MemberRole::Default);
+        group
+    }
+
+    #[test]
+    fn test_diff_group() {
+        let old = group();
+        let mut new = old.clone();
+        new.name = "Bouldering".to_string();
+        new.remove_member("alice");
+        new.add_member("bob", MemberRole::Default);
+        new.promote_to_admin("bob");
+        new.disappearing_messages_timer = 3600;
+
+        let updates = diff_group(Some(&old), &new, Some("admin"));
+        assert_eq!(
+            updates,
+            vec![
+                GroupUpdate::NameChanged("Bouldering".to_string()),
+                GroupUpdate::MembersAdded(vec!["bob".to_string()]),
+                GroupUpdate::MembersRemoved(vec!["alice".to_string()]),
+                GroupUpdate::DisappearingMessagesChanged(3600),
+            ]
+        );
+
+        // Removing yourself is leaving
+        let mut left = old.clone();
+        left.remove_member("alice");
+        assert_eq!(
+            diff_group(Some(&old), &left, Some("alice")),
+            vec![GroupUpdate::MemberLeft("alice".to_string())]
+        );
+
+        let mut promoted = old.clone();
+        promoted.promote_to_admin("alice");
+        assert_eq!(
+            diff_group(Some(&old), &promoted, None),
+            vec![GroupUpdate::AdminChanged { member: "alice".to_string(), admin: true }]
+        );
+
+        let mut created = Group::new("g", "New");
+        created.revision = 0;
+        assert_eq!(diff_group(None, &created, None), vec![GroupUpdate::Created]);
+        assert!(diff_group(None, &old, None).is_empty());
+    }
+
+    #[test]
+    fn test_describe_group_updates() {
+        let name = |uuid: &str| match uuid {
+            "me" => "You".to_string(),
+            other => other[..1].to_uppercase() + &other[1..],
+        };
+        let added = GroupUpdate::MembersAdded(vec!["bob".to_string(), "carol".to_string(), "dan".to_string()]);
+        assert_eq!(added.describe(Some("alice"), &name), "Alice added Bob, Carol and Dan");
+        assert_eq!(
+            GroupUpdate::MembersRemoved(vec!["bob".to_string()]).describe(None, &name),
+            "Bob was removed from the group"
+        );
+        assert_eq!(
+            GroupUpdate::AdminChanged { member: "me".to_string(), admin: true }.describe(None, &name),
+            "You are now an admin"
+        );
+        assert_eq!(
+            GroupUpdate::NameChanged("Crag".to_string()).describe(Some("me"), &name),
+            "You changed the group name to \u{201c}Crag\u{201d}"
+        );
+        assert_eq!(
+            GroupUpdate::DisappearingMessagesChanged(0).describe(Some("alice"), &name),
+            "Alice turned off disappearing messages"
+        );
     }
 }
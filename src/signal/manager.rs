@@ -7,45 +7,262 @@ macro_rules! send_event {
     }};
 }
 
+use crate::signal::attachments::{AttachmentManager, AttachmentMetadata};
+use crate::signal::connection::{self, KEEPALIVE_INTERVAL, KEEPALIVE_TIMEOUT};
+use crate::signal::groups;
+use crate::signal::outbox;
 use crate::signal::provisioning;
 use crate::signal::registration;
+use crate::signal::requests::{self, MessageRequestAction};
+use crate::signal::safety::{self, SafetyNumber};
+use crate::signal::stickers;
+use crate::signal::sync::SyncRequestKind;
 use crate::signal::SignalError;
+use crate::signal::messages::{
+    expiration_timer_text, Content as StoredContent, Message, MessageDirection, MessageStatus, Quote,
+    DELETED_MESSAGE_TEXT, DELETE_FOR_EVERYONE_SECS, MAX_EDITS,
+};
 use crate::storage::contacts::{ContactRepository, StoredContact};
+use crate::storage::conversations::{ConversationRepository, ConversationType};
+use crate::storage::database::Database;
+use crate::storage::groups::GroupRepository;
+use crate::storage::identities::IdentityRepository;
+use crate::storage::messages::MessageRepository;
+use crate::storage::outbox::{OutboxEntry, OutboxRepository};
+use crate::storage::settings::SettingsRepository;
+use crate::storage::stickers::StickerRepository;
 use crate::storage::Storage;
-use chrono::Utc;
+use chrono::{DateTime, TimeZone, Utc};
 use futures::channel::oneshot;
 use futures::StreamExt;
 use parking_lot::Mutex;
 use presage::libsignal_service::configuration::SignalServers;
 use presage::libsignal_service::prelude::Content;
-use presage::libsignal_service::protocol::ServiceId;
-use presage::libsignal_service::proto::DataMessage;
+use presage::libsignal_service::protocol::{DeviceId, IdentityKeyStore, ProtocolAddress, ServiceId};
+use presage::libsignal_service::content::ContentBody;
+use presage::libsignal_service::proto::data_message::{self, Delete, Reaction};
+use presage::libsignal_service::proto::{
+    receipt_message, sync_message, typing_message, DataMessage, EditMessage, GroupContextV2, ReceiptMessage,
+    SyncMessage, TypingMessage,
+};
+use presage::libsignal_service::zkgroup::groups::{GroupMasterKey, GroupSecretParams};
 use presage::model::messages::Received;
 use presage::manager::Registered;
-use presage::store::ContentsStore;
+use presage::store::{ContentsStore, Store};
 use presage::Manager;
 use presage_store_sqlite::{OnNewIdentity, SqliteStore};
 use rand::distr::{Alphanumeric, SampleString};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Notify};
 use uuid::Uuid;
 
+/// Destination of an outgoing message
+#[derive(Debug, Clone)]
+pub enum SendTarget {
+    /// A single recipient, addressed by ACI
+    Direct(Uuid),
+    /// A v2 group, addressed by its master key
+    Group(Vec<u8>),
+}
+
+impl SendTarget {
+    /// Resolve a conversation ID (recipient UUID or base64 group master key)
+    pub fn from_conversation_id(conversation_id: &str, is_group: bool) -> Result<Self, SignalError> {
+        if is_group {
+            let master_key = base64::Engine::decode(
+                &base64::engine::general_purpose::STANDARD,
+                conversation_id,
+            ).map_err(|e| SignalError::SendFailed(format!("Invalid group ID: {}", e)))?;
+            Ok(SendTarget::Group(master_key))
+        } else {
+            let recipient = Uuid::parse_str(&Self::normalize(conversation_id))
+                .map_err(|e| SignalError::SendFailed(format!("Invalid recipient UUID: {}", e)))?;
+            Ok(SendTarget::Direct(recipient))
+        }
+    }
+
+    /// Conversation ID this target belongs to
+    pub fn conversation_id(&self) -> String {
+        match self {
+            SendTarget::Direct(recipient) => recipient.to_string(),
+            SendTarget::Group(master_key) => {
+                base64::Engine::encode(&base64::engine::general_purpose::STANDARD, master_key)
+            }
+        }
+    }
+
+    fn normalize(service_id: &str) -> String {
+        let trimmed = service_id.trim_start_matches('<').trim_end_matches('>');
+        SignalManager::normalize_service_id(trimmed)
+    }
+}
+
+/// Public group identifier derived from a v2 group master key, as used in
+/// messages that don't carry the key itself (e.g. typing indicators)
+pub fn group_identifier(master_key: &[u8]) -> Option<[u8; 32]> {
+    let master_key: [u8; 32] = master_key.try_into().ok()?;
+    Some(GroupSecretParams::derive_from_master_key(GroupMasterKey::new(master_key)).get_group_identifier())
+}
+
+/// Quote carried by an outgoing reply
+#[derive(Debug, Clone)]
+pub struct OutgoingQuote {
+    /// ACI of the quoted author; `None` when quoting our own message
+    pub author: Option<Uuid>,
+    /// Sent timestamp of the quoted message
+    pub timestamp: u64,
+    pub text: Option<String>,
+    pub attachment: Option<OutgoingQuotedAttachment>,
+}
+
+/// Attachment of a quoted message, described by type and name
+#[derive(Debug, Clone)]
+pub struct OutgoingQuotedAttachment {
+    pub content_type: String,
+    pub filename: Option<String>,
+    /// Local image to send a thumbnail of
+    pub thumbnail: Option<PathBuf>,
+}
+
+impl OutgoingQuote {
+    /// Wire form of a stored quote. `None` if the quoted message can't be
+    /// addressed because its timestamp or author is unknown.
+    pub fn from_quote(quote: &Quote, attachments_dir: &Path) -> Option<Self> {
+        let author = match quote.author.as_str() {
+            "self" => None,
+            author => Some(Uuid::parse_str(&SignalManager::normalize_service_id(author)).ok()?),
+        };
+
+        let attachment = quote.attachment_preview.as_ref().map(|preview| OutgoingQuotedAttachment {
+            content_type: preview.content_type.clone(),
+            filename: preview.filename.clone(),
+            thumbnail: preview
+                .thumbnail_id
+                .as_ref()
+                .map(|id| attachments_dir.join(id))
+                .filter(|path| path.exists()),
+        });
+
+        Some(Self {
+            author,
+            timestamp: quote.timestamp?,
+            text: quote.text.clone(),
+            attachment,
+        })
+    }
+}
+
+/// An outbox entry rebuilt from its stored message
+struct OutboxSend {
+    target: SendTarget,
+    /// Text of a text message, or the caption of an attachment
+    body: Option<String>,
+    attachment: Option<PathBuf>,
+    /// Sticker, sent with its image from the sticker cache
+    sticker: Option<OutgoingSticker>,
+    timestamp: u64,
+    quote: Option<OutgoingQuote>,
+    expire_timer: Option<u32>,
+}
+
+struct OutgoingSticker {
+    pack_id: String,
+    pack_key: String,
+    sticker_id: u32,
+    emoji: Option<String>,
+    path: PathBuf,
+}
+
 pub enum SendCommand {
-    DirectMessage {
-        recipient: Uuid,
+    Edit {
+        target: SendTarget,
+        /// Sent timestamp of the original message
+        target_timestamp: u64,
         text: String,
+        timestamp: u64,
         reply: oneshot::Sender<Result<(), SignalError>>,
     },
-    GroupMessage {
-        group_key: Vec<u8>,
-        text: String,
+    Delete {
+        target: SendTarget,
+        /// Sent timestamp of the message to delete for everyone
+        target_timestamp: u64,
+        timestamp: u64,
+        reply: oneshot::Sender<Result<(), SignalError>>,
+    },
+    Reaction {
+        target: SendTarget,
+        emoji: String,
+        remove: bool,
+        /// ACI of the author of the message reacted to; `None` for our own messages
+        target_author: Option<Uuid>,
+        target_timestamp: u64,
+        timestamp: u64,
+        reply: oneshot::Sender<Result<(), SignalError>>,
+    },
+    /// Tell senders and our other devices that messages were read
+    ReadReceipts {
+        /// (sender, sent timestamp) of each message read
+        reads: Vec<(Uuid, u64)>,
+        /// Whether senders get read receipts; the sync always goes out
+        send_receipts: bool,
+        timestamp: u64,
+        reply: oneshot::Sender<Result<(), SignalError>>,
+    },
+    Typing {
+        target: SendTarget,
+        started: bool,
+        timestamp: u64,
+        reply: oneshot::Sender<Result<(), SignalError>>,
+    },
+    /// Change the disappearing message timer of a direct conversation
+    ExpirationTimer {
+        target: SendTarget,
+        /// New timer in seconds (0 = off)
+        seconds: u32,
+        timestamp: u64,
+        reply: oneshot::Sender<Result<(), SignalError>>,
+    },
+    /// Ask the primary device to send us its data again
+    SyncRequest {
+        kinds: Vec<SyncRequestKind>,
+        reply: oneshot::Sender<Result<(), SignalError>>,
+    },
+    /// Fetch the profiles (avatars) of these contacts again
+    RefreshProfiles {
+        uuids: Vec<String>,
+        reply: oneshot::Sender<Result<(), SignalError>>,
+    },
+    /// Tell our other devices about a change, e.g. our blocked list
+    Sync {
+        sync: Box<SyncMessage>,
         reply: oneshot::Sender<Result<(), SignalError>>,
     },
 }
 
+/// Why a send to a contact whose verified safety number changed is held
+const SAFETY_NUMBER_CHANGED: &str = "Safety number changed; review it before sending";
+
 static SEND_TX: Mutex<Option<mpsc::UnboundedSender<SendCommand>>> = Mutex::new(None);
 
+/// Our ACI, known once the receive loop has loaded the registration
+static SELF_ACI: Mutex<Option<String>> = Mutex::new(None);
+
+/// Our serialized ACI identity key, loaded with `SELF_ACI`
+static SELF_IDENTITY: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Wakes the send loop when a message was added to the outbox
+static OUTBOX_READY: Notify = Notify::const_new();
+
+/// Cuts the wait before the next reconnect short
+static RECONNECT_NOW: Notify = Notify::const_new();
+
+/// Bumped by every `start_receiving`, so a replaced connection stops
+/// reconnecting
+static CONNECTION_GENERATION: AtomicU64 = AtomicU64::new(0);
+
 /// Events emitted by the Signal manager
 #[derive(Debug, Clone)]
 pub enum SignalEvent {
@@ -69,20 +286,40 @@ pub enum SignalEvent {
     MessageReceived(IncomingMessage),
     /// Message sent successfully
     MessageSent { message_id: String },
-    /// Message delivery receipt
-    DeliveryReceipt { message_id: String, recipient: String },
-    /// Message read receipt
-    ReadReceipt { message_id: String, recipient: String },
-    /// Typing indicator
-    TypingStarted { conversation_id: String },
-    /// Typing stopped
-    TypingStopped { conversation_id: String },
+    /// The attachment of an outgoing message started uploading. Presage
+    /// uploads it in one request, so there is no finer progress to report.
+    AttachmentUploading { message_id: String },
+    /// An outgoing message ran out of attempts and is marked failed
+    MessageSendFailed { message_id: String, error: String },
+    /// Incoming attachment was fetched, verified and stored locally
+    AttachmentDownloaded { message_id: String, attachment_id: String },
+    /// Incoming attachment could not be downloaded after all retries
+    AttachmentDownloadFailed { message_id: String, attachment_id: String, error: String },
+    /// `recipient` got our messages sent at `timestamps` (ms); `at` is when they said so
+    DeliveryReceipt { recipient: String, timestamps: Vec<u64>, at: i64 },
+    /// `recipient` read our messages sent at `timestamps` (ms)
+    ReadReceipt { recipient: String, timestamps: Vec<u64>, at: i64 },
+    /// Messages read on another of our devices, as (sender, sent timestamp)
+    ReadSync { reads: Vec<(String, u64)> },
+    /// `sender` started typing in a conversation
+    TypingStarted { conversation_id: String, sender: String },
+    /// `sender` stopped typing
+    TypingStopped { conversation_id: String, sender: String },
+    /// Disappearing messages in these conversations expired and were deleted
+    MessagesExpired { conversation_ids: Vec<String> },
     /// Contact updated
     ContactUpdated { contact_id: String },
     /// Group updated
     GroupUpdated { group_id: String },
+    /// A conversation was accepted, blocked or deleted on another device, or
+    /// its contact's safety number changed
+    ConversationUpdated { conversation_id: String },
+    /// A sticker pack was installed, removed or finished downloading
+    StickerPacksChanged,
     /// Sync completed
     SyncCompleted,
+    /// The primary device answered a sync request and the data was stored
+    SyncResponse(SyncRequestKind),
     /// Error occurred
     Error(String),
 }
@@ -93,7 +330,16 @@ pub enum ConnectionState {
     Disconnected,
     Connecting,
     Connected,
-    Reconnecting,
+    /// The connection dropped; connecting again at `retry_at`
+    Reconnecting { attempt: u32, retry_at: DateTime<Utc> },
+}
+
+/// Why the receive loop ended
+enum LoopExit {
+    /// The websocket closed or stopped answering keepalives
+    Dropped(String),
+    /// The receive loop was replaced or shut down
+    Stopped,
 }
 
 /// Incoming message
@@ -105,6 +351,25 @@ pub struct IncomingMessage {
     pub content: MessageContent,
     pub timestamp: i64,
     pub server_timestamp: i64,
+    /// Message this one replies to
+    pub quote: Option<IncomingQuote>,
+    /// Disappearing message timer it was sent with, in seconds (0 = off).
+    /// `None` if the message didn't say, e.g. from an older client.
+    pub expire_timer: Option<u32>,
+}
+
+/// Quote of an earlier message in an incoming reply
+#[derive(Debug, Clone)]
+pub struct IncomingQuote {
+    /// Sent timestamp of the quoted message
+    pub timestamp: u64,
+    /// Author of the quoted message; `"self"` for our own
+    pub author: String,
+    pub text: Option<String>,
+    pub content_type: Option<String>,
+    pub filename: Option<String>,
+    /// Thumbnail of a quoted image or video, to be downloaded
+    pub thumbnail: Option<AttachmentMetadata>,
 }
 
 /// Message content types
@@ -112,24 +377,37 @@ pub struct IncomingMessage {
 pub enum MessageContent {
     Text(String),
     Attachment {
-        content_type: String,
-        filename: Option<String>,
-        size: u64,
-        attachment_id: String,
+        metadata: AttachmentMetadata,
+        caption: Option<String>,
     },
+    /// Sticker, by hex pack ID and key
     Sticker {
         pack_id: String,
+        pack_key: String,
         sticker_id: u32,
+        emoji: Option<String>,
     },
+    /// Reaction to the message `target_author` sent at `target_timestamp`.
+    /// The author is `"self"` for our own messages.
     Reaction {
         emoji: String,
-        target_message_id: String,
+        target_author: String,
+        target_timestamp: u64,
         remove: bool,
     },
-    Quote {
-        quoted_message_id: String,
+    /// New text for the message the same author sent at `target_timestamp`
+    Edit {
+        target_timestamp: u64,
         text: String,
     },
+    /// Delete for everyone of the message the same author sent at `target_timestamp`
+    Delete {
+        target_timestamp: u64,
+    },
+    /// The sender set the disappearing message timer (0 = off)
+    ExpirationTimerUpdate {
+        seconds: u32,
+    },
 }
 
 /// Result of device linking
@@ -465,41 +743,98 @@ impl SignalManager {
         Ok(())
     }
 
+    /// Run the receive loop on its own thread, reconnecting whenever the
+    /// connection drops. Replaces a receive loop started earlier.
     pub fn start_receiving(
         storage: Arc<Storage>,
         event_tx: mpsc::UnboundedSender<SignalEvent>,
     ) {
-        let (send_tx, send_rx) = mpsc::unbounded_channel::<SendCommand>();
-        
-        {
-            let mut guard = SEND_TX.lock();
-            *guard = Some(send_tx);
-        }
-        
+        let generation = CONNECTION_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+
         std::thread::spawn(move || {
             let rt = tokio::runtime::Builder::new_current_thread()
                 .enable_all()
                 .build()
                 .expect("Failed to create runtime for receiving");
 
-            rt.block_on(async move {
-                if let Err(e) = Self::receive_loop(&storage, event_tx.clone(), send_rx).await {
+            rt.block_on(Self::supervise_connection(&storage, &event_tx, generation));
+
+            if CONNECTION_GENERATION.load(Ordering::SeqCst) == generation {
+                *SELF_ACI.lock() = None;
+                *SELF_IDENTITY.lock() = None;
+            }
+        });
+    }
+
+    /// Keep the receive loop connected, reconnecting with a jittered backoff
+    /// after the connection drops, until it's replaced or fails for a reason
+    /// reconnecting won't fix
+    async fn supervise_connection(
+        storage: &Arc<Storage>,
+        event_tx: &mpsc::UnboundedSender<SignalEvent>,
+        generation: u64,
+    ) {
+        let is_current = || CONNECTION_GENERATION.load(Ordering::SeqCst) == generation;
+        let mut attempt = 0;
+
+        loop {
+            let started = tokio::time::Instant::now();
+            let result = Self::receive_loop(storage, event_tx.clone()).await;
+            if is_current() {
+                *SEND_TX.lock() = None;
+            }
+
+            match result {
+                Ok(LoopExit::Stopped) => return,
+                Ok(LoopExit::Dropped(reason)) => {
+                    tracing::warn!("Connection lost: {}", reason);
+                    if started.elapsed() >= connection::STABLE_CONNECTION {
+                        attempt = 0;
+                    }
+                }
+                Err(e) if connection::is_retryable(&e) => tracing::warn!("Connecting failed: {}", e),
+                Err(e) => {
                     tracing::error!("Message receive loop failed: {}", e);
                     send_event!(event_tx, SignalEvent::Error(e.to_string()));
                     send_event!(event_tx, SignalEvent::ConnectionStateChanged(ConnectionState::Disconnected));
+                    return;
                 }
-                
-                let mut guard = SEND_TX.lock();
-                *guard = None;
-            });
-        });
+            }
+            if !is_current() {
+                return;
+            }
+
+            attempt += 1;
+            let delay = connection::reconnect_delay(attempt, rand::random());
+            let retry_at = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+            tracing::info!("Reconnecting in {:.1}s (attempt {})", delay.as_secs_f32(), attempt);
+            send_event!(event_tx, SignalEvent::ConnectionStateChanged(ConnectionState::Reconnecting {
+                attempt,
+                retry_at,
+            }));
+
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = RECONNECT_NOW.notified() => tracing::info!("Reconnecting now"),
+            }
+            if !is_current() {
+                return;
+            }
+            send_event!(event_tx, SignalEvent::ConnectionStateChanged(ConnectionState::Connecting));
+        }
     }
 
+    /// Reconnect right away instead of waiting for the backoff
+    pub fn reconnect_now() {
+        RECONNECT_NOW.notify_one();
+    }
+
+    /// Connect, then receive messages and run send commands until the
+    /// connection drops. Commands are only taken while connected.
     async fn receive_loop(
         storage: &Arc<Storage>,
         event_tx: mpsc::UnboundedSender<SignalEvent>,
-        mut send_rx: mpsc::UnboundedReceiver<SendCommand>,
-    ) -> Result<(), SignalError> {
+    ) -> Result<LoopExit, SignalError> {
         let db_path = storage.signal_db_path();
         let db_url = format!("sqlite://{}", db_path.display());
         let passphrase = storage.get_encryption_key();
@@ -518,9 +853,14 @@ impl SignalManager {
             .await
             .map_err(|_| SignalError::NotRegistered)?;
 
-        tracing::info!("Starting message receive stream...");
-        send_event!(event_tx, SignalEvent::ConnectionStateChanged(ConnectionState::Connected));
+        let self_aci = manager.registration_data().service_ids.aci.to_string();
+        *SELF_ACI.lock() = Some(Self::normalize_service_id(&self_aci));
+        match manager.store().aci_protocol_store().get_identity_key_pair().await {
+            Ok(key_pair) => *SELF_IDENTITY.lock() = Some(key_pair.identity_key().serialize().to_vec()),
+            Err(e) => tracing::warn!("Failed to load our identity key: {}", e),
+        }
 
+        tracing::info!("Starting message receive stream...");
         let messages = manager
             .receive_messages()
             .await
@@ -528,9 +868,25 @@ impl SignalManager {
 
         futures::pin_mut!(messages);
 
+        let (send_tx, mut send_rx) = mpsc::unbounded_channel::<SendCommand>();
+        *SEND_TX.lock() = Some(send_tx);
+        send_event!(event_tx, SignalEvent::ConnectionStateChanged(ConnectionState::Connected));
+
+        // Whatever waited for a retry while we were offline goes out now
+        if let Some(db) = storage.database() {
+            match OutboxRepository::new(&db).resume() {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Resuming {} queued messages", count),
+                Err(e) => tracing::warn!("Failed to resume the outbox: {}", e),
+            }
+        }
+
+        let mut last_activity = tokio::time::Instant::now();
+
         loop {
             tokio::select! {
                 received = messages.next() => {
+                    last_activity = tokio::time::Instant::now();
                     match received {
                         Some(Received::QueueEmpty) => {
                             tracing::info!("Message queue synchronized");
@@ -566,6 +922,19 @@ impl SignalManager {
                                 }
                             }
 
+                            match Self::sync_groups_to_local(manager.store(), storage, &self_aci).await {
+                                Ok(count) if count > 0 => {
+                                    tracing::info!("Synced {} groups to local database", count);
+                                    send_event!(event_tx, SignalEvent::GroupUpdated { group_id: "all".to_string() });
+                                }
+                                Ok(_) => {}
+                                Err(e) => tracing::warn!("Group sync failed: {}", e),
+                            }
+
+                            for uuid in Self::check_identities(manager.store(), storage).await {
+                                send_event!(event_tx, SignalEvent::ConversationUpdated { conversation_id: uuid });
+                            }
+
                             send_event!(event_tx, SignalEvent::SyncCompleted);
                         }
                         Some(Received::Contacts) => {
@@ -575,6 +944,7 @@ impl SignalManager {
                             } else {
                                 tracing::info!("Contacts synced to local database");
                                 send_event!(event_tx, SignalEvent::ContactUpdated { contact_id: "all".to_string() });
+                                send_event!(event_tx, SignalEvent::SyncResponse(SyncRequestKind::Contacts));
 
                                 if let Err(e) = crate::signal::profiles::update_conversations_from_contacts(storage) {
                                     tracing::warn!("Failed to update conversations from contacts: {}", e);
@@ -611,39 +981,159 @@ impl SignalManager {
                         }
                         Some(Received::Content(content)) => {
                             Self::log_content_verbose(&content);
-                            if let Some(incoming) = Self::process_content(&content) {
+                            if Self::is_from_blocked(&content, storage, &self_aci) {
+                                tracing::debug!("Dropped content from blocked sender or group");
+                                continue;
+                            }
+                            let event = Self::receipt_event(&content)
+                                .or_else(|| Self::read_sync_event(&content))
+                                .or_else(|| Self::typing_event(&content, storage, &self_aci));
+                            if let Some(event) = event {
+                                send_event!(event_tx, event);
+                            }
+                            for kind in Self::apply_sync_responses(&content, storage, &self_aci) {
+                                send_event!(event_tx, SignalEvent::SyncResponse(kind));
+                            }
+                            if let Some(conversation_id) = Self::apply_message_request_response(&content, storage, &self_aci) {
+                                send_event!(event_tx, SignalEvent::ConversationUpdated { conversation_id });
+                            }
+                            if let Some(uuid) = Self::apply_verified_sync(&content, storage, &self_aci) {
+                                send_event!(event_tx, SignalEvent::ContactUpdated { contact_id: uuid.clone() });
+                                send_event!(event_tx, SignalEvent::ConversationUpdated { conversation_id: uuid });
+                            }
+                            if let Some(installed) = Self::apply_sticker_pack_sync(&content, storage, &self_aci) {
+                                send_event!(event_tx, SignalEvent::StickerPacksChanged);
+                                for (pack_id, pack_key) in installed {
+                                    let storage = storage.clone();
+                                    let event_tx = event_tx.clone();
+                                    tokio::spawn(async move {
+                                        match stickers::download_pack(&storage, &pack_id, &pack_key).await {
+                                            Ok(_) => send_event!(event_tx, SignalEvent::StickerPacksChanged),
+                                            Err(e) => tracing::warn!("Failed to download sticker pack {}: {}", pack_id, e),
+                                        }
+                                    });
+                                }
+                            }
+                            // Before the message, so the notice comes first
+                            let sender = content.metadata.sender.raw_uuid().to_string();
+                            if sender != Self::normalize_service_id(&self_aci)
+                                && Self::check_identity(manager.store(), storage, &sender).await
+                            {
+                                send_event!(event_tx, SignalEvent::ConversationUpdated { conversation_id: sender });
+                            }
+                            // Before the message, so a new conversation gets the group's name
+                            if let Some(group_id) = Self::refresh_group(manager.store(), storage, &content, &self_aci).await {
+                                send_event!(event_tx, SignalEvent::GroupUpdated { group_id });
+                            }
+                            for incoming in Self::process_content(&content, &self_aci) {
                                 tracing::info!("Received message from {}", incoming.sender);
                                 send_event!(event_tx, SignalEvent::MessageReceived(incoming));
                             }
                         }
-                        None => {
-                            tracing::warn!("Message stream ended");
-                            break;
-                        }
+                        None => return Ok(LoopExit::Dropped("Message stream ended".to_string())),
                     }
                 }
                 cmd = send_rx.recv() => {
                     match cmd {
-                        Some(SendCommand::DirectMessage { recipient, text, reply }) => {
-                            let result = Self::send_dm_with_manager(&mut manager, recipient, &text).await;
+                        Some(SendCommand::Edit { target, target_timestamp, text, timestamp, reply }) => {
+                            let result = async {
+                                Self::check_recipients(&manager, storage, &event_tx, &target.conversation_id()).await?;
+                                Self::send_edit_with_manager(
+                                    &mut manager,
+                                    storage,
+                                    target,
+                                    target_timestamp,
+                                    &text,
+                                    timestamp,
+                                ).await
+                            }.await;
+                            let _ = reply.send(result);
+                        }
+                        Some(SendCommand::Reaction { target, emoji, remove, target_author, target_timestamp, timestamp, reply }) => {
+                            let target_author = target_author
+                                .unwrap_or(manager.registration_data().service_ids.aci);
+                            let result = async {
+                                Self::check_recipients(&manager, storage, &event_tx, &target.conversation_id()).await?;
+                                Self::send_reaction_with_manager(
+                                    &mut manager,
+                                    storage,
+                                    target,
+                                    &emoji,
+                                    remove,
+                                    target_author,
+                                    target_timestamp,
+                                    timestamp,
+                                ).await
+                            }.await;
+                            let _ = reply.send(result);
+                        }
+                        Some(SendCommand::Delete { target, target_timestamp, timestamp, reply }) => {
+                            let result = async {
+                                Self::check_recipients(&manager, storage, &event_tx, &target.conversation_id()).await?;
+                                Self::send_delete_with_manager(
+                                    &mut manager,
+                                    storage,
+                                    target,
+                                    target_timestamp,
+                                    timestamp,
+                                ).await
+                            }.await;
+                            let _ = reply.send(result);
+                        }
+                        Some(SendCommand::Typing { target, started, timestamp, reply }) => {
+                            let result = async {
+                                Self::check_recipients(&manager, storage, &event_tx, &target.conversation_id()).await?;
+                                Self::send_typing_with_manager(&mut manager, target, started, timestamp).await
+                            }.await;
+                            let _ = reply.send(result);
+                        }
+                        Some(SendCommand::ExpirationTimer { target, seconds, timestamp, reply }) => {
+                            let result = async {
+                                Self::check_recipients(&manager, storage, &event_tx, &target.conversation_id()).await?;
+                                Self::send_expiration_timer_with_manager(&mut manager, storage, target, seconds, timestamp).await
+                            }.await;
+                            let _ = reply.send(result);
+                        }
+                        Some(SendCommand::SyncRequest { kinds, reply }) => {
+                            let result = Self::send_sync_requests_with_manager(&mut manager, &kinds, Self::now_millis()).await;
+                            let _ = reply.send(result);
+                        }
+                        Some(SendCommand::Sync { sync, reply }) => {
+                            let result = Self::send_sync_with_manager(&mut manager, *sync, Self::now_millis()).await;
+                            let _ = reply.send(result);
+                        }
+                        Some(SendCommand::RefreshProfiles { uuids, reply }) => {
+                            let result = Self::refresh_profiles_with_manager(&mut manager, storage, &event_tx, &uuids).await;
                             let _ = reply.send(result);
                         }
-                        Some(SendCommand::GroupMessage { group_key, text, reply }) => {
-                            let result = Self::send_group_with_manager(&mut manager, &group_key, &text).await;
+                        Some(SendCommand::ReadReceipts { reads, send_receipts, timestamp, reply }) => {
+                            let result = Self::send_read_receipts_with_manager(
+                                &mut manager,
+                                &reads,
+                                send_receipts,
+                                timestamp,
+                            ).await;
                             let _ = reply.send(result);
                         }
                         None => {
                             tracing::info!("Send channel closed");
-                            break;
+                            return Ok(LoopExit::Stopped);
                         }
                     }
                 }
+                _ = Self::outbox_ready(storage) => {
+                    Self::drain_outbox(&mut manager, storage, &event_tx).await;
+                }
+                _ = tokio::time::sleep_until(last_activity + KEEPALIVE_INTERVAL) => {
+                    // Nothing came in for a while; make sure the server still answers
+                    match tokio::time::timeout(KEEPALIVE_TIMEOUT, manager.whoami()).await {
+                        Ok(Ok(_)) => last_activity = tokio::time::Instant::now(),
+                        Ok(Err(e)) => return Ok(LoopExit::Dropped(format!("Keepalive failed: {:?}", e))),
+                        Err(_) => return Ok(LoopExit::Dropped("Keepalive timed out".to_string())),
+                    }
+                }
             }
         }
-
-        send_event!(event_tx, SignalEvent::ConnectionStateChanged(ConnectionState::Disconnected));
-
-        Ok(())
     }
 
     async fn sync_contacts_to_local(
@@ -669,33 +1159,22 @@ impl SignalManager {
             let uuid_str = presage_contact.uuid.to_string();
             let phone_str = presage_contact.phone_number.map(|p| p.to_string());
 
-            let existing = repo.get_by_uuid(&uuid_str);
-            let (existing_avatar_path, existing_created_at) = match existing {
-                Some(c) => (c.avatar_path, c.created_at),
-                None => (None, now),
-            };
-
-            let stored_contact = StoredContact {
-                id: uuid_str.clone(),
-                uuid: uuid_str,
-                phone_number: phone_str,
-                name: presage_contact.name.clone(),
-                profile_name: if presage_contact.name.is_empty() {
-                    None
-                } else {
-                    Some(presage_contact.name)
-                },
-                avatar_path: existing_avatar_path,
-                profile_key: if presage_contact.profile_key.is_empty() {
-                    None
-                } else {
-                    Some(presage_contact.profile_key)
-                },
-                is_blocked: false,
-                is_verified: false,
-                created_at: existing_created_at,
-                updated_at: now,
+            // Keep what only we know (avatar, block and verification state,
+            // nickname) and take the rest from presage
+            let mut stored_contact = repo
+                .get_by_uuid(&uuid_str)
+                .unwrap_or_else(|| StoredContact::new(&uuid_str, ""));
+            stored_contact.phone_number = phone_str.or(stored_contact.phone_number);
+            stored_contact.name = presage_contact.name.clone();
+            stored_contact.profile_name = if presage_contact.name.is_empty() {
+                None
+            } else {
+                Some(presage_contact.name)
             };
+            if !presage_contact.profile_key.is_empty() {
+                stored_contact.profile_key = Some(presage_contact.profile_key);
+            }
+            stored_contact.updated_at = now;
 
             if let Err(e) = repo.save(&stored_contact) {
                 tracing::warn!("Failed to save contact {}: {}", stored_contact.id, e);
@@ -705,20 +1184,326 @@ impl SignalManager {
         tracing::info!("Synced {} contacts to local database", repo.count());
         Ok(())
     }
-    
+
+    /// Copy every group presage knows about into the app database.
+    /// Returns the number of groups stored.
+    async fn sync_groups_to_local(
+        presage_store: &SqliteStore,
+        storage: &Arc<Storage>,
+        self_aci: &str,
+    ) -> Result<usize, SignalError> {
+        let presage_groups: Vec<_> = presage_store
+            .groups()
+            .await
+            .map_err(|e| SignalError::StorageError(format!("Failed to get groups from presage: {:?}", e)))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        let db = storage
+            .database()
+            .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+        let self_aci = Self::normalize_service_id(self_aci);
+
+        let mut stored = 0;
+        for (master_key, state) in presage_groups {
+            match groups::store_group_state(&db, &master_key, &state, &self_aci) {
+                Ok(_) => stored += 1,
+                Err(e) => tracing::warn!("Failed to save group {}: {}", state.title, e),
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Bring the stored copy of a group up to date when a message of ours or
+    /// someone else's shows a revision we don't have. Presage has fetched and
+    /// decrypted the new state from the master key by the time the message
+    /// reaches us. What changed is added to the timeline. Returns the
+    /// group's ID if it changed.
+    async fn refresh_group(
+        presage_store: &SqliteStore,
+        storage: &Arc<Storage>,
+        content: &Content,
+        self_aci: &str,
+    ) -> Option<String> {
+        let self_aci = Self::normalize_service_id(self_aci);
+        let (data_msg, sender) = match &content.body {
+            ContentBody::DataMessage(data_msg) => (data_msg, content.metadata.sender.raw_uuid().to_string()),
+            ContentBody::SynchronizeMessage(sync) => (
+                sync.sent.as_ref().and_then(|sent| sent.message.as_ref())?,
+                self_aci.clone(),
+            ),
+            _ => return None,
+        };
+        let context = data_msg.group_v2.as_ref()?;
+        let master_key = context.master_key.as_ref()?;
+        let revision = context.revision.unwrap_or(0);
+        let group_id = Self::group_conversation_id(data_msg)?;
+
+        let known = {
+            let db = storage.database()?;
+            GroupRepository::new(&db).get(&group_id).map(|g| g.revision)
+        };
+        if known.is_some_and(|known| known >= revision) && context.group_change.is_none() {
+            return None;
+        }
+
+        let key: [u8; 32] = master_key.as_slice().try_into().ok()?;
+        let state = match presage_store.group(key).await {
+            Ok(Some(state)) => state,
+            Ok(None) => {
+                tracing::warn!("No state for group {} in the Signal store", group_id);
+                return None;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to load group {}: {:?}", group_id, e);
+                return None;
+            }
+        };
+
+        let db = storage.database()?;
+        match groups::store_group_state(&db, master_key, &state, &self_aci) {
+            Ok((group, previous)) => {
+                tracing::info!("Group {} updated to revision {}", group.name, group.revision);
+
+                // The sender of a group change made it, unless we've since
+                // moved past its revision and the diff covers more than that
+                let editor = Some(sender.as_str())
+                    .filter(|_| context.group_change.is_some() && group.revision == revision);
+                let updates = groups::diff_group(previous.as_ref(), &group, editor);
+                let timestamp = data_msg.timestamp.unwrap_or(content.metadata.timestamp);
+                if let Err(e) = groups::record_group_updates(&db, &group, &updates, editor, &self_aci, timestamp) {
+                    tracing::error!("Failed to record updates of group {}: {}", group_id, e);
+                }
+                Some(group_id)
+            }
+            Err(e) => {
+                tracing::error!("Failed to save group {}: {}", group_id, e);
+                None
+            }
+        }
+    }
+
+    /// Resolves once the next outbox entry is due, or right away when a
+    /// message was queued
+    async fn outbox_ready(storage: &Arc<Storage>) {
+        let next = storage
+            .database()
+            .and_then(|db| OutboxRepository::new(&db).next_attempt_at());
+        let Some(next) = next else {
+            return OUTBOX_READY.notified().await;
+        };
+
+        let wait = (next - Self::now_millis() as i64).max(0) as u64;
+        tokio::select! {
+            _ = tokio::time::sleep(std::time::Duration::from_millis(wait)) => {}
+            _ = OUTBOX_READY.notified() => {}
+        }
+    }
+
+    /// Send every due outbox entry. Sending one makes the next message of its
+    /// conversation due, so this goes on until nothing is left to send now.
+    async fn drain_outbox(
+        manager: &mut Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        event_tx: &mpsc::UnboundedSender<SignalEvent>,
+    ) {
+        loop {
+            let due = match storage.database() {
+                Some(db) => OutboxRepository::new(&db).due(Self::now_millis() as i64),
+                None => return,
+            };
+            if due.is_empty() {
+                return;
+            }
+            for entry in due {
+                Self::send_outbox_entry(manager, storage, event_tx, &entry).await;
+            }
+        }
+    }
+
+    /// Make one attempt at sending an outbox entry and record how it went
+    async fn send_outbox_entry(
+        manager: &mut Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        event_tx: &mpsc::UnboundedSender<SignalEvent>,
+        entry: &OutboxEntry,
+    ) {
+        // Only to record key changes; outbox_send holds the entry if one did
+        let _ = Self::check_recipients(manager, storage, event_tx, &entry.conversation_id).await;
+        let prepared = {
+            let Some(db) = storage.database() else {
+                return;
+            };
+            let prepared = Self::outbox_send(&db, storage.attachments_dir(), storage.stickers_dir(), entry);
+            match &prepared {
+                Ok(Some(_)) => {}
+                // Deleted while it waited
+                Ok(None) => {
+                    if let Err(e) = OutboxRepository::new(&db).remove(&entry.message_id) {
+                        tracing::error!("Failed to remove {} from the outbox: {}", entry.message_id, e);
+                    }
+                }
+                Err(error) => {
+                    if let Err(e) = outbox::give_up(&db, &entry.message_id, error) {
+                        tracing::error!("Failed to mark {} failed: {}", entry.message_id, e);
+                    }
+                }
+            }
+            prepared
+        };
+        let send = match prepared {
+            Ok(Some(send)) => send,
+            Ok(None) => return,
+            Err(error) => {
+                send_event!(event_tx, SignalEvent::MessageSendFailed {
+                    message_id: entry.message_id.clone(),
+                    error,
+                });
+                return;
+            }
+        };
+
+        let quote = Self::build_quote(manager, storage, send.quote).await;
+        let result = match (send.sticker, send.attachment, send.target) {
+            (Some(sticker), _, target) => {
+                Self::send_sticker_with_manager(
+                    manager,
+                    storage,
+                    target,
+                    sticker,
+                    send.timestamp,
+                    quote,
+                    send.expire_timer,
+                ).await
+            }
+            (None, Some(path), target) => {
+                Self::send_attachment_with_manager(
+                    manager,
+                    storage,
+                    event_tx,
+                    target,
+                    &entry.message_id,
+                    &path,
+                    send.body,
+                    entry.voice_note,
+                    send.timestamp,
+                    quote,
+                    send.expire_timer,
+                ).await
+            }
+            (None, None, SendTarget::Direct(recipient)) => {
+                let text = send.body.unwrap_or_default();
+                Self::send_dm_with_manager(manager, recipient, &text, send.timestamp, quote, send.expire_timer).await
+            }
+            (None, None, SendTarget::Group(master_key)) => {
+                let text = send.body.unwrap_or_default();
+                Self::send_group_with_manager(manager, &master_key, &text, send.timestamp, quote, send.expire_timer).await
+            }
+        };
+
+        let gave_up = {
+            let Some(db) = storage.database() else {
+                return;
+            };
+            match &result {
+                Ok(()) => {
+                    tracing::info!("Message {} sent", entry.message_id);
+                    if let Err(e) = outbox::sent(&db, &entry.message_id) {
+                        tracing::error!("Failed to record {} as sent: {}", entry.message_id, e);
+                    }
+                    false
+                }
+                Err(error) => outbox::attempt_failed(&db, entry, &error.to_string(), Self::now_millis() as i64)
+                    .unwrap_or_else(|e| {
+                        tracing::error!("Failed to record failed attempt for {}: {}", entry.message_id, e);
+                        false
+                    }),
+            }
+        };
+
+        match result {
+            Ok(()) => send_event!(event_tx, SignalEvent::MessageSent { message_id: entry.message_id.clone() }),
+            Err(error) if gave_up => send_event!(event_tx, SignalEvent::MessageSendFailed {
+                message_id: entry.message_id.clone(),
+                error: error.to_string(),
+            }),
+            Err(_) => {}
+        }
+    }
+
+    /// What to send for an outbox entry, rebuilt from its stored message.
+    /// `None` if the message is gone; an error if it can never be sent as it
+    /// is.
+    fn outbox_send(
+        db: &Database,
+        attachments_dir: &Path,
+        stickers_dir: &Path,
+        entry: &OutboxEntry,
+    ) -> Result<Option<OutboxSend>, String> {
+        let Some(message) = MessageRepository::new(db).get(&entry.message_id) else {
+            return Ok(None);
+        };
+
+        let mut sticker = None;
+        let (body, attachment) = match message.content {
+            StoredContent::Text { body, .. } => (Some(body), None),
+            StoredContent::Sticker { pack_id, pack_key, sticker_id, emoji } => {
+                let path = stickers::sticker_path(stickers_dir, &pack_id, sticker_id);
+                if !path.exists() {
+                    return Err("The sticker image is missing".to_string());
+                }
+                sticker = Some(OutgoingSticker { pack_id, pack_key, sticker_id, emoji, path });
+                (None, None)
+            }
+            StoredContent::Image { attachment_id, caption, .. }
+            | StoredContent::Video { attachment_id, caption, .. } => (caption, Some(attachments_dir.join(attachment_id))),
+            StoredContent::Audio { attachment_id, .. } | StoredContent::File { attachment_id, .. } => {
+                (None, Some(attachments_dir.join(attachment_id)))
+            }
+            _ => return Err("Messages of this kind can't be sent".to_string()),
+        };
+        if attachment.as_ref().is_some_and(|path| !path.exists()) {
+            return Err("The attachment file is missing".to_string());
+        }
+
+        let is_group = ConversationRepository::new(db)
+            .get(&entry.conversation_id)
+            .is_some_and(|c| c.conversation_type == ConversationType::Group);
+        let target = SendTarget::from_conversation_id(&entry.conversation_id, is_group).map_err(|e| e.to_string())?;
+        // Held until the user has seen a verified contact's new safety number
+        if !safety::held_recipients(db, &entry.conversation_id).is_empty() {
+            return Err(SAFETY_NUMBER_CHANGED.to_string());
+        }
+
+        Ok(Some(OutboxSend {
+            target,
+            body,
+            attachment,
+            sticker,
+            timestamp: message
+                .signal_timestamp
+                .unwrap_or(message.sent_at.timestamp_millis() as u64),
+            quote: message
+                .quote
+                .as_ref()
+                .and_then(|q| OutgoingQuote::from_quote(q, attachments_dir)),
+            expire_timer: message.expires_in_seconds,
+        }))
+    }
+
     async fn send_dm_with_manager(
         manager: &mut Manager<SqliteStore, Registered>,
         recipient: Uuid,
         text: &str,
+        timestamp: u64,
+        quote: Option<data_message::Quote>,
+        expire_timer: Option<u32>,
     ) -> Result<(), SignalError> {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|e| SignalError::SendFailed(e.to_string()))?
-            .as_millis() as u64;
-        
         let data_message = DataMessage {
             body: Some(text.to_string()),
             timestamp: Some(timestamp),
+            quote,
+            expire_timer,
             ..Default::default()
         };
         
@@ -736,15 +1521,15 @@ impl SignalManager {
         manager: &mut Manager<SqliteStore, Registered>,
         master_key: &[u8],
         text: &str,
+        timestamp: u64,
+        quote: Option<data_message::Quote>,
+        expire_timer: Option<u32>,
     ) -> Result<(), SignalError> {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|e| SignalError::SendFailed(e.to_string()))?
-            .as_millis() as u64;
-        
         let data_message = DataMessage {
             body: Some(text.to_string()),
             timestamp: Some(timestamp),
+            quote,
+            expire_timer,
             ..Default::default()
         };
         
@@ -756,11 +1541,423 @@ impl SignalManager {
         Ok(())
     }
 
-    fn log_content_verbose(content: &Content) {
-        use presage::libsignal_service::content::ContentBody;
+    /// Encrypt and upload an attachment, then send it as a DataMessage
+    #[allow(clippy::too_many_arguments)]
+    async fn send_attachment_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        event_tx: &mpsc::UnboundedSender<SignalEvent>,
+        target: SendTarget,
+        message_id: &str,
+        path: &Path,
+        caption: Option<String>,
+        voice_note: bool,
+        timestamp: u64,
+        quote: Option<data_message::Quote>,
+        expire_timer: Option<u32>,
+    ) -> Result<(), SignalError> {
+        send_event!(event_tx, SignalEvent::AttachmentUploading {
+            message_id: message_id.to_string(),
+        });
 
-        let sender = format!("{:?}", content.metadata.sender);
-        let timestamp = content.metadata.timestamp;
+        let attachment_manager = AttachmentManager::new(storage.attachments_dir().clone());
+        let pointer = attachment_manager
+            .upload(manager, path, caption.clone(), voice_note)
+            .await?;
+
+        let data_message = DataMessage {
+            body: caption,
+            attachments: vec![pointer],
+            timestamp: Some(timestamp),
+            quote,
+            expire_timer,
+            ..Default::default()
+        };
+
+        match target {
+            SendTarget::Direct(recipient) => {
+                manager
+                    .send_message(ServiceId::Aci(recipient.into()), data_message, timestamp)
+                    .await
+                    .map_err(|e| SignalError::SendFailed(format!("{:?}", e)))?;
+            }
+            SendTarget::Group(master_key) => {
+                manager
+                    .send_message_to_group(&master_key, data_message, timestamp)
+                    .await
+                    .map_err(|e| SignalError::SendFailed(format!("{:?}", e)))?;
+            }
+        }
+
+        tracing::info!("Attachment message {} sent", message_id);
+        Ok(())
+    }
+
+    /// Upload a sticker's image and send it along with its pack ID and key
+    async fn send_sticker_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        target: SendTarget,
+        sticker: OutgoingSticker,
+        timestamp: u64,
+        quote: Option<data_message::Quote>,
+        expire_timer: Option<u32>,
+    ) -> Result<(), SignalError> {
+        let pack_id = hex::decode(&sticker.pack_id)
+            .map_err(|_| SignalError::ProtocolError("Invalid sticker pack ID".to_string()))?;
+        let pack_key = hex::decode(&sticker.pack_key)
+            .map_err(|_| SignalError::ProtocolError("Invalid sticker pack key".to_string()))?;
+        let content_type = storage.database().and_then(|db| {
+            StickerRepository::new(&db)
+                .get(&sticker.pack_id)?
+                .stickers
+                .into_iter()
+                .find(|s| s.sticker_id == sticker.sticker_id)?
+                .content_type
+        });
+
+        let attachment_manager = AttachmentManager::new(storage.attachments_dir().clone());
+        let pointer = attachment_manager
+            .upload_sticker(manager, &sticker.path, content_type)
+            .await?;
+
+        let data_message = DataMessage {
+            sticker: Some(data_message::Sticker {
+                pack_id: Some(pack_id),
+                pack_key: Some(pack_key),
+                sticker_id: Some(sticker.sticker_id),
+                data: Some(pointer),
+                emoji: sticker.emoji,
+            }),
+            timestamp: Some(timestamp),
+            quote,
+            expire_timer,
+            ..Default::default()
+        };
+
+        match target {
+            SendTarget::Direct(recipient) => {
+                manager
+                    .send_message(ServiceId::Aci(recipient.into()), data_message, timestamp)
+                    .await
+                    .map_err(|e| SignalError::SendFailed(format!("{:?}", e)))?;
+            }
+            SendTarget::Group(master_key) => {
+                manager
+                    .send_message_to_group(&master_key, data_message, timestamp)
+                    .await
+                    .map_err(|e| SignalError::SendFailed(format!("{:?}", e)))?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_typing_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        target: SendTarget,
+        started: bool,
+        timestamp: u64,
+    ) -> Result<(), SignalError> {
+        let action = if started {
+            typing_message::Action::Started
+        } else {
+            typing_message::Action::Stopped
+        };
+        let group_id = match &target {
+            SendTarget::Group(master_key) => Some(
+                group_identifier(master_key)
+                    .ok_or_else(|| SignalError::SendFailed("Invalid group master key".to_string()))?
+                    .to_vec(),
+            ),
+            SendTarget::Direct(_) => None,
+        };
+
+        let typing = TypingMessage {
+            timestamp: Some(timestamp),
+            action: Some(action as i32),
+            group_id,
+        };
+        Self::send_content_to_target(manager, target, ContentBody::TypingMessage(typing), timestamp).await
+    }
+
+    /// Send an expiration timer update, which sets the timer for both sides
+    async fn send_expiration_timer_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        target: SendTarget,
+        seconds: u32,
+        timestamp: u64,
+    ) -> Result<(), SignalError> {
+        let data_message = DataMessage {
+            flags: Some(data_message::Flags::ExpirationTimerUpdate as u32),
+            expire_timer: Some(seconds),
+            timestamp: Some(timestamp),
+            group_v2: Self::group_context(&target, storage),
+            ..Default::default()
+        };
+        Self::send_content_to_target(manager, target, ContentBody::DataMessage(data_message), timestamp).await
+    }
+
+    /// Send read receipts to each sender, then a read sync to our other
+    /// devices. A failed receipt doesn't stop the sync; the first error is
+    /// returned.
+    async fn send_read_receipts_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        reads: &[(Uuid, u64)],
+        send_receipts: bool,
+        timestamp: u64,
+    ) -> Result<(), SignalError> {
+        let mut result = Ok(());
+
+        if send_receipts {
+            let mut by_sender: Vec<(Uuid, Vec<u64>)> = Vec::new();
+            for &(sender, sent_at) in reads {
+                match by_sender.iter_mut().find(|(s, _)| *s == sender) {
+                    Some((_, timestamps)) => timestamps.push(sent_at),
+                    None => by_sender.push((sender, vec![sent_at])),
+                }
+            }
+
+            for (sender, timestamps) in by_sender {
+                let receipt = ReceiptMessage {
+                    r#type: Some(receipt_message::Type::Read as i32),
+                    timestamp: timestamps,
+                };
+                if let Err(e) = manager
+                    .send_message(ServiceId::Aci(sender.into()), ContentBody::ReceiptMessage(receipt), timestamp)
+                    .await
+                {
+                    tracing::warn!("Failed to send read receipt to {}: {:?}", sender, e);
+                    if result.is_ok() {
+                        result = Err(SignalError::SendFailed(format!("{:?}", e)));
+                    }
+                }
+            }
+        }
+
+        let sync = SyncMessage {
+            read: reads
+                .iter()
+                .map(|(sender, sent_at)| sync_message::Read {
+                    sender_aci: Some(sender.to_string()),
+                    timestamp: Some(*sent_at),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let self_aci = manager.registration_data().service_ids.aci;
+        manager
+            .send_message(ServiceId::Aci(self_aci.into()), ContentBody::SynchronizeMessage(sync), timestamp)
+            .await
+            .map_err(|e| SignalError::SendFailed(format!("{:?}", e)))?;
+
+        result
+    }
+
+    async fn send_sync_requests_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        kinds: &[SyncRequestKind],
+        timestamp: u64,
+    ) -> Result<(), SignalError> {
+        let self_aci = manager.registration_data().service_ids.aci;
+        for kind in kinds {
+            let sync = SyncMessage {
+                request: Some(sync_message::Request {
+                    r#type: Some(kind.request_type() as i32),
+                }),
+                ..Default::default()
+            };
+            manager
+                .send_message(ServiceId::Aci(self_aci.into()), ContentBody::SynchronizeMessage(sync), timestamp)
+                .await
+                .map_err(|e| SignalError::SendFailed(format!("{:?}", e)))?;
+        }
+        Ok(())
+    }
+
+    async fn send_sync_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        sync: SyncMessage,
+        timestamp: u64,
+    ) -> Result<(), SignalError> {
+        let self_aci = manager.registration_data().service_ids.aci;
+        manager
+            .send_message(ServiceId::Aci(self_aci.into()), ContentBody::SynchronizeMessage(sync), timestamp)
+            .await
+            .map_err(|e| SignalError::SendFailed(format!("{:?}", e)))
+    }
+
+    /// Build the protobuf quote of a reply, uploading a thumbnail of a quoted
+    /// image. A failed thumbnail upload only drops the thumbnail.
+    async fn build_quote(
+        manager: &Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        quote: Option<OutgoingQuote>,
+    ) -> Option<data_message::Quote> {
+        let quote = quote?;
+        let author = quote
+            .author
+            .unwrap_or(manager.registration_data().service_ids.aci);
+
+        let mut attachments = Vec::new();
+        if let Some(attachment) = quote.attachment {
+            let mut thumbnail = None;
+            if let Some(path) = attachment.thumbnail.filter(|_| attachment.content_type.starts_with("image/")) {
+                let attachment_manager = AttachmentManager::new(storage.attachments_dir().clone());
+                match attachment_manager.upload_thumbnail(manager, &path).await {
+                    Ok(pointer) => thumbnail = Some(pointer),
+                    Err(e) => tracing::warn!("Sending quote without thumbnail: {}", e),
+                }
+            }
+            attachments.push(data_message::quote::QuotedAttachment {
+                content_type: Some(attachment.content_type),
+                file_name: attachment.filename,
+                thumbnail,
+            });
+        }
+
+        Some(data_message::Quote {
+            id: Some(quote.timestamp),
+            author_aci: Some(author.to_string()),
+            text: quote.text,
+            attachments,
+            r#type: Some(data_message::quote::Type::Normal as i32),
+            ..Default::default()
+        })
+    }
+
+    /// Send an EditMessage replacing the text of a message we sent earlier
+    async fn send_edit_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        target: SendTarget,
+        target_timestamp: u64,
+        text: &str,
+        timestamp: u64,
+    ) -> Result<(), SignalError> {
+        let data_message = DataMessage {
+            body: Some(text.to_string()),
+            timestamp: Some(timestamp),
+            group_v2: Self::group_context(&target, storage),
+            ..Default::default()
+        };
+
+        let edit = ContentBody::EditMessage(EditMessage {
+            target_sent_timestamp: Some(target_timestamp),
+            data_message: Some(data_message),
+        });
+        Self::send_content_to_target(manager, target, edit, timestamp).await?;
+
+        tracing::info!("Edit of message sent at {} delivered", target_timestamp);
+        Ok(())
+    }
+
+    /// Send a remote delete for a message we sent earlier
+    async fn send_delete_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        target: SendTarget,
+        target_timestamp: u64,
+        timestamp: u64,
+    ) -> Result<(), SignalError> {
+        let data_message = DataMessage {
+            delete: Some(Delete {
+                target_sent_timestamp: Some(target_timestamp),
+            }),
+            timestamp: Some(timestamp),
+            group_v2: Self::group_context(&target, storage),
+            ..Default::default()
+        };
+        Self::send_content_to_target(manager, target, ContentBody::DataMessage(data_message), timestamp)
+            .await?;
+
+        tracing::info!("Delete of message sent at {} delivered", target_timestamp);
+        Ok(())
+    }
+
+    /// Send a reaction, or the removal of one, to a message in a direct or group conversation
+    #[allow(clippy::too_many_arguments)]
+    async fn send_reaction_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        target: SendTarget,
+        emoji: &str,
+        remove: bool,
+        target_author: Uuid,
+        target_timestamp: u64,
+        timestamp: u64,
+    ) -> Result<(), SignalError> {
+        let data_message = DataMessage {
+            reaction: Some(Reaction {
+                emoji: Some(emoji.to_string()),
+                remove: Some(remove),
+                target_author_aci: Some(target_author.to_string()),
+                target_sent_timestamp: Some(target_timestamp),
+                ..Default::default()
+            }),
+            timestamp: Some(timestamp),
+            group_v2: Self::group_context(&target, storage),
+            ..Default::default()
+        };
+        Self::send_content_to_target(manager, target, ContentBody::DataMessage(data_message), timestamp)
+            .await?;
+
+        tracing::info!("Reaction {} to message sent at {} delivered", emoji, target_timestamp);
+        Ok(())
+    }
+
+    /// Group context for messages that are built by hand rather than by
+    /// presage, at the revision of the group we last stored
+    fn group_context(target: &SendTarget, storage: &Arc<Storage>) -> Option<GroupContextV2> {
+        match target {
+            SendTarget::Direct(_) => None,
+            SendTarget::Group(master_key) => {
+                let group_id = target.conversation_id();
+                let revision = storage
+                    .database()
+                    .and_then(|db| GroupRepository::new(&db).get(&group_id))
+                    .map(|group| group.revision);
+                if revision.is_none() {
+                    tracing::warn!("No stored state for group {}, sending without a revision", group_id);
+                }
+                Some(GroupContextV2 {
+                    master_key: Some(master_key.clone()),
+                    revision,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    async fn send_content_to_target(
+        manager: &mut Manager<SqliteStore, Registered>,
+        target: SendTarget,
+        content: ContentBody,
+        timestamp: u64,
+    ) -> Result<(), SignalError> {
+        match target {
+            SendTarget::Direct(recipient) => {
+                manager
+                    .send_message(ServiceId::Aci(recipient.into()), content, timestamp)
+                    .await
+                    .map_err(|e| SignalError::SendFailed(format!("{:?}", e)))?;
+            }
+            SendTarget::Group(master_key) => {
+                manager
+                    .send_message_to_group(&master_key, content, timestamp)
+                    .await
+                    .map_err(|e| SignalError::SendFailed(format!("{:?}", e)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn log_content_verbose(content: &Content) {
+        use presage::libsignal_service::content::ContentBody;
+
+        let sender = format!("{:?}", content.metadata.sender);
+        let timestamp = content.metadata.timestamp;
 
         match &content.body {
             ContentBody::DataMessage(dm) => {
@@ -844,125 +2041,568 @@ impl SignalManager {
         }
     }
 
-    fn process_content(content: &Content) -> Option<IncomingMessage> {
+    /// Delivery or read receipt event for a `ReceiptMessage`. Viewed receipts
+    /// (for view-once media) aren't tracked.
+    fn receipt_event(content: &Content) -> Option<SignalEvent> {
         use presage::libsignal_service::content::ContentBody;
 
-        let sender = content.metadata.sender.raw_uuid().to_string();
-        let timestamp = content.metadata.timestamp as i64;
+        let ContentBody::ReceiptMessage(receipt) = &content.body else {
+            return None;
+        };
+        let recipient = content.metadata.sender.raw_uuid().to_string();
+        let timestamps = receipt.timestamp.clone();
+        let at = content.metadata.timestamp as i64;
+
+        match receipt.r#type() {
+            receipt_message::Type::Delivery => Some(SignalEvent::DeliveryReceipt { recipient, timestamps, at }),
+            receipt_message::Type::Read => Some(SignalEvent::ReadReceipt { recipient, timestamps, at }),
+            receipt_message::Type::Viewed => None,
+        }
+    }
 
-        match &content.body {
-            ContentBody::DataMessage(data_msg) => {
-                Self::process_data_message(data_msg, &sender, timestamp)
-            }
-            ContentBody::SynchronizeMessage(sync_msg) => {
-                Self::process_sync_message(sync_msg, &sender, timestamp)
+    /// Read state synced from our other devices
+    fn read_sync_event(content: &Content) -> Option<SignalEvent> {
+        use presage::libsignal_service::content::ContentBody;
+
+        let ContentBody::SynchronizeMessage(sync) = &content.body else {
+            return None;
+        };
+        let reads: Vec<(String, u64)> = sync
+            .read
+            .iter()
+            .filter_map(|read| {
+                let sender = Self::normalize_service_id(read.sender_aci.as_deref()?);
+                Some((sender, read.timestamp?))
+            })
+            .collect();
+
+        (!reads.is_empty()).then_some(SignalEvent::ReadSync { reads })
+    }
+
+    /// Store the blocked list, configuration and keys sent by the primary
+    /// device. Returns what was applied.
+    fn apply_sync_responses(content: &Content, storage: &Arc<Storage>, self_aci: &str) -> Vec<SyncRequestKind> {
+        use presage::libsignal_service::content::ContentBody;
+
+        let ContentBody::SynchronizeMessage(sync) = &content.body else {
+            return Vec::new();
+        };
+        // Only our own devices may change our settings
+        if content.metadata.sender.raw_uuid().to_string() != Self::normalize_service_id(self_aci) {
+            return Vec::new();
+        }
+        let Some(db) = storage.database() else {
+            return Vec::new();
+        };
+
+        let mut applied = Vec::new();
+        if let Some(blocked) = &sync.blocked {
+            let acis: Vec<String> = blocked.acis.iter().map(|aci| Self::normalize_service_id(aci)).collect();
+            match crate::signal::sync::apply_blocked_list(&db, &acis, &blocked.numbers, &blocked.group_ids) {
+                Ok(_) => applied.push(SyncRequestKind::Blocked),
+                Err(e) => tracing::error!("Failed to apply blocked list: {}", e),
             }
-            ContentBody::ReceiptMessage(receipt) => {
-                tracing::debug!("Received receipt: {:?}", receipt);
-                None
+        }
+        if let Some(configuration) = &sync.configuration {
+            match crate::signal::sync::apply_configuration(&db, configuration) {
+                Ok(()) => applied.push(SyncRequestKind::Configuration),
+                Err(e) => tracing::error!("Failed to apply configuration: {}", e),
             }
-            ContentBody::TypingMessage(typing) => {
-                tracing::debug!("Received typing indicator: {:?}", typing);
+        }
+        if sync.keys.is_some() {
+            // Keys are for the storage service, which we don't use yet
+            tracing::info!("Received keys from primary device");
+            applied.push(SyncRequestKind::Keys);
+        }
+        applied
+    }
+
+    /// Whether content comes from a blocked contact or was sent to a blocked
+    /// group. Our own devices are never blocked.
+    fn is_from_blocked(content: &Content, storage: &Arc<Storage>, self_aci: &str) -> bool {
+        let sender = content.metadata.sender.raw_uuid().to_string();
+        if sender == Self::normalize_service_id(self_aci) {
+            return false;
+        }
+        let Some(db) = storage.database() else {
+            return false;
+        };
+
+        let group = match &content.body {
+            ContentBody::DataMessage(data_msg) => Self::group_conversation_id(data_msg),
+            ContentBody::EditMessage(edit) => edit.data_message.as_ref().and_then(Self::group_conversation_id),
+            ContentBody::TypingMessage(typing) => typing
+                .group_id
+                .as_ref()
+                .and_then(|group_id| requests::group_conversation_for(&db, group_id)),
+            _ => None,
+        };
+        requests::is_blocked(&db, &sender, group.as_deref())
+    }
+
+    /// Apply a message request answer from another of our devices. Returns
+    /// the conversation it changed.
+    fn apply_message_request_response(content: &Content, storage: &Arc<Storage>, self_aci: &str) -> Option<String> {
+        let ContentBody::SynchronizeMessage(sync) = &content.body else {
+            return None;
+        };
+        let response = sync.message_request_response.as_ref()?;
+        if content.metadata.sender.raw_uuid().to_string() != Self::normalize_service_id(self_aci) {
+            return None;
+        }
+
+        let db = storage.database()?;
+        let (conversation_id, action) = requests::parse_response(&db, response)?;
+        match requests::apply_action(&db, &conversation_id, action) {
+            Ok(()) => Some(conversation_id),
+            Err(e) => {
+                tracing::error!("Failed to apply message request response: {}", e);
                 None
             }
-            _ => {
-                tracing::debug!("Received other message type");
+        }
+    }
+
+    /// Apply a safety number verification from another of our devices.
+    /// Returns the contact it changed.
+    fn apply_verified_sync(content: &Content, storage: &Arc<Storage>, self_aci: &str) -> Option<String> {
+        let ContentBody::SynchronizeMessage(sync) = &content.body else {
+            return None;
+        };
+        let verified = sync.verified.as_ref()?;
+        if content.metadata.sender.raw_uuid().to_string() != Self::normalize_service_id(self_aci) {
+            return None;
+        }
+
+        let db = storage.database()?;
+        match safety::apply_verified_sync(&db, verified) {
+            Ok(uuid) => uuid,
+            Err(e) => {
+                tracing::error!("Failed to apply verification sync: {}", e);
                 None
             }
         }
     }
 
-    fn process_data_message(
-        data_msg: &DataMessage,
-        sender: &str,
-        timestamp: i64,
-    ) -> Option<IncomingMessage> {
-        let text = data_msg.body.clone().unwrap_or_default();
-        if text.is_empty() && data_msg.attachments.is_empty() {
+    /// Install or remove sticker packs as another of our devices did. Returns
+    /// the newly installed packs, to be downloaded, or `None` if nothing
+    /// changed.
+    fn apply_sticker_pack_sync(
+        content: &Content,
+        storage: &Arc<Storage>,
+        self_aci: &str,
+    ) -> Option<Vec<(String, String)>> {
+        let ContentBody::SynchronizeMessage(sync) = &content.body else {
+            return None;
+        };
+        if sync.sticker_pack_operation.is_empty()
+            || content.metadata.sender.raw_uuid().to_string() != Self::normalize_service_id(self_aci)
+        {
             return None;
         }
 
-        let conversation_id = if let Some(group) = &data_msg.group_v2 {
-            if let Some(master_key) = &group.master_key {
-                use base64::Engine;
-                base64::engine::general_purpose::STANDARD.encode(master_key)
-            } else {
-                sender.to_string()
+        let db = storage.database()?;
+        match stickers::apply_pack_operations(&db, &sync.sticker_pack_operation) {
+            Ok(installed) => Some(installed),
+            Err(e) => {
+                tracing::error!("Failed to apply sticker pack operations: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Compare the identity key presage trusts for a contact with the one we
+    /// recorded. Returns true when their safety number changed.
+    async fn check_identity(presage_store: &SqliteStore, storage: &Arc<Storage>, uuid: &str) -> bool {
+        let Ok(device_id) = DeviceId::try_from(1u32) else {
+            return false;
+        };
+        let address = ProtocolAddress::new(uuid.to_string(), device_id);
+        let identity_key = match presage_store.aci_protocol_store().get_identity(&address).await {
+            Ok(Some(identity_key)) => identity_key.serialize(),
+            Ok(None) => return false,
+            Err(e) => {
+                tracing::warn!("Failed to load identity key of {}: {}", uuid, e);
+                return false;
             }
-        } else {
-            sender.to_string()
         };
 
-        Some(IncomingMessage {
-            id: uuid::Uuid::new_v4().to_string(),
-            sender: sender.to_string(),
-            conversation_id,
-            content: MessageContent::Text(text),
-            timestamp,
-            server_timestamp: timestamp,
+        let Some(db) = storage.database() else {
+            return false;
+        };
+        safety::record_identity(&db, uuid, &identity_key).unwrap_or_else(|e| {
+            tracing::error!("Failed to record identity key of {}: {}", uuid, e);
+            false
         })
     }
 
-    /// Normalize a service ID string to plain UUID format.
-    /// Signal uses formats like "ACI:uuid" or "PNI:uuid" - this strips the prefix.
-    fn normalize_service_id(service_id: &str) -> String {
-        if let Some(uuid_part) = service_id.strip_prefix("ACI:") {
-            uuid_part.to_lowercase()
-        } else if let Some(uuid_part) = service_id.strip_prefix("PNI:") {
-            uuid_part.to_lowercase()
-        } else {
-            service_id.to_lowercase()
+    /// Check the identity keys presage holds for everyone a send to a
+    /// conversation goes to, since it trusts keys fetched while sending as
+    /// well as ones that arrive with messages. Fails if the send has to wait
+    /// for the user to accept a changed safety number.
+    async fn check_recipients(
+        manager: &Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        event_tx: &mpsc::UnboundedSender<SignalEvent>,
+        conversation_id: &str,
+    ) -> Result<(), SignalError> {
+        let self_aci = manager.registration_data().service_ids.aci.to_string();
+        let recipients = match storage.database() {
+            Some(db) => safety::recipients(&db, conversation_id),
+            None => return Ok(()),
+        };
+        for uuid in recipients.iter().filter(|uuid| **uuid != self_aci) {
+            if Self::check_identity(manager.store(), storage, uuid).await {
+                send_event!(event_tx, SignalEvent::ConversationUpdated { conversation_id: uuid.clone() });
+            }
+        }
+
+        match storage.database() {
+            Some(db) if !safety::held_recipients(&db, conversation_id).is_empty() => {
+                Err(SignalError::SendFailed(SAFETY_NUMBER_CHANGED.to_string()))
+            }
+            _ => Ok(()),
         }
     }
 
-    fn process_sync_message(
-        sync_msg: &presage::libsignal_service::proto::SyncMessage,
-        _sender: &str,
-        timestamp: i64,
-    ) -> Option<IncomingMessage> {
-        if let Some(sent) = &sync_msg.sent {
-            if let Some(data_msg) = &sent.message {
-                let text = data_msg.body.clone().unwrap_or_default();
-                if text.is_empty() && data_msg.attachments.is_empty() {
-                    return None;
-                }
+    /// Check the identity keys of all contacts, e.g. after keys changed while
+    /// we were offline. Returns the contacts whose safety number changed.
+    async fn check_identities(presage_store: &SqliteStore, storage: &Arc<Storage>) -> Vec<String> {
+        let uuids: Vec<String> = match storage.database() {
+            Some(db) => ContactRepository::new(&db).list().into_iter().map(|c| c.uuid).collect(),
+            None => return Vec::new(),
+        };
 
-                let conversation_id = if let Some(group) = &data_msg.group_v2 {
-                    if let Some(master_key) = &group.master_key {
-                        use base64::Engine;
-                        base64::engine::general_purpose::STANDARD.encode(master_key)
-                    } else if let Some(dest) = &sent.destination_service_id {
-                        Self::normalize_service_id(dest)
-                    } else {
-                        return None;
-                    }
-                } else if let Some(dest) = &sent.destination_service_id {
-                    Self::normalize_service_id(dest)
-                } else {
-                    return None;
-                };
+        let mut changed = Vec::new();
+        for uuid in uuids {
+            if Self::check_identity(presage_store, storage, &uuid).await {
+                changed.push(uuid);
+            }
+        }
+        changed
+    }
 
-                let msg_timestamp = sent.timestamp.unwrap_or(timestamp as u64) as i64;
+    /// Typing event for a `TypingMessage`. Group indicators carry the group
+    /// identifier rather than the master key, so they're matched against our
+    /// group conversations; unknown groups are ignored.
+    fn typing_event(content: &Content, storage: &Arc<Storage>, self_aci: &str) -> Option<SignalEvent> {
+        use presage::libsignal_service::content::ContentBody;
 
-                tracing::info!(
-                    "Received sync of sent message to {} at {}",
+        let ContentBody::TypingMessage(typing) = &content.body else {
+            return None;
+        };
+        let sender = content.metadata.sender.raw_uuid().to_string();
+        if sender == Self::normalize_service_id(self_aci) {
+            return None;
+        }
+
+        let conversation_id = match &typing.group_id {
+            Some(group_id) => requests::group_conversation_for(&storage.database()?, group_id)?,
+            None => sender.clone(),
+        };
+
+        Some(match typing.action() {
+            typing_message::Action::Started => SignalEvent::TypingStarted { conversation_id, sender },
+            typing_message::Action::Stopped => SignalEvent::TypingStopped { conversation_id, sender },
+        })
+    }
+
+    fn process_content(content: &Content, self_aci: &str) -> Vec<IncomingMessage> {
+        use presage::libsignal_service::content::ContentBody;
+
+        let sender = content.metadata.sender.raw_uuid().to_string();
+        let timestamp = content.metadata.timestamp as i64;
+
+        match &content.body {
+            ContentBody::DataMessage(data_msg) => {
+                Self::process_data_message(data_msg, &sender, self_aci, timestamp)
+            }
+            ContentBody::SynchronizeMessage(sync_msg) => {
+                Self::process_sync_message(sync_msg, &sender, self_aci, timestamp)
+            }
+            // Receipts are turned into events by receipt_event
+            ContentBody::ReceiptMessage(_) => Vec::new(),
+            // Typing indicators are turned into events by typing_event
+            ContentBody::TypingMessage(_) => Vec::new(),
+            ContentBody::EditMessage(edit) => {
+                let conversation_id = edit
+                    .data_message
+                    .as_ref()
+                    .and_then(Self::group_conversation_id)
+                    .unwrap_or_else(|| sender.clone());
+                Self::process_edit_message(edit, &sender, &conversation_id, timestamp)
+                    .into_iter()
+                    .collect()
+            }
+            _ => {
+                tracing::debug!("Received other message type");
+                Vec::new()
+            }
+        }
+    }
+
+    fn process_data_message(
+        data_msg: &DataMessage,
+        sender: &str,
+        self_aci: &str,
+        timestamp: i64,
+    ) -> Vec<IncomingMessage> {
+        let conversation_id = Self::group_conversation_id(data_msg)
+            .unwrap_or_else(|| sender.to_string());
+
+        Self::data_message_to_incoming(data_msg, sender, &conversation_id, self_aci, timestamp, timestamp)
+    }
+
+    /// Conversation ID (base64 master key) of a group message
+    fn group_conversation_id(data_msg: &DataMessage) -> Option<String> {
+        use base64::Engine;
+        data_msg
+            .group_v2
+            .as_ref()
+            .and_then(|group| group.master_key.as_ref())
+            .map(|master_key| base64::engine::general_purpose::STANDARD.encode(master_key))
+    }
+
+    /// Turn an EditMessage into an `Edit` for the message it targets.
+    ///
+    /// Edits only replace text; attachments of the original are kept.
+    fn process_edit_message(
+        edit: &EditMessage,
+        sender: &str,
+        conversation_id: &str,
+        server_timestamp: i64,
+    ) -> Option<IncomingMessage> {
+        let target_timestamp = edit.target_sent_timestamp?;
+        let data_msg = edit.data_message.as_ref()?;
+
+        Some(IncomingMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            sender: sender.to_string(),
+            conversation_id: conversation_id.to_string(),
+            content: MessageContent::Edit {
+                target_timestamp,
+                text: data_msg.body.clone().unwrap_or_default(),
+            },
+            timestamp: data_msg.timestamp.map(|t| t as i64).unwrap_or(server_timestamp),
+            server_timestamp,
+            quote: None,
+            expire_timer: data_msg.expire_timer,
+        })
+    }
+
+    /// Split a DataMessage into one IncomingMessage per attachment.
+    ///
+    /// The body becomes the caption of the first image/video; otherwise it is
+    /// delivered as a separate text message ahead of the attachments. All of
+    /// them keep the DataMessage's timestamp, so anything that refers to it
+    /// later finds them together with `MessageRepository::find_all_by_timestamp`.
+    fn data_message_to_incoming(
+        data_msg: &DataMessage,
+        sender: &str,
+        conversation_id: &str,
+        self_aci: &str,
+        timestamp: i64,
+        server_timestamp: i64,
+    ) -> Vec<IncomingMessage> {
+        let make = |content: MessageContent| IncomingMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            sender: sender.to_string(),
+            conversation_id: conversation_id.to_string(),
+            content,
+            timestamp,
+            server_timestamp,
+            quote: None,
+            expire_timer: data_msg.expire_timer,
+        };
+
+        // A remote delete carries nothing else worth showing
+        if let Some(target_timestamp) = data_msg.delete.as_ref().and_then(|d| d.target_sent_timestamp) {
+            return vec![make(MessageContent::Delete { target_timestamp })];
+        }
+        if let Some(reaction) = &data_msg.reaction {
+            return Self::reaction_content(reaction, self_aci).map(make).into_iter().collect();
+        }
+        if data_msg.flags.unwrap_or(0) & data_message::Flags::ExpirationTimerUpdate as u32 != 0 {
+            return vec![make(MessageContent::ExpirationTimerUpdate {
+                seconds: data_msg.expire_timer.unwrap_or(0),
+            })];
+        }
+
+        let quote = || {
+            data_msg
+                .quote
+                .as_ref()
+                .and_then(|quote| Self::incoming_quote(quote, self_aci))
+        };
+        // The image is fetched by pack, so the attached pointer isn't needed
+        if let Some(sticker) = &data_msg.sticker {
+            if let (Some(pack_id), Some(pack_key), Some(sticker_id)) =
+                (&sticker.pack_id, &sticker.pack_key, sticker.sticker_id)
+            {
+                let mut message = make(MessageContent::Sticker {
+                    pack_id: hex::encode(pack_id),
+                    pack_key: hex::encode(pack_key),
+                    sticker_id,
+                    emoji: sticker.emoji.clone().filter(|e| !e.is_empty()),
+                });
+                message.quote = quote();
+                return vec![message];
+            }
+            tracing::warn!("Skipping sticker without pack ID, key or sticker ID");
+        }
+
+        let text = data_msg.body.clone().unwrap_or_default();
+        let attachments: Vec<AttachmentMetadata> = data_msg
+            .attachments
+            .iter()
+            .filter_map(|pointer| {
+                let metadata = AttachmentMetadata::from_pointer(pointer);
+                if metadata.is_none() {
+                    tracing::warn!("Skipping attachment pointer without key or CDN locator");
+                }
+                metadata
+            })
+            .collect();
+
+        let captionable = attachments.first().is_some_and(|a| {
+            a.content_type.starts_with("image/") || a.content_type.starts_with("video/")
+        });
+
+        let mut messages = Vec::new();
+        let mut caption = None;
+        if !text.is_empty() {
+            if captionable {
+                caption = Some(text);
+            } else {
+                messages.push(make(MessageContent::Text(text)));
+            }
+        }
+
+        for metadata in attachments {
+            messages.push(make(MessageContent::Attachment {
+                metadata,
+                caption: caption.take(),
+            }));
+        }
+
+        // The quote belongs to the body, which is always the first message
+        if let Some(first) = messages.first_mut() {
+            first.quote = quote();
+        }
+
+        messages
+    }
+
+    /// Quote of an incoming reply, with the author resolved like reactions
+    fn incoming_quote(quote: &data_message::Quote, self_aci: &str) -> Option<IncomingQuote> {
+        let author = Self::normalize_service_id(quote.author_aci.as_deref()?);
+        let author = if author == Self::normalize_service_id(self_aci) {
+            "self".to_string()
+        } else {
+            author
+        };
+        let attachment = quote.attachments.first();
+
+        Some(IncomingQuote {
+            timestamp: quote.id?,
+            author,
+            text: quote.text.clone().filter(|t| !t.is_empty()),
+            content_type: attachment.and_then(|a| a.content_type.clone()),
+            filename: attachment.and_then(|a| a.file_name.clone()),
+            thumbnail: attachment
+                .and_then(|a| a.thumbnail.as_ref())
+                .and_then(AttachmentMetadata::from_pointer),
+        })
+    }
+
+    /// Reaction content with the target author resolved; `"self"` for our own messages
+    fn reaction_content(reaction: &Reaction, self_aci: &str) -> Option<MessageContent> {
+        let target_timestamp = reaction.target_sent_timestamp?;
+        let target_author = Self::normalize_service_id(reaction.target_author_aci.as_deref()?);
+        let target_author = if target_author == Self::normalize_service_id(self_aci) {
+            "self".to_string()
+        } else {
+            target_author
+        };
+
+        Some(MessageContent::Reaction {
+            emoji: reaction.emoji.clone()?,
+            target_author,
+            target_timestamp,
+            remove: reaction.remove.unwrap_or(false),
+        })
+    }
+
+    fn now_millis() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+    }
+
+    /// Normalize a service ID string to plain UUID format.
+    /// Signal uses formats like "ACI:uuid" or "PNI:uuid" - this strips the prefix.
+    fn normalize_service_id(service_id: &str) -> String {
+        if let Some(uuid_part) = service_id.strip_prefix("ACI:") {
+            uuid_part.to_lowercase()
+        } else if let Some(uuid_part) = service_id.strip_prefix("PNI:") {
+            uuid_part.to_lowercase()
+        } else {
+            service_id.to_lowercase()
+        }
+    }
+
+    fn process_sync_message(
+        sync_msg: &presage::libsignal_service::proto::SyncMessage,
+        _sender: &str,
+        self_aci: &str,
+        timestamp: i64,
+    ) -> Vec<IncomingMessage> {
+        if let Some(sent) = &sync_msg.sent {
+            let destination = sent.destination_service_id.as_deref().map(Self::normalize_service_id);
+
+            if let Some(edit) = &sent.edit_message {
+                let conversation_id = edit
+                    .data_message
+                    .as_ref()
+                    .and_then(Self::group_conversation_id)
+                    .or(destination);
+                return match conversation_id {
+                    Some(conversation_id) => {
+                        tracing::info!("Received sync of edit in {}", conversation_id);
+                        Self::process_edit_message(edit, "self", &conversation_id, timestamp)
+                            .into_iter()
+                            .collect()
+                    }
+                    None => Vec::new(),
+                };
+            }
+
+            if let Some(data_msg) = &sent.message {
+                let Some(conversation_id) = Self::group_conversation_id(data_msg).or(destination) else {
+                    return Vec::new();
+                };
+
+                let msg_timestamp = sent.timestamp.unwrap_or(timestamp as u64) as i64;
+
+                tracing::info!(
+                    "Received sync of sent message to {} at {}",
                     conversation_id,
                     msg_timestamp
                 );
 
-                return Some(IncomingMessage {
-                    id: uuid::Uuid::new_v4().to_string(),
-                    sender: "self".to_string(),
-                    conversation_id,
-                    content: MessageContent::Text(text),
-                    timestamp: msg_timestamp,
-                    server_timestamp: timestamp,
-                });
+                return Self::data_message_to_incoming(
+                    data_msg,
+                    "self",
+                    &conversation_id,
+                    self_aci,
+                    msg_timestamp,
+                    timestamp,
+                );
             }
         }
 
         tracing::debug!("Received sync message without sent content");
-        None
+        Vec::new()
     }
 
     /// Disconnect from Signal servers
@@ -977,166 +2617,618 @@ impl SignalManager {
         Ok(())
     }
 
-    pub async fn send_message(
-        &self,
-        recipient: &str,
-        text: &str,
-    ) -> Result<String, SignalError> {
-        let message_id = Uuid::new_v4().to_string();
-        
-        let recipient_uuid = Uuid::parse_str(recipient)
-            .map_err(|e| SignalError::SendFailed(format!("Invalid recipient UUID: {}", e)))?;
-        
-        let event_tx = self.event_tx.clone();
-        let text = text.to_string();
-        let msg_id = message_id.clone();
-        
-        std::thread::spawn(move || {
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .expect("Failed to create runtime for sending");
-            
-            rt.block_on(async move {
-                match Self::send_via_channel(SendCommand::DirectMessage {
-                    recipient: recipient_uuid,
-                    text,
-                    reply: oneshot::channel().0,
-                }).await {
-                    Ok(()) => {
-                        tracing::info!("Message {} sent successfully", msg_id);
-                        send_event!(event_tx, SignalEvent::MessageSent { message_id: msg_id });
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to send message {}: {}", msg_id, e);
-                        send_event!(event_tx, SignalEvent::Error(format!("Send failed: {}", e)));
-                    }
+    /// React to a message, or take back our reaction with `remove`.
+    ///
+    /// The target is addressed by its author and sent timestamp. Once sent,
+    /// the reaction is applied to the local copy as well.
+    pub async fn send_reaction(
+        storage: &Arc<Storage>,
+        message_id: &str,
+        emoji: &str,
+        remove: bool,
+    ) -> Result<(), SignalError> {
+        let (target, target_author, target_timestamp) = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+
+            let message = MessageRepository::new(&db)
+                .get(message_id)
+                .ok_or_else(|| SignalError::SendFailed(format!("Unknown message {}", message_id)))?;
+            let target_timestamp = message.signal_timestamp.ok_or_else(|| {
+                SignalError::SendFailed("Message has no sent timestamp to react to".to_string())
+            })?;
+            if message.is_deleted() {
+                return Err(SignalError::SendFailed("Cannot react to a deleted message".to_string()));
+            }
+
+            let target_author = match message.direction {
+                MessageDirection::Outgoing => None,
+                MessageDirection::Incoming => Some(
+                    Uuid::parse_str(&Self::normalize_service_id(&message.sender)).map_err(|e| {
+                        SignalError::SendFailed(format!("Invalid author {}: {}", message.sender, e))
+                    })?,
+                ),
+            };
+
+            let is_group = ConversationRepository::new(&db)
+                .get(&message.conversation_id)
+                .is_some_and(|c| c.conversation_type == ConversationType::Group);
+            let target = SendTarget::from_conversation_id(&message.conversation_id, is_group)?;
+            (target, target_author, target_timestamp)
+        };
+
+        Self::send_via_channel(SendCommand::Reaction {
+            target,
+            emoji: emoji.to_string(),
+            remove,
+            target_author,
+            target_timestamp,
+            timestamp: Self::now_millis(),
+            reply: oneshot::channel().0,
+        }).await?;
+
+        let db = storage
+            .database()
+            .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+        MessageRepository::new(&db)
+            .apply_reaction(message_id, emoji, "self", remove)
+            .map_err(|e| SignalError::StorageError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Send read receipts for incoming messages the user has seen (if
+    /// enabled in settings, and never for message requests) and sync their
+    /// read state to our other devices
+    pub async fn mark_read(storage: &Arc<Storage>, messages: &[Message]) -> Result<(), SignalError> {
+        let reads: Vec<(Uuid, u64)> = messages
+            .iter()
+            .filter(|m| m.direction == MessageDirection::Incoming && m.sender != "self")
+            .filter_map(|m| {
+                let sender = Uuid::parse_str(&Self::normalize_service_id(&m.sender)).ok()?;
+                Some((sender, m.signal_timestamp?))
+            })
+            .collect();
+        if reads.is_empty() {
+            return Ok(());
+        }
+
+        let send_receipts = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+            let conv_repo = ConversationRepository::new(&db);
+            SettingsRepository::new(&db).get().read_receipts
+                && messages
+                    .iter()
+                    .all(|m| conv_repo.get(&m.conversation_id).is_some_and(|c| c.is_accepted))
+        };
+
+        tracing::info!("Marking {} messages as read", reads.len());
+        Self::send_via_channel(SendCommand::ReadReceipts {
+            reads,
+            send_receipts,
+            timestamp: Self::now_millis(),
+            reply: oneshot::channel().0,
+        })
+        .await
+    }
+
+    /// Tell a conversation we started or stopped typing. Does nothing when
+    /// typing indicators are turned off, for Note to Self or for message
+    /// requests.
+    pub async fn send_typing(storage: &Arc<Storage>, conversation_id: &str, started: bool) -> Result<(), SignalError> {
+        let target = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+            if !SettingsRepository::new(&db).get().typing_indicators {
+                return Ok(());
+            }
+
+            let conversation_type = ConversationRepository::new(&db)
+                .get(conversation_id)
+                .filter(|c| c.is_accepted)
+                .map(|c| c.conversation_type);
+            match conversation_type {
+                Some(ConversationType::NoteToSelf) | None => return Ok(()),
+                Some(conversation_type) => {
+                    SendTarget::from_conversation_id(conversation_id, conversation_type == ConversationType::Group)?
                 }
-            });
-        });
-        
-        Ok(message_id)
+            }
+        };
+
+        Self::send_via_channel(SendCommand::Typing {
+            target,
+            started,
+            timestamp: Self::now_millis(),
+            reply: oneshot::channel().0,
+        })
+        .await
     }
 
-    pub async fn send_group_message(
-        &self,
-        group_id: &str,
-        text: &str,
-    ) -> Result<String, SignalError> {
-        let message_id = Uuid::new_v4().to_string();
-        
-        let master_key = base64::Engine::decode(
-            &base64::engine::general_purpose::STANDARD,
-            group_id,
-        ).map_err(|e| SignalError::SendFailed(format!("Invalid group ID: {}", e)))?;
-        
-        let event_tx = self.event_tx.clone();
-        let text = text.to_string();
-        let msg_id = message_id.clone();
-        
-        std::thread::spawn(move || {
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .expect("Failed to create runtime for sending");
-            
-            rt.block_on(async move {
-                match Self::send_via_channel(SendCommand::GroupMessage {
-                    group_key: master_key,
-                    text,
-                    reply: oneshot::channel().0,
-                }).await {
-                    Ok(()) => {
-                        tracing::info!("Group message {} sent successfully", msg_id);
-                        send_event!(event_tx, SignalEvent::MessageSent { message_id: msg_id });
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to send group message {}: {}", msg_id, e);
-                        send_event!(event_tx, SignalEvent::Error(format!("Send failed: {}", e)));
-                    }
+    /// Change the disappearing message timer of a direct conversation and
+    /// record the change locally once it went out. Note to Self changes only
+    /// locally; group timers are part of the group state and can't be set here.
+    pub async fn set_expiration_timer(
+        storage: &Arc<Storage>,
+        conversation_id: &str,
+        seconds: u32,
+    ) -> Result<(), SignalError> {
+        let target = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+            let conv = ConversationRepository::new(&db)
+                .get(conversation_id)
+                .ok_or_else(|| SignalError::SendFailed(format!("Unknown conversation {}", conversation_id)))?;
+            if conv.disappearing_messages_timer == seconds {
+                return Ok(());
+            }
+
+            match conv.conversation_type {
+                ConversationType::Group => {
+                    return Err(SignalError::SendFailed(
+                        "The disappearing message timer of a group can only be changed by updating the group"
+                            .to_string(),
+                    ));
                 }
-            });
-        });
-        
-        Ok(message_id)
+                ConversationType::NoteToSelf => None,
+                ConversationType::Private => Some(SendTarget::from_conversation_id(conversation_id, false)?),
+            }
+        };
+
+        let timestamp = Self::now_millis();
+        if let Some(target) = target {
+            Self::send_via_channel(SendCommand::ExpirationTimer {
+                target,
+                seconds,
+                timestamp,
+                reply: oneshot::channel().0,
+            })
+            .await?;
+        }
+
+        let db = storage
+            .database()
+            .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+        Self::apply_expiration_timer(&db, conversation_id, "self", seconds, timestamp)
+            .map_err(|e| SignalError::StorageError(e.to_string()))?;
+        Ok(())
     }
 
-    /// Send a reaction
-    pub async fn send_reaction(
-        &self,
-        _conversation_id: &str,
-        message_id: &str,
-        emoji: &str,
-        remove: bool,
+    /// Set a conversation's disappearing message timer and add a notice about
+    /// the change, attributed to `author` (`"self"` for us). Returns false if
+    /// the timer was already set to `seconds`.
+    pub fn apply_expiration_timer(
+        db: &Database,
+        conversation_id: &str,
+        author: &str,
+        seconds: u32,
+        timestamp: u64,
+    ) -> anyhow::Result<bool> {
+        let conv_repo = ConversationRepository::new(db);
+        let Some(mut conv) = conv_repo.get(conversation_id) else {
+            return Ok(false);
+        };
+        if conv.disappearing_messages_timer == seconds {
+            return Ok(false);
+        }
+
+        let now = Utc::now();
+        let sent_at = Utc.timestamp_millis_opt(timestamp as i64).single().unwrap_or(now);
+        let ours = author == "self";
+        let notice = Message {
+            id: Uuid::new_v4().to_string(),
+            conversation_id: conversation_id.to_string(),
+            sender: author.to_string(),
+            direction: if ours { MessageDirection::Outgoing } else { MessageDirection::Incoming },
+            status: if ours { MessageStatus::Sent } else { MessageStatus::Read },
+            content: StoredContent::ExpirationTimerUpdate { expires_in_seconds: seconds },
+            sent_at,
+            server_timestamp: None,
+            delivered_at: None,
+            // Notices never count as unread
+            read_at: Some(now),
+            quote: None,
+            reactions: Vec::new(),
+            expires_in_seconds: None,
+            expires_at: None,
+            signal_timestamp: Some(timestamp),
+            edited_at: None,
+        };
+        MessageRepository::new(db).save(&notice)?;
+
+        conv.disappearing_messages_timer = seconds;
+        conv.update_last_message(&expiration_timer_text(seconds), sent_at);
+        conv_repo.save(&conv)?;
+
+        tracing::info!("Disappearing message timer of {} set to {}s by {}", conversation_id, seconds, author);
+        Ok(true)
+    }
+
+    /// Whether the receive loop is running and can take commands
+    pub fn receive_loop_running() -> bool {
+        SEND_TX.lock().is_some()
+    }
+
+    /// Our ACI, once connected
+    pub fn self_aci() -> Option<String> {
+        SELF_ACI.lock().clone()
+    }
+
+    /// Fetch the profiles of the given contacts again, e.g. when they went stale
+    pub async fn refresh_profiles(uuids: Vec<String>) -> Result<(), SignalError> {
+        Self::send_via_channel(SendCommand::RefreshProfiles {
+            uuids,
+            reply: oneshot::channel().0,
+        })
+        .await
+    }
+
+    async fn refresh_profiles_with_manager(
+        manager: &mut Manager<SqliteStore, Registered>,
+        storage: &Arc<Storage>,
+        event_tx: &mpsc::UnboundedSender<SignalEvent>,
+        uuids: &[String],
     ) -> Result<(), SignalError> {
-        tracing::info!(
-            "Sending reaction {} to message {} (remove: {})",
-            emoji,
-            message_id,
-            remove
-        );
+        let mut updated = 0;
+        for uuid in uuids {
+            match crate::signal::profiles::refresh_contact_avatar(manager, storage, uuid).await {
+                Ok(true) => updated += 1,
+                Ok(false) => {}
+                Err(e) => tracing::debug!("Failed to refresh profile of {}: {}", uuid, e),
+            }
+        }
 
+        if updated > 0 {
+            crate::signal::profiles::update_conversations_from_contacts(storage)?;
+            send_event!(event_tx, SignalEvent::ContactUpdated { contact_id: "avatars".to_string() });
+        }
         Ok(())
     }
 
-    /// Mark messages as read
-    pub async fn mark_read(
-        &self,
-        _conversation_id: &str,
-        message_ids: &[String],
+    /// Answer a message request. The answer takes effect locally right away
+    /// and is then synced to our other devices, with our blocked list when
+    /// it blocks.
+    pub async fn respond_to_message_request(
+        storage: &Arc<Storage>,
+        conversation_id: &str,
+        action: MessageRequestAction,
     ) -> Result<(), SignalError> {
-        tracing::info!("Marking {} messages as read", message_ids.len());
+        let syncs = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+            let response = requests::response_for(&db, conversation_id, action);
+            requests::apply_action(&db, conversation_id, action)
+                .map_err(|e| SignalError::StorageError(e.to_string()))?;
+
+            let mut syncs: Vec<SyncMessage> = response
+                .map(|response| SyncMessage {
+                    message_request_response: Some(response),
+                    ..Default::default()
+                })
+                .into_iter()
+                .collect();
+            if action.blocks() {
+                syncs.push(SyncMessage {
+                    blocked: Some(requests::blocked_list(&db)),
+                    ..Default::default()
+                });
+            }
+            syncs
+        };
+
+        for sync in syncs {
+            Self::send_via_channel(SendCommand::Sync {
+                sync: Box::new(sync),
+                reply: oneshot::channel().0,
+            })
+            .await?;
+        }
         Ok(())
     }
 
-    /// Send typing indicator
-    pub async fn send_typing(&self, _conversation_id: &str, _is_typing: bool) -> Result<(), SignalError> {
+    /// Block or unblock the contact or group of a conversation and sync our
+    /// blocked list to our other devices
+    pub async fn set_blocked(storage: &Arc<Storage>, conversation_id: &str, blocked: bool) -> Result<(), SignalError> {
+        let sync = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+            requests::set_blocked(&db, conversation_id, blocked)
+                .map_err(|e| SignalError::StorageError(e.to_string()))?;
+            SyncMessage {
+                blocked: Some(requests::blocked_list(&db)),
+                ..Default::default()
+            }
+        };
+
+        tracing::info!("{} {}", if blocked { "Blocked" } else { "Unblocked" }, conversation_id);
+        Self::send_via_channel(SendCommand::Sync {
+            sync: Box::new(sync),
+            reply: oneshot::channel().0,
+        })
+        .await
+    }
+
+    /// Our safety number with a contact. Needs the receive loop to have loaded
+    /// our identity key and a key recorded for the contact.
+    pub fn safety_number(storage: &Arc<Storage>, uuid: &str) -> Result<SafetyNumber, SignalError> {
+        let self_aci = Self::self_aci()
+            .ok_or_else(|| SignalError::ProtocolError("Not connected".to_string()))?;
+        let self_key = SELF_IDENTITY
+            .lock()
+            .clone()
+            .ok_or_else(|| SignalError::ProtocolError("Our identity key isn't loaded".to_string()))?;
+        let their_key = storage
+            .database()
+            .and_then(|db| IdentityRepository::new(&db).get(uuid))
+            .ok_or_else(|| SignalError::ProtocolError("No identity key for this contact yet".to_string()))?;
+
+        SafetyNumber::new(&self_aci, &self_key, uuid, &their_key).map_err(|e| SignalError::ProtocolError(e.to_string()))
+    }
+
+    /// Mark a contact's safety number as verified, or clear that, and tell our
+    /// other devices
+    pub async fn set_verified(storage: &Arc<Storage>, uuid: &str, verified: bool) -> Result<(), SignalError> {
+        let sync = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+            let identity_key = IdentityRepository::new(&db)
+                .get(uuid)
+                .ok_or_else(|| SignalError::ProtocolError("No identity key for this contact yet".to_string()))?;
+            safety::set_verified(&db, uuid, verified).map_err(|e| SignalError::StorageError(e.to_string()))?;
+            SyncMessage {
+                verified: Some(safety::verified_sync(uuid, &identity_key, verified)),
+                ..Default::default()
+            }
+        };
+
+        tracing::info!("{} safety number with {}", if verified { "Verified" } else { "Unverified" }, uuid);
+        Self::send_via_channel(SendCommand::Sync {
+            sync: Box::new(sync),
+            reply: oneshot::channel().0,
+        })
+        .await
+    }
+
+    /// Accept a contact's changed safety number without verifying it, so
+    /// messages can be sent to them again
+    pub fn accept_identity_change(storage: &Arc<Storage>, uuid: &str) -> Result<(), SignalError> {
+        let db = storage
+            .database()
+            .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+        safety::accept_identity_change(&db, uuid).map_err(|e| SignalError::StorageError(e.to_string()))
+    }
+
+    /// Queue a stored outgoing message. The send loop sends it after earlier
+    /// messages of its conversation, retries it with backoff while that fails
+    /// and picks it up again after a restart.
+    pub fn queue_message(
+        storage: &Arc<Storage>,
+        message_id: &str,
+        conversation_id: &str,
+        voice_note: bool,
+    ) -> Result<(), SignalError> {
+        let db = storage
+            .database()
+            .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+        outbox::queue(&db, message_id, conversation_id, voice_note)
+            .map_err(|e| SignalError::StorageError(e.to_string()))?;
+        OUTBOX_READY.notify_one();
         Ok(())
     }
 
-    pub async fn request_sync(&self) -> Result<(), SignalError> {
-        tracing::info!("Requesting sync from primary device...");
+    /// Send a failed message again
+    pub fn retry_message(storage: &Arc<Storage>, message_id: &str) -> Result<(), SignalError> {
+        let db = storage
+            .database()
+            .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+        outbox::retry(&db, message_id).map_err(|e| SignalError::SendFailed(e.to_string()))?;
+        OUTBOX_READY.notify_one();
         Ok(())
     }
-    
-    pub async fn send_message_static(
-        _storage: &Arc<Storage>,
-        recipient: &str,
-        text: &str,
+
+    /// Delete a message that couldn't be sent
+    pub fn discard_message(storage: &Arc<Storage>, message_id: &str) -> Result<(), SignalError> {
+        let db = storage
+            .database()
+            .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+        outbox::discard(&db, message_id).map_err(|e| SignalError::StorageError(e.to_string()))
+    }
+
+    /// Download and install a sticker pack, and tell our other devices
+    pub async fn install_sticker_pack(storage: &Arc<Storage>, pack_id: &str, pack_key: &str) -> Result<(), SignalError> {
+        stickers::download_pack(storage, pack_id, pack_key).await?;
+        Self::set_sticker_pack_installed(storage, pack_id, pack_key, true).await
+    }
+
+    /// Remove a sticker pack from the picker, and tell our other devices. Its
+    /// images stay cached for messages that show them.
+    pub async fn remove_sticker_pack(storage: &Arc<Storage>, pack_id: &str, pack_key: &str) -> Result<(), SignalError> {
+        Self::set_sticker_pack_installed(storage, pack_id, pack_key, false).await
+    }
+
+    async fn set_sticker_pack_installed(
+        storage: &Arc<Storage>,
+        pack_id: &str,
+        pack_key: &str,
+        installed: bool,
     ) -> Result<(), SignalError> {
-        let recipient_uuid = Uuid::parse_str(recipient)
-            .map_err(|e| SignalError::SendFailed(format!("Invalid recipient UUID: {}", e)))?;
-        
-        Self::send_via_channel(SendCommand::DirectMessage {
-            recipient: recipient_uuid,
-            text: text.to_string(),
+        let sync = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+            let operation = stickers::pack_operation(pack_id, pack_key, installed)?;
+            StickerRepository::new(&db)
+                .set_installed(pack_id, pack_key, installed)
+                .map_err(|e| SignalError::StorageError(e.to_string()))?;
+            SyncMessage {
+                sticker_pack_operation: vec![operation],
+                ..Default::default()
+            }
+        };
+
+        tracing::info!("{} sticker pack {}", if installed { "Installed" } else { "Removed" }, pack_id);
+        Self::send_via_channel(SendCommand::Sync {
+            sync: Box::new(sync),
+            reply: oneshot::channel().0,
+        })
+        .await
+    }
+
+    /// Ask the primary device for its contacts, blocked list, configuration
+    /// or keys. The answers arrive later as `SignalEvent::SyncResponse`.
+    pub async fn request_sync(kinds: &[SyncRequestKind]) -> Result<(), SignalError> {
+        tracing::info!("Requesting sync from primary device: {:?}", kinds);
+        Self::send_via_channel(SendCommand::SyncRequest {
+            kinds: kinds.to_vec(),
             reply: oneshot::channel().0,
-        }).await
+        })
+        .await
     }
     
-    pub async fn send_group_message_static(
-        _storage: &Arc<Storage>,
-        group_id: &str,
+    /// Edit the text of one of our sent messages.
+    ///
+    /// Only messages within Signal's edit window and edit limit can be edited.
+    /// The local copy is updated (keeping its history) once the edit was sent.
+    pub async fn edit_message(
+        storage: &Arc<Storage>,
+        message_id: &str,
         text: &str,
     ) -> Result<(), SignalError> {
-        let master_key = base64::Engine::decode(
-            &base64::engine::general_purpose::STANDARD,
-            group_id,
-        ).map_err(|e| SignalError::SendFailed(format!("Invalid group ID: {}", e)))?;
-        
-        Self::send_via_channel(SendCommand::GroupMessage {
-            group_key: master_key,
+        let (target, target_timestamp) = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+            let repo = MessageRepository::new(&db);
+
+            let message = repo
+                .get(message_id)
+                .ok_or_else(|| SignalError::SendFailed(format!("Unknown message {}", message_id)))?;
+            if !message.is_editable(Utc::now()) {
+                return Err(SignalError::SendFailed("This message can no longer be edited".to_string()));
+            }
+            if repo.edit_count(message_id) >= MAX_EDITS {
+                return Err(SignalError::SendFailed(format!(
+                    "Messages can be edited at most {} times",
+                    MAX_EDITS
+                )));
+            }
+
+            let is_group = ConversationRepository::new(&db)
+                .get(&message.conversation_id)
+                .is_some_and(|c| c.conversation_type == ConversationType::Group);
+            let target = SendTarget::from_conversation_id(&message.conversation_id, is_group)?;
+            // is_editable() guarantees the timestamp is known
+            (target, message.signal_timestamp.unwrap_or_default())
+        };
+
+        Self::send_via_channel(SendCommand::Edit {
+            target,
+            target_timestamp,
             text: text.to_string(),
+            timestamp: Self::now_millis(),
             reply: oneshot::channel().0,
-        }).await
+        }).await?;
+
+        let db = storage
+            .database()
+            .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+        MessageRepository::new(&db)
+            .apply_edit(message_id, text, Utc::now())
+            .map_err(|e| SignalError::StorageError(e.to_string()))?;
+        Ok(())
     }
-    
+
+    /// Delete one of our sent messages for everyone.
+    ///
+    /// Only allowed within `DELETE_FOR_EVERYONE_SECS` of sending. Once the
+    /// delete went out, the local copy becomes a tombstone as well.
+    pub async fn delete_for_everyone(
+        storage: &Arc<Storage>,
+        message_id: &str,
+    ) -> Result<(), SignalError> {
+        let (target, target_timestamp) = {
+            let db = storage
+                .database()
+                .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+
+            let message = MessageRepository::new(&db)
+                .get(message_id)
+                .ok_or_else(|| SignalError::SendFailed(format!("Unknown message {}", message_id)))?;
+            if !message.can_delete_for_everyone(Utc::now()) {
+                return Err(SignalError::SendFailed(format!(
+                    "Messages can only be deleted for everyone within {} hours of sending",
+                    DELETE_FOR_EVERYONE_SECS / 3600
+                )));
+            }
+
+            let is_group = ConversationRepository::new(&db)
+                .get(&message.conversation_id)
+                .is_some_and(|c| c.conversation_type == ConversationType::Group);
+            let target = SendTarget::from_conversation_id(&message.conversation_id, is_group)?;
+            (target, message.signal_timestamp.unwrap_or_default())
+        };
+
+        Self::send_via_channel(SendCommand::Delete {
+            target,
+            target_timestamp,
+            timestamp: Self::now_millis(),
+            reply: oneshot::channel().0,
+        }).await?;
+
+        let db = storage
+            .database()
+            .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+        Self::tombstone_message(&db, storage.attachments_dir(), message_id)
+            .map_err(|e| SignalError::StorageError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Turn a message into a "deleted" tombstone, remove its downloaded
+    /// attachments and update the conversation preview if it was the latest.
+    pub fn tombstone_message(
+        db: &Database,
+        attachments_dir: &Path,
+        message_id: &str,
+    ) -> anyhow::Result<Option<Message>> {
+        let message_repo = MessageRepository::new(db);
+        let Some(previous) = message_repo.tombstone(message_id)? else {
+            return Ok(None);
+        };
+
+        AttachmentManager::new(attachments_dir.to_path_buf()).remove_local(&previous.attachment_ids());
+
+        let is_latest = message_repo
+            .get_latest(&previous.conversation_id)
+            .is_some_and(|latest| latest.id == previous.id);
+        let conv_repo = ConversationRepository::new(db);
+        if let (true, Some(mut conv)) = (is_latest, conv_repo.get(&previous.conversation_id)) {
+            conv.update_last_message(DELETED_MESSAGE_TEXT, previous.sent_at);
+            conv_repo.save(&conv)?;
+        }
+
+        tracing::info!("Deleted message {} for everyone", message_id);
+        Ok(Some(previous))
+    }
+
     async fn send_via_channel(mut cmd: SendCommand) -> Result<(), SignalError> {
         let (tx, rx) = oneshot::channel();
         
         match &mut cmd {
-            SendCommand::DirectMessage { reply, .. } => *reply = tx,
-            SendCommand::GroupMessage { reply, .. } => *reply = tx,
+            SendCommand::Edit { reply, .. } => *reply = tx,
+            SendCommand::Delete { reply, .. } => *reply = tx,
+            SendCommand::Reaction { reply, .. } => *reply = tx,
+            SendCommand::ReadReceipts { reply, .. } => *reply = tx,
+            SendCommand::Typing { reply, .. } => *reply = tx,
+            SendCommand::ExpirationTimer { reply, .. } => *reply = tx,
+            SendCommand::SyncRequest { reply, .. } => *reply = tx,
+            SendCommand::RefreshProfiles { reply, .. } => *reply = tx,
+            SendCommand::Sync { reply, .. } => *reply = tx,
         }
         
         let send_tx = {
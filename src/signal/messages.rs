@@ -3,6 +3,64 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// How long after sending a message can still be edited, in seconds
+pub const EDIT_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// Maximum number of edits Signal clients accept for one message
+pub const MAX_EDITS: usize = 10;
+
+/// How long after sending a message can still be deleted for everyone, in seconds
+pub const DELETE_FOR_EVERYONE_SECS: i64 = 24 * 60 * 60;
+
+/// Remote deletes arriving later than this after the message are ignored
+pub const REMOTE_DELETE_RECEIVE_SECS: i64 = 48 * 60 * 60;
+
+/// Shown in place of a message that was deleted for everyone
+pub const DELETED_MESSAGE_TEXT: &str = "This message was deleted";
+
+/// Disappearing message timers offered in the composer, in seconds (0 = off)
+pub const EXPIRATION_TIMER_OPTIONS: [u32; 8] = [
+    0,
+    30,
+    5 * 60,
+    60 * 60,
+    8 * 60 * 60,
+    24 * 60 * 60,
+    7 * 24 * 60 * 60,
+    28 * 24 * 60 * 60,
+];
+
+/// Human readable disappearing message timer, e.g. "5 minutes" or "1 week"
+pub fn format_expiration_timer(seconds: u32) -> String {
+    const UNITS: [(u32, &str); 5] = [
+        (7 * 24 * 60 * 60, "week"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ];
+
+    if seconds == 0 {
+        return "Off".to_string();
+    }
+    let (size, unit) = UNITS
+        .iter()
+        .find(|(size, _)| seconds % size == 0)
+        .copied()
+        .unwrap_or((1, "second"));
+    let count = seconds / size;
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// Preview and notice text for a disappearing message timer change
+pub fn expiration_timer_text(seconds: u32) -> String {
+    if seconds == 0 {
+        "Disappearing messages turned off".to_string()
+    } else {
+        format!("Disappearing message time set to {}", format_expiration_timer(seconds))
+    }
+}
+
 /// Message direction
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum MessageDirection {
@@ -69,6 +127,13 @@ pub struct Message {
 
     /// When this message expires
     pub expires_at: Option<DateTime<Utc>>,
+
+    /// Sender's timestamp in milliseconds. Together with the author this
+    /// identifies the message in edits, deletes, reactions and quotes.
+    pub signal_timestamp: Option<u64>,
+
+    /// When the message was last edited
+    pub edited_at: Option<DateTime<Utc>>,
 }
 
 /// Message content types
@@ -157,10 +222,24 @@ pub enum Content {
 
     /// End session message
     EndSession,
+
+    /// Tombstone of a message its author deleted for everyone
+    Deleted,
+
+    /// The sender changed the disappearing message timer (0 = off)
+    ExpirationTimerUpdate {
+        expires_in_seconds: u32,
+    },
+
+    /// A contact's safety number changed or was (un)verified
+    IdentityUpdate {
+        update_type: IdentityUpdateType,
+        details: String,
+    },
 }
 
 /// Group update types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum GroupUpdateType {
     Created,
     NameChanged,
@@ -174,6 +253,14 @@ pub enum GroupUpdateType {
     DisappearingMessagesChanged,
 }
 
+/// Identity update types
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum IdentityUpdateType {
+    SafetyNumberChanged,
+    Verified,
+    Unverified,
+}
+
 /// A mention in a message
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Mention {
@@ -188,9 +275,12 @@ pub struct Mention {
 /// A quoted message
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Quote {
-    /// ID of the quoted message
+    /// ID of the quoted message (empty if it isn't stored locally)
     pub message_id: String,
-    /// Author of the quoted message
+    /// Sent timestamp of the quoted message, which identifies it on the wire
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Author of the quoted message (`"self"` for our own)
     pub author: String,
     /// Text preview of the quoted message
     pub text: Option<String>,
@@ -220,6 +310,7 @@ pub struct Reaction {
 impl Message {
     /// Create a new outgoing text message
     pub fn new_text(conversation_id: &str, sender: &str, body: &str) -> Self {
+        let sent_at = Utc::now();
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             conversation_id: conversation_id.to_string(),
@@ -230,7 +321,7 @@ impl Message {
                 body: body.to_string(),
                 mentions: Vec::new(),
             },
-            sent_at: Utc::now(),
+            sent_at,
             server_timestamp: None,
             delivered_at: None,
             read_at: None,
@@ -238,6 +329,8 @@ impl Message {
             reactions: Vec::new(),
             expires_in_seconds: None,
             expires_at: None,
+            signal_timestamp: Some(sent_at.timestamp_millis() as u64),
+            edited_at: None,
         }
     }
 
@@ -252,6 +345,71 @@ impl Message {
         )
     }
 
+    /// IDs of the local files belonging to this message (attachment and thumbnail)
+    pub fn attachment_ids(&self) -> Vec<&str> {
+        match &self.content {
+            Content::Image { attachment_id, .. }
+            | Content::Audio { attachment_id, .. }
+            | Content::File { attachment_id, .. } => vec![attachment_id.as_str()],
+            Content::Video { attachment_id, thumbnail_id, .. } => std::iter::once(attachment_id.as_str())
+                .chain(thumbnail_id.as_deref())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Start the disappearing countdown at `from`, unless it is already running
+    pub fn start_expiry(&mut self, from: DateTime<Utc>) {
+        if let (Some(seconds), None) = (self.expires_in_seconds.filter(|s| *s > 0), self.expires_at) {
+            self.expires_at = Some(from + chrono::Duration::seconds(seconds as i64));
+        }
+    }
+
+    /// Whether this message was deleted for everyone
+    pub fn is_deleted(&self) -> bool {
+        matches!(self.content, Content::Deleted)
+    }
+
+    /// Quote of this message for a reply to it
+    pub fn to_quote(&self) -> Quote {
+        let author = match self.direction {
+            MessageDirection::Outgoing => "self".to_string(),
+            MessageDirection::Incoming => self.sender.clone(),
+        };
+
+        let attachment_preview = match &self.content {
+            Content::Image { attachment_id, content_type, .. } => Some(AttachmentPreview {
+                content_type: content_type.clone(),
+                filename: None,
+                thumbnail_id: Some(attachment_id.clone()),
+            }),
+            Content::Video { content_type, thumbnail_id, .. } => Some(AttachmentPreview {
+                content_type: content_type.clone(),
+                filename: None,
+                thumbnail_id: thumbnail_id.clone(),
+            }),
+            Content::Audio { content_type, .. } => Some(AttachmentPreview {
+                content_type: content_type.clone(),
+                filename: None,
+                thumbnail_id: None,
+            }),
+            Content::File { content_type, filename, .. } => Some(AttachmentPreview {
+                content_type: content_type.clone(),
+                filename: Some(filename.clone()),
+                thumbnail_id: None,
+            }),
+            _ => None,
+        };
+
+        Quote {
+            message_id: self.id.clone(),
+            timestamp: self.signal_timestamp,
+            author,
+            text: self.text().map(str::to_string),
+            attachment_preview,
+        }
+    }
+
     /// Get text content if available
     pub fn text(&self) -> Option<&str> {
         match &self.content {
@@ -261,6 +419,74 @@ impl Message {
         }
     }
 
+    /// Text shown for this message in the conversation list
+    pub fn preview_text(&self) -> String {
+        match &self.content {
+            Content::Text { body, .. } => body.clone(),
+            Content::Image { caption: Some(caption), .. } | Content::Video { caption: Some(caption), .. } => {
+                caption.clone()
+            }
+            Content::Image { .. } => "[Image]".to_string(),
+            Content::Video { .. } => "[Video]".to_string(),
+            Content::Audio { .. } => "[Voice message]".to_string(),
+            Content::File { filename, .. } => format!("[File: {}]", filename),
+            Content::Sticker { .. } => "[Sticker]".to_string(),
+            Content::Contact { name, .. } => format!("[Contact: {}]", name),
+            Content::Location { .. } => "[Location]".to_string(),
+            Content::GroupUpdate { details, .. } | Content::IdentityUpdate { details, .. } => details.clone(),
+            Content::ProfileKeyUpdate | Content::EndSession => String::new(),
+            Content::Deleted => DELETED_MESSAGE_TEXT.to_string(),
+            Content::ExpirationTimerUpdate { expires_in_seconds } => expiration_timer_text(*expires_in_seconds),
+        }
+    }
+
+    /// Replace the text of a text message or the caption of an image/video.
+    ///
+    /// Returns false if the content has no editable text.
+    pub fn set_text(&mut self, text: &str) -> bool {
+        match &mut self.content {
+            Content::Text { body, mentions } => {
+                *body = text.to_string();
+                mentions.clear();
+                true
+            }
+            Content::Image { caption, .. } | Content::Video { caption, .. } => {
+                *caption = Some(text.to_string()).filter(|t| !t.is_empty());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether we can still edit this message: our own text message, already
+    /// sent and within `EDIT_WINDOW_SECS`. Attachment captions are not edited
+    /// from here because the edit would have to resend the attachment pointers.
+    pub fn is_editable(&self, now: DateTime<Utc>) -> bool {
+        self.direction == MessageDirection::Outgoing
+            && self.signal_timestamp.is_some()
+            && !matches!(self.status, MessageStatus::Sending | MessageStatus::Failed)
+            && matches!(self.content, Content::Text { .. })
+            && now.signed_duration_since(self.sent_at).num_seconds() < EDIT_WINDOW_SECS
+    }
+
+    /// Whether a reaction to this message can be sent: it needs the sent
+    /// timestamp that identifies it, and must not be deleted or unsent
+    pub fn is_reactable(&self) -> bool {
+        self.signal_timestamp.is_some()
+            && !self.is_deleted()
+            && !matches!(self.status, MessageStatus::Sending | MessageStatus::Failed)
+    }
+
+    /// Whether we can still delete this message for everyone: our own message,
+    /// already sent and within `DELETE_FOR_EVERYONE_SECS`
+    pub fn can_delete_for_everyone(&self, now: DateTime<Utc>) -> bool {
+        self.direction == MessageDirection::Outgoing
+            && self.signal_timestamp.is_some()
+            && !self.is_deleted()
+            && !matches!(self.status, MessageStatus::Sending | MessageStatus::Failed)
+            && now.signed_duration_since(self.sent_at).num_seconds() < DELETE_FOR_EVERYONE_SECS
+    }
+
     /// Add a reaction
     pub fn add_reaction(&mut self, emoji: &str, sender: &str) {
         // Remove existing reaction from same sender
@@ -278,3 +504,115 @@ impl Message {
         self.reactions.retain(|r| r.sender != sender);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_set_text_replaces_body_or_caption() {
+        let mut msg = Message::new_text("c1", "me", "helo");
+        assert!(msg.set_text("hello"));
+        assert_eq!(msg.text(), Some("hello"));
+
+        msg.content = Content::Image {
+            attachment_id: "a.jpg".to_string(),
+            content_type: "image/jpeg".to_string(),
+            width: 0,
+            height: 0,
+            size: 0,
+            caption: Some("old".to_string()),
+            blurhash: None,
+        };
+        assert!(msg.set_text(""));
+        assert_eq!(msg.text(), None);
+
+        msg.content = Content::EndSession;
+        assert!(!msg.set_text("nope"));
+    }
+
+    #[test]
+    fn test_edit_window() {
+        let mut msg = Message::new_text("c1", "me", "hi");
+        msg.status = MessageStatus::Sent;
+        let now = msg.sent_at;
+
+        assert!(msg.is_editable(now + Duration::hours(23)));
+        assert!(!msg.is_editable(now + Duration::seconds(EDIT_WINDOW_SECS)));
+
+        msg.status = MessageStatus::Failed;
+        assert!(!msg.is_editable(now));
+
+        msg.status = MessageStatus::Sent;
+        msg.direction = MessageDirection::Incoming;
+        assert!(!msg.is_editable(now));
+    }
+
+    #[test]
+    fn test_to_quote() {
+        let mut msg = Message::new_text("c1", "+15550001111", "see attached");
+        let quote = msg.to_quote();
+        assert_eq!(quote.message_id, msg.id);
+        assert_eq!(quote.author, "self");
+        assert_eq!(quote.timestamp, msg.signal_timestamp);
+        assert_eq!(quote.text.as_deref(), Some("see attached"));
+        assert!(quote.attachment_preview.is_none());
+
+        msg.direction = MessageDirection::Incoming;
+        msg.sender = "alice".to_string();
+        msg.content = Content::Image {
+            attachment_id: "a.jpg".to_string(),
+            content_type: "image/jpeg".to_string(),
+            width: 0,
+            height: 0,
+            size: 0,
+            caption: None,
+            blurhash: None,
+        };
+        let quote = msg.to_quote();
+        assert_eq!(quote.author, "alice");
+        assert_eq!(quote.text, None);
+        let preview = quote.attachment_preview.unwrap();
+        assert_eq!(preview.thumbnail_id.as_deref(), Some("a.jpg"));
+    }
+
+    #[test]
+    fn test_delete_for_everyone_window() {
+        let mut msg = Message::new_text("c1", "me", "oops");
+        msg.status = MessageStatus::Delivered;
+        let now = msg.sent_at;
+
+        assert!(msg.can_delete_for_everyone(now + Duration::hours(23)));
+        assert!(!msg.can_delete_for_everyone(now + Duration::seconds(DELETE_FOR_EVERYONE_SECS)));
+
+        msg.content = Content::Deleted;
+        assert!(!msg.can_delete_for_everyone(now));
+
+        msg.content = Content::Text { body: "oops".to_string(), mentions: Vec::new() };
+        msg.signal_timestamp = None;
+        assert!(!msg.can_delete_for_everyone(now));
+    }
+
+    #[test]
+    fn test_expiration_timer() {
+        assert_eq!(format_expiration_timer(0), "Off");
+        assert_eq!(format_expiration_timer(30), "30 seconds");
+        assert_eq!(format_expiration_timer(60 * 60), "1 hour");
+        assert_eq!(format_expiration_timer(4 * 7 * 24 * 60 * 60), "4 weeks");
+        assert_eq!(format_expiration_timer(90), "90 seconds");
+
+        let mut msg = Message::new_text("c1", "me", "secret");
+        msg.start_expiry(msg.sent_at);
+        assert!(msg.expires_at.is_none());
+
+        msg.expires_in_seconds = Some(30);
+        let read_at = msg.sent_at + Duration::seconds(5);
+        msg.start_expiry(read_at);
+        assert_eq!(msg.expires_at, Some(read_at + Duration::seconds(30)));
+
+        // A running countdown isn't restarted
+        msg.start_expiry(read_at + Duration::hours(1));
+        assert_eq!(msg.expires_at, Some(read_at + Duration::seconds(30)));
+    }
+}
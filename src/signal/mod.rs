@@ -2,13 +2,18 @@
 
 pub mod manager;
 pub mod messages;
-pub mod contacts;
 pub mod groups;
 pub mod attachments;
+pub mod connection;
 pub mod profiles;
 pub mod provisioning;
 pub mod registration;
+pub mod requests;
+pub mod safety;
+pub mod stickers;
+pub mod outbox;
 pub mod backup;
+pub mod sync;
 
 pub use manager::{ConnectionState, SignalEvent, SignalManager};
 
@@ -0,0 +1,196 @@
+//! Sending through the outbox
+//!
+//! Outgoing messages are written to the outbox together with the message
+//! itself, so they survive restarts and disconnects. The send loop takes due
+//! entries from it and records the outcome here: a sent message leaves the
+//! outbox, a failed attempt is retried with exponential backoff, and after
+//! `MAX_ATTEMPTS` the message is marked failed for the user to retry or
+//! delete.
+
+use crate::signal::messages::{MessageDirection, MessageStatus};
+use crate::storage::conversations::ConversationRepository;
+use crate::storage::database::Database;
+use crate::storage::messages::MessageRepository;
+use crate::storage::outbox::{OutboxEntry, OutboxRepository};
+use anyhow::{bail, Result};
+
+/// Attempts before a message is marked failed
+pub const MAX_ATTEMPTS: u32 = 8;
+const FIRST_RETRY_MS: i64 = 2_000;
+const MAX_RETRY_MS: i64 = 10 * 60 * 1000;
+
+/// Delay before trying again after `attempts` failed attempts
+pub fn retry_delay_ms(attempts: u32) -> i64 {
+    let doublings = attempts.saturating_sub(1).min(20);
+    FIRST_RETRY_MS.saturating_mul(1 << doublings).min(MAX_RETRY_MS)
+}
+
+/// Queue a stored outgoing message for sending
+pub fn queue(db: &Database, message_id: &str, conversation_id: &str, voice_note: bool) -> Result<()> {
+    MessageRepository::new(db).update_status(message_id, MessageStatus::Sending)?;
+    OutboxRepository::new(db).enqueue(message_id, conversation_id, voice_note)
+}
+
+/// The message went out
+pub fn sent(db: &Database, message_id: &str) -> Result<()> {
+    OutboxRepository::new(db).remove(message_id)?;
+    MessageRepository::new(db).mark_sent(message_id)
+}
+
+/// Record a failed attempt at `now` (ms). Returns true when the message ran
+/// out of attempts and is now failed.
+pub fn attempt_failed(db: &Database, entry: &OutboxEntry, error: &str, now: i64) -> Result<bool> {
+    let attempts = entry.attempts + 1;
+    if attempts >= MAX_ATTEMPTS {
+        give_up(db, &entry.message_id, error)?;
+        return Ok(true);
+    }
+
+    let retry_at = now + retry_delay_ms(attempts);
+    OutboxRepository::new(db).reschedule(&entry.message_id, attempts, retry_at, error)?;
+    tracing::warn!(
+        "Sending {} failed (attempt {}), retrying in {}s: {}",
+        entry.message_id,
+        attempts,
+        (retry_at - now) / 1000,
+        error
+    );
+    Ok(false)
+}
+
+/// Stop trying to send a message, e.g. one that can never go out as it is
+pub fn give_up(db: &Database, message_id: &str, error: &str) -> Result<()> {
+    OutboxRepository::new(db).mark_failed(message_id, error)?;
+    MessageRepository::new(db).update_status(message_id, MessageStatus::Failed)?;
+    tracing::error!("Giving up on sending {}: {}", message_id, error);
+    Ok(())
+}
+
+/// Send a failed message again, after any still waiting in its conversation
+pub fn retry(db: &Database, message_id: &str) -> Result<()> {
+    let Some(message) = MessageRepository::new(db).get(message_id) else {
+        bail!("Message {} no longer exists", message_id);
+    };
+    if message.direction != MessageDirection::Outgoing || message.status != MessageStatus::Failed {
+        bail!("Only failed messages can be sent again");
+    }
+
+    let voice_note = OutboxRepository::new(db).get(message_id).is_some_and(|e| e.voice_note);
+    queue(db, message_id, &message.conversation_id, voice_note)
+}
+
+/// Delete a message that couldn't be sent
+pub fn discard(db: &Database, message_id: &str) -> Result<()> {
+    let message_repo = MessageRepository::new(db);
+    let Some(message) = message_repo.get(message_id) else {
+        return OutboxRepository::new(db).remove(message_id);
+    };
+    if message.status != MessageStatus::Failed {
+        bail!("Only failed messages can be deleted from the outbox");
+    }
+
+    OutboxRepository::new(db).remove(message_id)?;
+    message_repo.delete(message_id)?;
+
+    let conv_repo = ConversationRepository::new(db);
+    if let Some(mut conv) = conv_repo.get(&message.conversation_id) {
+        match message_repo.get_latest(&message.conversation_id) {
+            Some(latest) => conv.update_last_message(&latest.preview_text(), latest.sent_at),
+            None => conv.last_message = None,
+        }
+        conv_repo.save(&conv)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::messages::{Content, Message};
+    use crate::storage::conversations::Conversation;
+    use chrono::Utc;
+    use tempfile::{tempdir, TempDir};
+
+    const TEST_KEY: &str = "test-passphrase-123";
+
+    fn create_test_db() -> (Database, TempDir) {
+        let dir = tempdir().unwrap();
+        let db = Database::open_encrypted(&dir.path().join("test.db"), TEST_KEY).unwrap();
+        (db, dir)
+    }
+
+    fn outgoing(db: &Database, id: &str) {
+        let now = Utc::now();
+        MessageRepository::new(db)
+            .save(&Message {
+                id: id.to_string(),
+                conversation_id: "alice".to_string(),
+                sender: "self".to_string(),
+                direction: MessageDirection::Outgoing,
+                status: MessageStatus::Sending,
+                content: Content::Text {
+                    body: id.to_string(),
+                    mentions: Vec::new(),
+                },
+                sent_at: now,
+                server_timestamp: None,
+                delivered_at: None,
+                read_at: None,
+                quote: None,
+                reactions: Vec::new(),
+                expires_in_seconds: None,
+                expires_at: None,
+                signal_timestamp: Some(now.timestamp_millis() as u64),
+                edited_at: None,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off() {
+        assert_eq!(retry_delay_ms(1), 2_000);
+        assert_eq!(retry_delay_ms(2), 4_000);
+        assert_eq!(retry_delay_ms(4), 16_000);
+        assert_eq!(retry_delay_ms(30), MAX_RETRY_MS);
+    }
+
+    #[test]
+    fn test_failed_message_can_be_retried_or_discarded() {
+        let (db, _dir) = create_test_db();
+        let messages = MessageRepository::new(&db);
+        let outbox = OutboxRepository::new(&db);
+        ConversationRepository::new(&db)
+            .save(&Conversation::new_private("alice", "Alice"))
+            .unwrap();
+        outgoing(&db, "m1");
+        queue(&db, "m1", "alice", true).unwrap();
+
+        for attempt in 1..MAX_ATTEMPTS {
+            let entry = outbox.get("m1").unwrap();
+            assert!(!attempt_failed(&db, &entry, "offline", 0).unwrap());
+            assert_eq!(outbox.get("m1").unwrap().attempts, attempt);
+        }
+        assert_eq!(messages.get("m1").unwrap().status, MessageStatus::Sending);
+        let entry = outbox.get("m1").unwrap();
+        assert!(attempt_failed(&db, &entry, "offline", 0).unwrap());
+        assert_eq!(messages.get("m1").unwrap().status, MessageStatus::Failed);
+
+        retry(&db, "m1").unwrap();
+        let entry = outbox.get("m1").unwrap();
+        assert_eq!(entry.attempts, 0);
+        assert!(entry.voice_note);
+        assert_eq!(messages.get("m1").unwrap().status, MessageStatus::Sending);
+        assert!(retry(&db, "m1").is_err());
+
+        sent(&db, "m1").unwrap();
+        assert!(outbox.get("m1").is_none());
+        assert_eq!(messages.get("m1").unwrap().status, MessageStatus::Sent);
+
+        outgoing(&db, "m2");
+        queue(&db, "m2", "alice", false).unwrap();
+        give_up(&db, "m2", "identity changed").unwrap();
+        discard(&db, "m2").unwrap();
+        assert!(messages.get("m2").is_none());
+        assert!(outbox.get("m2").is_none());
+    }
+}
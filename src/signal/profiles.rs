@@ -52,23 +52,10 @@ pub async fn sync_contact_profile_keys(
 
         // Get or create contact
         let mut contact = repo.get_by_uuid(&uuid_str).unwrap_or_else(|| {
-            crate::storage::contacts::StoredContact {
-                id: uuid_str.clone(),
-                uuid: uuid_str.clone(),
-                phone_number: presage_contact.phone_number.as_ref().map(|p| p.to_string()),
-                name: presage_contact.name.clone(),
-                profile_name: if presage_contact.name.is_empty() {
-                    None
-                } else {
-                    Some(presage_contact.name.clone())
-                },
-                avatar_path: None,
-                profile_key: None,
-                is_blocked: false,
-                is_verified: false,
-                created_at: chrono::Utc::now().timestamp(),
-                updated_at: chrono::Utc::now().timestamp(),
-            }
+            let mut contact = crate::storage::contacts::StoredContact::new(&uuid_str, &presage_contact.name);
+            contact.phone_number = presage_contact.phone_number.as_ref().map(|p| p.to_string());
+            contact.profile_name = Some(presage_contact.name.clone()).filter(|n| !n.is_empty());
+            contact
         });
 
         // Update profile key if missing or different
@@ -183,6 +170,7 @@ pub async fn sync_contact_avatars(
                 let mut updated_contact = contact.clone();
                 updated_contact.avatar_path = Some(path.to_string_lossy().to_string());
                 updated_contact.updated_at = chrono::Utc::now().timestamp();
+                updated_contact.profile_fetched_at = Some(updated_contact.updated_at);
 
                 if let Err(e) = repo.save(&updated_contact) {
                     tracing::warn!("Failed to update contact avatar path: {}", e);
@@ -274,9 +262,16 @@ pub async fn refresh_contact_avatar(
             let mut updated_contact = contact.clone();
             updated_contact.avatar_path = Some(path.to_string_lossy().to_string());
             updated_contact.updated_at = chrono::Utc::now().timestamp();
+            updated_contact.profile_fetched_at = Some(updated_contact.updated_at);
             repo.save(&updated_contact)?;
             Ok(true)
         }
-        None => Ok(false),
+        None => {
+            // Remember that we looked, so the profile isn't stale again right away
+            let mut updated_contact = contact.clone();
+            updated_contact.profile_fetched_at = Some(chrono::Utc::now().timestamp());
+            repo.save(&updated_contact)?;
+            Ok(false)
+        }
     }
 }
@@ -0,0 +1,289 @@
+//! Blocking and message requests
+//!
+//! Messages from blocked contacts and groups are dropped before they are
+//! stored. A conversation started by someone who isn't one of our contacts is
+//! a message request: it is listed, but we send no read receipts or typing
+//! indicators until it is accepted. Our other devices learn about both through
+//! `SyncMessage.blocked` and `SyncMessage.messageRequestResponse`.
+
+use crate::signal::manager::group_identifier;
+use crate::storage::contacts::ContactRepository;
+use crate::storage::conversations::{ConversationRepository, ConversationType};
+use crate::storage::database::Database;
+use crate::storage::groups::GroupRepository;
+use crate::storage::messages::MessageRepository;
+use anyhow::{bail, Result};
+use presage::libsignal_service::proto::sync_message::{self, message_request_response};
+
+/// Answer to a message request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageRequestAction {
+    Accept,
+    Delete,
+    Block,
+    BlockAndDelete,
+}
+
+impl MessageRequestAction {
+    fn response_type(self) -> message_request_response::Type {
+        match self {
+            Self::Accept => message_request_response::Type::Accept,
+            Self::Delete => message_request_response::Type::Delete,
+            Self::Block => message_request_response::Type::Block,
+            Self::BlockAndDelete => message_request_response::Type::BlockAndDelete,
+        }
+    }
+
+    /// Spam reports are blocks as far as this device is concerned
+    fn from_response_type(response_type: message_request_response::Type) -> Option<Self> {
+        match response_type {
+            message_request_response::Type::Accept => Some(Self::Accept),
+            message_request_response::Type::Delete => Some(Self::Delete),
+            message_request_response::Type::Block | message_request_response::Type::Spam => Some(Self::Block),
+            message_request_response::Type::BlockAndDelete | message_request_response::Type::BlockAndSpam => {
+                Some(Self::BlockAndDelete)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn blocks(self) -> bool {
+        matches!(self, Self::Block | Self::BlockAndDelete)
+    }
+}
+
+/// Whether a message from `sender` should be dropped. `group` is the group
+/// conversation it was sent to, if any.
+pub fn is_blocked(db: &Database, sender: &str, group: Option<&str>) -> bool {
+    let sender_blocked = ContactRepository::new(db)
+        .get_by_uuid(sender)
+        .is_some_and(|c| c.is_blocked);
+    sender_blocked
+        || group.is_some_and(|id| {
+            ConversationRepository::new(db)
+                .get(id)
+                .is_some_and(|c| c.is_blocked)
+        })
+}
+
+/// Whether a new conversation opened by `sender` is a message request, i.e.
+/// they aren't one of our contacts
+pub fn is_message_request(db: &Database, sender: &str) -> bool {
+    sender != "self" && ContactRepository::new(db).get_by_uuid(sender).is_none()
+}
+
+/// Group conversation whose group identifier (as used in typing indicators
+/// and sync messages) is `group_id`
+pub fn group_conversation_for(db: &Database, group_id: &[u8]) -> Option<String> {
+    ConversationRepository::new(db)
+        .list()
+        .into_iter()
+        .filter(|c| c.conversation_type == ConversationType::Group)
+        .find(|c| {
+            base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &c.id)
+                .ok()
+                .and_then(|key| group_identifier(&key))
+                .is_some_and(|id| id.as_slice() == group_id)
+        })
+        .map(|c| c.id)
+}
+
+/// Block or unblock the other side of a conversation: the contact of a
+/// private conversation, or a group
+pub fn set_blocked(db: &Database, conversation_id: &str, blocked: bool) -> Result<()> {
+    let conversation_type = ConversationRepository::new(db)
+        .get(conversation_id)
+        .map(|c| c.conversation_type)
+        .unwrap_or(ConversationType::Private);
+
+    match conversation_type {
+        ConversationType::Private => ContactRepository::new(db).set_blocked(conversation_id, blocked),
+        ConversationType::Group => GroupRepository::new(db).set_blocked(conversation_id, blocked),
+        ConversationType::NoteToSelf => bail!("Note to Self can't be blocked"),
+    }
+}
+
+/// Apply an answer to a message request, ours or one synced from another
+/// device
+pub fn apply_action(db: &Database, conversation_id: &str, action: MessageRequestAction) -> Result<()> {
+    let conv_repo = ConversationRepository::new(db);
+
+    if action.blocks() {
+        set_blocked(db, conversation_id, true)?;
+    }
+    match action {
+        MessageRequestAction::Accept => {
+            if let Some(mut conv) = conv_repo.get(conversation_id) {
+                if conv.is_blocked {
+                    set_blocked(db, conversation_id, false)?;
+                    conv.is_blocked = false;
+                }
+                conv.is_accepted = true;
+                conv_repo.save(&conv)?;
+            }
+        }
+        MessageRequestAction::Delete | MessageRequestAction::BlockAndDelete => {
+            MessageRepository::new(db).delete_for_conversation(conversation_id)?;
+            conv_repo.delete(conversation_id)?;
+        }
+        MessageRequestAction::Block => {}
+    }
+
+    tracing::info!("Applied message request answer {:?} to {}", action, conversation_id);
+    Ok(())
+}
+
+/// Sync message telling our other devices how we answered a request. Built
+/// before the answer is applied, as a delete removes the conversation.
+pub fn response_for(
+    db: &Database,
+    conversation_id: &str,
+    action: MessageRequestAction,
+) -> Option<sync_message::MessageRequestResponse> {
+    let conversation_type = ConversationRepository::new(db)
+        .get(conversation_id)
+        .map(|c| c.conversation_type)
+        .unwrap_or(ConversationType::Private);
+
+    let mut response = sync_message::MessageRequestResponse {
+        r#type: Some(action.response_type() as i32),
+        ..Default::default()
+    };
+    match conversation_type {
+        ConversationType::Private => response.thread_aci = Some(conversation_id.to_string()),
+        ConversationType::Group => {
+            let master_key = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, conversation_id).ok()?;
+            response.group_id = Some(group_identifier(&master_key)?.to_vec());
+        }
+        ConversationType::NoteToSelf => return None,
+    }
+    Some(response)
+}
+
+/// Conversation and answer of a message request response synced from
+/// another of our devices
+pub fn parse_response(
+    db: &Database,
+    response: &sync_message::MessageRequestResponse,
+) -> Option<(String, MessageRequestAction)> {
+    let action = MessageRequestAction::from_response_type(response.r#type())?;
+    let conversation_id = match (&response.group_id, &response.thread_aci) {
+        (Some(group_id), _) => group_conversation_for(db, group_id)?,
+        (None, Some(aci)) => aci.to_lowercase(),
+        (None, None) => return None,
+    };
+    Some((conversation_id, action))
+}
+
+/// Our complete blocked list, for `SyncMessage.blocked`
+pub fn blocked_list(db: &Database) -> sync_message::Blocked {
+    let contacts = ContactRepository::new(db).list_blocked();
+    let group_ids = GroupRepository::new(db)
+        .list()
+        .into_iter()
+        .filter(|g| g.blocked)
+        .filter_map(|g| group_identifier(g.master_key.as_deref()?))
+        .map(|id| id.to_vec())
+        .collect();
+
+    sync_message::Blocked {
+        acis: contacts.iter().map(|c| c.uuid.clone()).collect(),
+        numbers: contacts.iter().filter_map(|c| c.phone_number.clone()).collect(),
+        group_ids,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::groups::Group;
+    use crate::storage::contacts::StoredContact;
+    use crate::storage::conversations::Conversation;
+    use tempfile::{tempdir, TempDir};
+
+    const TEST_KEY: &str = "test-passphrase-123";
+
+    fn create_test_db() -> (Database, TempDir) {
+        let dir = tempdir().unwrap();
+        let db = Database::open_encrypted(&dir.path().join("test.db"), TEST_KEY).unwrap();
+        (db, dir)
+    }
+
+    #[test]
+    fn test_blocked_senders_and_groups() {
+        let (db, _dir) = create_test_db();
+        ContactRepository::new(&db).save(&StoredContact::new("friend", "Friend")).unwrap();
+        ConversationRepository::new(&db).save(&Conversation::new_group("group", "Group")).unwrap();
+
+        assert!(!is_blocked(&db, "friend", None));
+        assert!(is_message_request(&db, "stranger"));
+        assert!(!is_message_request(&db, "friend"));
+        assert!(!is_message_request(&db, "self"));
+
+        set_blocked(&db, "stranger", true).unwrap();
+        assert!(is_blocked(&db, "stranger", None));
+
+        set_blocked(&db, "group", true).unwrap();
+        assert!(is_blocked(&db, "friend", Some("group")));
+        assert!(!is_blocked(&db, "friend", Some("other-group")));
+    }
+
+    #[test]
+    fn test_message_request_actions() {
+        let (db, _dir) = create_test_db();
+        let conv_repo = ConversationRepository::new(&db);
+        let mut request = Conversation::new_private("stranger", "stranger");
+        request.is_accepted = false;
+        conv_repo.save(&request).unwrap();
+
+        apply_action(&db, "stranger", MessageRequestAction::Block).unwrap();
+        let conv = conv_repo.get("stranger").unwrap();
+        assert!(conv.is_blocked);
+        assert!(!conv.is_accepted);
+
+        // Accepting a blocked request unblocks it
+        apply_action(&db, "stranger", MessageRequestAction::Accept).unwrap();
+        let conv = conv_repo.get("stranger").unwrap();
+        assert!(conv.is_accepted);
+        assert!(!conv.is_blocked);
+        assert!(!ContactRepository::new(&db).get_by_uuid("stranger").unwrap().is_blocked);
+
+        apply_action(&db, "stranger", MessageRequestAction::BlockAndDelete).unwrap();
+        assert!(conv_repo.get("stranger").is_none());
+        assert!(is_blocked(&db, "stranger", None));
+    }
+
+    #[test]
+    fn test_sync_messages_round_trip() {
+        let (db, _dir) = create_test_db();
+        let master_key = [3u8; 32];
+        let group_id = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, master_key);
+        ConversationRepository::new(&db)
+            .save(&Conversation::new_group(&group_id, "Group"))
+            .unwrap();
+        let mut group = Group::new(&group_id, "Group");
+        group.master_key = Some(master_key.to_vec());
+        GroupRepository::new(&db).save(&group).unwrap();
+
+        let response = response_for(&db, &group_id, MessageRequestAction::Block).unwrap();
+        assert_eq!(parse_response(&db, &response), Some((group_id.clone(), MessageRequestAction::Block)));
+
+        let response = response_for(&db, "stranger", MessageRequestAction::Accept).unwrap();
+        assert_eq!(
+            parse_response(&db, &response),
+            Some(("stranger".to_string(), MessageRequestAction::Accept))
+        );
+
+        set_blocked(&db, &group_id, true).unwrap();
+        let mut contact = StoredContact::new("blocked", "Blocked");
+        contact.phone_number = Some("+15550002".to_string());
+        ContactRepository::new(&db).save(&contact).unwrap();
+        set_blocked(&db, "blocked", true).unwrap();
+
+        let blocked = blocked_list(&db);
+        assert_eq!(blocked.acis, vec!["blocked".to_string()]);
+        assert_eq!(blocked.numbers, vec!["+15550002".to_string()]);
+        assert_eq!(blocked.group_ids, vec![group_identifier(&master_key).unwrap().to_vec()]);
+    }
+}
@@ -0,0 +1,326 @@
+//! Safety numbers and identity key changes
+//!
+//! A safety number is the fingerprint of our identity key and a contact's.
+//! Comparing it with theirs, by reading the digits or scanning the QR code,
+//! proves nobody is in the middle. Presage trusts new identity keys as they
+//! arrive, so changes are noticed here against the key recorded in app.db,
+//! for senders as their messages come in and for every recipient before a
+//! send: a verified contact whose key changed drops back to unverified, and
+//! sending to them, directly or in a group, is held until the user accepts
+//! the new key.
+
+use crate::signal::messages::{Content, IdentityUpdateType, Message, MessageDirection, MessageStatus};
+use crate::storage::contacts::{ContactRepository, VerificationState};
+use crate::storage::conversations::ConversationRepository;
+use crate::storage::database::Database;
+use crate::storage::groups::GroupRepository;
+use crate::storage::identities::IdentityRepository;
+use crate::storage::messages::MessageRepository;
+use anyhow::Result;
+use chrono::Utc;
+use presage::libsignal_service::proto::{verified, Verified};
+use presage::libsignal_service::protocol::{Fingerprint, IdentityKey, ScannableFingerprint};
+use uuid::Uuid;
+
+/// Fingerprint version keyed by ACI, as the other Signal apps use
+const FINGERPRINT_VERSION: u32 = 2;
+const FINGERPRINT_ITERATIONS: u32 = 5200;
+
+/// Safety number of a conversation with one contact
+#[derive(Debug, Clone)]
+pub struct SafetyNumber {
+    /// The 60 digits, without spaces
+    pub digits: String,
+    /// Serialized scannable fingerprint, shown as a QR code
+    pub scannable: Vec<u8>,
+}
+
+impl SafetyNumber {
+    /// Safety number from our ACI and identity key and the contact's
+    pub fn new(self_aci: &str, self_key: &[u8], their_aci: &str, their_key: &[u8]) -> Result<Self> {
+        let fingerprint = Fingerprint::new(
+            FINGERPRINT_VERSION,
+            FINGERPRINT_ITERATIONS,
+            Uuid::parse_str(self_aci)?.as_bytes(),
+            &IdentityKey::decode(self_key)?,
+            Uuid::parse_str(their_aci)?.as_bytes(),
+            &IdentityKey::decode(their_key)?,
+        )?;
+
+        Ok(Self {
+            digits: fingerprint.display_string()?,
+            scannable: fingerprint.scannable.serialize()?,
+        })
+    }
+
+    /// The digits in the 12 groups of 5 the apps show
+    pub fn groups(&self) -> Vec<&str> {
+        (0..self.digits.len())
+            .step_by(5)
+            .map(|start| &self.digits[start..(start + 5).min(self.digits.len())])
+            .collect()
+    }
+
+    /// Whether a QR code scanned from the contact's device shows the same
+    /// safety number
+    pub fn matches_scanned(&self, scanned: &[u8]) -> Result<bool> {
+        Ok(ScannableFingerprint::deserialize(&self.scannable)?.compare(scanned)?)
+    }
+
+    /// Whether digits typed or pasted by the user match, ignoring spacing
+    pub fn matches_digits(&self, text: &str) -> bool {
+        let digits: String = text.chars().filter(char::is_ascii_digit).collect();
+        digits == self.digits
+    }
+}
+
+/// Record the identity key presage uses for a contact. The first key seen is
+/// just stored; returns true when a different one replaced it, i.e. the safety
+/// number changed.
+pub fn record_identity(db: &Database, uuid: &str, identity_key: &[u8]) -> Result<bool> {
+    let identities = IdentityRepository::new(db);
+    let Some(known) = identities.get(uuid) else {
+        identities.save(uuid, identity_key)?;
+        return Ok(false);
+    };
+    if known == identity_key {
+        return Ok(false);
+    }
+    identities.save(uuid, identity_key)?;
+
+    let contacts = ContactRepository::new(db);
+    if contacts.get_by_uuid(uuid).is_some_and(|c| c.is_verified()) {
+        contacts.set_verification(uuid, VerificationState::Unverified)?;
+    }
+    let details = format!("Your safety number with {} has changed", contact_name(db, uuid));
+    add_notice(db, uuid, IdentityUpdateType::SafetyNumberChanged, details, false)?;
+
+    tracing::warn!("Identity key of {} changed", uuid);
+    Ok(true)
+}
+
+/// Mark a contact's current safety number as verified, or clear that
+pub fn set_verified(db: &Database, uuid: &str, verified: bool) -> Result<()> {
+    let (state, update_type, word) = if verified {
+        (VerificationState::Verified, IdentityUpdateType::Verified, "verified")
+    } else {
+        (VerificationState::Default, IdentityUpdateType::Unverified, "unverified")
+    };
+    ContactRepository::new(db).set_verification(uuid, state)?;
+    let details = format!("You marked your safety number with {} {}", contact_name(db, uuid), word);
+    add_notice(db, uuid, update_type, details, true)
+}
+
+/// Accept a changed safety number without verifying it, so messages can be
+/// sent to the contact again
+pub fn accept_identity_change(db: &Database, uuid: &str) -> Result<()> {
+    let contacts = ContactRepository::new(db);
+    if contacts.get_by_uuid(uuid).is_some_and(|c| c.verification == VerificationState::Unverified) {
+        contacts.set_verification(uuid, VerificationState::Default)?;
+    }
+    Ok(())
+}
+
+/// Whether sending to a contact is held because their safety number changed
+/// since it was verified
+pub fn needs_acceptance(db: &Database, uuid: &str) -> bool {
+    ContactRepository::new(db)
+        .get_by_uuid(uuid)
+        .is_some_and(|c| c.verification == VerificationState::Unverified)
+}
+
+/// Who a send to a conversation goes to: the contact of a private one, every
+/// member of a group
+pub fn recipients(db: &Database, conversation_id: &str) -> Vec<String> {
+    match GroupRepository::new(db).get(conversation_id) {
+        Some(group) => group.members.into_iter().map(|m| m.uuid).collect(),
+        None => vec![conversation_id.to_string()],
+    }
+}
+
+/// Recipients of a conversation whose changed safety number holds sending to
+/// it
+pub fn held_recipients(db: &Database, conversation_id: &str) -> Vec<String> {
+    recipients(db, conversation_id)
+        .into_iter()
+        .filter(|uuid| needs_acceptance(db, uuid))
+        .collect()
+}
+
+/// `SyncMessage.verified` telling our other devices we (un)verified a
+/// contact's current identity key
+pub fn verified_sync(uuid: &str, identity_key: &[u8], verified: bool) -> Verified {
+    let state = if verified { verified::State::Verified } else { verified::State::Default };
+    Verified {
+        destination_aci: Some(uuid.to_string()),
+        identity_key: Some(identity_key.to_vec()),
+        state: Some(state as i32),
+        ..Default::default()
+    }
+}
+
+/// Apply a verification made on another of our devices. It only counts for
+/// the identity key we know; one for an older or newer key is ignored.
+/// Returns the contact it changed.
+pub fn apply_verified_sync(db: &Database, sync: &Verified) -> Result<Option<String>> {
+    let (Some(uuid), Some(identity_key)) = (&sync.destination_aci, &sync.identity_key) else {
+        return Ok(None);
+    };
+    let uuid = uuid.to_lowercase();
+    if IdentityRepository::new(db).get(&uuid).is_some_and(|known| known != *identity_key) {
+        tracing::info!("Ignoring verification of an old identity key of {}", uuid);
+        return Ok(None);
+    }
+    record_identity(db, &uuid, identity_key)?;
+
+    let current = ContactRepository::new(db).get_by_uuid(&uuid).map(|c| c.verification);
+    match sync.state() {
+        verified::State::Verified if current != Some(VerificationState::Verified) => set_verified(db, &uuid, true)?,
+        verified::State::Default if current == Some(VerificationState::Verified) => set_verified(db, &uuid, false)?,
+        verified::State::Unverified if current != Some(VerificationState::Unverified) => {
+            ContactRepository::new(db).set_verification(&uuid, VerificationState::Unverified)?
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(uuid))
+}
+
+fn contact_name(db: &Database, uuid: &str) -> String {
+    ContactRepository::new(db)
+        .get_by_uuid(uuid)
+        .map(|c| c.display_name().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "this contact".to_string())
+}
+
+/// System row in the private conversation with `uuid`, if we have one
+fn add_notice(db: &Database, uuid: &str, update_type: IdentityUpdateType, details: String, ours: bool) -> Result<()> {
+    if ConversationRepository::new(db).get(uuid).is_none() {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    MessageRepository::new(db).save(&Message {
+        id: Uuid::new_v4().to_string(),
+        conversation_id: uuid.to_string(),
+        sender: if ours { "self".to_string() } else { uuid.to_string() },
+        direction: if ours { MessageDirection::Outgoing } else { MessageDirection::Incoming },
+        status: if ours { MessageStatus::Sent } else { MessageStatus::Read },
+        content: Content::IdentityUpdate { update_type, details },
+        sent_at: now,
+        server_timestamp: None,
+        delivered_at: None,
+        // System rows never count as unread
+        read_at: Some(now),
+        quote: None,
+        reactions: Vec::new(),
+        expires_in_seconds: None,
+        expires_at: None,
+        signal_timestamp: None,
+        edited_at: None,
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::groups::{Group, MemberRole};
+    use crate::storage::contacts::StoredContact;
+    use crate::storage::conversations::Conversation;
+    use tempfile::{tempdir, TempDir};
+
+    const TEST_KEY: &str = "test-passphrase-123";
+
+    fn create_test_db() -> (Database, TempDir) {
+        let dir = tempdir().unwrap();
+        let db = Database::open_encrypted(&dir.path().join("test.db"), TEST_KEY).unwrap();
+        (db, dir)
+    }
+
+    fn notices(db: &Database, uuid: &str) -> Vec<IdentityUpdateType> {
+        MessageRepository::new(db)
+            .get_for_conversation(uuid, 100, None)
+            .into_iter()
+            .filter_map(|m| match m.content {
+                Content::IdentityUpdate { update_type, .. } => Some(update_type),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_changed_key_unverifies_contact() {
+        let (db, _dir) = create_test_db();
+        let contacts = ContactRepository::new(&db);
+        contacts.save(&StoredContact::new("alice", "Alice")).unwrap();
+        ConversationRepository::new(&db)
+            .save(&Conversation::new_private("alice", "Alice"))
+            .unwrap();
+
+        assert!(!record_identity(&db, "alice", &[1; 33]).unwrap());
+        assert!(!record_identity(&db, "alice", &[1; 33]).unwrap());
+        set_verified(&db, "alice", true).unwrap();
+        assert!(contacts.get_by_uuid("alice").unwrap().is_verified());
+
+        assert!(record_identity(&db, "alice", &[2; 33]).unwrap());
+        assert!(needs_acceptance(&db, "alice"));
+        let notices = notices(&db, "alice");
+        assert_eq!(notices.len(), 2);
+        assert!(notices.contains(&IdentityUpdateType::SafetyNumberChanged));
+
+        accept_identity_change(&db, "alice").unwrap();
+        assert!(!needs_acceptance(&db, "alice"));
+        assert_eq!(contacts.get_by_uuid("alice").unwrap().verification, VerificationState::Default);
+    }
+
+    #[test]
+    fn test_changed_key_of_member_holds_group_send() {
+        let (db, _dir) = create_test_db();
+        let mut group = Group::new("group-1", "Climbing");
+        group.add_member("alice", MemberRole::Default);
+        group.add_member("bob", MemberRole::Default);
+        GroupRepository::new(&db).save(&group).unwrap();
+        ContactRepository::new(&db).save(&StoredContact::new("bob", "Bob")).unwrap();
+
+        record_identity(&db, "bob", &[1; 33]).unwrap();
+        set_verified(&db, "bob", true).unwrap();
+        assert!(held_recipients(&db, "group-1").is_empty());
+
+        record_identity(&db, "bob", &[2; 33]).unwrap();
+        assert_eq!(held_recipients(&db, "group-1"), vec!["bob".to_string()]);
+        assert_eq!(held_recipients(&db, "bob"), vec!["bob".to_string()]);
+        assert!(held_recipients(&db, "alice").is_empty());
+
+        accept_identity_change(&db, "bob").unwrap();
+        assert!(held_recipients(&db, "group-1").is_empty());
+    }
+
+    #[test]
+    fn test_verified_sync_only_applies_to_known_key() {
+        let (db, _dir) = create_test_db();
+        IdentityRepository::new(&db).save("bob", &[1; 33]).unwrap();
+
+        let stale = verified_sync("bob", &[9; 33], true);
+        assert_eq!(apply_verified_sync(&db, &stale).unwrap(), None);
+        assert!(ContactRepository::new(&db).get_by_uuid("bob").is_none());
+
+        let current = verified_sync("BOB", &[1; 33], true);
+        assert_eq!(apply_verified_sync(&db, &current).unwrap(), Some("bob".to_string()));
+        assert!(ContactRepository::new(&db).get_by_uuid("bob").unwrap().is_verified());
+        // Applying it again changes nothing
+        assert_eq!(apply_verified_sync(&db, &current).unwrap(), None);
+    }
+
+    #[test]
+    fn test_digits_compare_ignores_spacing() {
+        let number = SafetyNumber {
+            digits: "0123456789".repeat(6),
+            scannable: Vec::new(),
+        };
+        assert_eq!(number.groups().len(), 12);
+        assert_eq!(number.groups()[1], "56789");
+        assert!(number.matches_digits(&number.groups().join(" ")));
+        assert!(!number.matches_digits("01234 56789"));
+    }
+}
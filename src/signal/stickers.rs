@@ -0,0 +1,304 @@
+//! Sticker packs
+//!
+//! A pack is published on the Signal CDN under its ID: a manifest listing the
+//! stickers, and one image per sticker. Both are encrypted like attachments,
+//! with a key derived from the pack key, so only people who were sent the key
+//! can see them. Decrypted manifests and images are cached in the stickers
+//! directory as `<pack id>/manifest.proto` and `<pack id>/<sticker id>`.
+
+use crate::signal::attachments::decrypt_attachment;
+use crate::signal::SignalError;
+use crate::storage::database::Database;
+use crate::storage::stickers::{Sticker, StickerPack, StickerRepository};
+use crate::storage::Storage;
+use hkdf::Hkdf;
+use presage::libsignal_service::proto::sync_message::{sticker_pack_operation, StickerPackOperation};
+use presage::libsignal_service::proto::Pack;
+use prost::Message as _;
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+
+const CDN_URL: &str = "https://cdn.signal.org/stickers";
+const MANIFEST_FILE: &str = "manifest.proto";
+const PACK_ID_LEN: usize = 16;
+const PACK_KEY_LEN: usize = 32;
+
+/// Key for a pack's manifest and images: AES key followed by HMAC key
+pub fn derive_key(pack_key: &[u8]) -> [u8; 64] {
+    let mut key = [0u8; 64];
+    Hkdf::<Sha256>::new(Some(&[0u8; 32]), pack_key)
+        .expand(b"Sticker Pack", &mut key)
+        .expect("valid HKDF output length");
+    key
+}
+
+/// Where a sticker's image is cached
+pub fn sticker_path(stickers_dir: &Path, pack_id: &str, sticker_id: u32) -> PathBuf {
+    stickers_dir.join(pack_id).join(sticker_id.to_string())
+}
+
+/// Pack ID and key from a pack link like
+/// `https://signal.art/addstickers/#pack_id=<hex>&pack_key=<hex>`
+pub fn parse_pack_link(link: &str) -> Option<(String, String)> {
+    let (_, fragment) = link.trim().split_once('#')?;
+    let mut pack_id = None;
+    let mut pack_key = None;
+    for pair in fragment.split('&') {
+        match pair.split_once('=') {
+            Some(("pack_id", value)) => pack_id = Some(value.to_lowercase()),
+            Some(("pack_key", value)) => pack_key = Some(value.to_lowercase()),
+            _ => {}
+        }
+    }
+
+    let (pack_id, pack_key) = (pack_id?, pack_key?);
+    decode_pack(&pack_id, &pack_key).ok()?;
+    Some((pack_id, pack_key))
+}
+
+/// Check a hex pack ID and key, returning the key bytes
+fn decode_pack(pack_id: &str, pack_key: &str) -> Result<Vec<u8>, SignalError> {
+    let id = hex::decode(pack_id).map_err(|_| SignalError::ProtocolError("Invalid sticker pack ID".into()))?;
+    let key = hex::decode(pack_key).map_err(|_| SignalError::ProtocolError("Invalid sticker pack key".into()))?;
+    if id.len() != PACK_ID_LEN || key.len() != PACK_KEY_LEN {
+        return Err(SignalError::ProtocolError("Invalid sticker pack ID or key length".into()));
+    }
+    Ok(key)
+}
+
+/// Read a decrypted manifest
+pub fn parse_manifest(pack_id: &str, pack_key: &str, data: &[u8]) -> Result<StickerPack, SignalError> {
+    let pack = Pack::decode(data)
+        .map_err(|e| SignalError::ProtocolError(format!("Invalid sticker pack manifest: {}", e)))?;
+    let non_empty = |s: Option<String>| s.filter(|s| !s.is_empty());
+
+    Ok(StickerPack {
+        pack_id: pack_id.to_string(),
+        pack_key: pack_key.to_string(),
+        title: non_empty(pack.title),
+        author: non_empty(pack.author),
+        cover_sticker_id: pack.cover.and_then(|c| c.id),
+        installed: false,
+        stickers: pack
+            .stickers
+            .into_iter()
+            .filter_map(|s| {
+                Some(Sticker {
+                    sticker_id: s.id?,
+                    emoji: non_empty(s.emoji),
+                    content_type: non_empty(s.content_type),
+                })
+            })
+            .collect(),
+    })
+}
+
+/// Download and decrypt one file of a pack from the CDN
+async fn fetch(path: &str, key: &[u8]) -> Result<Vec<u8>, SignalError> {
+    let client = crate::signal::backup::api::build_signal_client()?;
+    let response = client
+        .get(format!("{}/{}", CDN_URL, path))
+        .send()
+        .await
+        .map_err(|e| SignalError::NetworkError(format!("Failed to download sticker: {}", e)))?;
+    if !response.status().is_success() {
+        return Err(SignalError::NetworkError(format!(
+            "Sticker download failed with status {}",
+            response.status()
+        )));
+    }
+    let blob = response
+        .bytes()
+        .await
+        .map_err(|e| SignalError::NetworkError(format!("Failed to read sticker: {}", e)))?;
+
+    decrypt_attachment(&blob, &derive_key(key), None, None)
+}
+
+/// Write through a temp file so a partial write never looks cached
+async fn write_cached(path: &Path, data: &[u8]) -> Result<(), SignalError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| SignalError::AttachmentError(e.to_string()))?;
+    }
+    let tmp_path = path.with_extension("part");
+    fs::write(&tmp_path, data)
+        .await
+        .map_err(|e| SignalError::AttachmentError(e.to_string()))?;
+    fs::rename(&tmp_path, path)
+        .await
+        .map_err(|e| SignalError::AttachmentError(e.to_string()))
+}
+
+/// A pack's manifest, from the cache or the CDN
+pub async fn download_manifest(stickers_dir: &Path, pack_id: &str, pack_key: &str) -> Result<StickerPack, SignalError> {
+    let key = decode_pack(pack_id, pack_key)?;
+    let path = stickers_dir.join(pack_id).join(MANIFEST_FILE);
+
+    let data = match fs::read(&path).await {
+        Ok(data) => data,
+        Err(_) => {
+            tracing::info!("Downloading sticker pack manifest {}", pack_id);
+            let data = fetch(&format!("{}/manifest.proto", pack_id), &key).await?;
+            write_cached(&path, &data).await?;
+            data
+        }
+    };
+    parse_manifest(pack_id, pack_key, &data)
+}
+
+/// A sticker's image, from the cache or the CDN
+pub async fn download_sticker(
+    stickers_dir: &Path,
+    pack_id: &str,
+    pack_key: &str,
+    sticker_id: u32,
+) -> Result<PathBuf, SignalError> {
+    let path = sticker_path(stickers_dir, pack_id, sticker_id);
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let key = decode_pack(pack_id, pack_key)?;
+    let data = fetch(&format!("{}/full/{}", pack_id, sticker_id), &key).await?;
+    write_cached(&path, &data).await?;
+    Ok(path)
+}
+
+/// Download a pack's manifest and every sticker not cached yet, and record
+/// the manifest. A sticker that fails to download is left for later.
+pub async fn download_pack(storage: &Arc<Storage>, pack_id: &str, pack_key: &str) -> Result<StickerPack, SignalError> {
+    let stickers_dir = storage.stickers_dir();
+    let pack = download_manifest(stickers_dir, pack_id, pack_key).await?;
+
+    for sticker in &pack.stickers {
+        if let Err(e) = download_sticker(stickers_dir, pack_id, pack_key, sticker.sticker_id).await {
+            tracing::warn!("Failed to download sticker {} of pack {}: {}", sticker.sticker_id, pack_id, e);
+        }
+    }
+
+    let db = storage
+        .database()
+        .ok_or_else(|| SignalError::StorageError("App database not available".to_string()))?;
+    StickerRepository::new(&db)
+        .save_manifest(&pack)
+        .map_err(|e| SignalError::StorageError(e.to_string()))?;
+    tracing::info!("Sticker pack {} has {} stickers", pack_id, pack.stickers.len());
+    Ok(pack)
+}
+
+/// `SyncMessage.sticker_pack_operation` telling our other devices we
+/// installed or removed a pack
+pub fn pack_operation(pack_id: &str, pack_key: &str, installed: bool) -> Result<StickerPackOperation, SignalError> {
+    let key = decode_pack(pack_id, pack_key)?;
+    let operation = if installed {
+        sticker_pack_operation::Type::Install
+    } else {
+        sticker_pack_operation::Type::Remove
+    };
+    Ok(StickerPackOperation {
+        pack_id: hex::decode(pack_id).ok(),
+        pack_key: Some(key),
+        r#type: Some(operation as i32),
+    })
+}
+
+/// Apply packs installed or removed on another of our devices. Returns the
+/// newly installed packs as (pack ID, pack key), to be downloaded.
+pub fn apply_pack_operations(db: &Database, operations: &[StickerPackOperation]) -> anyhow::Result<Vec<(String, String)>> {
+    let repo = StickerRepository::new(db);
+    let mut installed = Vec::new();
+
+    for operation in operations {
+        let Some(pack_id) = operation.pack_id.as_deref().map(hex::encode) else {
+            continue;
+        };
+        let install = operation.r#type() == sticker_pack_operation::Type::Install;
+        let pack_key = match operation.pack_key.as_deref() {
+            Some(key) => hex::encode(key),
+            // Removing only needs the ID of a pack we know
+            None => match repo.get(&pack_id) {
+                Some(pack) => pack.pack_key,
+                None => continue,
+            },
+        };
+        if decode_pack(&pack_id, &pack_key).is_err() {
+            tracing::warn!("Ignoring sticker pack operation with an invalid pack ID or key");
+            continue;
+        }
+
+        if repo.set_installed(&pack_id, &pack_key, install)? && install {
+            installed.push((pack_id, pack_key));
+        }
+    }
+    Ok(installed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use presage::libsignal_service::proto::pack;
+    use tempfile::tempdir;
+
+    const PACK_ID: &str = "00112233445566778899aabbccddeeff";
+
+    fn pack_key() -> String {
+        "ab".repeat(PACK_KEY_LEN)
+    }
+
+    #[test]
+    fn test_parse_pack_link() {
+        let link = format!("https://signal.art/addstickers/#pack_id={}&pack_key={}", PACK_ID, pack_key());
+        assert_eq!(parse_pack_link(&link), Some((PACK_ID.to_string(), pack_key())));
+        assert_eq!(parse_pack_link("https://signal.art/addstickers/#pack_id=00"), None);
+        assert_eq!(parse_pack_link(&format!("#pack_id=zz&pack_key={}", pack_key())), None);
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Pack {
+            title: Some("Cats".to_string()),
+            author: Some(String::new()),
+            cover: Some(pack::Sticker { id: Some(2), ..Default::default() }),
+            stickers: vec![
+                pack::Sticker {
+                    id: Some(1),
+                    emoji: Some("😺".to_string()),
+                    content_type: Some("image/webp".to_string()),
+                },
+                pack::Sticker { id: None, ..Default::default() },
+            ],
+        };
+
+        let pack = parse_manifest(PACK_ID, &pack_key(), &manifest.encode_to_vec()).unwrap();
+        assert_eq!(pack.title.as_deref(), Some("Cats"));
+        assert_eq!(pack.author, None);
+        assert_eq!(pack.cover_sticker_id, Some(2));
+        assert_eq!(pack.stickers.len(), 1);
+        assert_eq!(pack.stickers[0].emoji.as_deref(), Some("😺"));
+    }
+
+    #[test]
+    fn test_pack_operations_install_and_remove() {
+        let dir = tempdir().unwrap();
+        let db = Database::open_encrypted(&dir.path().join("test.db"), "test-passphrase-123").unwrap();
+
+        let install = pack_operation(PACK_ID, &pack_key(), true).unwrap();
+        assert_eq!(
+            apply_pack_operations(&db, &[install.clone()]).unwrap(),
+            vec![(PACK_ID.to_string(), pack_key())]
+        );
+        // Installing again doesn't download it again
+        assert!(apply_pack_operations(&db, &[install]).unwrap().is_empty());
+
+        let remove = StickerPackOperation {
+            pack_key: None,
+            ..pack_operation(PACK_ID, &pack_key(), false).unwrap()
+        };
+        assert!(apply_pack_operations(&db, &[remove]).unwrap().is_empty());
+        assert!(!StickerRepository::new(&db).get(PACK_ID).unwrap().installed);
+    }
+}
@@ -0,0 +1,216 @@
+//! Sync with the primary device
+//!
+//! A linked device asks the primary for its contacts, blocked list,
+//! configuration and keys with `SyncMessage.Request`. The answers come back as
+//! sync messages of their own and are applied to local storage here.
+
+use crate::signal::manager::group_identifier;
+use crate::storage::contacts::ContactRepository;
+use crate::storage::conversations::{ConversationRepository, ConversationType};
+use crate::storage::database::Database;
+use crate::storage::groups::GroupRepository;
+use crate::storage::settings::SettingsRepository;
+use presage::libsignal_service::proto::sync_message;
+use std::collections::{HashMap, HashSet};
+
+/// Data a linked device can request from the primary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncRequestKind {
+    Contacts,
+    Blocked,
+    Configuration,
+    Keys,
+}
+
+impl SyncRequestKind {
+    pub const ALL: [Self; 4] = [Self::Contacts, Self::Blocked, Self::Configuration, Self::Keys];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Contacts => "Contacts",
+            Self::Blocked => "Blocked list",
+            Self::Configuration => "Settings",
+            Self::Keys => "Keys",
+        }
+    }
+
+    pub fn request_type(self) -> sync_message::request::Type {
+        match self {
+            Self::Contacts => sync_message::request::Type::Contacts,
+            Self::Blocked => sync_message::request::Type::Blocked,
+            Self::Configuration => sync_message::request::Type::Configuration,
+            Self::Keys => sync_message::request::Type::Keys,
+        }
+    }
+}
+
+/// Apply the blocked list from the primary. The list is complete, so contacts
+/// and groups not on it are unblocked. Returns the number of conversations
+/// whose block state changed.
+pub fn apply_blocked_list(
+    db: &Database,
+    acis: &[String],
+    numbers: &[String],
+    group_ids: &[Vec<u8>],
+) -> anyhow::Result<usize> {
+    let acis: HashSet<String> = acis.iter().map(|aci| aci.to_lowercase()).collect();
+
+    let conv_repo = ConversationRepository::new(db);
+    let was_blocked: HashMap<String, bool> =
+        conv_repo.list().into_iter().map(|c| (c.id, c.is_blocked)).collect();
+
+    // Contacts first; blocking one also blocks its conversation. Blocked
+    // senders we don't know yet get a contact so the block sticks.
+    let contact_repo = ContactRepository::new(db);
+    let mut blocked_contacts = HashSet::new();
+    for contact in contact_repo.list() {
+        let blocked = acis.contains(&contact.uuid)
+            || contact.phone_number.as_ref().is_some_and(|n| numbers.contains(n));
+        if blocked {
+            blocked_contacts.insert(contact.uuid.clone());
+        }
+        if contact.is_blocked != blocked {
+            contact_repo.set_blocked(&contact.uuid, blocked)?;
+        }
+    }
+    let unknown: Vec<String> = acis.difference(&blocked_contacts).cloned().collect();
+    for aci in unknown {
+        contact_repo.set_blocked(&aci, true)?;
+        blocked_contacts.insert(aci);
+    }
+
+    let group_repo = GroupRepository::new(db);
+    for conv in conv_repo.list() {
+        match conv.conversation_type {
+            ConversationType::Group => {
+                let blocked = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &conv.id)
+                    .ok()
+                    .and_then(|key| group_identifier(&key))
+                    .is_some_and(|id| group_ids.iter().any(|g| g.as_slice() == id.as_slice()));
+                if conv.is_blocked != blocked {
+                    group_repo.set_blocked(&conv.id, blocked)?;
+                }
+            }
+            // Conversations with no contact row
+            ConversationType::Private if conv.is_blocked != blocked_contacts.contains(&conv.id) => {
+                let mut conv = conv;
+                conv.is_blocked = !conv.is_blocked;
+                conv_repo.save(&conv)?;
+            }
+            _ => {}
+        }
+    }
+
+    let changed = conv_repo
+        .list()
+        .iter()
+        .filter(|c| was_blocked.get(&c.id).is_some_and(|&before| before != c.is_blocked))
+        .count();
+
+    tracing::info!(
+        "Applied blocked list: {} contacts, {} groups, {} conversations changed",
+        blocked_contacts.len(),
+        group_ids.len(),
+        changed
+    );
+    Ok(changed)
+}
+
+/// Apply the privacy settings from the primary. Settings it didn't send are
+/// left alone.
+pub fn apply_configuration(db: &Database, configuration: &sync_message::Configuration) -> anyhow::Result<()> {
+    let mut settings_repo = SettingsRepository::new(db);
+    let settings = settings_repo.get_mut();
+    if let Some(read_receipts) = configuration.read_receipts {
+        settings.read_receipts = read_receipts;
+    }
+    if let Some(typing_indicators) = configuration.typing_indicators {
+        settings.typing_indicators = typing_indicators;
+    }
+    if let Some(link_previews) = configuration.link_previews {
+        settings.link_previews = link_previews;
+    }
+    settings_repo.save()?;
+
+    tracing::info!("Applied configuration from primary device");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::contacts::StoredContact;
+    use crate::storage::conversations::Conversation;
+    use tempfile::{tempdir, TempDir};
+
+    const TEST_KEY: &str = "test-passphrase-123";
+
+    fn create_test_db() -> (Database, TempDir) {
+        let dir = tempdir().unwrap();
+        let db = Database::open_encrypted(&dir.path().join("test.db"), TEST_KEY).unwrap();
+        (db, dir)
+    }
+
+    #[test]
+    fn test_blocked_list_replaces_previous_state() {
+        let (db, _dir) = create_test_db();
+        let contacts = ContactRepository::new(&db);
+        let conversations = ConversationRepository::new(&db);
+
+        let mut by_number = StoredContact::new("bbbb", "Bob");
+        by_number.phone_number = Some("+15550001".to_string());
+        contacts.save(&by_number).unwrap();
+        let mut unblocked = StoredContact::new("cccc", "Carol");
+        unblocked.is_blocked = true;
+        contacts.save(&unblocked).unwrap();
+
+        for id in ["aaaa", "bbbb", "cccc"] {
+            conversations.save(&Conversation::new_private(id, id)).unwrap();
+        }
+        let mut carol = conversations.get("cccc").unwrap();
+        carol.is_blocked = true;
+        conversations.save(&carol).unwrap();
+
+        let master_key = [7u8; 32];
+        let group_id = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, master_key);
+        conversations.save(&Conversation::new_group(&group_id, "Group")).unwrap();
+
+        let changed = apply_blocked_list(
+            &db,
+            &["AAAA".to_string()],
+            &["+15550001".to_string()],
+            &[group_identifier(&master_key).unwrap().to_vec()],
+        )
+        .unwrap();
+
+        assert_eq!(changed, 4);
+        assert!(conversations.get("aaaa").unwrap().is_blocked);
+        assert!(conversations.get("bbbb").unwrap().is_blocked);
+        assert!(!conversations.get("cccc").unwrap().is_blocked);
+        assert!(conversations.get(&group_id).unwrap().is_blocked);
+        assert!(contacts.get_by_uuid("bbbb").unwrap().is_blocked);
+        assert!(!contacts.get_by_uuid("cccc").unwrap().is_blocked);
+        // Blocked but unknown senders get a contact, so the block sticks
+        assert!(contacts.get_by_uuid("aaaa").unwrap().is_blocked);
+    }
+
+    #[test]
+    fn test_configuration_keeps_unsent_settings() {
+        let (db, _dir) = create_test_db();
+        let mut settings = SettingsRepository::new(&db);
+        settings.get_mut().link_previews = false;
+        settings.save().unwrap();
+
+        let configuration = sync_message::Configuration {
+            read_receipts: Some(false),
+            typing_indicators: Some(false),
+            ..Default::default()
+        };
+        apply_configuration(&db, &configuration).unwrap();
+
+        let settings = SettingsRepository::new(&db);
+        assert!(!settings.get().read_receipts);
+        assert!(!settings.get().typing_indicators);
+        assert!(!settings.get().link_previews);
+    }
+}
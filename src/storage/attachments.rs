@@ -0,0 +1,529 @@
+use crate::signal::attachments::AttachmentMetadata;
+use crate::storage::database::Database;
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::params;
+
+/// Local download state of an attachment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadStatus {
+    /// Pointer received, nothing fetched yet
+    Pending,
+    /// Fetch from the CDN in progress
+    Downloading,
+    /// Decrypted and stored under the attachments dir
+    Downloaded,
+    /// Fetch, digest check or decryption failed
+    Failed,
+    /// Not fetched because of the auto-download policy; can be fetched manually
+    Skipped,
+}
+
+impl DownloadStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Downloading => "downloading",
+            Self::Downloaded => "downloaded",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "downloading" => Self::Downloading,
+            "downloaded" => Self::Downloaded,
+            "failed" => Self::Failed,
+            "skipped" => Self::Skipped,
+            _ => Self::Pending,
+        }
+    }
+}
+
+/// Attachment pointer as persisted alongside its message.
+///
+/// `id` is the file name under the attachments dir and matches the
+/// `attachment_id` stored in the message content.
+#[derive(Debug, Clone)]
+pub struct StoredAttachment {
+    pub id: String,
+    pub message_id: String,
+    pub content_type: String,
+    pub filename: Option<String>,
+    pub size: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub voice_note: bool,
+    pub cdn_number: u32,
+    /// CDN key (cdn2/cdn3) or the numeric attachment id for cdn0
+    pub cdn_key: String,
+    pub key: Vec<u8>,
+    pub digest: Option<Vec<u8>>,
+    pub status: DownloadStatus,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl StoredAttachment {
+    /// Record an incoming pointer for `message_id`, not yet downloaded
+    pub fn from_metadata(message_id: &str, metadata: &AttachmentMetadata) -> Self {
+        let now = Utc::now().timestamp();
+        Self {
+            id: metadata.id.clone(),
+            message_id: message_id.to_string(),
+            content_type: metadata.content_type.clone(),
+            filename: metadata.filename.clone(),
+            size: metadata.size,
+            width: metadata.width,
+            height: metadata.height,
+            voice_note: metadata.voice_note,
+            cdn_number: metadata.cdn_number.unwrap_or(0),
+            cdn_key: metadata.cdn_key.clone().unwrap_or_default(),
+            key: metadata.key.clone().unwrap_or_default(),
+            digest: metadata.digest.clone(),
+            status: DownloadStatus::Pending,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Metadata needed to fetch this attachment again
+    pub fn to_metadata(&self) -> AttachmentMetadata {
+        AttachmentMetadata {
+            id: self.id.clone(),
+            content_type: self.content_type.clone(),
+            filename: self.filename.clone(),
+            size: self.size,
+            width: self.width,
+            height: self.height,
+            duration_ms: None,
+            blurhash: None,
+            waveform: None,
+            cdn_number: Some(self.cdn_number),
+            cdn_key: Some(self.cdn_key.clone()),
+            key: Some(self.key.clone()),
+            digest: self.digest.clone(),
+            voice_note: self.voice_note,
+        }
+    }
+}
+
+pub struct AttachmentRepository<'a> {
+    db: &'a Database,
+}
+
+impl<'a> AttachmentRepository<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    pub fn get(&self, id: &str) -> Option<StoredAttachment> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT id, message_id, content_type, filename, size, width, height, voice_note,
+                    cdn_number, cdn_key, key, digest, status, created_at, updated_at
+             FROM attachments WHERE id = ?",
+            params![id],
+            Self::row_to_attachment,
+        )
+        .ok()
+    }
+
+    pub fn get_for_message(&self, message_id: &str) -> Vec<StoredAttachment> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.prepare(
+            "SELECT id, message_id, content_type, filename, size, width, height, voice_note,
+                    cdn_number, cdn_key, key, digest, status, created_at, updated_at
+             FROM attachments WHERE message_id = ?
+             ORDER BY created_at ASC",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![message_id], Self::row_to_attachment)
+                .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn list_by_status(&self, status: DownloadStatus) -> Vec<StoredAttachment> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.prepare(
+            "SELECT id, message_id, content_type, filename, size, width, height, voice_note,
+                    cdn_number, cdn_key, key, digest, status, created_at, updated_at
+             FROM attachments WHERE status = ?
+             ORDER BY created_at ASC",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![status.as_str()], Self::row_to_attachment)
+                .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn save(&self, attachment: &StoredAttachment) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO attachments
+             (id, message_id, content_type, filename, size, width, height, voice_note,
+              cdn_number, cdn_key, key, digest, status, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                attachment.id,
+                attachment.message_id,
+                attachment.content_type,
+                attachment.filename,
+                attachment.size as i64,
+                attachment.width,
+                attachment.height,
+                attachment.voice_note as i64,
+                attachment.cdn_number,
+                attachment.cdn_key,
+                attachment.key,
+                attachment.digest,
+                attachment.status.as_str(),
+                attachment.created_at,
+                attachment.updated_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_status(&self, id: &str, status: DownloadStatus) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "UPDATE attachments SET status = ?, updated_at = ? WHERE id = ?",
+            params![status.as_str(), Utc::now().timestamp(), id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_for_message(&self, message_id: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "DELETE FROM attachments WHERE message_id = ?",
+            params![message_id],
+        )?;
+        Ok(())
+    }
+
+    fn row_to_attachment(row: &rusqlite::Row<'_>) -> rusqlite::Result<StoredAttachment> {
+        Ok(StoredAttachment {
+            id: row.get(0)?,
+            message_id: row.get(1)?,
+            content_type: row.get(2)?,
+            filename: row.get(3)?,
+            size: row.get::<_, i64>(4)? as u64,
+            width: row.get(5)?,
+            height: row.get(6)?,
+            voice_note: row.get::<_, i64>(7)? != 0,
+            cdn_number: row.get(8)?,
+            cdn_key: row.get(9)?,
+            key: row.get(10)?,
+            digest: row.get(11)?,
+            status: DownloadStatus::parse(&row.get::<_, String>(12)?),
+            created_at: row.get(13)?,
+            updated_at: row.get(14)?,
+        })
+    }
+}
+
+/// State of a queued attachment download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    /// Waiting for `next_attempt_at`
+    Queued,
+    /// Picked up by the scheduler
+    Running,
+    /// Gave up after the maximum number of attempts
+    Failed,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "running" => Self::Running,
+            "failed" => Self::Failed,
+            _ => Self::Queued,
+        }
+    }
+}
+
+/// Persisted attachment download job, so pending downloads survive restarts
+#[derive(Debug, Clone)]
+pub struct DownloadJob {
+    pub attachment_id: String,
+    pub message_id: String,
+    pub state: JobState,
+    pub attempts: u32,
+    pub next_attempt_at: i64,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+}
+
+pub struct DownloadJobRepository<'a> {
+    db: &'a Database,
+}
+
+impl<'a> DownloadJobRepository<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    pub fn get(&self, attachment_id: &str) -> Option<DownloadJob> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT attachment_id, message_id, state, attempts, next_attempt_at, last_error, created_at
+             FROM download_jobs WHERE attachment_id = ?",
+            params![attachment_id],
+            Self::row_to_job,
+        )
+        .ok()
+    }
+
+    /// Queue a download to run as soon as possible, resetting any previous attempts
+    pub fn enqueue(&self, attachment_id: &str, message_id: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let now = Utc::now().timestamp();
+        conn.execute(
+            "INSERT OR REPLACE INTO download_jobs
+             (attachment_id, message_id, state, attempts, next_attempt_at, last_error, created_at)
+             VALUES (?, ?, 'queued', 0, ?, NULL, ?)",
+            params![attachment_id, message_id, now, now],
+        )?;
+        Ok(())
+    }
+
+    /// Claim up to `limit` queued jobs that are due, marking them running
+    pub fn take_due(&self, now: i64, limit: usize) -> Result<Vec<DownloadJob>> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let jobs: Vec<DownloadJob> = conn
+            .prepare(
+                "SELECT attachment_id, message_id, state, attempts, next_attempt_at, last_error, created_at
+                 FROM download_jobs
+                 WHERE state = 'queued' AND next_attempt_at <= ?
+                 ORDER BY next_attempt_at ASC
+                 LIMIT ?",
+            )?
+            .query_map(params![now, limit as i64], Self::row_to_job)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        for job in &jobs {
+            conn.execute(
+                "UPDATE download_jobs SET state = 'running' WHERE attachment_id = ?",
+                params![job.attachment_id],
+            )?;
+        }
+
+        Ok(jobs
+            .into_iter()
+            .map(|job| DownloadJob { state: JobState::Running, ..job })
+            .collect())
+    }
+
+    /// Put a failed job back in the queue to retry at `next_attempt_at`
+    pub fn reschedule(&self, attachment_id: &str, attempts: u32, next_attempt_at: i64, error: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "UPDATE download_jobs
+             SET state = 'queued', attempts = ?, next_attempt_at = ?, last_error = ?
+             WHERE attachment_id = ?",
+            params![attempts, next_attempt_at, error, attachment_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn mark_failed(&self, attachment_id: &str, attempts: u32, error: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "UPDATE download_jobs SET state = 'failed', attempts = ?, last_error = ? WHERE attachment_id = ?",
+            params![attempts, error, attachment_id],
+        )?;
+        Ok(())
+    }
+
+    /// Return jobs left running by a previous session to the queue
+    pub fn requeue_running(&self) -> Result<usize> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let count = conn.execute(
+            "UPDATE download_jobs SET state = 'queued' WHERE state = 'running'",
+            [],
+        )?;
+        Ok(count)
+    }
+
+    pub fn delete(&self, attachment_id: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "DELETE FROM download_jobs WHERE attachment_id = ?",
+            params![attachment_id],
+        )?;
+        Ok(())
+    }
+
+    fn row_to_job(row: &rusqlite::Row<'_>) -> rusqlite::Result<DownloadJob> {
+        Ok(DownloadJob {
+            attachment_id: row.get(0)?,
+            message_id: row.get(1)?,
+            state: JobState::parse(&row.get::<_, String>(2)?),
+            attempts: row.get(3)?,
+            next_attempt_at: row.get(4)?,
+            last_error: row.get(5)?,
+            created_at: row.get(6)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    const TEST_KEY: &str = "test-passphrase-123";
+
+    fn create_test_db() -> (Database, TempDir) {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::open_encrypted(&db_path, TEST_KEY).unwrap();
+        (db, dir)
+    }
+
+    fn test_attachment(id: &str, message_id: &str) -> StoredAttachment {
+        let now = Utc::now().timestamp();
+        StoredAttachment {
+            id: id.to_string(),
+            message_id: message_id.to_string(),
+            content_type: "image/jpeg".to_string(),
+            filename: Some("photo.jpg".to_string()),
+            size: 1234,
+            width: Some(640),
+            height: Some(480),
+            voice_note: false,
+            cdn_number: 2,
+            cdn_key: "abc123".to_string(),
+            key: vec![7u8; 64],
+            digest: Some(vec![1u8; 32]),
+            status: DownloadStatus::Pending,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_save_and_get_attachment() {
+        let (db, _dir) = create_test_db();
+        let repo = AttachmentRepository::new(&db);
+
+        repo.save(&test_attachment("a1.jpg", "msg1")).unwrap();
+
+        let retrieved = repo.get("a1.jpg").unwrap();
+        assert_eq!(retrieved.message_id, "msg1");
+        assert_eq!(retrieved.cdn_number, 2);
+        assert_eq!(retrieved.cdn_key, "abc123");
+        assert_eq!(retrieved.key, vec![7u8; 64]);
+        assert_eq!(retrieved.digest, Some(vec![1u8; 32]));
+        assert_eq!(retrieved.status, DownloadStatus::Pending);
+    }
+
+    #[test]
+    fn test_set_status() {
+        let (db, _dir) = create_test_db();
+        let repo = AttachmentRepository::new(&db);
+
+        repo.save(&test_attachment("a1.jpg", "msg1")).unwrap();
+        repo.save(&test_attachment("a2.jpg", "msg2")).unwrap();
+        repo.set_status("a1.jpg", DownloadStatus::Downloaded).unwrap();
+
+        assert_eq!(repo.get("a1.jpg").unwrap().status, DownloadStatus::Downloaded);
+        let pending = repo.list_by_status(DownloadStatus::Pending);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, "a2.jpg");
+    }
+
+    #[test]
+    fn test_delete_for_message() {
+        let (db, _dir) = create_test_db();
+        let repo = AttachmentRepository::new(&db);
+
+        repo.save(&test_attachment("a1.jpg", "msg1")).unwrap();
+        repo.save(&test_attachment("a2.jpg", "msg1")).unwrap();
+        assert_eq!(repo.get_for_message("msg1").len(), 2);
+
+        repo.delete_for_message("msg1").unwrap();
+        assert!(repo.get_for_message("msg1").is_empty());
+    }
+
+    #[test]
+    fn test_download_job_take_due_and_reschedule() {
+        let (db, _dir) = create_test_db();
+        let jobs = DownloadJobRepository::new(&db);
+
+        jobs.enqueue("a1.jpg", "msg1").unwrap();
+        jobs.enqueue("a2.jpg", "msg2").unwrap();
+
+        let now = Utc::now().timestamp();
+        let due = jobs.take_due(now, 1).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].state, JobState::Running);
+
+        // The claimed job is not handed out twice
+        let due_again = jobs.take_due(now, 10).unwrap();
+        assert_eq!(due_again.len(), 1);
+        assert_ne!(due_again[0].attachment_id, due[0].attachment_id);
+
+        jobs.reschedule(&due[0].attachment_id, 1, now + 60, "timeout").unwrap();
+        assert!(jobs.take_due(now, 10).unwrap().is_empty());
+        assert_eq!(jobs.take_due(now + 60, 10).unwrap().len(), 1);
+
+        let job = jobs.get(&due[0].attachment_id).unwrap();
+        assert_eq!(job.attempts, 1);
+        assert_eq!(job.last_error.as_deref(), Some("timeout"));
+    }
+
+    #[test]
+    fn test_download_job_requeue_running() {
+        let (db, _dir) = create_test_db();
+        let jobs = DownloadJobRepository::new(&db);
+
+        jobs.enqueue("a1.jpg", "msg1").unwrap();
+        let now = Utc::now().timestamp();
+        assert_eq!(jobs.take_due(now, 10).unwrap().len(), 1);
+
+        assert_eq!(jobs.requeue_running().unwrap(), 1);
+        assert_eq!(jobs.get("a1.jpg").unwrap().state, JobState::Queued);
+    }
+}
@@ -1,7 +1,51 @@
+//! Contacts: profile data, verification and block state
+//!
+//! This is the one place contacts are kept. The protocol layer writes what it
+//! learns from presage and the primary device, the UI reads and edits the same
+//! rows, so both always agree.
+
 use crate::storage::database::Database;
 use anyhow::Result;
-use chrono::{TimeZone, Utc};
-use rusqlite::params;
+use chrono::Utc;
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+
+/// Age after which a contact's profile is fetched again
+pub const PROFILE_REFRESH_SECS: i64 = 24 * 60 * 60;
+
+const CONTACT_COLUMNS: &str = "id, uuid, phone_number, name, profile_name, avatar_path,
+    profile_key, is_blocked, verification_state, nickname, profile_fetched_at,
+    created_at, updated_at";
+
+/// Identity verification state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum VerificationState {
+    /// Default - not verified
+    #[default]
+    Default,
+    /// Verified (safety number checked)
+    Verified,
+    /// Unverified (identity key changed after being verified)
+    Unverified,
+}
+
+impl VerificationState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Verified => "verified",
+            Self::Unverified => "unverified",
+        }
+    }
+
+    fn from_str(s: &str) -> Self {
+        match s {
+            "verified" => Self::Verified,
+            "unverified" => Self::Unverified,
+            _ => Self::Default,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct StoredContact {
@@ -13,7 +57,11 @@ pub struct StoredContact {
     pub avatar_path: Option<String>,
     pub profile_key: Option<Vec<u8>>,
     pub is_blocked: bool,
-    pub is_verified: bool,
+    pub verification: VerificationState,
+    /// Name we gave the contact ourselves; shown before any other
+    pub nickname: Option<String>,
+    /// When the profile (name, avatar) was last fetched
+    pub profile_fetched_at: Option<i64>,
     pub created_at: i64,
     pub updated_at: i64,
 }
@@ -30,20 +78,53 @@ impl StoredContact {
             avatar_path: None,
             profile_key: None,
             is_blocked: false,
-            is_verified: false,
+            verification: VerificationState::Default,
+            nickname: None,
+            profile_fetched_at: None,
             created_at: now,
             updated_at: now,
         }
     }
 
+    /// Nickname, then profile name, then contact name, phone number or UUID
     pub fn display_name(&self) -> &str {
-        self.profile_name
+        self.nickname
             .as_deref()
             .filter(|s| !s.is_empty())
+            .or(self.profile_name.as_deref().filter(|s| !s.is_empty()))
             .or(Some(self.name.as_str()).filter(|s| !s.is_empty()))
             .or(self.phone_number.as_deref())
             .unwrap_or(&self.uuid)
     }
+
+    pub fn is_verified(&self) -> bool {
+        self.verification == VerificationState::Verified
+    }
+
+    /// Whether the profile was never fetched or is older than a day
+    pub fn needs_profile_refresh(&self, now: i64) -> bool {
+        !self
+            .profile_fetched_at
+            .is_some_and(|fetched| now - fetched <= PROFILE_REFRESH_SECS)
+    }
+}
+
+fn row_to_contact(row: &Row) -> rusqlite::Result<StoredContact> {
+    Ok(StoredContact {
+        id: row.get(0)?,
+        uuid: row.get(1)?,
+        phone_number: row.get(2)?,
+        name: row.get(3)?,
+        profile_name: row.get(4)?,
+        avatar_path: row.get(5)?,
+        profile_key: row.get(6)?,
+        is_blocked: row.get::<_, i64>(7)? != 0,
+        verification: VerificationState::from_str(&row.get::<_, String>(8)?),
+        nickname: row.get(9)?,
+        profile_fetched_at: row.get(10)?,
+        created_at: row.get(11)?,
+        updated_at: row.get(12)?,
+    })
 }
 
 pub struct ContactRepository<'a> {
@@ -60,25 +141,9 @@ impl<'a> ContactRepository<'a> {
         let conn = conn.lock().unwrap();
 
         conn.query_row(
-            "SELECT id, uuid, phone_number, name, profile_name, avatar_path, 
-                    profile_key, is_blocked, is_verified, created_at, updated_at
-             FROM contacts WHERE id = ?",
+            &format!("SELECT {} FROM contacts WHERE id = ?", CONTACT_COLUMNS),
             params![id],
-            |row| {
-                Ok(StoredContact {
-                    id: row.get(0)?,
-                    uuid: row.get(1)?,
-                    phone_number: row.get(2)?,
-                    name: row.get(3)?,
-                    profile_name: row.get(4)?,
-                    avatar_path: row.get(5)?,
-                    profile_key: row.get(6)?,
-                    is_blocked: row.get::<_, i64>(7)? != 0,
-                    is_verified: row.get::<_, i64>(8)? != 0,
-                    created_at: row.get(9)?,
-                    updated_at: row.get(10)?,
-                })
-            },
+            row_to_contact,
         )
         .ok()
     }
@@ -88,25 +153,21 @@ impl<'a> ContactRepository<'a> {
         let conn = conn.lock().unwrap();
 
         conn.query_row(
-            "SELECT id, uuid, phone_number, name, profile_name, avatar_path, 
-                    profile_key, is_blocked, is_verified, created_at, updated_at
-             FROM contacts WHERE uuid = ?",
+            &format!("SELECT {} FROM contacts WHERE uuid = ?", CONTACT_COLUMNS),
             params![uuid],
-            |row| {
-                Ok(StoredContact {
-                    id: row.get(0)?,
-                    uuid: row.get(1)?,
-                    phone_number: row.get(2)?,
-                    name: row.get(3)?,
-                    profile_name: row.get(4)?,
-                    avatar_path: row.get(5)?,
-                    profile_key: row.get(6)?,
-                    is_blocked: row.get::<_, i64>(7)? != 0,
-                    is_verified: row.get::<_, i64>(8)? != 0,
-                    created_at: row.get(9)?,
-                    updated_at: row.get(10)?,
-                })
-            },
+            row_to_contact,
+        )
+        .ok()
+    }
+
+    pub fn get_by_phone(&self, phone_number: &str) -> Option<StoredContact> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.query_row(
+            &format!("SELECT {} FROM contacts WHERE phone_number = ?", CONTACT_COLUMNS),
+            params![phone_number],
+            row_to_contact,
         )
         .ok()
     }
@@ -115,11 +176,13 @@ impl<'a> ContactRepository<'a> {
         let conn = self.db.connection();
         let conn = conn.lock().unwrap();
 
+        // `is_verified` is kept for older builds reading the same database
         conn.execute(
-            "INSERT OR REPLACE INTO contacts 
-             (id, uuid, phone_number, name, profile_name, avatar_path, 
-              profile_key, is_blocked, is_verified, created_at, updated_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            &format!(
+                "INSERT OR REPLACE INTO contacts ({}, is_verified)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                CONTACT_COLUMNS
+            ),
             params![
                 contact.id,
                 contact.uuid,
@@ -129,44 +192,112 @@ impl<'a> ContactRepository<'a> {
                 contact.avatar_path,
                 contact.profile_key,
                 contact.is_blocked as i64,
-                contact.is_verified as i64,
+                contact.verification.as_str(),
+                contact.nickname,
+                contact.profile_fetched_at,
                 contact.created_at,
                 contact.updated_at,
+                contact.is_verified() as i64,
             ],
         )?;
         Ok(())
     }
 
     pub fn list(&self) -> Vec<StoredContact> {
+        self.query(&format!("SELECT {} FROM contacts ORDER BY name ASC", CONTACT_COLUMNS))
+    }
+
+    pub fn list_blocked(&self) -> Vec<StoredContact> {
+        self.query(&format!(
+            "SELECT {} FROM contacts WHERE is_blocked = 1 ORDER BY name ASC",
+            CONTACT_COLUMNS
+        ))
+    }
+
+    fn query(&self, sql: &str) -> Vec<StoredContact> {
         let conn = self.db.connection();
         let conn = conn.lock().unwrap();
 
-        let mut stmt = match conn.prepare(
-            "SELECT id, uuid, phone_number, name, profile_name, avatar_path, 
-                    profile_key, is_blocked, is_verified, created_at, updated_at
-             FROM contacts ORDER BY name ASC",
-        ) {
+        let mut stmt = match conn.prepare(sql) {
             Ok(s) => s,
             Err(_) => return Vec::new(),
         };
 
-        stmt.query_map([], |row| {
-            Ok(StoredContact {
-                id: row.get(0)?,
-                uuid: row.get(1)?,
-                phone_number: row.get(2)?,
-                name: row.get(3)?,
-                profile_name: row.get(4)?,
-                avatar_path: row.get(5)?,
-                profile_key: row.get(6)?,
-                is_blocked: row.get::<_, i64>(7)? != 0,
-                is_verified: row.get::<_, i64>(8)? != 0,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-            })
-        })
-        .map(|rows| rows.filter_map(|r| r.ok()).collect())
-        .unwrap_or_default()
+        stmt.query_map([], row_to_contact)
+            .map(|rows| rows.filter_map(|r| r.ok()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Block or unblock a contact together with its conversation. Unknown
+    /// senders get a contact row so the block sticks.
+    pub fn set_blocked(&self, uuid: &str, blocked: bool) -> Result<()> {
+        let conn = self.db.connection();
+        let mut conn = conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().timestamp();
+
+        tx.execute(
+            "INSERT OR IGNORE INTO contacts (id, uuid, name, created_at, updated_at) VALUES (?1, ?1, '', ?2, ?2)",
+            params![uuid, now],
+        )?;
+        tx.execute(
+            "UPDATE contacts SET is_blocked = ?, updated_at = ? WHERE uuid = ?",
+            params![blocked as i64, now, uuid],
+        )?;
+        tx.execute(
+            "UPDATE conversations SET is_blocked = ? WHERE id = ? AND conversation_type = 'private'",
+            params![blocked as i64, uuid],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Set how far we trust a contact's identity key. Like a block, this
+    /// creates the contact if we didn't have one.
+    pub fn set_verification(&self, uuid: &str, verification: VerificationState) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        conn.execute(
+            "INSERT OR IGNORE INTO contacts (id, uuid, name, created_at, updated_at) VALUES (?1, ?1, '', ?2, ?2)",
+            params![uuid, now],
+        )?;
+        conn.execute(
+            "UPDATE contacts SET verification_state = ?, is_verified = ?, updated_at = ? WHERE uuid = ?",
+            params![
+                verification.as_str(),
+                (verification == VerificationState::Verified) as i64,
+                now,
+                uuid
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Set or clear (`None`) the nickname we gave a contact
+    pub fn set_nickname(&self, uuid: &str, nickname: Option<&str>) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute(
+            "UPDATE contacts SET nickname = ?, updated_at = ? WHERE uuid = ?",
+            params![
+                nickname.map(str::trim).filter(|n| !n.is_empty()),
+                Utc::now().timestamp(),
+                uuid
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Record that a contact's profile was just fetched
+    pub fn mark_profile_fetched(&self, uuid: &str, at: i64) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute(
+            "UPDATE contacts SET profile_fetched_at = ? WHERE uuid = ?",
+            params![at, uuid],
+        )?;
+        Ok(())
     }
 
     pub fn delete(&self, id: &str) -> Result<()> {
@@ -261,4 +392,51 @@ mod tests {
 
         assert_eq!(repo.count(), 2);
     }
+
+    #[test]
+    fn test_domain_state_survives_reopen() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+
+        {
+            let db = Database::open_encrypted(&db_path, TEST_KEY).unwrap();
+            let repo = ContactRepository::new(&db);
+            let mut contact = StoredContact::new("uuid-1", "Alice");
+            contact.profile_name = Some("Alice A.".to_string());
+            repo.save(&contact).unwrap();
+
+            repo.set_nickname("uuid-1", Some("  Ali ")).unwrap();
+            repo.set_verification("uuid-1", VerificationState::Verified).unwrap();
+            repo.mark_profile_fetched("uuid-1", 1_000).unwrap();
+            repo.set_blocked("uuid-1", true).unwrap();
+        }
+
+        let db = Database::open_encrypted(&db_path, TEST_KEY).unwrap();
+        let contact = ContactRepository::new(&db).get_by_uuid("uuid-1").unwrap();
+        assert_eq!(contact.display_name(), "Ali");
+        assert!(contact.is_verified());
+        assert!(contact.is_blocked);
+        assert_eq!(contact.profile_fetched_at, Some(1_000));
+        assert!(contact.needs_profile_refresh(1_000 + PROFILE_REFRESH_SECS + 1));
+        assert!(!contact.needs_profile_refresh(1_000 + 60));
+    }
+
+    #[test]
+    fn test_block_updates_conversation() {
+        use crate::storage::conversations::{Conversation, ConversationRepository};
+
+        let (db, _dir) = create_test_db();
+        let conversations = ConversationRepository::new(&db);
+        conversations.save(&Conversation::new_private("stranger", "stranger")).unwrap();
+
+        let repo = ContactRepository::new(&db);
+        repo.set_blocked("stranger", true).unwrap();
+        assert!(repo.get_by_uuid("stranger").unwrap().is_blocked);
+        assert!(conversations.get("stranger").unwrap().is_blocked);
+        assert_eq!(repo.list_blocked().len(), 1);
+
+        repo.set_blocked("stranger", false).unwrap();
+        assert!(!conversations.get("stranger").unwrap().is_blocked);
+        assert!(repo.list_blocked().is_empty());
+    }
 }
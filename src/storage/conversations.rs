@@ -42,6 +42,8 @@ pub struct Conversation {
     pub muted_until: Option<DateTime<Utc>>,
     pub is_archived: bool,
     pub is_blocked: bool,
+    /// False while the conversation is a message request from a stranger
+    pub is_accepted: bool,
     pub disappearing_messages_timer: u32,
     pub draft: Option<String>,
     pub created_at: DateTime<Utc>,
@@ -64,6 +66,7 @@ impl Conversation {
             muted_until: None,
             is_archived: false,
             is_blocked: false,
+            is_accepted: true,
             disappearing_messages_timer: 0,
             draft: None,
             created_at: now,
@@ -86,6 +89,7 @@ impl Conversation {
             muted_until: None,
             is_archived: false,
             is_blocked: false,
+            is_accepted: true,
             disappearing_messages_timer: 0,
             draft: None,
             created_at: now,
@@ -145,7 +149,7 @@ impl<'a> ConversationRepository<'a> {
         conn.query_row(
             "SELECT id, conversation_type, name, avatar_path, last_message, 
                     last_message_at, unread_count, is_pinned, is_muted, muted_until,
-                    is_archived, is_blocked, disappearing_timer, draft, created_at, updated_at
+                    is_archived, is_blocked, disappearing_timer, draft, created_at, updated_at, is_accepted
              FROM conversations WHERE id = ?",
             params![id],
             |row| {
@@ -166,6 +170,7 @@ impl<'a> ConversationRepository<'a> {
                     draft: row.get(13)?,
                     created_at: Utc.timestamp_opt(row.get::<_, i64>(14)?, 0).unwrap(),
                     updated_at: Utc.timestamp_opt(row.get::<_, i64>(15)?, 0).unwrap(),
+                    is_accepted: row.get::<_, i64>(16)? != 0,
                 })
             },
         ).ok()
@@ -179,8 +184,8 @@ impl<'a> ConversationRepository<'a> {
             "INSERT OR REPLACE INTO conversations 
              (id, conversation_type, name, avatar_path, last_message, last_message_at,
               unread_count, is_pinned, is_muted, muted_until, is_archived, is_blocked,
-              disappearing_timer, draft, created_at, updated_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+              disappearing_timer, draft, created_at, updated_at, is_accepted)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 conv.id,
                 conv.conversation_type.as_str(),
@@ -198,6 +203,7 @@ impl<'a> ConversationRepository<'a> {
                 conv.draft,
                 conv.created_at.timestamp(),
                 conv.updated_at.timestamp(),
+                conv.is_accepted as i64,
             ],
         )?;
         Ok(())
@@ -210,7 +216,7 @@ impl<'a> ConversationRepository<'a> {
         let mut stmt = match conn.prepare(
             "SELECT id, conversation_type, name, avatar_path, last_message, 
                     last_message_at, unread_count, is_pinned, is_muted, muted_until,
-                    is_archived, is_blocked, disappearing_timer, draft, created_at, updated_at
+                    is_archived, is_blocked, disappearing_timer, draft, created_at, updated_at, is_accepted
              FROM conversations 
              ORDER BY is_pinned DESC, updated_at DESC"
         ) {
@@ -236,6 +242,7 @@ impl<'a> ConversationRepository<'a> {
                 draft: row.get(13)?,
                 created_at: Utc.timestamp_opt(row.get::<_, i64>(14)?, 0).unwrap(),
                 updated_at: Utc.timestamp_opt(row.get::<_, i64>(15)?, 0).unwrap(),
+                is_accepted: row.get::<_, i64>(16)? != 0,
             })
         })
         .map(|rows| rows.filter_map(|r| r.ok()).collect())
@@ -249,7 +256,7 @@ impl<'a> ConversationRepository<'a> {
         let mut stmt = match conn.prepare(
             "SELECT c.id, c.conversation_type, c.name, c.avatar_path, c.last_message, 
                     c.last_message_at, c.unread_count, c.is_pinned, c.is_muted, c.muted_until,
-                    c.is_archived, c.is_blocked, c.disappearing_timer, c.draft, c.created_at, c.updated_at
+                    c.is_archived, c.is_blocked, c.disappearing_timer, c.draft, c.created_at, c.updated_at, c.is_accepted
              FROM conversations c
              WHERE c.is_archived = 0
                AND EXISTS (SELECT 1 FROM messages m WHERE m.conversation_id = c.id)
@@ -277,6 +284,7 @@ impl<'a> ConversationRepository<'a> {
                 draft: row.get(13)?,
                 created_at: Utc.timestamp_opt(row.get::<_, i64>(14)?, 0).unwrap(),
                 updated_at: Utc.timestamp_opt(row.get::<_, i64>(15)?, 0).unwrap(),
+                is_accepted: row.get::<_, i64>(16)? != 0,
             })
         })
         .map(|rows| rows.filter_map(|r| r.ok()).collect())
@@ -1,3 +1,4 @@
+use super::migrations;
 use anyhow::Result;
 use rusqlite::Connection;
 use std::path::Path;
@@ -14,86 +15,24 @@ impl Database {
         let db = Self {
             conn: Arc::new(Mutex::new(conn)),
         };
-        db.initialize()?;
+        db.initialize(path)?;
         Ok(db)
     }
 
-    fn initialize(&self) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        
-        conn.execute_batch(
-            "
-            CREATE TABLE IF NOT EXISTS conversations (
-                id TEXT PRIMARY KEY,
-                conversation_type TEXT NOT NULL,
-                name TEXT NOT NULL,
-                avatar_path TEXT,
-                last_message TEXT,
-                last_message_at INTEGER,
-                unread_count INTEGER DEFAULT 0,
-                is_pinned INTEGER DEFAULT 0,
-                is_muted INTEGER DEFAULT 0,
-                muted_until INTEGER,
-                is_archived INTEGER DEFAULT 0,
-                is_blocked INTEGER DEFAULT 0,
-                disappearing_timer INTEGER DEFAULT 0,
-                draft TEXT,
-                created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
-            );
-
-            CREATE TABLE IF NOT EXISTS messages (
-                id TEXT PRIMARY KEY,
-                conversation_id TEXT NOT NULL,
-                sender TEXT NOT NULL,
-                direction TEXT NOT NULL,
-                status TEXT NOT NULL,
-                content_type TEXT NOT NULL,
-                content_json TEXT NOT NULL,
-                sent_at INTEGER NOT NULL,
-                server_timestamp INTEGER,
-                delivered_at INTEGER,
-                read_at INTEGER,
-                quote_json TEXT,
-                reactions_json TEXT,
-                expires_in_seconds INTEGER,
-                expires_at INTEGER,
-                FOREIGN KEY (conversation_id) REFERENCES conversations(id)
-            );
-
-            CREATE TABLE IF NOT EXISTS contacts (
-                id TEXT PRIMARY KEY,
-                phone_number TEXT,
-                uuid TEXT UNIQUE,
-                name TEXT NOT NULL,
-                profile_name TEXT,
-                avatar_path TEXT,
-                profile_key BLOB,
-                is_blocked INTEGER DEFAULT 0,
-                is_verified INTEGER DEFAULT 0,
-                created_at INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
-            );
-
-            CREATE TABLE IF NOT EXISTS settings (
-                key TEXT PRIMARY KEY,
-                value TEXT NOT NULL
-            );
-
-            CREATE INDEX IF NOT EXISTS idx_messages_conversation 
-                ON messages(conversation_id, sent_at DESC);
-            CREATE INDEX IF NOT EXISTS idx_messages_sender 
-                ON messages(sender);
-            CREATE INDEX IF NOT EXISTS idx_conversations_updated 
-                ON conversations(updated_at DESC);
-            CREATE INDEX IF NOT EXISTS idx_contacts_uuid 
-                ON contacts(uuid);
-            "
-        )?;
-
+    fn initialize(&self, path: &Path) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let version = migrations::run(&mut conn, Some(path))?;
+        tracing::debug!("App database at schema version {}", version);
         Ok(())
     }
 
+    /// Rebuild the search index from scratch, e.g. after rowids changed
+    pub fn rebuild_search_index(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM messages_fts", [])?;
+        migrations::backfill_search_index(&conn)
+    }
+
     pub fn connection(&self) -> Arc<Mutex<Connection>> {
         self.conn.clone()
     }
@@ -133,6 +72,46 @@ mod tests {
         assert!(tables.contains(&"messages".to_string()));
         assert!(tables.contains(&"contacts".to_string()));
         assert!(tables.contains(&"settings".to_string()));
+        assert!(tables.contains(&"attachments".to_string()));
+        assert!(tables.contains(&"download_jobs".to_string()));
+        assert!(tables.contains(&"messages_fts".to_string()));
+        assert!(tables.contains(&"groups_v2".to_string()));
+        assert!(tables.contains(&"group_members".to_string()));
+        assert!(tables.contains(&"identities".to_string()));
+        assert!(tables.contains(&"outbox".to_string()));
+        assert!(tables.contains(&"sticker_packs".to_string()));
+        assert!(tables.contains(&"stickers".to_string()));
+    }
+
+    #[test]
+    fn test_search_index_backfilled_on_upgrade() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+
+        {
+            let db = Database::open_encrypted(&db_path, TEST_PASSPHRASE).unwrap();
+            let conn = db.conn.lock().unwrap();
+            conn.execute_batch(
+                "INSERT INTO conversations (id, conversation_type, name, created_at, updated_at)
+                     VALUES ('c1', 'private', 'Test', 0, 0);
+                 INSERT INTO messages (id, conversation_id, sender, direction, status, content_type, content_json, sent_at)
+                     VALUES ('m1', 'c1', 's1', 'incoming', 'read', 'text', '{\"type\":\"Text\",\"body\":\"legacy row\",\"mentions\":[]}', 0);
+                 DROP TABLE messages_fts;
+                 PRAGMA user_version = 2;",
+            )
+            .unwrap();
+        }
+
+        let db = Database::open_encrypted(&db_path, TEST_PASSPHRASE).unwrap();
+        let conn = db.conn.lock().unwrap();
+        let hits: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'legacy'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hits, 1);
     }
 
     #[test]
@@ -0,0 +1,275 @@
+//! Group storage with SQLite
+
+use crate::signal::groups::{AccessControl, Group, GroupMember, MemberRole, PendingMember};
+use crate::storage::database::Database;
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use rusqlite::{params, Connection, Row};
+
+const GROUP_COLUMNS: &str = "id, master_key, name, description, avatar_path, attributes_access,
+    members_access, link_enabled, invite_link, disappearing_timer, is_blocked, has_left,
+    revision, created_at, updated_at";
+
+/// Group repository for database operations
+pub struct GroupRepository<'a> {
+    db: &'a Database,
+}
+
+impl<'a> GroupRepository<'a> {
+    /// Create a new repository with database reference
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Get a group with its members
+    pub fn get(&self, id: &str) -> Option<Group> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let mut group = conn
+            .query_row(
+                &format!("SELECT {} FROM groups_v2 WHERE id = ?", GROUP_COLUMNS),
+                params![id],
+                row_to_group,
+            )
+            .ok()?;
+        load_members(&conn, &mut group);
+        Some(group)
+    }
+
+    /// Save a group, replacing its member list
+    pub fn save(&self, group: &Group) -> Result<()> {
+        let conn = self.db.connection();
+        let mut conn = conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            &format!(
+                "INSERT OR REPLACE INTO groups_v2 ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                GROUP_COLUMNS
+            ),
+            params![
+                group.id,
+                group.master_key,
+                group.name,
+                group.description,
+                group.avatar_path,
+                group.attributes_access.as_str(),
+                group.members_access.as_str(),
+                group.link_enabled as i64,
+                group.invite_link,
+                group.disappearing_messages_timer,
+                group.blocked as i64,
+                group.left as i64,
+                group.revision,
+                group.created_at.timestamp(),
+                group.updated_at.timestamp(),
+            ],
+        )?;
+
+        tx.execute("DELETE FROM group_members WHERE group_id = ?", params![group.id])?;
+        for member in &group.members {
+            tx.execute(
+                "INSERT INTO group_members (group_id, uuid, role, pending, added_by, joined_at)
+                 VALUES (?, ?, ?, 0, NULL, ?)",
+                params![group.id, member.uuid, member.role.as_str(), member.joined_at.timestamp()],
+            )?;
+        }
+        for pending in &group.pending_members {
+            tx.execute(
+                "INSERT OR IGNORE INTO group_members (group_id, uuid, role, pending, added_by, joined_at)
+                 VALUES (?, ?, 'default', 1, ?, ?)",
+                params![group.id, pending.uuid, pending.added_by, pending.invited_at.timestamp()],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get all groups, by name
+    pub fn list(&self) -> Vec<Group> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let mut stmt = match conn.prepare(&format!("SELECT {} FROM groups_v2 ORDER BY name ASC", GROUP_COLUMNS)) {
+            Ok(s) => s,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut groups: Vec<Group> = stmt
+            .query_map([], row_to_group)
+            .map(|rows| rows.filter_map(|r| r.ok()).collect())
+            .unwrap_or_default();
+        for group in &mut groups {
+            load_members(&conn, group);
+        }
+        groups
+    }
+
+    /// Get groups we're still in and haven't blocked
+    pub fn list_active(&self) -> Vec<Group> {
+        self.list().into_iter().filter(|g| !g.left && !g.blocked).collect()
+    }
+
+    /// Block or unblock a group together with its conversation
+    pub fn set_blocked(&self, id: &str, blocked: bool) -> Result<()> {
+        let conn = self.db.connection();
+        let mut conn = conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "UPDATE groups_v2 SET is_blocked = ?, updated_at = ? WHERE id = ?",
+            params![blocked as i64, Utc::now().timestamp(), id],
+        )?;
+        tx.execute(
+            "UPDATE conversations SET is_blocked = ? WHERE id = ? AND conversation_type = 'group'",
+            params![blocked as i64, id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Delete a group and its members
+    pub fn delete(&self, id: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute("DELETE FROM group_members WHERE group_id = ?", params![id])?;
+        conn.execute("DELETE FROM groups_v2 WHERE id = ?", params![id])?;
+        Ok(())
+    }
+}
+
+fn row_to_group(row: &Row) -> rusqlite::Result<Group> {
+    let timestamp = |secs: i64| Utc.timestamp_opt(secs, 0).single().unwrap_or_else(Utc::now);
+
+    Ok(Group {
+        id: row.get(0)?,
+        master_key: row.get(1)?,
+        name: row.get(2)?,
+        description: row.get(3)?,
+        avatar_path: row.get(4)?,
+        members: Vec::new(),
+        pending_members: Vec::new(),
+        attributes_access: AccessControl::from_str(&row.get::<_, String>(5)?),
+        members_access: AccessControl::from_str(&row.get::<_, String>(6)?),
+        link_enabled: row.get::<_, i64>(7)? != 0,
+        invite_link: row.get(8)?,
+        disappearing_messages_timer: row.get(9)?,
+        blocked: row.get::<_, i64>(10)? != 0,
+        left: row.get::<_, i64>(11)? != 0,
+        revision: row.get(12)?,
+        created_at: timestamp(row.get(13)?),
+        updated_at: timestamp(row.get(14)?),
+    })
+}
+
+fn load_members(conn: &Connection, group: &mut Group) {
+    let Ok(mut stmt) = conn.prepare(
+        "SELECT uuid, role, pending, added_by, joined_at FROM group_members
+         WHERE group_id = ? ORDER BY joined_at ASC, uuid ASC",
+    ) else {
+        return;
+    };
+
+    let rows = stmt.query_map(params![group.id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)? != 0,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, i64>(4)?,
+        ))
+    });
+
+    for (uuid, role, pending, added_by, at) in rows.into_iter().flatten().filter_map(|r| r.ok()) {
+        let at = Utc.timestamp_opt(at, 0).single().unwrap_or_else(Utc::now);
+        if pending {
+            group.pending_members.push(PendingMember {
+                uuid,
+                added_by: added_by.unwrap_or_default(),
+                invited_at: at,
+            });
+        } else {
+            group.members.push(GroupMember {
+                uuid,
+                role: MemberRole::from_str(&role),
+                joined_at: at,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TEST_KEY: &str = "test-passphrase-123";
+
+    fn create_test_db() -> (Database, tempfile::TempDir) {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::open_encrypted(&db_path, TEST_KEY).unwrap();
+        (db, dir)
+    }
+
+    #[test]
+    fn test_save_and_get_group_with_members() {
+        let (db, _dir) = create_test_db();
+        let repo = GroupRepository::new(&db);
+
+        let mut group = Group::new("group-1", "Climbing");
+        group.master_key = Some(vec![7; 32]);
+        group.members_access = AccessControl::Administrator;
+        group.add_member("alice", MemberRole::Administrator);
+        group.add_member("bob", MemberRole::Default);
+        group.pending_members.push(PendingMember {
+            uuid: "carol".to_string(),
+            added_by: "alice".to_string(),
+            invited_at: Utc::now(),
+        });
+        repo.save(&group).unwrap();
+
+        let stored = repo.get("group-1").unwrap();
+        assert_eq!(stored.name, "Climbing");
+        assert_eq!(stored.master_key, Some(vec![7; 32]));
+        assert_eq!(stored.members_access, AccessControl::Administrator);
+        assert_eq!(stored.member_count(), 2);
+        assert!(stored.is_admin("alice"));
+        assert!(!stored.is_admin("bob"));
+        assert!(stored.is_pending("carol"));
+        assert_eq!(stored.revision, group.revision);
+
+        // Saving again replaces the member list
+        group.remove_member("bob");
+        group.pending_members.clear();
+        repo.save(&group).unwrap();
+        let stored = repo.get("group-1").unwrap();
+        assert_eq!(stored.member_count(), 1);
+        assert!(stored.pending_members.is_empty());
+    }
+
+    #[test]
+    fn test_list_active_skips_left_groups() {
+        let (db, _dir) = create_test_db();
+        let repo = GroupRepository::new(&db);
+
+        repo.save(&Group::new("a", "Active")).unwrap();
+        let mut left = Group::new("b", "Left");
+        left.left = true;
+        repo.save(&left).unwrap();
+
+        assert_eq!(repo.list().len(), 2);
+        let active = repo.list_active();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].id, "a");
+
+        repo.set_blocked("a", true).unwrap();
+        assert!(repo.get("a").unwrap().blocked);
+        assert!(repo.list_active().is_empty());
+
+        repo.delete("a").unwrap();
+        assert!(repo.get("a").is_none());
+    }
+}
@@ -0,0 +1,73 @@
+//! Identity keys last seen for each contact
+//!
+//! Presage keeps the identity keys it uses for encryption. This copy is what
+//! we last showed the user, so a key that differs from it is a safety number
+//! change.
+
+use crate::storage::database::Database;
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::params;
+
+pub struct IdentityRepository<'a> {
+    db: &'a Database,
+}
+
+impl<'a> IdentityRepository<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Serialized identity key recorded for a contact
+    pub fn get(&self, uuid: &str) -> Option<Vec<u8>> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT identity_key FROM identities WHERE uuid = ?",
+            params![uuid],
+            |row| row.get(0),
+        )
+        .ok()
+    }
+
+    pub fn save(&self, uuid: &str, identity_key: &[u8]) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO identities (uuid, identity_key, updated_at) VALUES (?, ?, ?)",
+            params![uuid, identity_key, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete(&self, uuid: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute("DELETE FROM identities WHERE uuid = ?", params![uuid])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TEST_KEY: &str = "test-passphrase-123";
+
+    #[test]
+    fn test_save_replaces_key() {
+        let dir = tempdir().unwrap();
+        let db = Database::open_encrypted(&dir.path().join("test.db"), TEST_KEY).unwrap();
+        let repo = IdentityRepository::new(&db);
+
+        assert!(repo.get("alice").is_none());
+        repo.save("alice", &[1; 33]).unwrap();
+        repo.save("alice", &[2; 33]).unwrap();
+        assert_eq!(repo.get("alice"), Some(vec![2; 33]));
+
+        repo.delete("alice").unwrap();
+        assert!(repo.get("alice").is_none());
+    }
+}
@@ -4,7 +4,10 @@ use crate::signal::messages::{Content, Message, MessageDirection, MessageStatus,
 use crate::storage::database::Database;
 use anyhow::Result;
 use chrono::{DateTime, TimeZone, Utc};
-use rusqlite::params;
+use chrono::{Local, NaiveDate};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter};
+use std::ops::Range;
 
 /// Message repository for database operations
 pub struct MessageRepository<'a> {
@@ -25,7 +28,7 @@ impl<'a> MessageRepository<'a> {
         conn.query_row(
             "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
                     sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
-                    expires_in_seconds, expires_at
+                    expires_in_seconds, expires_at, signal_timestamp, edited_at
              FROM messages WHERE id = ?",
             params![id],
             |row| Ok(Self::row_to_message(row)),
@@ -34,6 +37,216 @@ impl<'a> MessageRepository<'a> {
         .flatten()
     }
 
+    /// Find a message by its author and sent timestamp, the way other
+    /// messages refer to it. `"self"` matches our own messages, both those
+    /// sent from here and those synced from our other devices.
+    ///
+    /// A message with several attachments is stored as one row each; this
+    /// returns the first of `find_all_by_timestamp`, so it is always the row
+    /// holding the text or caption when there is one.
+    pub fn find_by_timestamp(&self, author: &str, signal_timestamp: u64) -> Option<Message> {
+        self.find_all_by_timestamp(author, signal_timestamp).into_iter().next()
+    }
+
+    /// Every row stored for the message its author sent at `signal_timestamp`.
+    ///
+    /// The row that can hold text comes first (the body, then a captioned or
+    /// captionable image/video), the rest follow by ID, so the order is the
+    /// same on every lookup.
+    pub fn find_all_by_timestamp(&self, author: &str, signal_timestamp: u64) -> Vec<Message> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let mut messages: Vec<Message> = conn
+            .prepare(
+                "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
+                        sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
+                        expires_in_seconds, expires_at, signal_timestamp, edited_at
+                 FROM messages
+                 WHERE signal_timestamp = ?
+                   AND CASE WHEN ? = 'self' THEN direction = 'outgoing' OR sender = 'self'
+                            ELSE sender = ? END
+                 ORDER BY id ASC",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map(params![signal_timestamp as i64, author, author], |row| {
+                    Ok(Self::row_to_message(row))
+                })
+                .map(|rows| rows.filter_map(|r| r.ok().flatten()).collect())
+            })
+            .unwrap_or_default();
+
+        messages.sort_by_key(|message| match &message.content {
+            Content::Text { .. } => 0,
+            Content::Image { caption: Some(_), .. } | Content::Video { caption: Some(_), .. } => 1,
+            Content::Image { .. } | Content::Video { .. } => 2,
+            _ => 3,
+        });
+        messages
+    }
+
+    /// Replace the text of a message, keeping the previous version in
+    /// `message_edits`. Returns the updated message, or `None` if the message
+    /// doesn't exist or has no editable text.
+    pub fn apply_edit(
+        &self,
+        message_id: &str,
+        text: &str,
+        edited_at: DateTime<Utc>,
+    ) -> Result<Option<Message>> {
+        let Some(mut message) = self.get(message_id) else {
+            return Ok(None);
+        };
+
+        let (_, previous_json) = Self::serialize_content(&message.content);
+        let previous_at = message.edited_at.unwrap_or(message.sent_at);
+        if !message.set_text(text) {
+            return Ok(None);
+        }
+        message.edited_at = Some(edited_at);
+        let (content_type, content_json) = Self::serialize_content(&message.content);
+
+        let conn = self.db.connection();
+        let mut conn = conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO message_edits (message_id, content_json, created_at, replaced_at)
+             VALUES (?, ?, ?, ?)",
+            params![message_id, previous_json, previous_at.timestamp(), edited_at.timestamp()],
+        )?;
+        tx.execute(
+            "UPDATE messages SET content_type = ?, content_json = ?, edited_at = ? WHERE id = ?",
+            params![content_type, content_json, edited_at.timestamp(), message_id],
+        )?;
+        tx.commit()?;
+
+        Ok(Some(message))
+    }
+
+    /// Replace a message with a tombstone after a delete for everyone.
+    ///
+    /// Drops its text, quote, reactions, edit history and attachment records in
+    /// one transaction; the row itself stays so the conversation keeps its
+    /// place. Returns the message as it was before, so the caller can remove
+    /// downloaded files, or `None` if it doesn't exist.
+    pub fn tombstone(&self, message_id: &str) -> Result<Option<Message>> {
+        let Some(message) = self.get(message_id) else {
+            return Ok(None);
+        };
+        let (content_type, content_json) = Self::serialize_content(&Content::Deleted);
+
+        let conn = self.db.connection();
+        let mut conn = conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute("DELETE FROM message_edits WHERE message_id = ?", params![message_id])?;
+        tx.execute("DELETE FROM download_jobs WHERE message_id = ?", params![message_id])?;
+        tx.execute("DELETE FROM attachments WHERE message_id = ?", params![message_id])?;
+        tx.execute(
+            "UPDATE messages
+             SET content_type = ?, content_json = ?, quote_json = NULL, reactions_json = NULL,
+                 edited_at = NULL
+             WHERE id = ?",
+            params![content_type, content_json, message_id],
+        )?;
+        tx.commit()?;
+
+        Ok(Some(message))
+    }
+
+    /// Record the duration and waveform of a voice note once its audio was
+    /// decoded. Returns false if the message isn't audio.
+    pub fn set_audio_details(&self, message_id: &str, duration: u64, wave: Vec<u8>) -> Result<bool> {
+        let Some(mut message) = self.get(message_id) else {
+            return Ok(false);
+        };
+        let Content::Audio { duration_ms, waveform, .. } = &mut message.content else {
+            return Ok(false);
+        };
+        *duration_ms = duration;
+        *waveform = Some(wave);
+        let (content_type, content_json) = Self::serialize_content(&message.content);
+
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute(
+            "UPDATE messages SET content_type = ?, content_json = ? WHERE id = ?",
+            params![content_type, content_json, message_id],
+        )?;
+        Ok(true)
+    }
+
+    /// Add `sender`'s reaction to a message, replacing their previous one, or
+    /// take it back with `remove`. A removal only applies while `emoji` is
+    /// still their current reaction. Returns false if nothing changed.
+    pub fn apply_reaction(&self, message_id: &str, emoji: &str, sender: &str, remove: bool) -> Result<bool> {
+        let Some(mut message) = self.get(message_id) else {
+            return Ok(false);
+        };
+
+        if remove {
+            if !message.reactions.iter().any(|r| r.sender == sender && r.emoji == emoji) {
+                return Ok(false);
+            }
+            message.remove_reaction(sender);
+        } else {
+            message.add_reaction(emoji, sender);
+        }
+
+        let reactions_json = if message.reactions.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&message.reactions)?)
+        };
+
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute(
+            "UPDATE messages SET reactions_json = ? WHERE id = ?",
+            params![reactions_json, message_id],
+        )?;
+        Ok(true)
+    }
+
+    /// Earlier versions of an edited message, oldest first
+    pub fn get_edit_history(&self, message_id: &str) -> Vec<MessageVersion> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.prepare(
+            "SELECT content_json, created_at, replaced_at FROM message_edits
+             WHERE message_id = ? ORDER BY id ASC",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![message_id], |row| {
+                let content_json: String = row.get(0)?;
+                let created_at: i64 = row.get(1)?;
+                let replaced_at: i64 = row.get(2)?;
+                Ok(serde_json::from_str(&content_json).ok().map(|content| MessageVersion {
+                    content,
+                    created_at: Utc.timestamp_opt(created_at, 0).single().unwrap_or_default(),
+                    replaced_at: Utc.timestamp_opt(replaced_at, 0).single().unwrap_or_default(),
+                }))
+            })
+            .map(|rows| rows.filter_map(|r| r.ok().flatten()).collect())
+        })
+        .unwrap_or_default()
+    }
+
+    /// Number of times a message has been edited
+    pub fn edit_count(&self, message_id: &str) -> usize {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT COUNT(*) FROM message_edits WHERE message_id = ?",
+            params![message_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap_or(0) as usize
+    }
+
     /// Save a message (insert or update)
     pub fn save(&self, message: &Message) -> Result<()> {
         let conn = self.db.connection();
@@ -64,8 +277,8 @@ impl<'a> MessageRepository<'a> {
             "INSERT OR REPLACE INTO messages 
              (id, conversation_id, sender, direction, status, content_type, content_json,
               sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
-              expires_in_seconds, expires_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+              expires_in_seconds, expires_at, signal_timestamp, edited_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 message.id,
                 message.conversation_id,
@@ -82,6 +295,8 @@ impl<'a> MessageRepository<'a> {
                 reactions_json,
                 message.expires_in_seconds,
                 message.expires_at.map(|t| t.timestamp()),
+                message.signal_timestamp.map(|t| t as i64),
+                message.edited_at.map(|t| t.timestamp()),
             ],
         )?;
 
@@ -102,7 +317,7 @@ impl<'a> MessageRepository<'a> {
             conn.prepare(
                 "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
                         sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
-                        expires_in_seconds, expires_at
+                        expires_in_seconds, expires_at, signal_timestamp, edited_at
                  FROM messages 
                  WHERE conversation_id = ? AND sent_at < ?
                  ORDER BY sent_at DESC
@@ -119,7 +334,7 @@ impl<'a> MessageRepository<'a> {
             conn.prepare(
                 "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
                         sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
-                        expires_in_seconds, expires_at
+                        expires_in_seconds, expires_at, signal_timestamp, edited_at
                  FROM messages 
                  WHERE conversation_id = ?
                  ORDER BY sent_at DESC
@@ -144,7 +359,7 @@ impl<'a> MessageRepository<'a> {
         conn.prepare(
             "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
                     sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
-                    expires_in_seconds, expires_at
+                    expires_in_seconds, expires_at, signal_timestamp, edited_at
              FROM messages 
              WHERE conversation_id = ? AND direction = 'incoming' AND read_at IS NULL
              ORDER BY sent_at ASC",
@@ -158,53 +373,172 @@ impl<'a> MessageRepository<'a> {
         .unwrap_or_default()
     }
 
-    /// Search messages containing text
+    /// Full-text search over message bodies, captions, filenames and quotes.
+    ///
+    /// Bare words match as prefixes and `"quoted phrases"` match exactly; all
+    /// terms must match. Results are ranked by relevance, then recency.
     pub fn search(
         &self,
         conversation_id: Option<&str>,
         query: &str,
         limit: usize,
-    ) -> Vec<Message> {
+    ) -> Vec<SearchResult> {
+        let query = SearchQuery {
+            text: query.to_string(),
+            conversation_id: conversation_id.map(str::to_string),
+            ..Default::default()
+        };
+        self.search_query(&query, limit)
+    }
+
+    /// Search with filters. Without search text, matching messages are
+    /// returned newest first.
+    pub fn search_query(&self, query: &SearchQuery, limit: usize) -> Vec<SearchResult> {
+        let fts_query = build_fts_query(&query.text);
+        if fts_query.is_none() && !query.has_filters() {
+            return Vec::new();
+        }
+
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        let (source, snippet, order) = match fts_query {
+            Some(fts_query) => {
+                conditions.push("messages_fts MATCH ?");
+                values.push(Value::Text(fts_query));
+                (
+                    "messages_fts JOIN messages m ON m.rowid = messages_fts.rowid",
+                    format!(
+                        "snippet(messages_fts, 0, '{}', '{}', '…', 12)",
+                        HIGHLIGHT_START, HIGHLIGHT_END
+                    ),
+                    "bm25(messages_fts), m.sent_at DESC",
+                )
+            }
+            None => (
+                "messages m",
+                "coalesce(json_extract(m.content_json, '$.body'), json_extract(m.content_json, '$.caption'),
+                          json_extract(m.content_json, '$.filename'), '')"
+                    .to_string(),
+                "m.sent_at DESC",
+            ),
+        };
+
+        if let Some(conversation_id) = &query.conversation_id {
+            conditions.push("m.conversation_id = ?");
+            values.push(Value::Text(conversation_id.clone()));
+        }
+        if let Some(conversation) = &query.in_conversation {
+            conditions.push("(m.conversation_id = ? OR c.name LIKE ? ESCAPE '\\')");
+            values.push(Value::Text(conversation.clone()));
+            values.push(Value::Text(like_pattern(conversation)));
+        }
+        if let Some(from) = &query.from {
+            if from.eq_ignore_ascii_case("me") {
+                conditions.push("m.direction = 'outgoing'");
+            } else {
+                conditions.push(
+                    "(m.sender = ? OR m.sender IN (
+                        SELECT uuid FROM contacts
+                        WHERE name LIKE ? ESCAPE '\\' OR profile_name LIKE ? ESCAPE '\\' OR phone_number = ?))",
+                );
+                values.push(Value::Text(from.clone()));
+                values.push(Value::Text(like_pattern(from)));
+                values.push(Value::Text(like_pattern(from)));
+                values.push(Value::Text(from.clone()));
+            }
+        }
+        if let Some(before) = query.before {
+            conditions.push("m.sent_at < ?");
+            values.push(Value::Integer(start_of_day(before)));
+        }
+        if let Some(after) = query.after {
+            conditions.push("m.sent_at >= ?");
+            values.push(Value::Integer(start_of_day(after + chrono::Duration::days(1))));
+        }
+        if query.has_attachment {
+            conditions.push("m.content_type IN ('image', 'video', 'audio', 'file')");
+        }
+
+        values.push(Value::Integer(limit as i64));
+
+        let sql = format!(
+            "SELECT m.id, m.conversation_id, m.sender, m.direction, m.status, m.content_type, m.content_json,
+                    m.sent_at, m.server_timestamp, m.delivered_at, m.read_at, m.quote_json, m.reactions_json,
+                    m.expires_in_seconds, m.expires_at, m.signal_timestamp, m.edited_at,
+                    coalesce(c.name, m.conversation_id),
+                    {snippet}
+             FROM {source}
+             LEFT JOIN conversations c ON c.id = m.conversation_id
+             WHERE {conditions}
+             ORDER BY {order}
+             LIMIT ?",
+            snippet = snippet,
+            source = source,
+            conditions = conditions.join(" AND "),
+            order = order,
+        );
+
         let conn = self.db.connection();
         let conn = conn.lock().unwrap();
 
-        let search_pattern = format!("%{}%", query);
-
-        let result = if let Some(conv_id) = conversation_id {
-            conn.prepare(
-                "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
-                        sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
-                        expires_in_seconds, expires_at
-                 FROM messages 
-                 WHERE conversation_id = ? AND content_json LIKE ?
-                 ORDER BY sent_at DESC
-                 LIMIT ?",
-            )
+        conn.prepare(&sql)
             .and_then(|mut stmt| {
-                stmt.query_map(params![conv_id, search_pattern, limit as i64], |row| {
-                    Ok(Self::row_to_message(row))
+                stmt.query_map(params_from_iter(values), |row| {
+                    Ok(Self::row_to_search_result(row))
                 })
                 .map(|rows| rows.filter_map(|r| r.ok().flatten()).collect())
             })
-        } else {
-            conn.prepare(
-                "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
-                        sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
-                        expires_in_seconds, expires_at
-                 FROM messages 
-                 WHERE content_json LIKE ?
-                 ORDER BY sent_at DESC
-                 LIMIT ?",
-            )
-            .and_then(|mut stmt| {
-                stmt.query_map(params![search_pattern, limit as i64], |row| {
-                    Ok(Self::row_to_message(row))
-                })
-                .map(|rows| rows.filter_map(|r| r.ok().flatten()).collect())
+            .unwrap_or_else(|e| {
+                tracing::warn!("Message search failed: {}", e);
+                Vec::new()
             })
+    }
+
+    /// Get a window of messages around `message_id`, newest first like
+    /// `get_for_conversation`. Used to open a conversation at a search hit.
+    pub fn get_around(&self, conversation_id: &str, message_id: &str, context: usize) -> Vec<Message> {
+        let Some(target) = self.get(message_id) else {
+            return Vec::new();
         };
 
-        result.unwrap_or_default()
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let query = |sql: &str| -> Vec<Message> {
+            conn.prepare(sql)
+                .and_then(|mut stmt| {
+                    stmt.query_map(
+                        params![conversation_id, target.sent_at.timestamp(), context as i64 + 1],
+                        |row| Ok(Self::row_to_message(row)),
+                    )
+                    .map(|rows| rows.filter_map(|r| r.ok().flatten()).collect())
+                })
+                .unwrap_or_default()
+        };
+
+        let older = query(
+            "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
+                    sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
+                    expires_in_seconds, expires_at, signal_timestamp, edited_at
+             FROM messages
+             WHERE conversation_id = ? AND sent_at <= ?
+             ORDER BY sent_at DESC
+             LIMIT ?",
+        );
+        let mut newer = query(
+            "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
+                    sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
+                    expires_in_seconds, expires_at, signal_timestamp, edited_at
+             FROM messages
+             WHERE conversation_id = ? AND sent_at > ?
+             ORDER BY sent_at ASC
+             LIMIT ?",
+        );
+
+        newer.reverse();
+        newer.extend(older);
+        newer
     }
 
     /// Update message status
@@ -228,6 +562,87 @@ impl<'a> MessageRepository<'a> {
         Ok(())
     }
 
+    /// Mark an outgoing message as sent, unless a receipt already moved it
+    /// further. A disappearing message starts its countdown here.
+    pub fn mark_sent(&self, id: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.execute(
+            "UPDATE messages
+             SET status = CASE WHEN status IN ('sending', 'failed') THEN 'sent' ELSE status END,
+                 expires_at = CASE WHEN expires_in_seconds > 0 AND expires_at IS NULL
+                                   THEN ?1 + expires_in_seconds ELSE expires_at END
+             WHERE id = ?2",
+            params![Utc::now().timestamp(), id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Record a delivery or read receipt from one recipient of an outgoing
+    /// message and advance the message status. A read receipt implies
+    /// delivery; the earliest time of each is kept.
+    pub fn record_receipt(
+        &self,
+        message_id: &str,
+        recipient: &str,
+        receipt: ReceiptType,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        let at = at.timestamp();
+        let read_at = (receipt == ReceiptType::Read).then_some(at);
+
+        let conn = self.db.connection();
+        let mut conn = conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO message_receipts (message_id, recipient, delivered_at, read_at)
+             VALUES (?, ?, ?, ?)
+             ON CONFLICT (message_id, recipient) DO UPDATE SET
+                 delivered_at = min(coalesce(delivered_at, excluded.delivered_at), excluded.delivered_at),
+                 read_at = coalesce(read_at, excluded.read_at)",
+            params![message_id, recipient, at, read_at],
+        )?;
+        tx.execute(
+            "UPDATE messages
+             SET delivered_at = coalesce(delivered_at, ?1),
+                 read_at = coalesce(read_at, ?2),
+                 status = CASE WHEN ?2 IS NOT NULL OR status = 'read' THEN 'read' ELSE 'delivered' END
+             WHERE id = ?3",
+            params![at, read_at, message_id],
+        )?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Receipts for an outgoing message, one per recipient that sent any
+    pub fn get_receipts(&self, message_id: &str) -> Vec<MessageReceipt> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let Ok(mut stmt) = conn.prepare(
+            "SELECT recipient, delivered_at, read_at FROM message_receipts
+             WHERE message_id = ?
+             ORDER BY coalesce(read_at, delivered_at) ASC",
+        ) else {
+            return Vec::new();
+        };
+
+        let to_time = |ts: Option<i64>| ts.and_then(|ts| Utc.timestamp_opt(ts, 0).single());
+        stmt.query_map(params![message_id], |row| {
+            Ok(MessageReceipt {
+                recipient: row.get(0)?,
+                delivered_at: to_time(row.get(1)?),
+                read_at: to_time(row.get(2)?),
+            })
+        })
+        .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default()
+    }
+
     /// Mark messages as delivered
     pub fn mark_delivered(&self, message_ids: &[String], timestamp: DateTime<Utc>) -> Result<()> {
         let conn = self.db.connection();
@@ -243,7 +658,8 @@ impl<'a> MessageRepository<'a> {
         Ok(())
     }
 
-    /// Mark messages as read up to a timestamp
+    /// Mark messages as read up to a timestamp. Disappearing messages start
+    /// their countdown once read.
     pub fn mark_read(&self, conversation_id: &str, up_to_timestamp: DateTime<Utc>) -> Result<()> {
         let conn = self.db.connection();
         let conn = conn.lock().unwrap();
@@ -252,8 +668,10 @@ impl<'a> MessageRepository<'a> {
 
         conn.execute(
             "UPDATE messages 
-             SET status = 'read', read_at = ?
-             WHERE conversation_id = ? AND sent_at <= ? AND direction = 'incoming' AND read_at IS NULL",
+             SET status = 'read', read_at = ?1,
+                 expires_at = CASE WHEN expires_in_seconds > 0 AND expires_at IS NULL
+                                   THEN ?1 + expires_in_seconds ELSE expires_at END
+             WHERE conversation_id = ?2 AND sent_at <= ?3 AND direction = 'incoming' AND read_at IS NULL",
             params![now, conversation_id, up_to_timestamp.timestamp()],
         )?;
 
@@ -265,6 +683,8 @@ impl<'a> MessageRepository<'a> {
         let conn = self.db.connection();
         let conn = conn.lock().unwrap();
 
+        conn.execute("DELETE FROM message_edits WHERE message_id = ?", params![id])?;
+        conn.execute("DELETE FROM message_receipts WHERE message_id = ?", params![id])?;
         conn.execute("DELETE FROM messages WHERE id = ?", params![id])?;
 
         Ok(())
@@ -275,6 +695,16 @@ impl<'a> MessageRepository<'a> {
         let conn = self.db.connection();
         let conn = conn.lock().unwrap();
 
+        conn.execute(
+            "DELETE FROM message_edits
+             WHERE message_id IN (SELECT id FROM messages WHERE conversation_id = ?)",
+            params![conversation_id],
+        )?;
+        conn.execute(
+            "DELETE FROM message_receipts
+             WHERE message_id IN (SELECT id FROM messages WHERE conversation_id = ?)",
+            params![conversation_id],
+        )?;
         conn.execute(
             "DELETE FROM messages WHERE conversation_id = ?",
             params![conversation_id],
@@ -283,18 +713,59 @@ impl<'a> MessageRepository<'a> {
         Ok(())
     }
 
-    /// Delete expired disappearing messages
-    pub fn delete_expired(&self) -> Result<usize> {
+    /// Delete disappearing messages that expired by `now`, along with their
+    /// edits, receipts and attachment records, in one transaction. Returns the
+    /// deleted messages so the caller can remove their files.
+    pub fn delete_expired(&self, now: DateTime<Utc>) -> Result<Vec<Message>> {
+        let now = now.timestamp();
+
         let conn = self.db.connection();
-        let conn = conn.lock().unwrap();
+        let mut conn = conn.lock().unwrap();
+        let tx = conn.transaction()?;
 
-        let now = Utc::now().timestamp();
-        let deleted = conn.execute(
-            "DELETE FROM messages WHERE expires_at IS NOT NULL AND expires_at < ?",
+        let expired: Vec<Message> = {
+            let mut stmt = tx.prepare(
+                "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
+                        sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
+                        expires_in_seconds, expires_at, signal_timestamp, edited_at
+                 FROM messages WHERE expires_at IS NOT NULL AND expires_at <= ?",
+            )?;
+            let rows = stmt.query_map(params![now], |row| Ok(Self::row_to_message(row)))?;
+            rows.filter_map(|r| r.ok().flatten()).collect()
+        };
+
+        for table in ["message_edits", "message_receipts", "download_jobs", "attachments"] {
+            tx.execute(
+                &format!(
+                    "DELETE FROM {} WHERE message_id IN
+                        (SELECT id FROM messages WHERE expires_at IS NOT NULL AND expires_at <= ?)",
+                    table
+                ),
+                params![now],
+            )?;
+        }
+        tx.execute(
+            "DELETE FROM messages WHERE expires_at IS NOT NULL AND expires_at <= ?",
             params![now],
         )?;
+        tx.commit()?;
 
-        Ok(deleted)
+        Ok(expired)
+    }
+
+    /// When the next disappearing message expires
+    pub fn next_expiry(&self) -> Option<DateTime<Utc>> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT min(expires_at) FROM messages WHERE expires_at IS NOT NULL",
+            [],
+            |row| row.get::<_, Option<i64>>(0),
+        )
+        .ok()
+        .flatten()
+        .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
     }
 
     /// Get message count for a conversation
@@ -329,7 +800,7 @@ impl<'a> MessageRepository<'a> {
         conn.query_row(
             "SELECT id, conversation_id, sender, direction, status, content_type, content_json,
                     sent_at, server_timestamp, delivered_at, read_at, quote_json, reactions_json,
-                    expires_in_seconds, expires_at
+                    expires_in_seconds, expires_at, signal_timestamp, edited_at
              FROM messages 
              WHERE conversation_id = ?
              ORDER BY sent_at DESC
@@ -354,11 +825,28 @@ impl<'a> MessageRepository<'a> {
             Content::GroupUpdate { .. } => "group_update",
             Content::ProfileKeyUpdate => "profile_key_update",
             Content::EndSession => "end_session",
+            Content::Deleted => "deleted",
+            Content::ExpirationTimerUpdate { .. } => "timer_update",
+            Content::IdentityUpdate { .. } => "identity_update",
         };
         let json = serde_json::to_string(content).unwrap_or_default();
         (content_type.to_string(), json)
     }
 
+    fn row_to_search_result(row: &rusqlite::Row<'_>) -> Option<SearchResult> {
+        let message = Self::row_to_message(row)?;
+        let conversation_name: String = row.get(17).ok()?;
+        let snippet: String = row.get(18).ok()?;
+        let (match_preview, highlights) = parse_snippet(&snippet);
+
+        Some(SearchResult {
+            message,
+            conversation_name,
+            match_preview,
+            highlights,
+        })
+    }
+
     fn row_to_message(row: &rusqlite::Row<'_>) -> Option<Message> {
         let id: String = row.get(0).ok()?;
         let conversation_id: String = row.get(1).ok()?;
@@ -427,6 +915,18 @@ impl<'a> MessageRepository<'a> {
             .flatten()
             .and_then(|ts| Utc.timestamp_opt(ts, 0).single());
 
+        let signal_timestamp: Option<u64> = row
+            .get::<_, Option<i64>>(15)
+            .ok()
+            .flatten()
+            .map(|ts| ts as u64);
+
+        let edited_at: Option<DateTime<Utc>> = row
+            .get::<_, Option<i64>>(16)
+            .ok()
+            .flatten()
+            .and_then(|ts| Utc.timestamp_opt(ts, 0).single());
+
         Some(Message {
             id,
             conversation_id,
@@ -442,16 +942,211 @@ impl<'a> MessageRepository<'a> {
             reactions,
             expires_in_seconds,
             expires_at,
+            signal_timestamp,
+            edited_at,
         })
     }
 }
 
+/// A replaced version of an edited message
+#[derive(Debug, Clone)]
+pub struct MessageVersion {
+    pub content: Content,
+    /// When this version was sent or written
+    pub created_at: DateTime<Utc>,
+    /// When the next edit replaced it
+    pub replaced_at: DateTime<Utc>,
+}
+
+/// Kind of receipt sent back by a recipient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiptType {
+    Delivery,
+    Read,
+}
+
+/// Delivery and read state of an outgoing message for one recipient
+#[derive(Debug, Clone)]
+pub struct MessageReceipt {
+    pub recipient: String,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
 /// Message search result
 #[derive(Debug, Clone)]
 pub struct SearchResult {
     pub message: Message,
     pub conversation_name: String,
+    /// Snippet of the matching text around the hit
     pub match_preview: String,
+    /// Byte ranges of the matched terms within `match_preview`
+    pub highlights: Vec<Range<usize>>,
+}
+
+/// Parsed search input: free text plus `from:`, `in:`, `before:`, `after:`
+/// and `has:attachment` filters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    /// Free text, passed through `build_fts_query`
+    pub text: String,
+    /// Restrict to one conversation by ID
+    pub conversation_id: Option<String>,
+    /// `in:` - conversation ID or part of its name
+    pub in_conversation: Option<String>,
+    /// `from:` - sender ID, phone number, part of a contact name, or `me`
+    pub from: Option<String>,
+    /// `before:YYYY-MM-DD` - sent before that day
+    pub before: Option<NaiveDate>,
+    /// `after:YYYY-MM-DD` - sent after that day
+    pub after: Option<NaiveDate>,
+    /// `has:attachment`
+    pub has_attachment: bool,
+}
+
+impl SearchQuery {
+    /// Parse search box input. Unknown or malformed filters are kept as text.
+    pub fn parse(input: &str) -> Self {
+        let mut query = Self::default();
+        let mut text = Vec::new();
+
+        for token in tokenize(input) {
+            let (key, value) = match token.split_once(':') {
+                Some((key, value)) if !value.is_empty() => (key.to_lowercase(), value.trim_matches('"')),
+                _ => {
+                    text.push(token);
+                    continue;
+                }
+            };
+
+            let date = || NaiveDate::parse_from_str(value, "%Y-%m-%d").ok();
+            match key.as_str() {
+                "from" => query.from = Some(value.to_string()),
+                "in" => query.in_conversation = Some(value.to_string()),
+                "before" if date().is_some() => query.before = date(),
+                "after" if date().is_some() => query.after = date(),
+                "has" if value.eq_ignore_ascii_case("attachment") => query.has_attachment = true,
+                _ => text.push(token),
+            }
+        }
+
+        query.text = text.join(" ");
+        query
+    }
+
+    pub fn has_filters(&self) -> bool {
+        self.conversation_id.is_some()
+            || self.in_conversation.is_some()
+            || self.from.is_some()
+            || self.before.is_some()
+            || self.after.is_some()
+            || self.has_attachment
+    }
+}
+
+/// Split on whitespace, keeping `"quoted text"` together (quotes included)
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in input.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+fn like_pattern(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Unix timestamp of local midnight at the start of `date`
+fn start_of_day(date: NaiveDate) -> i64 {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|t| t.timestamp())
+        .unwrap_or_else(|| midnight.and_utc().timestamp())
+}
+
+/// Markers passed to FTS5 `snippet()`; control characters never occur in message text
+const HIGHLIGHT_START: char = '\u{2}';
+const HIGHLIGHT_END: char = '\u{3}';
+
+/// Turn user input into an FTS5 query.
+///
+/// `"quoted text"` becomes a phrase, every other word a prefix term. Everything
+/// is quoted so FTS5 operators and punctuation in the input are taken literally.
+pub fn build_fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut rest = input.trim();
+
+    while !rest.is_empty() {
+        if let Some(after_quote) = rest.strip_prefix('"') {
+            let (phrase, remainder) = match after_quote.find('"') {
+                Some(end) => (&after_quote[..end], &after_quote[end + 1..]),
+                None => (after_quote, ""),
+            };
+            if !phrase.trim().is_empty() {
+                terms.push(format!("\"{}\"", phrase.trim().replace('"', "\"\"")));
+            }
+            rest = remainder.trim_start();
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let word = rest[..end].trim_matches('"');
+            if !word.is_empty() {
+                terms.push(format!("\"{}\"*", word.replace('"', "\"\"")));
+            }
+            rest = rest[end..].trim_start();
+        }
+    }
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Strip highlight markers from a snippet, returning the text and marked ranges
+fn parse_snippet(snippet: &str) -> (String, Vec<Range<usize>>) {
+    let mut text = String::with_capacity(snippet.len());
+    let mut highlights = Vec::new();
+    let mut start = None;
+
+    for c in snippet.chars() {
+        match c {
+            HIGHLIGHT_START => start = Some(text.len()),
+            HIGHLIGHT_END => {
+                if let Some(s) = start.take() {
+                    highlights.push(s..text.len());
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+
+    (text, highlights)
 }
 
 #[cfg(test)]
@@ -493,6 +1188,507 @@ mod tests {
         assert_eq!(retrieved.text(), Some("Hello World"));
     }
 
+    #[test]
+    fn test_find_by_timestamp_matches_author() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let mut incoming = Message::new_text("conv1", "alice", "from alice");
+        incoming.direction = MessageDirection::Incoming;
+        incoming.signal_timestamp = Some(1_700_000_000_123);
+        repo.save(&incoming).unwrap();
+
+        let mut outgoing = Message::new_text("conv1", "+15550001111", "from me");
+        outgoing.signal_timestamp = Some(1_700_000_000_123);
+        repo.save(&outgoing).unwrap();
+
+        assert_eq!(repo.find_by_timestamp("alice", 1_700_000_000_123).unwrap().id, incoming.id);
+        assert_eq!(repo.find_by_timestamp("self", 1_700_000_000_123).unwrap().id, outgoing.id);
+        assert!(repo.find_by_timestamp("bob", 1_700_000_000_123).is_none());
+        assert!(repo.find_by_timestamp("alice", 1_700_000_000_124).is_none());
+    }
+
+    /// One of our messages sent from another device, stored the way the
+    /// sync path stores it: sender `"self"`, direction incoming
+    fn save_synced_message(repo: &MessageRepository, text: &str, signal_timestamp: u64) -> Message {
+        let mut message = Message::new_text("conv1", "self", text);
+        message.direction = MessageDirection::Incoming;
+        message.status = MessageStatus::Delivered;
+        message.signal_timestamp = Some(signal_timestamp);
+        repo.save(&message).unwrap();
+        message
+    }
+
+    #[test]
+    fn test_edit_synced_self_message() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+        let synced = save_synced_message(&repo, "sent from the phone", 1_700_000_000_123);
+
+        let target = repo.find_by_timestamp("self", 1_700_000_000_123).unwrap();
+        assert_eq!(target.id, synced.id);
+        repo.apply_edit(&target.id, "edited on the phone", Utc::now()).unwrap().unwrap();
+        assert_eq!(repo.get(&synced.id).unwrap().text(), Some("edited on the phone"));
+    }
+
+    #[test]
+    fn test_quote_of_synced_self_message_resolves() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+        let synced = save_synced_message(&repo, "sent from the phone", 1_700_000_000_123);
+
+        // A reply quoting it names us as the author, resolved to "self"
+        let mut reply = Message::new_text("conv1", "alice", "agreed");
+        reply.direction = MessageDirection::Incoming;
+        reply.quote = Some(Quote {
+            message_id: repo
+                .find_by_timestamp("self", 1_700_000_000_123)
+                .filter(|quoted| quoted.conversation_id == "conv1")
+                .map(|quoted| quoted.id)
+                .unwrap_or_default(),
+            timestamp: Some(1_700_000_000_123),
+            author: "self".to_string(),
+            text: Some("sent from the phone".to_string()),
+            attachment_preview: None,
+        });
+        repo.save(&reply).unwrap();
+
+        assert_eq!(repo.get(&reply.id).unwrap().quote.unwrap().message_id, synced.id);
+    }
+
+    /// Rows of a DataMessage with its text and two attachments, stored the
+    /// way the receive path splits it
+    fn save_split_message(repo: &MessageRepository, sender: &str, signal_timestamp: u64) -> Vec<Message> {
+        let mut rows = vec![
+            Message::new_text("conv1", sender, ""),
+            Message::new_text("conv1", sender, ""),
+            Message::new_text("conv1", sender, "both of them"),
+        ];
+        rows[0].content = Content::File {
+            attachment_id: "notes.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            filename: "notes.pdf".to_string(),
+            size: 2048,
+        };
+        rows[1].content = Content::Image {
+            attachment_id: "photo.jpg".to_string(),
+            content_type: "image/jpeg".to_string(),
+            width: 640,
+            height: 480,
+            size: 1024,
+            caption: None,
+            blurhash: None,
+        };
+        for row in &mut rows {
+            row.direction = MessageDirection::Incoming;
+            row.signal_timestamp = Some(signal_timestamp);
+            repo.save(row).unwrap();
+        }
+        rows
+    }
+
+    #[test]
+    fn test_find_by_timestamp_prefers_body_row() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+        let rows = save_split_message(&repo, "alice", 1_700_000_000_123);
+
+        let all = repo.find_all_by_timestamp("alice", 1_700_000_000_123);
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].id, rows[2].id);
+        assert_eq!(all[1].id, rows[1].id);
+        assert_eq!(all[2].id, rows[0].id);
+
+        // Re-saving a row doesn't change which one is found
+        repo.save(&rows[2]).unwrap();
+        assert_eq!(repo.find_by_timestamp("alice", 1_700_000_000_123).unwrap().id, rows[2].id);
+        assert!(repo.find_all_by_timestamp("bob", 1_700_000_000_123).is_empty());
+    }
+
+    #[test]
+    fn test_apply_edit_keeps_history() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let msg = Message::new_text("conv1", "alice", "frist");
+        repo.save(&msg).unwrap();
+
+        let first_edit = msg.sent_at + chrono::Duration::minutes(1);
+        let edited = repo.apply_edit(&msg.id, "first", first_edit).unwrap().unwrap();
+        assert_eq!(edited.text(), Some("first"));
+        repo.apply_edit(&msg.id, "first!", first_edit + chrono::Duration::minutes(1)).unwrap();
+
+        let stored = repo.get(&msg.id).unwrap();
+        assert_eq!(stored.text(), Some("first!"));
+        assert_eq!(stored.edited_at.unwrap().timestamp(), first_edit.timestamp() + 60);
+        assert_eq!(stored.signal_timestamp, msg.signal_timestamp);
+
+        let history = repo.get_edit_history(&msg.id);
+        assert_eq!(history.len(), 2);
+        assert!(matches!(&history[0].content, Content::Text { body, .. } if body == "frist"));
+        assert_eq!(history[0].created_at.timestamp(), msg.sent_at.timestamp());
+        assert!(matches!(&history[1].content, Content::Text { body, .. } if body == "first"));
+        assert_eq!(repo.edit_count(&msg.id), 2);
+
+        // The search index follows the edit
+        assert!(repo.search(None, "frist", 10).is_empty());
+        assert_eq!(repo.search(None, "first", 10).len(), 1);
+
+        repo.delete(&msg.id).unwrap();
+        assert!(repo.get_edit_history(&msg.id).is_empty());
+    }
+
+    #[test]
+    fn test_apply_edit_to_captioned_split_message() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        // The body became the caption of the first image
+        let mut rows = vec![
+            Message::new_text("conv1", "alice", ""),
+            Message::new_text("conv1", "alice", ""),
+        ];
+        rows[0].content = Content::Image {
+            attachment_id: "photo.jpg".to_string(),
+            content_type: "image/jpeg".to_string(),
+            width: 640,
+            height: 480,
+            size: 1024,
+            caption: Some("the crag".to_string()),
+            blurhash: None,
+        };
+        rows[1].content = Content::Audio {
+            attachment_id: "note.m4a".to_string(),
+            content_type: "audio/aac".to_string(),
+            duration_ms: 0,
+            size: 1024,
+            waveform: None,
+        };
+        for row in &mut rows {
+            row.direction = MessageDirection::Incoming;
+            row.signal_timestamp = Some(1_700_000_000_123);
+            repo.save(row).unwrap();
+        }
+
+        let target = repo.find_by_timestamp("alice", 1_700_000_000_123).unwrap();
+        assert_eq!(target.id, rows[0].id);
+        let edited = repo.apply_edit(&target.id, "the crag at dusk", Utc::now()).unwrap().unwrap();
+        assert_eq!(edited.text(), Some("the crag at dusk"));
+        assert_eq!(repo.get(&rows[0].id).unwrap().text(), Some("the crag at dusk"));
+        assert!(repo.get(&rows[1].id).unwrap().edited_at.is_none());
+    }
+
+    #[test]
+    fn test_tombstone_clears_content() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let mut msg = Message::new_text("conv1", "alice", "secret plans");
+        msg.add_reaction("👍", "bob");
+        repo.save(&msg).unwrap();
+        repo.apply_edit(&msg.id, "secret plans v2", Utc::now()).unwrap();
+
+        let previous = repo.tombstone(&msg.id).unwrap().unwrap();
+        assert_eq!(previous.text(), Some("secret plans v2"));
+
+        let stored = repo.get(&msg.id).unwrap();
+        assert!(stored.is_deleted());
+        assert!(stored.reactions.is_empty());
+        assert!(stored.edited_at.is_none());
+        assert_eq!(stored.signal_timestamp, msg.signal_timestamp);
+        assert!(repo.get_edit_history(&msg.id).is_empty());
+        assert!(repo.search(None, "secret", 10).is_empty());
+
+        assert!(repo.tombstone("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_tombstone_split_message() {
+        use crate::storage::attachments::{
+            AttachmentRepository, DownloadJobRepository, DownloadStatus, StoredAttachment,
+        };
+
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+        let attachments = AttachmentRepository::new(&db);
+        let jobs = DownloadJobRepository::new(&db);
+
+        let rows = save_split_message(&repo, "alice", 1_700_000_000_123);
+        let attached: Vec<(String, String)> = rows
+            .iter()
+            .flat_map(|row| row.attachment_ids().into_iter().map(|id| (id.to_string(), row.id.clone())))
+            .collect();
+        assert_eq!(attached.len(), 2);
+        for (attachment_id, message_id) in &attached {
+            attachments
+                .save(&StoredAttachment {
+                    id: attachment_id.clone(),
+                    message_id: message_id.clone(),
+                    content_type: "application/octet-stream".to_string(),
+                    filename: None,
+                    size: 1024,
+                    width: None,
+                    height: None,
+                    voice_note: false,
+                    cdn_number: 2,
+                    cdn_key: "abc123".to_string(),
+                    key: vec![7u8; 64],
+                    digest: None,
+                    status: DownloadStatus::Pending,
+                    created_at: 0,
+                    updated_at: 0,
+                })
+                .unwrap();
+            jobs.enqueue(attachment_id, message_id).unwrap();
+        }
+
+        for row in repo.find_all_by_timestamp("alice", 1_700_000_000_123) {
+            repo.tombstone(&row.id).unwrap();
+        }
+
+        for row in &rows {
+            assert!(repo.get(&row.id).unwrap().is_deleted());
+        }
+        for (attachment_id, message_id) in &attached {
+            assert!(attachments.get_for_message(message_id).is_empty());
+            assert!(jobs.get(attachment_id).is_none());
+        }
+    }
+
+    #[test]
+    fn test_tombstone_synced_self_message() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+        let synced = save_synced_message(&repo, "sent by mistake", 1_700_000_000_123);
+
+        // A delete for everyone sent from the phone arrives as a sync from "self"
+        let targets = repo.find_all_by_timestamp("self", 1_700_000_000_123);
+        assert_eq!(targets.len(), 1);
+        for target in targets {
+            repo.tombstone(&target.id).unwrap();
+        }
+        assert!(repo.get(&synced.id).unwrap().is_deleted());
+    }
+
+    #[test]
+    fn test_set_audio_details() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let mut voice = Message::new_text("conv1", "sender1", "");
+        voice.content = Content::Audio {
+            attachment_id: "note.m4a".to_string(),
+            content_type: "audio/aac".to_string(),
+            duration_ms: 0,
+            size: 1024,
+            waveform: None,
+        };
+        repo.save(&voice).unwrap();
+        assert!(repo.set_audio_details(&voice.id, 4_200, vec![0, 128, 255]).unwrap());
+
+        match repo.get(&voice.id).unwrap().content {
+            Content::Audio { duration_ms, waveform, .. } => {
+                assert_eq!(duration_ms, 4_200);
+                assert_eq!(waveform, Some(vec![0, 128, 255]));
+            }
+            other => panic!("expected audio, got {:?}", other),
+        }
+
+        let text = Message::new_text("conv1", "sender1", "Hello");
+        repo.save(&text).unwrap();
+        assert!(!repo.set_audio_details(&text.id, 4_200, vec![1]).unwrap());
+        assert!(!repo.set_audio_details("missing", 4_200, vec![1]).unwrap());
+    }
+
+    #[test]
+    fn test_apply_reaction() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let msg = Message::new_text("conv1", "alice", "lunch?");
+        repo.save(&msg).unwrap();
+
+        assert!(repo.apply_reaction(&msg.id, "👍", "bob", false).unwrap());
+        assert!(repo.apply_reaction(&msg.id, "❤️", "self", false).unwrap());
+        // A new reaction replaces the sender's previous one
+        assert!(repo.apply_reaction(&msg.id, "😂", "bob", false).unwrap());
+
+        let reactions = repo.get(&msg.id).unwrap().reactions;
+        assert_eq!(reactions.len(), 2);
+        assert!(reactions.iter().any(|r| r.sender == "bob" && r.emoji == "😂"));
+
+        // Removing an emoji that was already replaced is a no-op
+        assert!(!repo.apply_reaction(&msg.id, "👍", "bob", true).unwrap());
+        assert!(repo.apply_reaction(&msg.id, "😂", "bob", true).unwrap());
+
+        let reactions = repo.get(&msg.id).unwrap().reactions;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].sender, "self");
+
+        assert!(!repo.apply_reaction("missing", "👍", "bob", false).unwrap());
+    }
+
+    #[test]
+    fn test_reaction_to_synced_self_message() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+        let synced = save_synced_message(&repo, "sent from the phone", 1_700_000_000_123);
+
+        // Others react to it with our ACI as target author, resolved to "self"
+        let target = repo.find_by_timestamp("self", 1_700_000_000_123).unwrap();
+        assert!(repo.apply_reaction(&target.id, "👍", "bob", false).unwrap());
+        let reactions = repo.get(&synced.id).unwrap().reactions;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].sender, "bob");
+    }
+
+    #[test]
+    fn test_reactions_to_split_message_share_a_row() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let rows = save_split_message(&repo, "alice", 1_700_000_000_123);
+        // Without its text the message is still reacted to on one fixed row
+        repo.delete(&rows[2].id).unwrap();
+
+        let target = repo.find_by_timestamp("alice", 1_700_000_000_123).unwrap();
+        assert_eq!(target.id, rows[1].id);
+        assert!(repo.apply_reaction(&target.id, "👍", "bob", false).unwrap());
+
+        let target = repo.find_by_timestamp("alice", 1_700_000_000_123).unwrap();
+        assert_eq!(target.id, rows[1].id);
+        assert!(repo.apply_reaction(&target.id, "👍", "bob", true).unwrap());
+        assert!(repo.get(&rows[0].id).unwrap().reactions.is_empty());
+        assert!(repo.get(&rows[1].id).unwrap().reactions.is_empty());
+    }
+
+    #[test]
+    fn test_record_receipts_per_recipient() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "group1");
+        let repo = MessageRepository::new(&db);
+
+        let msg = Message::new_text("group1", "+15550001111", "hi all");
+        repo.save(&msg).unwrap();
+        repo.mark_sent(&msg.id).unwrap();
+        assert_eq!(repo.get(&msg.id).unwrap().status, MessageStatus::Sent);
+
+        let t0 = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let t1 = t0 + chrono::Duration::seconds(30);
+        repo.record_receipt(&msg.id, "alice", ReceiptType::Delivery, t0).unwrap();
+        assert_eq!(repo.get(&msg.id).unwrap().status, MessageStatus::Delivered);
+
+        // Bob's read receipt overtakes his delivery receipt
+        repo.record_receipt(&msg.id, "bob", ReceiptType::Read, t1).unwrap();
+        repo.record_receipt(&msg.id, "bob", ReceiptType::Delivery, t0).unwrap();
+        repo.record_receipt(&msg.id, "alice", ReceiptType::Delivery, t1).unwrap();
+
+        let stored = repo.get(&msg.id).unwrap();
+        assert_eq!(stored.status, MessageStatus::Read);
+        assert_eq!(stored.delivered_at, Some(t0));
+        assert_eq!(stored.read_at, Some(t1));
+
+        let receipts = repo.get_receipts(&msg.id);
+        assert_eq!(receipts.len(), 2);
+        let alice = receipts.iter().find(|r| r.recipient == "alice").unwrap();
+        assert_eq!((alice.delivered_at, alice.read_at), (Some(t0), None));
+        let bob = receipts.iter().find(|r| r.recipient == "bob").unwrap();
+        assert_eq!((bob.delivered_at, bob.read_at), (Some(t0), Some(t1)));
+
+        // A late send confirmation doesn't move the status back
+        repo.mark_sent(&msg.id).unwrap();
+        assert_eq!(repo.get(&msg.id).unwrap().status, MessageStatus::Read);
+
+        repo.delete(&msg.id).unwrap();
+        assert!(repo.get_receipts(&msg.id).is_empty());
+    }
+
+    #[test]
+    fn test_receipt_for_synced_self_message() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+        let synced = save_synced_message(&repo, "sent from the phone", 1_700_000_000_123);
+
+        let read_at = Utc.timestamp_opt(1_700_000_100, 0).unwrap();
+        for message in repo.find_all_by_timestamp("self", 1_700_000_000_123) {
+            repo.record_receipt(&message.id, "conv1", ReceiptType::Read, read_at).unwrap();
+        }
+
+        let stored = repo.get(&synced.id).unwrap();
+        assert_eq!(stored.status, MessageStatus::Read);
+        assert_eq!(stored.read_at, Some(read_at));
+        assert_eq!(repo.get_receipts(&synced.id).len(), 1);
+    }
+
+    #[test]
+    fn test_mark_read_up_to() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let base = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let mut msg = Message::new_text("conv1", "alice", &format!("Message {}", i));
+            msg.direction = MessageDirection::Incoming;
+            msg.sent_at = base + chrono::Duration::seconds(i);
+            repo.save(&msg).unwrap();
+            ids.push(msg.id);
+        }
+
+        // Reading the second message on another device reads the first too
+        repo.mark_read("conv1", base + chrono::Duration::seconds(1)).unwrap();
+        let unread = repo.get_unread("conv1");
+        assert_eq!(unread.len(), 1);
+        assert_eq!(unread[0].id, ids[2]);
+    }
+
+    #[test]
+    fn test_disappearing_messages_expire_after_read() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let mut secret = Message::new_text("conv1", "alice", "gone soon");
+        secret.direction = MessageDirection::Incoming;
+        secret.expires_in_seconds = Some(30);
+        repo.save(&secret).unwrap();
+
+        let mut kept = Message::new_text("conv1", "alice", "stays");
+        kept.direction = MessageDirection::Incoming;
+        repo.save(&kept).unwrap();
+
+        // The countdown only starts once the message is read
+        assert!(repo.next_expiry().is_none());
+        assert!(repo.delete_expired(Utc::now() + chrono::Duration::days(1)).unwrap().is_empty());
+
+        repo.mark_read("conv1", Utc::now()).unwrap();
+        let expires_at = repo.get(&secret.id).unwrap().expires_at.unwrap();
+        assert_eq!(repo.next_expiry(), Some(expires_at));
+        assert!(repo.get(&kept.id).unwrap().expires_at.is_none());
+
+        assert!(repo.delete_expired(expires_at - chrono::Duration::seconds(1)).unwrap().is_empty());
+        let expired = repo.delete_expired(expires_at).unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, secret.id);
+        assert!(repo.get(&secret.id).is_none());
+        assert!(repo.get(&kept.id).is_some());
+    }
+
     #[test]
     fn test_get_for_conversation() {
         let (db, _dir) = create_test_db();
@@ -542,6 +1738,142 @@ mod tests {
         assert_eq!(results.len(), 1);
     }
 
+    #[test]
+    fn test_search_ignores_json_keys() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        repo.save(&Message::new_text("conv1", "sender1", "Hello")).unwrap();
+
+        assert!(repo.search(None, "body", 10).is_empty());
+        assert!(repo.search(None, "mentions", 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_prefix_and_phrase() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        repo.save(&Message::new_text("conv1", "sender1", "see you tomorrow morning")).unwrap();
+        repo.save(&Message::new_text("conv1", "sender1", "morning tomorrow then")).unwrap();
+
+        assert_eq!(repo.search(None, "tomo", 10).len(), 2);
+        assert_eq!(repo.search(None, "\"tomorrow morning\"", 10).len(), 1);
+    }
+
+    #[test]
+    fn test_search_index_follows_updates_and_deletes() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let mut msg = Message::new_text("conv1", "sender1", "original text");
+        repo.save(&msg).unwrap();
+        assert_eq!(repo.search(None, "original", 10).len(), 1);
+
+        msg.content = Content::Text {
+            body: "edited text".to_string(),
+            mentions: Vec::new(),
+        };
+        repo.save(&msg).unwrap();
+        assert!(repo.search(None, "original", 10).is_empty());
+        assert_eq!(repo.search(None, "edited", 10).len(), 1);
+
+        repo.delete(&msg.id).unwrap();
+        assert!(repo.search(None, "edited", 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_highlights() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        repo.save(&Message::new_text("conv1", "sender1", "lunch at noon?")).unwrap();
+
+        let results = repo.search(None, "noon", 10);
+        assert_eq!(results.len(), 1);
+        let result = &results[0];
+        assert_eq!(result.conversation_name, "Test");
+        assert_eq!(result.match_preview, "lunch at noon?");
+        assert_eq!(result.highlights, vec![9..13]);
+    }
+
+    #[test]
+    fn test_search_query_parse() {
+        let query = SearchQuery::parse(
+            "lunch from:alice in:\"Family chat\" after:2024-01-31 has:attachment \"next week\" before:soon",
+        );
+
+        assert_eq!(query.text, "lunch \"next week\" before:soon");
+        assert_eq!(query.from.as_deref(), Some("alice"));
+        assert_eq!(query.in_conversation.as_deref(), Some("Family chat"));
+        assert_eq!(query.after, NaiveDate::from_ymd_opt(2024, 1, 31));
+        assert_eq!(query.before, None);
+        assert!(query.has_attachment);
+    }
+
+    #[test]
+    fn test_search_query_filters() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        create_test_conversation(&db, "conv2");
+        let repo = MessageRepository::new(&db);
+
+        repo.save(&Message::new_text("conv1", "alice", "meeting notes")).unwrap();
+        repo.save(&Message::new_text("conv2", "bob", "meeting moved")).unwrap();
+        let mut old = Message::new_text("conv2", "bob", "meeting last year");
+        old.sent_at = Utc.with_ymd_and_hms(2020, 6, 1, 12, 0, 0).unwrap();
+        repo.save(&old).unwrap();
+
+        let results = repo.search_query(&SearchQuery::parse("meeting from:alice"), 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message.sender, "alice");
+
+        let results = repo.search_query(&SearchQuery::parse("meeting in:conv2"), 10);
+        assert_eq!(results.len(), 2);
+
+        let results = repo.search_query(&SearchQuery::parse("meeting before:2021-01-01"), 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message.id, old.id);
+
+        // Filters alone are enough to search
+        let results = repo.search_query(&SearchQuery::parse("from:bob after:2021-01-01"), 10);
+        assert_eq!(results.len(), 1);
+
+        assert!(repo.search_query(&SearchQuery::parse("meeting has:attachment"), 10).is_empty());
+    }
+
+    #[test]
+    fn test_get_around() {
+        let (db, _dir) = create_test_db();
+        create_test_conversation(&db, "conv1");
+        let repo = MessageRepository::new(&db);
+
+        let base = Utc::now();
+        let mut ids = Vec::new();
+        for i in 0..10 {
+            let mut msg = Message::new_text("conv1", "sender1", &format!("Message {}", i));
+            msg.sent_at = base + chrono::Duration::seconds(i);
+            repo.save(&msg).unwrap();
+            ids.push(msg.id);
+        }
+
+        let around = repo.get_around("conv1", &ids[5], 2);
+        let around_ids: Vec<&str> = around.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(around_ids, vec![&ids[7], &ids[6], &ids[5], &ids[4], &ids[3]]);
+    }
+
+    #[test]
+    fn test_build_fts_query() {
+        assert_eq!(build_fts_query("  "), None);
+        assert_eq!(build_fts_query("foo bar").as_deref(), Some("\"foo\"* \"bar\"*"));
+        assert_eq!(build_fts_query("\"foo bar\" baz").as_deref(), Some("\"foo bar\" \"baz\"*"));
+        assert_eq!(build_fts_query("a OR b-c").as_deref(), Some("\"a\"* \"OR\"* \"b-c\"*"));
+    }
+
     #[test]
     fn test_delete() {
         let (db, _dir) = create_test_db();
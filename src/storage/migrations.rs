@@ -0,0 +1,762 @@
+//! Versioned schema migrations for the app database
+//!
+//! The schema version lives in `PRAGMA user_version`. Each migration runs in
+//! its own transaction together with the version bump, so an interrupted
+//! upgrade leaves the database at the last completed version. Before an
+//! existing database is upgraded, a copy of the file is kept next to it.
+//!
+//! Databases created before versioning have `user_version = 0` but already
+//! contain the baseline tables; they are treated as version 1. Steps that
+//! shipped ahead of versioning use `IF NOT EXISTS` so they can run over them.
+
+use anyhow::Result;
+use rusqlite::{Connection, Transaction};
+use std::path::{Path, PathBuf};
+
+/// A single schema upgrade step
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    up: fn(&Transaction) -> Result<()>,
+}
+
+/// All migrations, in order. Never edit a released step - add a new one.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Baseline schema",
+        up: v1_baseline,
+    },
+    Migration {
+        version: 2,
+        description: "Attachments and download jobs",
+        up: v2_attachments,
+    },
+    Migration {
+        version: 3,
+        description: "Full-text message search index",
+        up: v3_search_index,
+    },
+    Migration {
+        version: 4,
+        description: "Remove conversations with invalid numeric IDs",
+        up: v4_remove_invalid_conversations,
+    },
+    Migration {
+        version: 5,
+        description: "Message timestamps and edit history",
+        up: v5_message_edits,
+    },
+    Migration {
+        version: 6,
+        description: "Per-recipient delivery and read receipts",
+        up: v6_message_receipts,
+    },
+    Migration {
+        version: 7,
+        description: "Groups and group members",
+        up: v7_groups,
+    },
+    Migration {
+        version: 8,
+        description: "Contact verification, nicknames and profile fetch time",
+        up: v8_contact_state,
+    },
+    Migration {
+        version: 9,
+        description: "Message requests",
+        up: v9_message_requests,
+    },
+    Migration {
+        version: 10,
+        description: "Identity keys for safety numbers",
+        up: v10_identities,
+    },
+    Migration {
+        version: 11,
+        description: "Outbox",
+        up: v11_outbox,
+    },
+    Migration {
+        version: 12,
+        description: "Sticker packs",
+        up: v12_sticker_packs,
+    },
+];
+
+/// Schema version produced by running every migration
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Current `user_version` of the database
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Bring the database up to the latest version.
+///
+/// `path` is the database file; when set, it is copied to a backup before an
+/// existing database is upgraded. Returns the resulting version.
+pub fn run(conn: &mut Connection, path: Option<&Path>) -> Result<u32> {
+    migrate_to(conn, path, latest_version())
+}
+
+/// Apply pending migrations up to and including `target`
+pub fn migrate_to(conn: &mut Connection, path: Option<&Path>, target: u32) -> Result<u32> {
+    let mut current = schema_version(conn)?;
+
+    if current == 0 && table_exists(conn, "conversations")? {
+        tracing::info!("Found unversioned database, treating it as schema version 1");
+        current = 1;
+    }
+
+    if current > latest_version() {
+        anyhow::bail!(
+            "Database schema version {} is newer than this build supports ({})",
+            current,
+            latest_version()
+        );
+    }
+
+    let pending: Vec<&Migration> = MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current && m.version <= target)
+        .collect();
+
+    if pending.is_empty() {
+        return Ok(current);
+    }
+
+    if current > 0 {
+        if let Some(path) = path {
+            let backup = backup_path(path, current);
+            std::fs::copy(path, &backup)?;
+            tracing::info!("Backed up database to {:?} before upgrading", backup);
+        }
+    }
+
+    for migration in pending {
+        tracing::info!(
+            "Migrating database to version {}: {}",
+            migration.version,
+            migration.description
+        );
+
+        let tx = conn.transaction()?;
+        (migration.up)(&tx).map_err(|e| {
+            anyhow::anyhow!("Migration to version {} failed: {}", migration.version, e)
+        })?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+
+        current = migration.version;
+    }
+
+    Ok(current)
+}
+
+/// Where the pre-upgrade copy of a database at `version` is kept
+pub fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{}.bak", version));
+    path.with_file_name(name)
+}
+
+fn table_exists(conn: &Connection, name: &str) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+        [name],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn v1_baseline(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS conversations (
+            id TEXT PRIMARY KEY,
+            conversation_type TEXT NOT NULL,
+            name TEXT NOT NULL,
+            avatar_path TEXT,
+            last_message TEXT,
+            last_message_at INTEGER,
+            unread_count INTEGER DEFAULT 0,
+            is_pinned INTEGER DEFAULT 0,
+            is_muted INTEGER DEFAULT 0,
+            muted_until INTEGER,
+            is_archived INTEGER DEFAULT 0,
+            is_blocked INTEGER DEFAULT 0,
+            disappearing_timer INTEGER DEFAULT 0,
+            draft TEXT,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS messages (
+            id TEXT PRIMARY KEY,
+            conversation_id TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            direction TEXT NOT NULL,
+            status TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            content_json TEXT NOT NULL,
+            sent_at INTEGER NOT NULL,
+            server_timestamp INTEGER,
+            delivered_at INTEGER,
+            read_at INTEGER,
+            quote_json TEXT,
+            reactions_json TEXT,
+            expires_in_seconds INTEGER,
+            expires_at INTEGER,
+            FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS contacts (
+            id TEXT PRIMARY KEY,
+            phone_number TEXT,
+            uuid TEXT UNIQUE,
+            name TEXT NOT NULL,
+            profile_name TEXT,
+            avatar_path TEXT,
+            profile_key BLOB,
+            is_blocked INTEGER DEFAULT 0,
+            is_verified INTEGER DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_messages_conversation
+            ON messages(conversation_id, sent_at DESC);
+        CREATE INDEX IF NOT EXISTS idx_messages_sender
+            ON messages(sender);
+        CREATE INDEX IF NOT EXISTS idx_conversations_updated
+            ON conversations(updated_at DESC);
+        CREATE INDEX IF NOT EXISTS idx_contacts_uuid
+            ON contacts(uuid);
+        ",
+    )?;
+    Ok(())
+}
+
+fn v2_attachments(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS attachments (
+            id TEXT PRIMARY KEY,
+            message_id TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            filename TEXT,
+            size INTEGER NOT NULL DEFAULT 0,
+            width INTEGER,
+            height INTEGER,
+            voice_note INTEGER DEFAULT 0,
+            cdn_number INTEGER NOT NULL DEFAULT 0,
+            cdn_key TEXT NOT NULL,
+            key BLOB NOT NULL,
+            digest BLOB,
+            status TEXT NOT NULL DEFAULT 'pending',
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS download_jobs (
+            attachment_id TEXT PRIMARY KEY,
+            message_id TEXT NOT NULL,
+            state TEXT NOT NULL DEFAULT 'queued',
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at INTEGER NOT NULL,
+            last_error TEXT,
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_attachments_message
+            ON attachments(message_id);
+        ",
+    )?;
+    Ok(())
+}
+
+/// Create the FTS5 index over message text and backfill it.
+///
+/// `messages_fts` shares rowids with `messages` and is maintained by triggers,
+/// so every write path (including `INSERT OR REPLACE`) keeps it in sync.
+fn v3_search_index(tx: &Transaction) -> Result<()> {
+    let exists = table_exists(tx, "messages_fts")?;
+
+    tx.execute_batch(&format!(
+        "
+        CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+            text,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS messages_fts_before_insert BEFORE INSERT ON messages BEGIN
+            DELETE FROM messages_fts
+            WHERE rowid = (SELECT rowid FROM messages WHERE id = new.id);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS messages_fts_after_insert AFTER INSERT ON messages BEGIN
+            INSERT INTO messages_fts (rowid, text) VALUES (new.rowid, {new_text});
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS messages_fts_after_update
+        AFTER UPDATE OF content_json, quote_json ON messages BEGIN
+            DELETE FROM messages_fts WHERE rowid = old.rowid;
+            INSERT INTO messages_fts (rowid, text) VALUES (new.rowid, {new_text});
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS messages_fts_after_delete AFTER DELETE ON messages BEGIN
+            DELETE FROM messages_fts WHERE rowid = old.rowid;
+        END;
+        ",
+        new_text = searchable_text_sql("new"),
+    ))?;
+
+    // Databases from before versioning may already have a populated index
+    if !exists {
+        let indexed = backfill_search_index(tx)?;
+        tracing::info!("Built message search index ({} messages)", indexed);
+    }
+
+    Ok(())
+}
+
+/// Conversations early builds created keyed by small integers instead of
+/// service IDs, which the old cleanup tool listed. None of them is a valid
+/// service or group ID, so they can never receive messages. Only those exact
+/// IDs are dropped, with their messages; real conversations are never touched.
+const INVALID_CONVERSATION_IDS: &str = "'1', '2', '3', '23', '24'";
+
+fn v4_remove_invalid_conversations(tx: &Transaction) -> Result<()> {
+    let removed: Vec<(String, String)> = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT id, name FROM conversations WHERE id IN ({})",
+            INVALID_CONVERSATION_IDS
+        ))?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    let messages = tx.execute(
+        &format!("DELETE FROM messages WHERE conversation_id IN ({})", INVALID_CONVERSATION_IDS),
+        [],
+    )?;
+    tx.execute(
+        &format!("DELETE FROM conversations WHERE id IN ({})", INVALID_CONVERSATION_IDS),
+        [],
+    )?;
+
+    for (id, name) in &removed {
+        tracing::info!("Removed invalid conversation {:?} ({:?})", id, name);
+    }
+    if !removed.is_empty() || messages > 0 {
+        tracing::info!(
+            "Removed {} invalid conversations and {} of their messages",
+            removed.len(),
+            messages
+        );
+    }
+    Ok(())
+}
+
+fn v5_message_edits(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        ALTER TABLE messages ADD COLUMN signal_timestamp INTEGER;
+        ALTER TABLE messages ADD COLUMN edited_at INTEGER;
+
+        CREATE INDEX idx_messages_signal_timestamp
+            ON messages(signal_timestamp);
+
+        CREATE TABLE message_edits (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            message_id TEXT NOT NULL,
+            content_json TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            replaced_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX idx_message_edits_message
+            ON message_edits(message_id, id);
+        ",
+    )?;
+    Ok(())
+}
+
+fn v6_message_receipts(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE message_receipts (
+            message_id TEXT NOT NULL,
+            recipient TEXT NOT NULL,
+            delivered_at INTEGER,
+            read_at INTEGER,
+            PRIMARY KEY (message_id, recipient)
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+fn v7_groups(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE groups_v2 (
+            id TEXT PRIMARY KEY,
+            master_key BLOB,
+            name TEXT NOT NULL,
+            description TEXT,
+            avatar_path TEXT,
+            attributes_access TEXT NOT NULL DEFAULT 'any',
+            members_access TEXT NOT NULL DEFAULT 'any',
+            link_enabled INTEGER DEFAULT 0,
+            invite_link TEXT,
+            disappearing_timer INTEGER DEFAULT 0,
+            is_blocked INTEGER DEFAULT 0,
+            has_left INTEGER DEFAULT 0,
+            revision INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE group_members (
+            group_id TEXT NOT NULL,
+            uuid TEXT NOT NULL,
+            role TEXT NOT NULL DEFAULT 'default',
+            pending INTEGER NOT NULL DEFAULT 0,
+            added_by TEXT,
+            joined_at INTEGER NOT NULL,
+            PRIMARY KEY (group_id, uuid)
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+/// Verification used to be a flag; `Unverified` (key changed after being
+/// verified) needs a third state. The flag column stays for older builds.
+fn v8_contact_state(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        ALTER TABLE contacts ADD COLUMN verification_state TEXT NOT NULL DEFAULT 'default';
+        ALTER TABLE contacts ADD COLUMN nickname TEXT;
+        ALTER TABLE contacts ADD COLUMN profile_fetched_at INTEGER;
+
+        UPDATE contacts SET verification_state = 'verified' WHERE is_verified = 1;
+        ",
+    )?;
+    Ok(())
+}
+
+/// Conversations that exist already were accepted implicitly
+fn v9_message_requests(tx: &Transaction) -> Result<()> {
+    tx.execute_batch("ALTER TABLE conversations ADD COLUMN is_accepted INTEGER NOT NULL DEFAULT 1;")?;
+    Ok(())
+}
+
+fn v10_identities(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE identities (
+            uuid TEXT PRIMARY KEY,
+            identity_key BLOB NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+/// Outgoing messages waiting to be sent, in the order they were written.
+/// Messages left in 'sending' by earlier versions were never going to be
+/// sent, so they become 'failed' and can be retried.
+fn v11_outbox(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE outbox (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            message_id TEXT NOT NULL UNIQUE,
+            conversation_id TEXT NOT NULL,
+            voice_note INTEGER NOT NULL DEFAULT 0,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at INTEGER NOT NULL DEFAULT 0,
+            last_error TEXT,
+            failed INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL
+        );
+        CREATE INDEX idx_outbox_conversation ON outbox(conversation_id, seq);
+
+        UPDATE messages SET status = 'failed' WHERE direction = 'outgoing' AND status = 'sending';
+        ",
+    )?;
+    Ok(())
+}
+
+/// Sticker packs we know of, installed or only seen in a message, and the
+/// stickers of those whose manifest was downloaded
+fn v12_sticker_packs(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE sticker_packs (
+            pack_id TEXT PRIMARY KEY,
+            pack_key TEXT NOT NULL,
+            title TEXT,
+            author TEXT,
+            cover_sticker_id INTEGER,
+            installed INTEGER NOT NULL DEFAULT 0,
+            installed_at INTEGER
+        );
+        CREATE TABLE stickers (
+            pack_id TEXT NOT NULL,
+            sticker_id INTEGER NOT NULL,
+            emoji TEXT,
+            content_type TEXT,
+            PRIMARY KEY (pack_id, sticker_id)
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+/// Index every message that exists now. Returns the number of rows indexed.
+pub(super) fn backfill_search_index(conn: &Connection) -> Result<usize> {
+    let count = conn.execute(
+        &format!(
+            "INSERT INTO messages_fts (rowid, text) SELECT m.rowid, {} FROM messages m",
+            searchable_text_sql("m"),
+        ),
+        [],
+    )?;
+    Ok(count)
+}
+
+/// SQL expression with the searchable text of a message row: body, caption,
+/// attachment filename and quoted text. JSON keys and IDs are left out.
+fn searchable_text_sql(row: &str) -> String {
+    format!(
+        "trim(
+            coalesce(json_extract({row}.content_json, '$.body'), '') || ' ' ||
+            coalesce(json_extract({row}.content_json, '$.caption'), '') || ' ' ||
+            coalesce(json_extract({row}.content_json, '$.filename'), '') || ' ' ||
+            coalesce(json_extract({row}.quote_json, '$.text'), '')
+        )",
+        row = row
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TEST_KEY: &str = "test-migration-key";
+
+    fn open(path: &Path) -> Connection {
+        let conn = Connection::open(path).unwrap();
+        conn.pragma_update(None, "key", TEST_KEY).unwrap();
+        conn
+    }
+
+    /// Normalised `CREATE` statements, used to compare upgraded and fresh schemas
+    fn schema(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT sql FROM sqlite_master WHERE sql IS NOT NULL ORDER BY type, name")
+            .unwrap();
+        stmt.query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .filter_map(|r| r.ok())
+            .map(|sql| sql.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    /// Schema of the first release, before versioning
+    const UNVERSIONED_INITIAL: &str = include_str!("schema/unversioned_initial.sql");
+    /// Schema of the last build before versioning
+    const UNVERSIONED_SEARCH: &str = include_str!("schema/unversioned_search.sql");
+
+    /// Database created from the frozen `unversioned` schema and upgraded to
+    /// `version`, holding two conversations with a message each, one that
+    /// v4 removes and one with a short numeric ID that it keeps
+    fn fixture(path: &Path, unversioned: &str, version: u32) -> Connection {
+        let mut conn = open(path);
+        conn.execute_batch(unversioned).unwrap();
+        if version > 0 {
+            // Version 1 is the first release's schema, see the test below
+            conn.pragma_update(None, "user_version", 1).unwrap();
+            migrate_to(&mut conn, None, version).unwrap();
+        }
+
+        conn.execute_batch(
+            "INSERT INTO conversations (id, conversation_type, name, created_at, updated_at)
+                 VALUES ('6f2c1a7e-0000-4000-8000-000000000001', 'private', 'Alice', 0, 0),
+                        ('6cf1d9af-96d7-40bc-9fc6-a752244d79c4', 'private', 'Bob', 0, 0),
+                        ('23', 'private', 'Bogus', 0, 0),
+                        ('12345', 'private', 'Short', 0, 0);
+             INSERT INTO messages (id, conversation_id, sender, direction, status, content_type, content_json, sent_at)
+                 VALUES ('m1', '6f2c1a7e-0000-4000-8000-000000000001', 'alice', 'incoming', 'read', 'text',
+                         '{\"type\":\"Text\",\"body\":\"fixture message\",\"mentions\":[]}', 100),
+                        ('m4', '6cf1d9af-96d7-40bc-9fc6-a752244d79c4', 'bob', 'incoming', 'read', 'text',
+                         '{\"type\":\"Text\",\"body\":\"still here\",\"mentions\":[]}', 100),
+                        ('m2', '23', 'bogus', 'incoming', 'read', 'text',
+                         '{\"type\":\"Text\",\"body\":\"orphan\",\"mentions\":[]}', 100),
+                        ('m3', '12345', 'short', 'incoming', 'read', 'text',
+                         '{\"type\":\"Text\",\"body\":\"keep me\",\"mentions\":[]}', 100);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_versions_are_sequential() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn test_fresh_database_is_latest() {
+        let dir = tempdir().unwrap();
+        let mut conn = open(&dir.path().join("app.db"));
+
+        assert_eq!(run(&mut conn, None).unwrap(), latest_version());
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        // Running again is a no-op
+        assert_eq!(run(&mut conn, None).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn test_baseline_matches_first_release() {
+        let dir = tempdir().unwrap();
+        let mut migrated = open(&dir.path().join("migrated.db"));
+        migrate_to(&mut migrated, None, 1).unwrap();
+        let released = open(&dir.path().join("released.db"));
+        released.execute_batch(UNVERSIONED_INITIAL).unwrap();
+
+        assert_eq!(schema(&migrated), schema(&released));
+    }
+
+    #[test]
+    fn test_upgrade_from_every_version() {
+        let dir = tempdir().unwrap();
+        let mut fresh = open(&dir.path().join("fresh.db"));
+        run(&mut fresh, None).unwrap();
+        let expected_schema = schema(&fresh);
+
+        let starts = (0..latest_version())
+            .map(|version| (format!("v{}", version), UNVERSIONED_INITIAL, version))
+            .chain(std::iter::once(("unversioned search".to_string(), UNVERSIONED_SEARCH, 0)));
+        for (name, unversioned, version) in starts {
+            let path = dir.path().join(format!("{}.db", name));
+            drop(fixture(&path, unversioned, version));
+
+            let mut conn = open(&path);
+            assert_eq!(run(&mut conn, Some(&path)).unwrap(), latest_version(), "from {}", name);
+            assert_eq!(schema(&conn), expected_schema, "from {}", name);
+
+            let kept: i64 = conn
+                .query_row(
+                    "SELECT (SELECT COUNT(*) FROM messages WHERE id IN ('m1', 'm3', 'm4'))
+                          + (SELECT COUNT(*) FROM conversations
+                             WHERE id IN ('6cf1d9af-96d7-40bc-9fc6-a752244d79c4', '12345'))",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(kept, 5, "from {}", name);
+
+            let indexed: i64 = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'fixture'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(indexed, 1, "from {}", name);
+
+            let invalid: i64 = conn
+                .query_row(
+                    "SELECT (SELECT COUNT(*) FROM conversations WHERE id = '23')
+                          + (SELECT COUNT(*) FROM messages WHERE id = 'm2')",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(invalid, 0, "from {}", name);
+        }
+    }
+
+    #[test]
+    fn test_v4_keeps_service_id_conversations() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        let mut conn = fixture(&path, UNVERSIONED_INITIAL, 3);
+
+        migrate_to(&mut conn, None, 4).unwrap();
+
+        let conversations: Vec<String> = conn
+            .prepare("SELECT id FROM conversations ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .filter_map(|r| r.ok())
+            .collect();
+        assert_eq!(
+            conversations,
+            vec!["12345", "6cf1d9af-96d7-40bc-9fc6-a752244d79c4", "6f2c1a7e-0000-4000-8000-000000000001"]
+        );
+        let messages: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM messages WHERE conversation_id = '6cf1d9af-96d7-40bc-9fc6-a752244d79c4'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(messages, 1);
+    }
+
+    #[test]
+    fn test_backup_taken_before_upgrade() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        drop(fixture(&path, UNVERSIONED_INITIAL, 1));
+
+        let mut conn = open(&path);
+        run(&mut conn, Some(&path)).unwrap();
+
+        let backup = backup_path(&path, 1);
+        assert!(backup.exists());
+        let backup_conn = open(&backup);
+        assert_eq!(schema_version(&backup_conn).unwrap(), 1);
+        let messages: i64 = backup_conn
+            .query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(messages, 4);
+    }
+
+    #[test]
+    fn test_no_backup_for_new_database() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        let mut conn = open(&path);
+        run(&mut conn, Some(&path)).unwrap();
+
+        assert!(!backup_path(&path, 0).exists());
+    }
+
+    #[test]
+    fn test_newer_schema_rejected() {
+        let dir = tempdir().unwrap();
+        let mut conn = open(&dir.path().join("app.db"));
+        run(&mut conn, None).unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+
+        assert!(run(&mut conn, None).is_err());
+    }
+}
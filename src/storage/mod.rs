@@ -1,9 +1,15 @@
+pub mod attachments;
 pub mod contacts;
 pub mod conversations;
 pub mod database;
 pub mod encryption;
+pub mod groups;
+pub mod identities;
 pub mod messages;
+pub mod migrations;
+pub mod outbox;
 pub mod settings;
+pub mod stickers;
 
 use anyhow::Result;
 use database::Database;
@@ -49,6 +55,7 @@ pub struct Storage {
     data_dir: PathBuf,
     attachments_dir: PathBuf,
     avatars_dir: PathBuf,
+    stickers_dir: PathBuf,
     has_account: AtomicBool,
     phone_number: RwLock<Option<String>>,
     device_id: RwLock<Option<u32>>,
@@ -67,8 +74,10 @@ impl Storage {
 
         let attachments_dir = data_dir.join("attachments");
         let avatars_dir = data_dir.join("avatars");
+        let stickers_dir = data_dir.join("stickers");
         std::fs::create_dir_all(&attachments_dir)?;
         std::fs::create_dir_all(&avatars_dir)?;
+        std::fs::create_dir_all(&stickers_dir)?;
 
         tracing::info!("Storage initialized at: {:?}", data_dir);
 
@@ -113,6 +122,7 @@ impl Storage {
             data_dir,
             attachments_dir,
             avatars_dir,
+            stickers_dir,
             has_account: AtomicBool::new(has_account),
             phone_number: RwLock::new(phone_number),
             device_id: RwLock::new(device_id),
@@ -343,6 +353,11 @@ impl Storage {
             std::fs::create_dir_all(&self.avatars_dir)?;
         }
 
+        if self.stickers_dir.exists() {
+            std::fs::remove_dir_all(&self.stickers_dir)?;
+            std::fs::create_dir_all(&self.stickers_dir)?;
+        }
+
         self.has_account.store(false, Ordering::SeqCst);
         *self.phone_number.write() = None;
         *self.device_id.write() = None;
@@ -373,6 +388,11 @@ impl Storage {
         &self.avatars_dir
     }
 
+    /// Downloaded sticker packs, one directory per pack
+    pub fn stickers_dir(&self) -> &PathBuf {
+        &self.stickers_dir
+    }
+
     pub fn signal_db_path(&self) -> PathBuf {
         self.data_dir.join("signal_protocol.db")
     }
@@ -397,6 +417,7 @@ impl Storage {
 
         total += dir_size(&self.attachments_dir)?;
         total += dir_size(&self.avatars_dir)?;
+        total += dir_size(&self.stickers_dir)?;
 
         Ok(total)
     }
@@ -452,6 +473,7 @@ mod tests {
             data_dir: dir.to_path_buf(),
             attachments_dir,
             avatars_dir,
+            stickers_dir: dir.join("stickers"),
             has_account: AtomicBool::new(false),
             phone_number: RwLock::new(None),
             device_id: RwLock::new(None),
@@ -511,6 +533,7 @@ mod tests {
             data_dir: dir.path().to_path_buf(),
             attachments_dir: dir.path().join("attachments"),
             avatars_dir: dir.path().join("avatars"),
+            stickers_dir: dir.path().join("stickers"),
             has_account: AtomicBool::new(true),
             phone_number: RwLock::new(Some("+1234567890".to_string())),
             device_id: RwLock::new(Some(1)),
@@ -0,0 +1,216 @@
+//! Outgoing messages waiting to be sent
+//!
+//! The message itself is stored as usual with status `Sending`; its outbox
+//! entry tracks the attempts to send it. Only the oldest pending entry of each
+//! conversation is handed out, so messages arrive in the order they were
+//! written. Entries that ran out of attempts stay behind as failed until the
+//! user retries or deletes the message.
+
+use crate::storage::database::Database;
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Row};
+
+const OUTBOX_COLUMNS: &str =
+    "seq, message_id, conversation_id, voice_note, attempts, next_attempt_at, last_error, failed";
+
+/// Matches the oldest pending entry of its conversation, aliased `o`
+const IS_CONVERSATION_HEAD: &str = "o.failed = 0
+    AND o.seq = (SELECT MIN(seq) FROM outbox WHERE conversation_id = o.conversation_id AND failed = 0)";
+
+/// A message waiting in the outbox
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboxEntry {
+    /// Position in the outbox; lower is older
+    pub seq: i64,
+    pub message_id: String,
+    pub conversation_id: String,
+    /// An audio attachment recorded as a voice note
+    pub voice_note: bool,
+    /// Failed attempts so far
+    pub attempts: u32,
+    /// When to try next, in milliseconds
+    pub next_attempt_at: i64,
+    pub last_error: Option<String>,
+    /// Out of attempts; waits for the user
+    pub failed: bool,
+}
+
+pub struct OutboxRepository<'a> {
+    db: &'a Database,
+}
+
+impl<'a> OutboxRepository<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Add a message at the end of the outbox, due right away. A message
+    /// already in it, e.g. a failed one being retried, starts over at the end.
+    pub fn enqueue(&self, message_id: &str, conversation_id: &str, voice_note: bool) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO outbox (message_id, conversation_id, voice_note, attempts, next_attempt_at, created_at)
+             VALUES (?, ?, ?, 0, 0, ?)",
+            params![message_id, conversation_id, voice_note as i64, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&self, message_id: &str) -> Option<OutboxEntry> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT {} FROM outbox WHERE message_id = ?", OUTBOX_COLUMNS),
+            params![message_id],
+            row_to_entry,
+        )
+        .ok()
+    }
+
+    /// The oldest pending entry of each conversation, if it's due at `now`
+    /// (ms)
+    pub fn due(&self, now: i64) -> Vec<OutboxEntry> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let mut stmt = match conn.prepare(&format!(
+            "SELECT {} FROM outbox o WHERE {} AND o.next_attempt_at <= ? ORDER BY o.seq ASC",
+            OUTBOX_COLUMNS, IS_CONVERSATION_HEAD
+        )) {
+            Ok(s) => s,
+            Err(_) => return Vec::new(),
+        };
+
+        stmt.query_map(params![now], row_to_entry)
+            .map(|rows| rows.filter_map(|r| r.ok()).collect())
+            .unwrap_or_default()
+    }
+
+    /// When the next conversation's oldest pending entry is due (ms)
+    pub fn next_attempt_at(&self) -> Option<i64> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT MIN(o.next_attempt_at) FROM outbox o WHERE {}", IS_CONVERSATION_HEAD),
+            [],
+            |row| row.get(0),
+        )
+        .ok()
+        .flatten()
+    }
+
+    /// Record a failed attempt and when to try again
+    pub fn reschedule(&self, message_id: &str, attempts: u32, next_attempt_at: i64, error: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute(
+            "UPDATE outbox SET attempts = ?, next_attempt_at = ?, last_error = ? WHERE message_id = ?",
+            params![attempts, next_attempt_at, error, message_id],
+        )?;
+        Ok(())
+    }
+
+    /// Stop retrying an entry; it stays until the user retries or deletes
+    /// the message
+    pub fn mark_failed(&self, message_id: &str, error: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute(
+            "UPDATE outbox SET failed = 1, last_error = ? WHERE message_id = ?",
+            params![error, message_id],
+        )?;
+        Ok(())
+    }
+
+    /// Make every pending entry due now, keeping its attempt count, e.g.
+    /// after reconnecting
+    pub fn resume(&self) -> Result<usize> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        Ok(conn.execute(
+            "UPDATE outbox SET next_attempt_at = 0 WHERE failed = 0 AND next_attempt_at > 0",
+            [],
+        )?)
+    }
+
+    pub fn remove(&self, message_id: &str) -> Result<()> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.execute("DELETE FROM outbox WHERE message_id = ?", params![message_id])?;
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+        conn.query_row("SELECT COUNT(*) FROM outbox", [], |row| row.get::<_, i64>(0))
+            .unwrap_or(0) as usize
+    }
+}
+
+fn row_to_entry(row: &Row) -> rusqlite::Result<OutboxEntry> {
+    Ok(OutboxEntry {
+        seq: row.get(0)?,
+        message_id: row.get(1)?,
+        conversation_id: row.get(2)?,
+        voice_note: row.get::<_, i64>(3)? != 0,
+        attempts: row.get(4)?,
+        next_attempt_at: row.get(5)?,
+        last_error: row.get(6)?,
+        failed: row.get::<_, i64>(7)? != 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TEST_KEY: &str = "test-passphrase-123";
+
+    fn ids(entries: &[OutboxEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.message_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_due_keeps_conversation_order() {
+        let dir = tempdir().unwrap();
+        let db = Database::open_encrypted(&dir.path().join("test.db"), TEST_KEY).unwrap();
+        let repo = OutboxRepository::new(&db);
+
+        repo.enqueue("a1", "alice", false).unwrap();
+        repo.enqueue("a2", "alice", false).unwrap();
+        repo.enqueue("b1", "bob", true).unwrap();
+        assert_eq!(ids(&repo.due(1_000)), vec!["a1", "b1"]);
+        assert!(repo.get("b1").unwrap().voice_note);
+
+        // A message waiting on a retry holds back the rest of its conversation
+        repo.reschedule("a1", 1, 5_000, "timeout").unwrap();
+        assert_eq!(ids(&repo.due(1_000)), vec!["b1"]);
+        assert_eq!(repo.next_attempt_at(), Some(0));
+        repo.remove("b1").unwrap();
+        assert_eq!(repo.next_attempt_at(), Some(5_000));
+        assert!(repo.due(1_000).is_empty());
+
+        let entry = repo.get("a1").unwrap();
+        assert_eq!(entry.attempts, 1);
+        assert_eq!(entry.last_error.as_deref(), Some("timeout"));
+
+        assert_eq!(repo.resume().unwrap(), 1);
+        assert_eq!(repo.get("a1").unwrap().attempts, 1);
+        assert_eq!(ids(&repo.due(1_000)), vec!["a1"]);
+
+        // A failed message no longer holds up its conversation
+        repo.mark_failed("a1", "gave up").unwrap();
+        assert!(repo.get("a1").unwrap().failed);
+        assert_eq!(ids(&repo.due(1_000)), vec!["a2"]);
+
+        // Enqueueing again moves it to the back of the line
+        repo.enqueue("a1", "alice", false).unwrap();
+        assert!(!repo.get("a1").unwrap().failed);
+        assert_eq!(ids(&repo.due(1_000)), vec!["a2"]);
+        assert_eq!(repo.count(), 2);
+    }
+}
@@ -0,0 +1,68 @@
+-- Schema of app.db as created by the first release, before user_version
+-- was used. Frozen: used by the migration tests as an unversioned install.
+
+CREATE TABLE IF NOT EXISTS conversations (
+    id TEXT PRIMARY KEY,
+    conversation_type TEXT NOT NULL,
+    name TEXT NOT NULL,
+    avatar_path TEXT,
+    last_message TEXT,
+    last_message_at INTEGER,
+    unread_count INTEGER DEFAULT 0,
+    is_pinned INTEGER DEFAULT 0,
+    is_muted INTEGER DEFAULT 0,
+    muted_until INTEGER,
+    is_archived INTEGER DEFAULT 0,
+    is_blocked INTEGER DEFAULT 0,
+    disappearing_timer INTEGER DEFAULT 0,
+    draft TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+    id TEXT PRIMARY KEY,
+    conversation_id TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    status TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    content_json TEXT NOT NULL,
+    sent_at INTEGER NOT NULL,
+    server_timestamp INTEGER,
+    delivered_at INTEGER,
+    read_at INTEGER,
+    quote_json TEXT,
+    reactions_json TEXT,
+    expires_in_seconds INTEGER,
+    expires_at INTEGER,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+);
+
+CREATE TABLE IF NOT EXISTS contacts (
+    id TEXT PRIMARY KEY,
+    phone_number TEXT,
+    uuid TEXT UNIQUE,
+    name TEXT NOT NULL,
+    profile_name TEXT,
+    avatar_path TEXT,
+    profile_key BLOB,
+    is_blocked INTEGER DEFAULT 0,
+    is_verified INTEGER DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_messages_conversation
+    ON messages(conversation_id, sent_at DESC);
+CREATE INDEX IF NOT EXISTS idx_messages_sender
+    ON messages(sender);
+CREATE INDEX IF NOT EXISTS idx_conversations_updated
+    ON conversations(updated_at DESC);
+CREATE INDEX IF NOT EXISTS idx_contacts_uuid
+    ON contacts(uuid);
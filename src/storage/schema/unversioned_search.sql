@@ -0,0 +1,133 @@
+-- Schema of app.db as created by the last build before user_version was
+-- used: the first release plus attachments, download jobs and the search
+-- index. Frozen: used by the migration tests as an unversioned install.
+
+CREATE TABLE IF NOT EXISTS conversations (
+    id TEXT PRIMARY KEY,
+    conversation_type TEXT NOT NULL,
+    name TEXT NOT NULL,
+    avatar_path TEXT,
+    last_message TEXT,
+    last_message_at INTEGER,
+    unread_count INTEGER DEFAULT 0,
+    is_pinned INTEGER DEFAULT 0,
+    is_muted INTEGER DEFAULT 0,
+    muted_until INTEGER,
+    is_archived INTEGER DEFAULT 0,
+    is_blocked INTEGER DEFAULT 0,
+    disappearing_timer INTEGER DEFAULT 0,
+    draft TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+    id TEXT PRIMARY KEY,
+    conversation_id TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    status TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    content_json TEXT NOT NULL,
+    sent_at INTEGER NOT NULL,
+    server_timestamp INTEGER,
+    delivered_at INTEGER,
+    read_at INTEGER,
+    quote_json TEXT,
+    reactions_json TEXT,
+    expires_in_seconds INTEGER,
+    expires_at INTEGER,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+);
+
+CREATE TABLE IF NOT EXISTS contacts (
+    id TEXT PRIMARY KEY,
+    phone_number TEXT,
+    uuid TEXT UNIQUE,
+    name TEXT NOT NULL,
+    profile_name TEXT,
+    avatar_path TEXT,
+    profile_key BLOB,
+    is_blocked INTEGER DEFAULT 0,
+    is_verified INTEGER DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS attachments (
+    id TEXT PRIMARY KEY,
+    message_id TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    filename TEXT,
+    size INTEGER NOT NULL DEFAULT 0,
+    width INTEGER,
+    height INTEGER,
+    voice_note INTEGER DEFAULT 0,
+    cdn_number INTEGER NOT NULL DEFAULT 0,
+    cdn_key TEXT NOT NULL,
+    key BLOB NOT NULL,
+    digest BLOB,
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS download_jobs (
+    attachment_id TEXT PRIMARY KEY,
+    message_id TEXT NOT NULL,
+    state TEXT NOT NULL DEFAULT 'queued',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL,
+    last_error TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_messages_conversation
+    ON messages(conversation_id, sent_at DESC);
+CREATE INDEX IF NOT EXISTS idx_messages_sender
+    ON messages(sender);
+CREATE INDEX IF NOT EXISTS idx_conversations_updated
+    ON conversations(updated_at DESC);
+CREATE INDEX IF NOT EXISTS idx_contacts_uuid
+    ON contacts(uuid);
+CREATE INDEX IF NOT EXISTS idx_attachments_message
+    ON attachments(message_id);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+    text,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_before_insert BEFORE INSERT ON messages BEGIN
+    DELETE FROM messages_fts
+    WHERE rowid = (SELECT rowid FROM messages WHERE id = new.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_after_insert AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts (rowid, text) VALUES (new.rowid, trim(
+        coalesce(json_extract(new.content_json, '$.body'), '') || ' ' ||
+        coalesce(json_extract(new.content_json, '$.caption'), '') || ' ' ||
+        coalesce(json_extract(new.content_json, '$.filename'), '') || ' ' ||
+        coalesce(json_extract(new.quote_json, '$.text'), '')
+    ));
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_after_update
+AFTER UPDATE OF content_json, quote_json ON messages BEGIN
+    DELETE FROM messages_fts WHERE rowid = old.rowid;
+    INSERT INTO messages_fts (rowid, text) VALUES (new.rowid, trim(
+        coalesce(json_extract(new.content_json, '$.body'), '') || ' ' ||
+        coalesce(json_extract(new.content_json, '$.caption'), '') || ' ' ||
+        coalesce(json_extract(new.content_json, '$.filename'), '') || ' ' ||
+        coalesce(json_extract(new.quote_json, '$.text'), '')
+    ));
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_after_delete AFTER DELETE ON messages BEGIN
+    DELETE FROM messages_fts WHERE rowid = old.rowid;
+END;
@@ -165,6 +165,14 @@ pub struct MediaSettings {
     /// Auto-download files on mobile
     pub auto_download_files_mobile: bool,
 
+    /// Treat the current connection as metered and apply the "mobile" policies
+    #[serde(default)]
+    pub metered_connection: bool,
+
+    /// Skip automatic download of attachments larger than this (0 = no limit)
+    #[serde(default = "default_auto_download_max_size_mb")]
+    pub auto_download_max_size_mb: u32,
+
     /// Default media quality
     pub media_quality: MediaQuality,
 
@@ -184,6 +192,8 @@ impl Default for MediaSettings {
             auto_download_videos_mobile: false,
             auto_download_files_wifi: true,
             auto_download_files_mobile: false,
+            metered_connection: false,
+            auto_download_max_size_mb: default_auto_download_max_size_mb(),
             media_quality: MediaQuality::Standard,
             save_to_gallery: false,
             gallery_path: None,
@@ -191,6 +201,10 @@ impl Default for MediaSettings {
     }
 }
 
+fn default_auto_download_max_size_mb() -> u32 {
+    100
+}
+
 /// Media quality options
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum MediaQuality {
@@ -374,6 +388,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_media_settings_missing_fields_use_defaults() {
+        let json = r#"{
+            "auto_download_images_wifi": true,
+            "auto_download_images_mobile": false,
+            "auto_download_videos_wifi": true,
+            "auto_download_videos_mobile": false,
+            "auto_download_files_wifi": false,
+            "auto_download_files_mobile": false,
+            "media_quality": "Standard",
+            "save_to_gallery": false,
+            "gallery_path": null
+        }"#;
+        let media: MediaSettings = serde_json::from_str(json).unwrap();
+
+        assert!(!media.metered_connection);
+        assert_eq!(media.auto_download_max_size_mb, 100);
+        assert!(!media.auto_download_files_wifi);
+    }
+
     #[test]
     fn test_settings_reset() {
         let dir = tempdir().unwrap();
@@ -0,0 +1,219 @@
+//! Sticker pack storage
+//!
+//! A pack is recorded as soon as we see it, in a message or a sync from
+//! another device, and filled in once its manifest was downloaded. The images
+//! themselves live in the stickers directory, see `signal::stickers`.
+
+use crate::storage::database::Database;
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+const PACK_COLUMNS: &str = "pack_id, pack_key, title, author, cover_sticker_id, installed";
+
+/// A sticker pack, addressed by its hex ID and key
+#[derive(Debug, Clone, PartialEq)]
+pub struct StickerPack {
+    pub pack_id: String,
+    pub pack_key: String,
+    /// From the manifest; `None` until it was downloaded
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub cover_sticker_id: Option<u32>,
+    /// Shown in the sticker picker
+    pub installed: bool,
+    pub stickers: Vec<Sticker>,
+}
+
+/// One sticker of a pack
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sticker {
+    pub sticker_id: u32,
+    /// Emoji the sticker stands for
+    pub emoji: Option<String>,
+    pub content_type: Option<String>,
+}
+
+pub struct StickerRepository<'a> {
+    db: &'a Database,
+}
+
+impl<'a> StickerRepository<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    pub fn get(&self, pack_id: &str) -> Option<StickerPack> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let mut pack = conn
+            .query_row(
+                &format!("SELECT {} FROM sticker_packs WHERE pack_id = ?", PACK_COLUMNS),
+                params![pack_id],
+                row_to_pack,
+            )
+            .ok()?;
+        load_stickers(&conn, &mut pack);
+        Some(pack)
+    }
+
+    /// Installed packs, in the order they were installed
+    pub fn installed(&self) -> Vec<StickerPack> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let mut stmt = match conn.prepare(&format!(
+            "SELECT {} FROM sticker_packs WHERE installed = 1 ORDER BY installed_at ASC",
+            PACK_COLUMNS
+        )) {
+            Ok(s) => s,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut packs: Vec<StickerPack> = stmt
+            .query_map([], row_to_pack)
+            .map(|rows| rows.filter_map(|r| r.ok()).collect())
+            .unwrap_or_default();
+        for pack in &mut packs {
+            load_stickers(&conn, pack);
+        }
+        packs
+    }
+
+    /// Save a pack's manifest and stickers, keeping whether it's installed
+    pub fn save_manifest(&self, pack: &StickerPack) -> Result<()> {
+        let conn = self.db.connection();
+        let mut conn = conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO sticker_packs (pack_id, pack_key, title, author, cover_sticker_id)
+             VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT(pack_id) DO UPDATE SET
+                pack_key = excluded.pack_key,
+                title = excluded.title,
+                author = excluded.author,
+                cover_sticker_id = excluded.cover_sticker_id",
+            params![pack.pack_id, pack.pack_key, pack.title, pack.author, pack.cover_sticker_id],
+        )?;
+
+        tx.execute("DELETE FROM stickers WHERE pack_id = ?", params![pack.pack_id])?;
+        for sticker in &pack.stickers {
+            tx.execute(
+                "INSERT OR REPLACE INTO stickers (pack_id, sticker_id, emoji, content_type) VALUES (?, ?, ?, ?)",
+                params![pack.pack_id, sticker.sticker_id, sticker.emoji, sticker.content_type],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Install or remove a pack, recording it if it's new. Returns false when
+    /// it already was in that state.
+    pub fn set_installed(&self, pack_id: &str, pack_key: &str, installed: bool) -> Result<bool> {
+        let conn = self.db.connection();
+        let conn = conn.lock().unwrap();
+
+        let current: Option<bool> = conn
+            .query_row(
+                "SELECT installed FROM sticker_packs WHERE pack_id = ?",
+                params![pack_id],
+                |row| Ok(row.get::<_, i64>(0)? != 0),
+            )
+            .optional()?;
+        if current == Some(installed) {
+            return Ok(false);
+        }
+
+        let installed_at = installed.then(|| Utc::now().timestamp_millis());
+        conn.execute(
+            "INSERT INTO sticker_packs (pack_id, pack_key, installed, installed_at) VALUES (?, ?, ?, ?)
+             ON CONFLICT(pack_id) DO UPDATE SET
+                pack_key = excluded.pack_key,
+                installed = excluded.installed,
+                installed_at = excluded.installed_at",
+            params![pack_id, pack_key, installed as i64, installed_at],
+        )?;
+        Ok(true)
+    }
+}
+
+fn row_to_pack(row: &Row) -> rusqlite::Result<StickerPack> {
+    Ok(StickerPack {
+        pack_id: row.get(0)?,
+        pack_key: row.get(1)?,
+        title: row.get(2)?,
+        author: row.get(3)?,
+        cover_sticker_id: row.get(4)?,
+        installed: row.get::<_, i64>(5)? != 0,
+        stickers: Vec::new(),
+    })
+}
+
+fn load_stickers(conn: &Connection, pack: &mut StickerPack) {
+    let Ok(mut stmt) = conn.prepare(
+        "SELECT sticker_id, emoji, content_type FROM stickers WHERE pack_id = ? ORDER BY sticker_id ASC",
+    ) else {
+        return;
+    };
+
+    pack.stickers = stmt
+        .query_map(params![pack.pack_id], |row| {
+            Ok(Sticker {
+                sticker_id: row.get(0)?,
+                emoji: row.get(1)?,
+                content_type: row.get(2)?,
+            })
+        })
+        .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TEST_KEY: &str = "test-passphrase-123";
+
+    #[test]
+    fn test_manifest_keeps_installed_state() {
+        let dir = tempdir().unwrap();
+        let db = Database::open_encrypted(&dir.path().join("test.db"), TEST_KEY).unwrap();
+        let repo = StickerRepository::new(&db);
+
+        assert!(repo.set_installed("aa", "k1", true).unwrap());
+        assert!(!repo.set_installed("aa", "k1", true).unwrap());
+        assert_eq!(repo.get("aa").unwrap().title, None);
+
+        let pack = StickerPack {
+            pack_id: "aa".to_string(),
+            pack_key: "k1".to_string(),
+            title: Some("Cats".to_string()),
+            author: Some("Someone".to_string()),
+            cover_sticker_id: Some(1),
+            installed: false,
+            stickers: vec![
+                Sticker { sticker_id: 0, emoji: Some("😺".to_string()), content_type: Some("image/webp".to_string()) },
+                Sticker { sticker_id: 1, emoji: None, content_type: None },
+            ],
+        };
+        repo.save_manifest(&pack).unwrap();
+
+        let saved = repo.get("aa").unwrap();
+        assert!(saved.installed);
+        assert_eq!(saved.title.as_deref(), Some("Cats"));
+        assert_eq!(saved.stickers, pack.stickers);
+        assert_eq!(repo.installed().len(), 1);
+
+        // A pack only seen in a message isn't offered in the picker
+        repo.save_manifest(&StickerPack { pack_id: "bb".to_string(), ..pack }).unwrap();
+        assert_eq!(repo.installed().len(), 1);
+
+        assert!(repo.set_installed("aa", "k1", false).unwrap());
+        assert!(repo.installed().is_empty());
+        assert_eq!(repo.get("aa").unwrap().stickers.len(), 2);
+    }
+}
@@ -1,12 +1,16 @@
 //! Chat list panel - shows all conversations
 
 use crate::app::SignalApp;
+use crate::signal::messages::MessageDirection;
 use crate::storage::contacts::{ContactRepository, StoredContact};
 use crate::storage::conversations::{Conversation, ConversationType, ConversationRepository};
+use crate::storage::messages::{MessageRepository, SearchQuery, SearchResult};
 use crate::ui::avatar_cache::AvatarCache;
 use crate::ui::components::emoji_text::paint_emoji_text;
 use crate::ui::theme::SignalColors;
+use crate::ui::widgets::search_bar::SearchBar;
 use chrono::{DateTime, Local, Utc};
+use egui::text::{LayoutJob, TextFormat};
 use egui::{Color32, Rounding, Sense, Vec2};
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -19,11 +23,20 @@ static mut PIN_REQUEST: Option<String> = None;
 static mut MUTE_REQUEST: Option<String> = None;
 static mut ARCHIVE_REQUEST: Option<String> = None;
 static mut DELETE_REQUEST: Option<String> = None;
+static mut MESSAGE_SEARCH: String = String::new();
+static mut LAST_SEARCH_QUERY: String = String::new();
+static mut CACHED_SEARCH_RESULTS: Vec<SearchResult> = Vec::new();
+static mut OPEN_MESSAGE_REQUEST: Option<(String, String)> = None;
 static CONVERSATIONS_DIRTY: AtomicBool = AtomicBool::new(true);
 static CONTACTS_DIRTY: AtomicBool = AtomicBool::new(true);
+static SEARCH_DIRTY: AtomicBool = AtomicBool::new(true);
+
+/// Maximum number of message search hits shown
+const SEARCH_RESULT_LIMIT: usize = 100;
 
 pub fn invalidate_conversations_cache() {
     CONVERSATIONS_DIRTY.store(true, Ordering::SeqCst);
+    SEARCH_DIRTY.store(true, Ordering::SeqCst);
 }
 
 pub fn invalidate_contacts_cache() {
@@ -42,7 +55,10 @@ pub struct ConversationItem {
     pub is_group: bool,
     pub is_muted: bool,
     pub is_pinned: bool,
-    pub typing_indicator: bool,
+    /// Message request we haven't answered yet
+    pub is_request: bool,
+    /// "X is typing…", shown instead of the last message
+    pub typing: Option<String>,
 }
 
 impl ConversationItem {
@@ -85,7 +101,8 @@ impl From<&Conversation> for ConversationItem {
             is_group: matches!(conv.conversation_type, ConversationType::Group),
             is_muted: conv.is_currently_muted(),
             is_pinned: conv.is_pinned,
-            typing_indicator: false,
+            is_request: !conv.is_accepted,
+            typing: None,
         }
     }
 }
@@ -107,13 +124,27 @@ pub fn show(app: &mut SignalApp, ui: &mut egui::Ui) {
 
     let mut new_selection: Option<String> = None;
 
+    let message_search = unsafe { &raw mut MESSAGE_SEARCH };
+    let message_search = unsafe { &mut *message_search };
+
+    if !*show_picker {
+        ui.add_space(4.0);
+        SearchBar::new("Search messages").show(ui, message_search);
+        ui.add_space(4.0);
+    }
+
     if *show_picker {
         if let Some(selected) = show_contact_picker(app, ui) {
             new_selection = Some(selected);
             *show_picker = false;
         }
+    } else if !message_search.trim().is_empty() {
+        show_search_results(app, ui, message_search);
     } else {
-        let conversations = load_conversations(app);
+        let mut conversations = load_conversations(app);
+        for conv in &mut conversations {
+            conv.typing = super::chat_view::typing_label(app, &conv.id);
+        }
         let selected_id = app.selected_conversation_id();
         let avatar_cache = app.avatar_cache();
 
@@ -174,11 +205,165 @@ pub fn show(app: &mut SignalApp, ui: &mut egui::Ui) {
         app.delete_conversation(&conv_id);
     }
 
+    let open_req = unsafe { &raw mut OPEN_MESSAGE_REQUEST };
+    let open_req = unsafe { &mut *open_req };
+    if let Some((conv_id, message_id)) = open_req.take() {
+        app.open_message(&conv_id, &message_id);
+    }
+
     if let Some(id) = new_selection {
         app.select_conversation(Some(id));
     }
 }
 
+/// Message search mode: hits grouped by conversation, ordered by each
+/// conversation's best hit
+fn show_search_results(app: &SignalApp, ui: &mut egui::Ui, search: &str) {
+    let results = load_search_results(app, search);
+
+    let mut groups: Vec<(&str, &str, Vec<&SearchResult>)> = Vec::new();
+    for result in &results {
+        let conv_id = result.message.conversation_id.as_str();
+        match groups.iter_mut().find(|(id, _, _)| *id == conv_id) {
+            Some((_, _, hits)) => hits.push(result),
+            None => groups.push((conv_id, result.conversation_name.as_str(), vec![result])),
+        }
+    }
+
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+
+            if results.is_empty() {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(40.0);
+                    ui.label("No messages found");
+                    ui.add_space(8.0);
+                    ui.label(
+                        egui::RichText::new("Filters: from:, in:, before:YYYY-MM-DD, after:YYYY-MM-DD, has:attachment")
+                            .small()
+                            .color(SignalColors::TEXT_TERTIARY),
+                    );
+                });
+                return;
+            }
+
+            for (conv_id, name, hits) in &groups {
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    ui.add_space(12.0);
+                    ui.label(egui::RichText::new(*name).strong().color(SignalColors::TEXT_PRIMARY));
+                    ui.label(
+                        egui::RichText::new(format!("({})", hits.len()))
+                            .small()
+                            .color(SignalColors::TEXT_TERTIARY),
+                    );
+                });
+
+                for hit in hits {
+                    if show_search_hit(ui, hit) {
+                        let req = unsafe { &raw mut OPEN_MESSAGE_REQUEST };
+                        unsafe { *req = Some((conv_id.to_string(), hit.message.id.clone())) };
+                    }
+                }
+            }
+
+            if results.len() >= SEARCH_RESULT_LIMIT {
+                ui.add_space(8.0);
+                ui.vertical_centered(|ui| {
+                    ui.label(
+                        egui::RichText::new("Showing the first 100 results")
+                            .small()
+                            .color(SignalColors::TEXT_TERTIARY),
+                    );
+                });
+            }
+        });
+}
+
+fn load_search_results(app: &SignalApp, search: &str) -> Vec<SearchResult> {
+    let cache = unsafe { &raw mut CACHED_SEARCH_RESULTS };
+    let cache = unsafe { &mut *cache };
+    let last_query = unsafe { &raw mut LAST_SEARCH_QUERY };
+    let last_query = unsafe { &mut *last_query };
+
+    if last_query == search && !SEARCH_DIRTY.load(Ordering::SeqCst) {
+        return cache.clone();
+    }
+
+    if let Some(db) = app.storage().database() {
+        let message_repo = MessageRepository::new(&*db);
+        *cache = message_repo.search_query(&SearchQuery::parse(search), SEARCH_RESULT_LIMIT);
+        *last_query = search.to_string();
+        SEARCH_DIRTY.store(false, Ordering::SeqCst);
+    }
+
+    cache.clone()
+}
+
+/// Render one hit with its matched terms highlighted. Returns true if clicked.
+fn show_search_hit(ui: &mut egui::Ui, hit: &SearchResult) -> bool {
+    let font = egui::FontId::proportional(13.0);
+    let plain = TextFormat::simple(font.clone(), SignalColors::TEXT_SECONDARY);
+    let highlighted = TextFormat {
+        color: SignalColors::TEXT_PRIMARY,
+        background: SignalColors::SIGNAL_BLUE.linear_multiply(0.5),
+        ..TextFormat::simple(font, SignalColors::TEXT_PRIMARY)
+    };
+
+    let mut job = LayoutJob::default();
+    if hit.message.direction == MessageDirection::Outgoing {
+        job.append("You: ", 0.0, plain.clone());
+    }
+
+    let preview = &hit.match_preview;
+    let mut pos = 0;
+    for range in &hit.highlights {
+        if range.start < pos || range.end > preview.len() {
+            continue;
+        }
+        job.append(&preview[pos..range.start], 0.0, plain.clone());
+        job.append(&preview[range.clone()], 0.0, highlighted.clone());
+        pos = range.end;
+    }
+    job.append(&preview[pos..], 0.0, plain);
+    job.wrap.max_rows = 2;
+    job.wrap.break_anywhere = false;
+
+    let background = ui.painter().add(egui::Shape::Noop);
+    let response = egui::Frame::none()
+        .inner_margin(egui::Margin::symmetric(12.0, 6.0))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.horizontal(|ui| {
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    ui.label(
+                        egui::RichText::new(format_time(&hit.message.sent_at))
+                            .size(12.0)
+                            .color(SignalColors::TEXT_TERTIARY),
+                    );
+                    ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
+                        job.wrap.max_width = ui.available_width();
+                        ui.label(job);
+                    });
+                });
+            });
+        })
+        .response
+        .interact(Sense::click());
+
+    if response.hovered() {
+        ui.painter().set(
+            background,
+            egui::Shape::rect_filled(response.rect, Rounding::ZERO, SignalColors::DARK_SURFACE_ELEVATED),
+        );
+        ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+    }
+
+    response.clicked()
+}
+
 fn show_contact_picker(app: &mut SignalApp, ui: &mut egui::Ui) -> Option<String> {
     let search = unsafe { &raw mut CONTACT_SEARCH };
     let search = unsafe { &mut *search };
@@ -487,21 +672,15 @@ fn show_conversation_item(
         );
     }
 
-    // Last message preview
-    if let Some(msg) = &conv.last_message {
-        let preview = if conv.typing_indicator {
-            "typing...".to_string()
-        } else if msg.len() > 40 {
-            format!("{}...", &msg[..40])
-        } else {
-            msg.clone()
-        };
-
-        let preview_color = if conv.typing_indicator {
-            SignalColors::SIGNAL_BLUE
-        } else {
-            SignalColors::TEXT_SECONDARY
-        };
+    // Last message preview, or who is typing
+    let preview = match (&conv.typing, &conv.last_message) {
+        (Some(typing), _) => Some((typing.clone(), SignalColors::SIGNAL_BLUE)),
+        (None, _) if conv.is_request => Some(("Message request".to_string(), SignalColors::SIGNAL_BLUE)),
+        (None, Some(msg)) if msg.len() > 40 => Some((format!("{}...", &msg[..40]), SignalColors::TEXT_SECONDARY)),
+        (None, Some(msg)) => Some((msg.clone(), SignalColors::TEXT_SECONDARY)),
+        (None, None) => None,
+    };
+    if let Some((preview, preview_color)) = preview {
 
         paint_emoji_text(
             ui,
@@ -1,14 +1,22 @@
 //! Chat view - displays messages in a conversation
 
 use crate::app::SignalApp;
+use crate::services::typing::{OutgoingTyping, TypingUpdate};
+use crate::signal::requests::MessageRequestAction;
+use crate::signal::safety;
 use crate::signal::messages::{
     Content as StorageContent, Message as StorageMessage,
-    MessageDirection as StorageDirection, MessageStatus as StorageStatus,
+    MessageDirection as StorageDirection, MessageStatus as StorageStatus, Quote as StorageQuote,
+    format_expiration_timer, GroupUpdateType, IdentityUpdateType, DELETED_MESSAGE_TEXT, EXPIRATION_TIMER_OPTIONS,
 };
+use crate::storage::attachments::{AttachmentRepository, DownloadStatus};
 use crate::storage::conversations::ConversationRepository;
 use crate::storage::messages::MessageRepository;
+use crate::storage::Storage;
 use crate::ui::theme::SignalColors;
 use crate::ui::widgets::emoji_picker::EmojiPicker;
+use crate::ui::widgets::sticker::{show_sticker, PickedSticker, StickerPicker};
+use crate::ui::widgets::voice_player::{self, show_voice_player};
 use crate::ui::widgets::voice_recorder::VoiceRecorder;
 use chrono::{DateTime, Local, Utc};
 use egui::{Color32, Rounding, Sense, Vec2};
@@ -16,19 +24,86 @@ use crate::ui::components::emoji_text::{
     emoji_aware_layouter, overlay_emoji_on_textedit, show_emoji_text, show_emoji_text_styled,
 };
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Instant;
 
 static mut CACHED_CONVERSATION_ID: Option<String> = None;
 static mut CACHED_CONVERSATION_NAME: String = String::new();
 static mut CACHED_MESSAGES: Vec<MessageItem> = Vec::new();
+/// Disappearing message timer of the cached conversation, 0 when off
+static mut CACHED_DISAPPEARING_TIMER: u32 = 0;
+static mut CACHED_IS_GROUP: bool = false;
+/// Whether the cached conversation is a message request we haven't answered
+static mut CACHED_IS_REQUEST: bool = false;
+static mut CACHED_IS_BLOCKED: bool = false;
+/// (uuid, name) of a recipient of the cached conversation who changed their
+/// safety number after we verified it; sending waits until the user accepts it
+static mut CACHED_IDENTITY_CHANGED: Option<(String, String)> = None;
 static MESSAGES_DIRTY: AtomicBool = AtomicBool::new(true);
+/// (conversation_id, message_id) to open the history at instead of the latest messages
+static mut FOCUSED_MESSAGE: Option<(String, String)> = None;
+static SCROLL_TO_FOCUSED: AtomicBool = AtomicBool::new(false);
+static mut MESSAGE_INPUT: String = String::new();
+/// (conversation_id, message_id) of the sent message being edited in the input bar
+static mut EDITING_MESSAGE: Option<(String, String)> = None;
+/// Open edit history window: message ID and its versions, oldest first
+static mut EDIT_HISTORY: Option<(String, Vec<(DateTime<Utc>, String)>)> = None;
+/// Sent message waiting for the user to confirm a delete for everyone
+static mut CONFIRM_DELETE: Option<String> = None;
+/// Message the next one sent from the input bar replies to
+static mut REPLYING_TO: Option<ReplyDraft> = None;
+/// Open message info window
+static mut MESSAGE_INFO: Option<MessageInfo> = None;
+/// Our typing indicator for the conversation being typed in
+static mut OUTGOING_TYPING: OutgoingTyping = OutgoingTyping::new();
+
+/// Reactions offered in a message's context menu
+const QUICK_REACTIONS: [&str; 6] = ["❤️", "👍", "👎", "😂", "😮", "😢"];
+
+/// Number of messages loaded on each side of a focused message
+const FOCUS_CONTEXT: usize = 50;
+
+/// Narrowest a bubble gets when it contains a quote
+const QUOTE_MIN_WIDTH: f32 = 180.0;
+
+/// Size stickers are shown at in the conversation
+const STICKER_SIZE: f32 = 128.0;
+
+/// Width of the inline voice note player
+const VOICE_PLAYER_WIDTH: f32 = 240.0;
 
 pub fn invalidate_messages_cache() {
     MESSAGES_DIRTY.store(true, Ordering::SeqCst);
 }
 
+/// Load the history around `message_id` and scroll to it on the next frame
+pub fn focus_message(conversation_id: &str, message_id: &str) {
+    let focused = unsafe { &raw mut FOCUSED_MESSAGE };
+    let focused = unsafe { &mut *focused };
+    *focused = Some((conversation_id.to_string(), message_id.to_string()));
+    SCROLL_TO_FOCUSED.store(true, Ordering::SeqCst);
+    invalidate_messages_cache();
+}
+
+fn clear_focused_message() {
+    let focused = unsafe { &raw mut FOCUSED_MESSAGE };
+    let focused = unsafe { &mut *focused };
+    if focused.take().is_some() {
+        invalidate_messages_cache();
+    }
+}
+
+fn focused_message_id(conversation_id: &str) -> Option<String> {
+    let focused = unsafe { &raw const FOCUSED_MESSAGE };
+    let focused = unsafe { &*focused };
+    focused
+        .as_ref()
+        .filter(|(conv_id, _)| conv_id == conversation_id)
+        .map(|(_, msg_id)| msg_id.clone())
+}
+
 /// Message direction
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MessageDirection {
@@ -55,8 +130,74 @@ pub struct MessageItem {
     pub timestamp: DateTime<Utc>,
     pub status: MessageStatus,
     pub sender_name: Option<String>, // For group messages
-    pub reply_to: Option<Box<MessageItem>>,
+    pub quote: Option<QuoteItem>,
     pub reactions: Vec<Reaction>,
+    /// Download state of an incoming attachment
+    pub attachment: Option<AttachmentState>,
+    /// The text was changed after sending
+    pub edited: bool,
+    /// Our own message that can still be edited
+    pub editable: bool,
+    /// Our own message that can still be deleted for everyone
+    pub deletable: bool,
+    /// Reactions can be sent to this message
+    pub reactable: bool,
+    /// When a disappearing message will be deleted
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Quoted message shown at the top of a reply
+#[derive(Debug, Clone)]
+pub struct QuoteItem {
+    /// Local ID of the quoted message, empty if we don't have it
+    pub message_id: String,
+    pub author_name: String,
+    /// Quoted text, or a label for the quoted attachment
+    pub text: String,
+    /// Image thumbnail as (URI, bytes) for the image loader
+    pub thumbnail: Option<(String, Arc<[u8]>)>,
+}
+
+/// Reply being composed in the input bar
+struct ReplyDraft {
+    conversation_id: String,
+    message_id: String,
+    author_name: String,
+    text: String,
+}
+
+/// Delivery details of a sent message for the info window
+struct MessageInfo {
+    sent_at: DateTime<Utc>,
+    /// (recipient name, delivered at, read at)
+    recipients: Vec<(String, Option<DateTime<Utc>>, Option<DateTime<Utc>>)>,
+}
+
+/// Download state of a received attachment
+#[derive(Debug, Clone)]
+pub struct AttachmentState {
+    pub id: String,
+    pub size: u64,
+    pub status: DownloadStatus,
+}
+
+/// Action requested from a message bubble, applied after rendering
+pub enum MessageAction {
+    DownloadAttachment(String),
+    /// Start editing a sent message: (message ID, current text)
+    EditMessage(String, String),
+    ShowEditHistory(String),
+    DeleteForEveryone(String),
+    /// React to a message: (message ID, emoji, remove our reaction)
+    React(String, String, bool),
+    Reply(String),
+    /// Scroll to the message a quote refers to
+    JumpToMessage(String),
+    ShowInfo(String),
+    /// Send a failed message again
+    RetrySend(String),
+    /// Delete a message that couldn't be sent
+    DeleteFailed(String),
 }
 
 /// Message content types
@@ -65,10 +206,14 @@ pub enum MessageContent {
     Text(String),
     Image { path: String, caption: Option<String> },
     File { name: String, size: u64 },
-    Voice { duration_secs: u32 },
-    Sticker { pack_id: String, sticker_id: String },
+    Voice { path: PathBuf, duration_ms: u64, waveform: Option<Vec<u8>> },
+    Sticker { pack_id: String, pack_key: String, sticker_id: u32, emoji: Option<String> },
     Contact { name: String },
     Location { lat: f64, lon: f64 },
+    Deleted,
+    /// Centered system row, e.g. a disappearing message timer change or a
+    /// group update, with the icon shown in front of it
+    Notice { icon: &'static str, text: String },
 }
 
 /// A reaction to a message
@@ -77,6 +222,8 @@ pub struct Reaction {
     pub emoji: String,
     pub count: u32,
     pub from_me: bool,
+    /// Display names of everyone who reacted with this emoji
+    pub senders: Vec<String>,
 }
 
 impl MessageItem {
@@ -85,6 +232,8 @@ impl MessageItem {
         my_id: Option<&str>,
         is_group: bool,
         conv_repo: &ConversationRepository,
+        attachment_repo: &AttachmentRepository,
+        attachments_dir: &Path,
     ) -> Self {
         let direction = match msg.direction {
             StorageDirection::Incoming => MessageDirection::Received,
@@ -109,37 +258,82 @@ impl MessageItem {
                 path: attachment_id.clone(),
                 caption: caption.clone(),
             },
-            StorageContent::Audio { duration_ms, .. } => MessageContent::Voice {
-                duration_secs: (*duration_ms / 1000) as u32,
+            StorageContent::Audio { attachment_id, duration_ms, waveform, .. } => MessageContent::Voice {
+                path: attachments_dir.join(attachment_id),
+                duration_ms: *duration_ms,
+                waveform: waveform.clone(),
             },
             StorageContent::File { filename, size, .. } => MessageContent::File {
                 name: filename.clone(),
                 size: *size,
             },
-            StorageContent::Sticker { pack_id, sticker_id, .. } => MessageContent::Sticker {
+            StorageContent::Sticker { pack_id, pack_key, sticker_id, emoji } => MessageContent::Sticker {
                 pack_id: pack_id.clone(),
-                sticker_id: sticker_id.to_string(),
+                pack_key: pack_key.clone(),
+                sticker_id: *sticker_id,
+                emoji: emoji.clone(),
             },
             StorageContent::Contact { name, .. } => MessageContent::Contact { name: name.clone() },
             StorageContent::Location { latitude, longitude, .. } => MessageContent::Location {
                 lat: *latitude,
                 lon: *longitude,
             },
+            StorageContent::Deleted => MessageContent::Deleted,
+            StorageContent::ExpirationTimerUpdate { expires_in_seconds } => {
+                let author = if msg.direction == StorageDirection::Outgoing
+                    || msg.sender == "self"
+                    || my_id == Some(msg.sender.as_str())
+                {
+                    "You".to_string()
+                } else {
+                    conv_repo.get(&msg.sender).map(|c| c.name).unwrap_or_else(|| msg.sender.clone())
+                };
+                MessageContent::Notice {
+                    icon: "⏱",
+                    text: match expires_in_seconds {
+                        0 => format!("{} turned off disappearing messages", author),
+                        seconds => format!(
+                            "{} set the disappearing message time to {}",
+                            author,
+                            format_expiration_timer(*seconds)
+                        ),
+                    },
+                }
+            }
+            StorageContent::GroupUpdate { update_type, details } => MessageContent::Notice {
+                icon: group_update_icon(*update_type),
+                text: details.clone(),
+            },
+            StorageContent::IdentityUpdate { update_type, details } => MessageContent::Notice {
+                icon: match update_type {
+                    IdentityUpdateType::SafetyNumberChanged => "⚠",
+                    IdentityUpdateType::Verified | IdentityUpdateType::Unverified => "🛡",
+                },
+                text: details.clone(),
+            },
             _ => MessageContent::Text("[Unsupported message type]".to_string()),
         };
 
-        let mut reaction_counts: HashMap<String, (u32, bool)> = HashMap::new();
+        let mut reaction_counts: HashMap<String, Reaction> = HashMap::new();
         for r in &msg.reactions {
-            let entry = reaction_counts.entry(r.emoji.clone()).or_insert((0, false));
-            entry.0 += 1;
-            if my_id == Some(r.sender.as_str()) {
-                entry.1 = true;
+            let entry = reaction_counts.entry(r.emoji.clone()).or_insert_with(|| Reaction {
+                emoji: r.emoji.clone(),
+                count: 0,
+                from_me: false,
+                senders: Vec::new(),
+            });
+            entry.count += 1;
+            if r.sender == "self" || my_id == Some(r.sender.as_str()) {
+                entry.from_me = true;
+                entry.senders.push("You".to_string());
+            } else {
+                entry
+                    .senders
+                    .push(conv_repo.get(&r.sender).map(|c| c.name).unwrap_or_else(|| r.sender.clone()));
             }
         }
-        let reactions: Vec<Reaction> = reaction_counts
-            .into_iter()
-            .map(|(emoji, (count, from_me))| Reaction { emoji, count, from_me })
-            .collect();
+        let mut reactions: Vec<Reaction> = reaction_counts.into_values().collect();
+        reactions.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.emoji.cmp(&b.emoji)));
 
         // Only show sender name for group conversations, and look up the contact name
         let sender_name = if is_group && direction == MessageDirection::Received {
@@ -155,6 +349,21 @@ impl MessageItem {
             None
         };
 
+        let attachment = match (&msg.direction, &msg.content) {
+            (
+                StorageDirection::Incoming,
+                StorageContent::Image { attachment_id, .. }
+                | StorageContent::Video { attachment_id, .. }
+                | StorageContent::Audio { attachment_id, .. }
+                | StorageContent::File { attachment_id, .. },
+            ) => attachment_repo.get(attachment_id).map(|a| AttachmentState {
+                id: a.id,
+                size: a.size,
+                status: a.status,
+            }),
+            _ => None,
+        };
+
         MessageItem {
             id: msg.id.clone(),
             direction,
@@ -162,8 +371,54 @@ impl MessageItem {
             timestamp: msg.sent_at,
             status,
             sender_name,
-            reply_to: None,
+            quote: msg.quote.as_ref().map(|q| QuoteItem::from_storage(q, my_id, conv_repo, attachments_dir)),
             reactions,
+            attachment,
+            edited: msg.edited_at.is_some(),
+            editable: msg.is_editable(Utc::now()),
+            deletable: msg.can_delete_for_everyone(Utc::now()),
+            reactable: msg.is_reactable(),
+            expires_at: msg.expires_at,
+        }
+    }
+}
+
+impl QuoteItem {
+    fn from_storage(
+        quote: &StorageQuote,
+        my_id: Option<&str>,
+        conv_repo: &ConversationRepository,
+        attachments_dir: &Path,
+    ) -> Self {
+        let author_name = if quote.author == "self" || my_id == Some(quote.author.as_str()) {
+            "You".to_string()
+        } else {
+            conv_repo.get(&quote.author).map(|c| c.name).unwrap_or_else(|| quote.author.clone())
+        };
+
+        let preview = quote.attachment_preview.as_ref();
+        let text = match (quote.text.as_deref().filter(|t| !t.is_empty()), preview) {
+            (Some(text), _) => text.to_string(),
+            (None, Some(p)) if p.content_type.starts_with("image/") => "📷 Photo".to_string(),
+            (None, Some(p)) if p.content_type.starts_with("video/") => "🎬 Video".to_string(),
+            (None, Some(p)) if p.content_type.starts_with("audio/") => "🎤 Voice message".to_string(),
+            (None, Some(p)) => format!("📄 {}", p.filename.as_deref().unwrap_or("File")),
+            (None, None) => "Message".to_string(),
+        };
+
+        let thumbnail = preview
+            .filter(|p| p.content_type.starts_with("image/"))
+            .and_then(|p| p.thumbnail_id.as_ref())
+            .and_then(|id| {
+                let bytes = std::fs::read(attachments_dir.join(id)).ok()?;
+                Some((format!("bytes://quote/{}", id), Arc::from(bytes)))
+            });
+
+        Self {
+            message_id: quote.message_id.clone(),
+            author_name,
+            text,
+            thumbnail,
         }
     }
 }
@@ -205,12 +460,30 @@ pub fn show(app: &mut SignalApp, ui: &mut egui::Ui) {
 
     show_conversation_header(ui, app, conversation_id, &conversation_name);
 
+    let focused_id = focused_message_id(conversation_id);
+    if focused_id.is_some() {
+        ui.horizontal(|ui| {
+            ui.add_space(8.0);
+            ui.label(
+                egui::RichText::new("Viewing older messages")
+                    .size(12.0)
+                    .color(SignalColors::TEXT_SECONDARY),
+            );
+            if ui.small_button("Jump to latest ⬇").clicked() {
+                clear_focused_message();
+            }
+        });
+    }
+
+    autoplay_next_voice_note(&messages);
+
     let available_height = ui.available_height() - 60.0;
+    let mut actions: Vec<MessageAction> = Vec::new();
 
     egui::ScrollArea::vertical()
         .max_height(available_height)
         .auto_shrink([false, false])
-        .stick_to_bottom(true)
+        .stick_to_bottom(focused_id.is_none())
         .show(ui, |ui| {
             ui.set_width(ui.available_width());
 
@@ -222,7 +495,28 @@ pub fn show(app: &mut SignalApp, ui: &mut egui::Ui) {
                 }
                 last_date = Some(msg.timestamp);
 
-                show_message(ui, msg);
+                if let MessageContent::Notice { icon, text } = &msg.content {
+                    show_notice(ui, icon, text);
+                    continue;
+                }
+
+                let is_focused = focused_id.as_deref() == Some(msg.id.as_str());
+                if is_focused {
+                    let rect = ui.available_rect_before_wrap();
+                    ui.painter().rect_filled(
+                        egui::Rect::from_min_size(rect.min, Vec2::new(3.0, 24.0)),
+                        Rounding::same(1.5),
+                        SignalColors::SIGNAL_BLUE,
+                    );
+                }
+
+                if let Some(action) = show_message(ui, app.storage(), msg, app.is_uploading(&msg.id)) {
+                    actions.push(action);
+                }
+
+                if is_focused && SCROLL_TO_FOCUSED.swap(false, Ordering::SeqCst) {
+                    ui.scroll_to_cursor(Some(egui::Align::Center));
+                }
                 ui.add_space(4.0);
             }
 
@@ -237,7 +531,356 @@ pub fn show(app: &mut SignalApp, ui: &mut egui::Ui) {
         });
 
     ui.separator();
-    show_message_input(app, ui, conversation_id);
+    let (is_request, is_blocked) = unsafe { (*(&raw const CACHED_IS_REQUEST), *(&raw const CACHED_IS_BLOCKED)) };
+    let mut close_conversation = false;
+    if is_request {
+        if let Some(action) = show_message_request(ui, &conversation_name, is_blocked) {
+            answer_message_request(app, conversation_id, action);
+            close_conversation = action != MessageRequestAction::Accept;
+        }
+    } else if is_blocked {
+        show_blocked_notice(app, ui, conversation_id);
+    } else if let Some((uuid, name)) = unsafe { (*(&raw const CACHED_IDENTITY_CHANGED)).clone() } {
+        show_identity_changed(app, ui, &uuid, &name);
+    } else {
+        show_message_input(app, ui, conversation_id);
+    }
+
+    show_edit_history(ui.ctx());
+    show_message_info(ui.ctx());
+    show_delete_confirmation(app, ui.ctx());
+    super::group_details::show(app, ui.ctx());
+    super::safety_number::show(app, ui.ctx());
+
+    for action in actions {
+        match action {
+            MessageAction::DownloadAttachment(attachment_id) => app.download_attachment(&attachment_id),
+            MessageAction::EditMessage(message_id, text) => {
+                let editing = unsafe { &raw mut EDITING_MESSAGE };
+                unsafe { *editing = Some((conversation_id.to_string(), message_id)) };
+                let replying = unsafe { &raw mut REPLYING_TO };
+                unsafe { *replying = None };
+                let input = unsafe { &raw mut MESSAGE_INPUT };
+                unsafe { *input = text };
+            }
+            MessageAction::ShowEditHistory(message_id) => open_edit_history(app, &message_id),
+            MessageAction::React(message_id, emoji, remove) => react_to_message(app, &message_id, &emoji, remove),
+            MessageAction::DeleteForEveryone(message_id) => {
+                let confirm = unsafe { &raw mut CONFIRM_DELETE };
+                unsafe { *confirm = Some(message_id) };
+            }
+            MessageAction::Reply(message_id) => {
+                let Some(msg) = messages.iter().find(|m| m.id == message_id) else {
+                    continue;
+                };
+                let author_name = match msg.direction {
+                    MessageDirection::Sent => "You".to_string(),
+                    MessageDirection::Received => {
+                        msg.sender_name.clone().unwrap_or_else(|| conversation_name.clone())
+                    }
+                };
+
+                // A reply replaces an edit in progress, along with its text
+                let editing = unsafe { &raw mut EDITING_MESSAGE };
+                if unsafe { (*editing).take() }.is_some() {
+                    let input = unsafe { &raw mut MESSAGE_INPUT };
+                    unsafe { (*input).clear() };
+                }
+                let replying = unsafe { &raw mut REPLYING_TO };
+                unsafe {
+                    *replying = Some(ReplyDraft {
+                        conversation_id: conversation_id.to_string(),
+                        message_id,
+                        author_name,
+                        text: content_preview(&msg.content),
+                    })
+                };
+            }
+            MessageAction::JumpToMessage(message_id) => focus_message(conversation_id, &message_id),
+            MessageAction::ShowInfo(message_id) => open_message_info(app, &message_id),
+            MessageAction::RetrySend(message_id) => retry_failed_message(app, &message_id),
+            MessageAction::DeleteFailed(message_id) => discard_failed_message(app, &message_id),
+        }
+    }
+
+    if close_conversation {
+        app.select_conversation(None);
+    }
+}
+
+/// Load the versions of an edited message for the history window
+fn open_edit_history(app: &SignalApp, message_id: &str) {
+    let Some(db) = app.storage().database() else {
+        return;
+    };
+    let msg_repo = MessageRepository::new(&*db);
+    let Some(current) = msg_repo.get(message_id) else {
+        return;
+    };
+
+    let mut versions: Vec<(DateTime<Utc>, String)> = msg_repo
+        .get_edit_history(message_id)
+        .into_iter()
+        .map(|version| (version.created_at, content_text(&version.content)))
+        .collect();
+    versions.push((
+        current.edited_at.unwrap_or(current.sent_at),
+        content_text(&current.content),
+    ));
+
+    let history = unsafe { &raw mut EDIT_HISTORY };
+    unsafe { *history = Some((message_id.to_string(), versions)) };
+}
+
+fn show_edit_history(ctx: &egui::Context) {
+    let history = unsafe { &raw mut EDIT_HISTORY };
+    let history = unsafe { &mut *history };
+    let Some((_, versions)) = history.as_ref() else {
+        return;
+    };
+
+    let mut open = true;
+    egui::Window::new("Edit history")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .default_width(320.0)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                for (i, (at, text)) in versions.iter().enumerate().rev() {
+                    let label = if i + 1 == versions.len() {
+                        "Current"
+                    } else if i == 0 {
+                        "Original"
+                    } else {
+                        "Edited"
+                    };
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "{} · {}",
+                            label,
+                            at.with_timezone(&Local).format("%b %d, %H:%M")
+                        ))
+                        .size(11.0)
+                        .color(SignalColors::TEXT_TERTIARY),
+                    );
+                    show_emoji_text(ui, text, SignalColors::TEXT_PRIMARY);
+                    ui.add_space(8.0);
+                }
+            });
+        });
+
+    if !open {
+        *history = None;
+    }
+}
+
+/// Load who received and read a sent message for the info window
+fn open_message_info(app: &SignalApp, message_id: &str) {
+    let Some(db) = app.storage().database() else {
+        return;
+    };
+    let msg_repo = MessageRepository::new(&*db);
+    let conv_repo = ConversationRepository::new(&*db);
+    let Some(message) = msg_repo.get(message_id) else {
+        return;
+    };
+
+    let name = |id: &str| conv_repo.get(id).map(|c| c.name).unwrap_or_else(|| id.to_string());
+    let mut recipients: Vec<_> = msg_repo
+        .get_receipts(message_id)
+        .into_iter()
+        .map(|r| (name(&r.recipient), r.delivered_at, r.read_at))
+        .collect();
+
+    // Group members are only known once they send a receipt; a 1:1 chat
+    // always has its one recipient
+    let is_group = conv_repo
+        .get(&message.conversation_id)
+        .is_some_and(|c| c.conversation_type == crate::storage::conversations::ConversationType::Group);
+    if recipients.is_empty() && !is_group {
+        recipients.push((name(&message.conversation_id), None, None));
+    }
+
+    let info = unsafe { &raw mut MESSAGE_INFO };
+    unsafe {
+        *info = Some(MessageInfo {
+            sent_at: message.sent_at,
+            recipients,
+        })
+    };
+}
+
+fn show_message_info(ctx: &egui::Context) {
+    let info = unsafe { &raw mut MESSAGE_INFO };
+    let info = unsafe { &mut *info };
+    let Some(message_info) = info.as_ref() else {
+        return;
+    };
+
+    let format_time = |at: &DateTime<Utc>| at.with_timezone(&Local).format("%b %d, %H:%M").to_string();
+    let read: Vec<_> = message_info
+        .recipients
+        .iter()
+        .filter_map(|(name, _, read_at)| Some((name, (*read_at)?)))
+        .collect();
+    let delivered: Vec<_> = message_info
+        .recipients
+        .iter()
+        .filter(|(_, _, read_at)| read_at.is_none())
+        .filter_map(|(name, delivered_at, _)| Some((name, (*delivered_at)?)))
+        .collect();
+    let pending: Vec<_> = message_info
+        .recipients
+        .iter()
+        .filter(|(_, delivered_at, read_at)| delivered_at.is_none() && read_at.is_none())
+        .map(|(name, _, _)| name)
+        .collect();
+
+    let mut open = true;
+    egui::Window::new("Message info")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .default_width(320.0)
+        .show(ctx, |ui| {
+            ui.label(
+                egui::RichText::new(format!("Sent {}", format_time(&message_info.sent_at)))
+                    .color(SignalColors::TEXT_SECONDARY),
+            );
+            ui.add_space(8.0);
+
+            let section = |ui: &mut egui::Ui, title: &str, rows: Vec<(&String, Option<DateTime<Utc>>)>| {
+                if rows.is_empty() {
+                    return;
+                }
+                ui.label(egui::RichText::new(title).strong().color(SignalColors::TEXT_PRIMARY));
+                for (name, at) in rows {
+                    ui.horizontal(|ui| {
+                        show_emoji_text(ui, name, SignalColors::TEXT_PRIMARY);
+                        if let Some(at) = at {
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                ui.label(
+                                    egui::RichText::new(format_time(&at))
+                                        .size(11.0)
+                                        .color(SignalColors::TEXT_TERTIARY),
+                                );
+                            });
+                        }
+                    });
+                }
+                ui.add_space(8.0);
+            };
+
+            section(ui, "Read by", read.into_iter().map(|(n, at)| (n, Some(at))).collect());
+            section(ui, "Delivered to", delivered.into_iter().map(|(n, at)| (n, Some(at))).collect());
+            section(ui, "Sent to", pending.into_iter().map(|n| (n, None)).collect());
+
+            if message_info.recipients.is_empty() {
+                ui.label(
+                    egui::RichText::new("No receipts yet")
+                        .italics()
+                        .color(SignalColors::TEXT_SECONDARY),
+                );
+            }
+        });
+
+    if !open {
+        *info = None;
+    }
+}
+
+/// Ask before deleting a sent message for everyone; it can't be undone
+fn show_delete_confirmation(app: &SignalApp, ctx: &egui::Context) {
+    let confirm = unsafe { &raw mut CONFIRM_DELETE };
+    let confirm = unsafe { &mut *confirm };
+    let Some(message_id) = confirm.clone() else {
+        return;
+    };
+
+    let mut open = true;
+    egui::Window::new("Delete for everyone?")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label("This message will be deleted for everyone in the conversation.");
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                if ui.button("Delete for everyone").clicked() {
+                    delete_sent_message(app, &message_id);
+                    *confirm = None;
+                }
+                if ui.button("Cancel").clicked() {
+                    *confirm = None;
+                }
+            });
+        });
+
+    if !open {
+        *confirm = None;
+    }
+}
+
+/// Whether a voice note's audio is on disk: always for our own, once
+/// downloaded for incoming ones
+fn voice_note_playable(msg: &MessageItem) -> bool {
+    msg.attachment.as_ref().is_none_or(|a| a.status == DownloadStatus::Downloaded)
+}
+
+/// After a voice note played to its end, play the one right after it
+fn autoplay_next_voice_note(messages: &[MessageItem]) {
+    let Some(finished) = voice_player::take_finished() else {
+        return;
+    };
+    let next = messages
+        .iter()
+        .position(|msg| msg.id == finished)
+        .and_then(|i| messages.get(i + 1));
+    if let Some(next) = next.filter(|msg| voice_note_playable(msg)) {
+        if let MessageContent::Voice { path, .. } = &next.content {
+            voice_player::play_note(&next.id, path);
+        }
+    }
+}
+
+/// Placeholder shown instead of the content of a deleted message
+fn show_deleted_placeholder(ui: &mut egui::Ui) {
+    ui.label(
+        egui::RichText::new(format!("🚫 {}", DELETED_MESSAGE_TEXT))
+            .italics()
+            .color(Color32::from_white_alpha(160)),
+    );
+}
+
+/// One-line description of a message for the reply banner
+fn content_preview(content: &MessageContent) -> String {
+    match content {
+        MessageContent::Text(text) => text.clone(),
+        MessageContent::Image { caption: Some(caption), .. } => caption.clone(),
+        MessageContent::Image { .. } => "📷 Photo".to_string(),
+        MessageContent::File { name, .. } => format!("📄 {}", name),
+        MessageContent::Voice { .. } => "🎤 Voice message".to_string(),
+        MessageContent::Sticker { emoji, .. } => match emoji {
+            Some(emoji) => format!("{} Sticker", emoji),
+            None => "Sticker".to_string(),
+        },
+        MessageContent::Contact { name } => format!("👤 {}", name),
+        MessageContent::Location { .. } => "📍 Location".to_string(),
+        MessageContent::Deleted => DELETED_MESSAGE_TEXT.to_string(),
+        MessageContent::Notice { text, .. } => text.clone(),
+    }
+}
+
+fn content_text(content: &StorageContent) -> String {
+    match content {
+        StorageContent::Text { body, .. } => body.clone(),
+        StorageContent::Image { caption, .. } | StorageContent::Video { caption, .. } => {
+            caption.clone().unwrap_or_default()
+        }
+        _ => String::new(),
+    }
 }
 
 fn load_conversation_data(app: &SignalApp, conversation_id: &str) -> (String, u32, Vec<MessageItem>) {
@@ -258,6 +901,7 @@ fn load_conversation_data(app: &SignalApp, conversation_id: &str) -> (String, u3
     if let Some(db) = app.storage().database() {
         let conv_repo = ConversationRepository::new(&*db);
         let msg_repo = MessageRepository::new(&*db);
+        let attachment_repo = AttachmentRepository::new(&*db);
 
         let conversation = conv_repo.get(conversation_id);
         let name = conversation.as_ref()
@@ -272,17 +916,42 @@ fn load_conversation_data(app: &SignalApp, conversation_id: &str) -> (String, u3
             .map(|c| c.conversation_type == crate::storage::conversations::ConversationType::Group)
             .unwrap_or(false);
 
+        if conversation_changed && focused_message_id(conversation_id).is_none() {
+            clear_focused_message();
+        }
+
         let my_id = app.storage().get_phone_number();
-        let mut messages: Vec<MessageItem> = msg_repo
-            .get_for_conversation(conversation_id, 100, None)
+        let attachments_dir = app.storage().attachments_dir();
+        let stored = match focused_message_id(conversation_id) {
+            Some(message_id) => msg_repo.get_around(conversation_id, &message_id, FOCUS_CONTEXT),
+            None => msg_repo.get_for_conversation(conversation_id, 100, None),
+        };
+        let mut messages: Vec<MessageItem> = stored
             .iter()
-            .map(|m| MessageItem::from_storage(m, my_id.as_deref(), is_group, &conv_repo))
+            .map(|m| {
+                MessageItem::from_storage(m, my_id.as_deref(), is_group, &conv_repo, &attachment_repo, attachments_dir)
+            })
             .collect();
         messages.reverse();
 
         *cached_id = Some(conversation_id.to_string());
         *cached_name = name.clone();
         *cached_messages = messages.clone();
+        unsafe {
+            *(&raw mut CACHED_DISAPPEARING_TIMER) =
+                conversation.as_ref().map(|c| c.disappearing_messages_timer).unwrap_or(0);
+            *(&raw mut CACHED_IS_GROUP) = is_group;
+            *(&raw mut CACHED_IS_REQUEST) = conversation.as_ref().is_some_and(|c| !c.is_accepted);
+            *(&raw mut CACHED_IS_BLOCKED) = conversation.as_ref().is_some_and(|c| c.is_blocked);
+            *(&raw mut CACHED_IDENTITY_CHANGED) = safety::held_recipients(&db, conversation_id)
+                .into_iter()
+                .next()
+                .map(|uuid| {
+                    let name = if is_group { conv_repo.get(&uuid).map(|c| c.name) } else { Some(name.clone()) };
+                    let name = name.unwrap_or_else(|| uuid.clone());
+                    (uuid, name)
+                });
+        }
         MESSAGES_DIRTY.store(false, Ordering::SeqCst);
 
         (name, unread_count, messages)
@@ -359,18 +1028,42 @@ fn show_conversation_header(ui: &mut egui::Ui, app: &SignalApp, conversation_id:
 
         ui.add_space(12.0);
 
+        let typing = typing_label(app, conversation_id);
         ui.vertical(|ui| {
             ui.add_space(8.0);
             show_emoji_text_styled(ui, name, 16.0, SignalColors::TEXT_PRIMARY, true);
-            ui.label(egui::RichText::new("").size(12.0).color(SignalColors::TEXT_SECONDARY));
+            match typing {
+                Some(typing) => {
+                    ui.label(egui::RichText::new(typing).size(12.0).italics().color(SignalColors::SIGNAL_BLUE));
+                }
+                None => {
+                    ui.label(egui::RichText::new("").size(12.0).color(SignalColors::TEXT_SECONDARY));
+                }
+            }
         });
 
         // Right side buttons
         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
             ui.add_space(8.0);
 
-            if ui.button("⋮").on_hover_text("More options").clicked() {
-                tracing::info!("More options: not yet implemented");
+            let is_blocked = unsafe { *(&raw const CACHED_IS_BLOCKED) };
+            let is_group = unsafe { *(&raw const CACHED_IS_GROUP) };
+            ui.menu_button("⋮", |ui| {
+                if !is_group && ui.button("View safety number").clicked() {
+                    super::safety_number::open(conversation_id, name);
+                    ui.close_menu();
+                }
+                let label = if is_blocked { "Unblock" } else { "Block" };
+                if ui.button(label).clicked() {
+                    set_conversation_blocked(app, conversation_id, !is_blocked);
+                    ui.close_menu();
+                }
+            })
+            .response
+            .on_hover_text("More options");
+
+            if is_group && ui.button("ℹ").on_hover_text("Group details").clicked() {
+                super::group_details::open(conversation_id);
             }
 
             if ui.button("📞").on_hover_text("Voice call").clicked() {
@@ -390,6 +1083,31 @@ fn show_conversation_header(ui: &mut egui::Ui, app: &SignalApp, conversation_id:
     ui.separator();
 }
 
+/// "X is typing…" for a conversation, or `None` if nobody is
+pub fn typing_label(app: &SignalApp, conversation_id: &str) -> Option<String> {
+    let senders = app.typing_senders(conversation_id);
+    if senders.is_empty() {
+        return None;
+    }
+
+    let db = app.storage().database();
+    let conv_repo = db.as_deref().map(ConversationRepository::new);
+    let name = |id: &str| {
+        conv_repo
+            .as_ref()
+            .and_then(|repo| repo.get(id))
+            .map(|c| c.name)
+            .unwrap_or_else(|| "Someone".to_string())
+    };
+
+    Some(match senders.as_slice() {
+        [sender] if *sender == conversation_id => "typing…".to_string(),
+        [sender] => format!("{} is typing…", name(sender)),
+        [first, second] => format!("{} and {} are typing…", name(first), name(second)),
+        senders => format!("{} people are typing…", senders.len()),
+    })
+}
+
 /// Check if we should show a date separator
 fn should_show_date_separator(last_date: &Option<DateTime<Utc>>, current: &DateTime<Utc>) -> bool {
     match last_date {
@@ -423,6 +1141,33 @@ fn show_date_separator(ui: &mut egui::Ui, date: &DateTime<Utc>) {
     ui.add_space(16.0);
 }
 
+/// Icon in front of a group update row
+fn group_update_icon(update_type: GroupUpdateType) -> &'static str {
+    match update_type {
+        GroupUpdateType::Created => "👥",
+        GroupUpdateType::NameChanged | GroupUpdateType::DescriptionChanged | GroupUpdateType::AvatarChanged => "✏",
+        GroupUpdateType::MembersAdded
+        | GroupUpdateType::MembersRemoved
+        | GroupUpdateType::MemberJoined
+        | GroupUpdateType::MemberLeft => "👤",
+        GroupUpdateType::AdminsChanged => "⭐",
+        GroupUpdateType::DisappearingMessagesChanged => "⏱",
+    }
+}
+
+/// Show a centered system notice in place of a bubble
+fn show_notice(ui: &mut egui::Ui, icon: &str, text: &str) {
+    ui.add_space(8.0);
+    ui.vertical_centered(|ui| {
+        ui.label(
+            egui::RichText::new(format!("{} {}", icon, text))
+                .size(12.0)
+                .color(SignalColors::TEXT_TERTIARY),
+        );
+    });
+    ui.add_space(8.0);
+}
+
 /// Format date for separator
 fn format_date(date: &DateTime<Utc>) -> String {
     let local: DateTime<Local> = date.with_timezone(&Local);
@@ -438,8 +1183,14 @@ fn format_date(date: &DateTime<Utc>) -> String {
 }
 
 /// Show a single message
-fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
+fn show_message(
+    ui: &mut egui::Ui,
+    storage: &Arc<Storage>,
+    msg: &MessageItem,
+    uploading: bool,
+) -> Option<MessageAction> {
     let is_sent = msg.direction == MessageDirection::Sent;
+    let mut action = None;
 
     // Content-based sizing with max-width (like modern messaging apps)
     // Bubbles shrink to fit short messages, expand up to max-width for longer ones
@@ -450,7 +1201,10 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
     // Calculate max content width (excluding frame margins)
     let max_content_width = max_bubble_width - (frame_margin * 2.0);
 
-    let bubble_color = if is_sent {
+    // Stickers stand on their own, without a bubble behind them
+    let bubble_color = if matches!(msg.content, MessageContent::Sticker { .. }) {
+        Color32::TRANSPARENT
+    } else if is_sent {
         SignalColors::BUBBLE_SENT
     } else {
         SignalColors::BUBBLE_RECEIVED
@@ -474,9 +1228,22 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
                     .x
                     + 30.0
             }
-            MessageContent::Voice { .. } => 80.0,
+            MessageContent::Voice { .. } => VOICE_PLAYER_WIDTH,
+            MessageContent::Sticker { .. } => STICKER_SIZE,
+            MessageContent::Deleted => {
+                ui.fonts(|f| {
+                    f.layout_no_wrap(format!("🚫 {}", DELETED_MESSAGE_TEXT), body_font.clone(), Color32::WHITE)
+                })
+                .size()
+                .x
+            }
             _ => max_content_width,
         };
+        let content_text_width = if msg.quote.is_some() {
+            content_text_width.max(QUOTE_MIN_WIDTH)
+        } else {
+            content_text_width
+        };
 
         // Measure timestamp + status width
         let time_str = msg.timestamp.with_timezone(&Local).format("%H:%M").to_string();
@@ -490,7 +1257,9 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
             })
             .size()
             .x
-            + 25.0; // status icon + spacing
+            + 25.0 // status icon + spacing
+            + if msg.edited { 36.0 } else { 0.0 }
+            + if msg.expires_at.is_some() { 36.0 } else { 0.0 };
 
         let inner_width = content_text_width.max(time_width);
         let bubble_width = (inner_width + frame_margin * 2.0 + 4.0).min(max_bubble_width);
@@ -502,7 +1271,7 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
         ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
             ui.add_space(spacer);
 
-            egui::Frame::none()
+            let bubble = egui::Frame::none()
                 .fill(bubble_color)
                 .rounding(Rounding {
                     nw: 16.0,
@@ -516,6 +1285,10 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
                         ui.set_max_width(max_content_width);
                         ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Wrap);
 
+                        if let Some(jump) = show_quote(ui, msg) {
+                            action = Some(jump);
+                        }
+
                         // Message content
                         match &msg.content {
                             MessageContent::Text(text) => {
@@ -556,15 +1329,20 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
                                     });
                                 });
                             }
-                            MessageContent::Voice { duration_secs } => {
-                                ui.horizontal(|ui| {
-                                    ui.label("🎤");
-                                    ui.label(
-                                        egui::RichText::new(format_duration(*duration_secs))
-                                            .color(Color32::WHITE),
-                                    );
-                                });
+                            MessageContent::Voice { path, duration_ms, waveform } => {
+                                show_voice_player(
+                                    ui,
+                                    &msg.id,
+                                    path,
+                                    *duration_ms,
+                                    waveform.as_deref(),
+                                    voice_note_playable(msg),
+                                );
                             }
+                            MessageContent::Sticker { pack_id, pack_key, sticker_id, emoji } => {
+                                show_sticker(ui, storage, pack_id, pack_key, *sticker_id, emoji.as_deref(), STICKER_SIZE);
+                            }
+                            MessageContent::Deleted => show_deleted_placeholder(ui),
                             _ => {
                                 ui.label(
                                     egui::RichText::new("[Unsupported content]")
@@ -585,21 +1363,39 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
                                     .size(10.0)
                                     .color(Color32::from_rgba_unmultiplied(255, 255, 255, 180)),
                             );
+                            if show_edited_marker(ui, msg) {
+                                action = Some(MessageAction::ShowEditHistory(msg.id.clone()));
+                            }
+                            show_expiry_countdown(ui, msg);
                             ui.add_space(3.0);
                             match msg.status {
                                 MessageStatus::Sending => {
+                                    let text = if uploading { "Uploading…" } else { "..." };
                                     ui.label(
-                                        egui::RichText::new("...")
+                                        egui::RichText::new(text)
                                             .size(10.0)
                                             .color(Color32::from_white_alpha(140)),
                                     );
                                 }
                                 MessageStatus::Failed => {
                                     ui.label(
-                                        egui::RichText::new("!")
+                                        egui::RichText::new("! Not sent")
                                             .size(10.0)
                                             .color(SignalColors::ERROR),
                                     );
+                                    let small = |text: &str| {
+                                        egui::Button::new(
+                                            egui::RichText::new(text).size(10.0).color(Color32::WHITE),
+                                        )
+                                        .small()
+                                        .frame(false)
+                                    };
+                                    if ui.add(small("Retry")).clicked() {
+                                        action = Some(MessageAction::RetrySend(msg.id.clone()));
+                                    }
+                                    if ui.add(small("Delete")).clicked() {
+                                        action = Some(MessageAction::DeleteFailed(msg.id.clone()));
+                                    }
                                 }
                                 _ => {
                                     let color = match msg.status {
@@ -643,25 +1439,49 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
                             }
                         });
 
-                        // Reactions
-                        if !msg.reactions.is_empty() {
-                            ui.horizontal(|ui| {
-                                for reaction in &msg.reactions {
-                                    let text =
-                                        format!("{} {}", reaction.emoji, reaction.count);
-                                    ui.small_button(text);
-                                }
-                            });
+                        if let Some(reaction) = show_reactions(ui, msg) {
+                            action = Some(reaction);
                         }
                     });
                 });
+
+            bubble.response.context_menu(|ui| {
+                if msg.reactable {
+                    if let Some(reaction) = show_reaction_picker(ui, msg) {
+                        action = Some(reaction);
+                    }
+                    ui.separator();
+                    if ui.button("Reply").clicked() {
+                        action = Some(MessageAction::Reply(msg.id.clone()));
+                        ui.close_menu();
+                    }
+                }
+                if let (true, MessageContent::Text(text)) = (msg.editable, &msg.content) {
+                    if ui.button("Edit").clicked() {
+                        action = Some(MessageAction::EditMessage(msg.id.clone(), text.clone()));
+                        ui.close_menu();
+                    }
+                }
+                if msg.edited && ui.button("Edit history").clicked() {
+                    action = Some(MessageAction::ShowEditHistory(msg.id.clone()));
+                    ui.close_menu();
+                }
+                if ui.button("Info").clicked() {
+                    action = Some(MessageAction::ShowInfo(msg.id.clone()));
+                    ui.close_menu();
+                }
+                if msg.deletable && ui.button("Delete for everyone").clicked() {
+                    action = Some(MessageAction::DeleteForEveryone(msg.id.clone()));
+                    ui.close_menu();
+                }
+            });
         });
     } else {
         // Left-align received messages
         ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
             ui.add_space(edge_margin);
 
-            egui::Frame::none()
+            let bubble = egui::Frame::none()
                 .fill(bubble_color)
                 .rounding(Rounding {
                     nw: 4.0,
@@ -686,6 +1506,10 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
                             );
                         }
 
+                        if let Some(jump) = show_quote(ui, msg) {
+                            action = Some(jump);
+                        }
+
                         // Message content
                         match &msg.content {
                             MessageContent::Text(text) => {
@@ -719,17 +1543,48 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
                                     });
                                 });
                             }
-                            MessageContent::Voice { duration_secs } => {
-                                ui.horizontal(|ui| {
-                                    ui.label("🎤");
-                                    ui.label(egui::RichText::new(format_duration(*duration_secs)).color(Color32::WHITE));
-                                });
+                            MessageContent::Voice { path, duration_ms, waveform } => {
+                                show_voice_player(
+                                    ui,
+                                    &msg.id,
+                                    path,
+                                    *duration_ms,
+                                    waveform.as_deref(),
+                                    voice_note_playable(msg),
+                                );
+                            }
+                            MessageContent::Sticker { pack_id, pack_key, sticker_id, emoji } => {
+                                show_sticker(ui, storage, pack_id, pack_key, *sticker_id, emoji.as_deref(), STICKER_SIZE);
                             }
+                            MessageContent::Deleted => show_deleted_placeholder(ui),
                             _ => {
                                 ui.label(egui::RichText::new("[Unsupported content]").color(Color32::WHITE));
                             }
                         }
 
+                        if let Some(attachment) = &msg.attachment {
+                            match attachment.status {
+                                DownloadStatus::Skipped | DownloadStatus::Failed => {
+                                    let label = if attachment.status == DownloadStatus::Failed {
+                                        format!("⟳ Retry download ({})", format_file_size(attachment.size))
+                                    } else {
+                                        format!("⬇ Download ({})", format_file_size(attachment.size))
+                                    };
+                                    if ui.small_button(label).clicked() {
+                                        action = Some(MessageAction::DownloadAttachment(attachment.id.clone()));
+                                    }
+                                }
+                                DownloadStatus::Pending | DownloadStatus::Downloading => {
+                                    ui.label(
+                                        egui::RichText::new("Downloading…")
+                                            .size(11.0)
+                                            .color(SignalColors::TEXT_SECONDARY)
+                                    );
+                                }
+                                DownloadStatus::Downloaded => {}
+                            }
+                        }
+
                         // Timestamp
                         ui.horizontal(|ui| {
                             let time_str = msg.timestamp.with_timezone(&Local).format("%H:%M").to_string();
@@ -738,49 +1593,245 @@ fn show_message(ui: &mut egui::Ui, msg: &MessageItem) {
                                     .size(10.0)
                                     .color(Color32::from_rgba_unmultiplied(255, 255, 255, 140))
                             );
+                            if show_edited_marker(ui, msg) {
+                                action = Some(MessageAction::ShowEditHistory(msg.id.clone()));
+                            }
+                            show_expiry_countdown(ui, msg);
                         });
 
-                        // Reactions
-                        if !msg.reactions.is_empty() {
-                            ui.horizontal(|ui| {
-                                for reaction in &msg.reactions {
-                                    let text = format!("{} {}", reaction.emoji, reaction.count);
-                                    ui.small_button(text);
-                                }
-                            });
+                        if let Some(reaction) = show_reactions(ui, msg) {
+                            action = Some(reaction);
                         }
                     });
                 });
+
+            if msg.reactable {
+                bubble.response.context_menu(|ui| {
+                    if let Some(reaction) = show_reaction_picker(ui, msg) {
+                        action = Some(reaction);
+                    }
+                    ui.separator();
+                    if ui.button("Reply").clicked() {
+                        action = Some(MessageAction::Reply(msg.id.clone()));
+                        ui.close_menu();
+                    }
+                });
+            }
         });
     }
+
+    action
 }
 
-/// Voice recording state machine
-enum VoiceState {
-    Idle,
-    Recording {
-        start_time: Instant,
-        recorder: VoiceRecorder,
-    },
-    Recorded {
+/// Quoted message at the top of a reply. Clicking it jumps to the original
+/// when we have it.
+fn show_quote(ui: &mut egui::Ui, msg: &MessageItem) -> Option<MessageAction> {
+    let quote = msg.quote.as_ref()?;
+
+    let frame = egui::Frame::none()
+        .fill(Color32::from_black_alpha(50))
+        .rounding(Rounding::same(8.0))
+        .inner_margin(egui::Margin::symmetric(8.0, 6.0))
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                let (bar, _) = ui.allocate_exact_size(Vec2::new(3.0, 30.0), Sense::hover());
+                ui.painter().rect_filled(bar, Rounding::same(1.5), SignalColors::SIGNAL_BLUE);
+
+                ui.vertical(|ui| {
+                    ui.label(
+                        egui::RichText::new(&quote.author_name)
+                            .size(12.0)
+                            .strong()
+                            .color(Color32::WHITE),
+                    );
+                    ui.add(
+                        egui::Label::new(
+                            egui::RichText::new(&quote.text)
+                                .size(12.0)
+                                .color(Color32::from_white_alpha(200)),
+                        )
+                        .truncate(),
+                    );
+                });
+
+                if let Some((uri, bytes)) = &quote.thumbnail {
+                    ui.add(
+                        egui::Image::from_bytes(uri.clone(), bytes.clone())
+                            .fit_to_exact_size(Vec2::splat(36.0))
+                            .rounding(Rounding::same(4.0)),
+                    );
+                }
+            });
+        });
+
+    if quote.message_id.is_empty() {
+        return None;
+    }
+    let response = frame
+        .response
+        .interact(Sense::click())
+        .on_hover_cursor(egui::CursorIcon::PointingHand)
+        .on_hover_text("Go to message");
+    response
+        .clicked()
+        .then(|| MessageAction::JumpToMessage(quote.message_id.clone()))
+}
+
+/// Reaction chips under a message. Hovering lists who reacted; clicking
+/// adds the same reaction, or removes ours.
+fn show_reactions(ui: &mut egui::Ui, msg: &MessageItem) -> Option<MessageAction> {
+    if msg.reactions.is_empty() {
+        return None;
+    }
+
+    let mut action = None;
+    ui.horizontal(|ui| {
+        for reaction in &msg.reactions {
+            let chip = egui::Button::new(format!("{} {}", reaction.emoji, reaction.count))
+                .small()
+                .selected(reaction.from_me);
+            let response = ui.add_enabled(msg.reactable, chip).on_hover_text(reaction.senders.join("\n"));
+            if response.clicked() {
+                action = Some(MessageAction::React(
+                    msg.id.clone(),
+                    reaction.emoji.clone(),
+                    reaction.from_me,
+                ));
+            }
+        }
+    });
+    action
+}
+
+/// Quick reaction row for a message's context menu
+fn show_reaction_picker(ui: &mut egui::Ui, msg: &MessageItem) -> Option<MessageAction> {
+    let mine = msg.reactions.iter().find(|r| r.from_me).map(|r| r.emoji.as_str());
+
+    let mut action = None;
+    ui.horizontal(|ui| {
+        for emoji in QUICK_REACTIONS {
+            let selected = mine == Some(emoji);
+            if ui.selectable_label(selected, egui::RichText::new(emoji).size(18.0)).clicked() {
+                action = Some(MessageAction::React(msg.id.clone(), emoji.to_string(), selected));
+                ui.close_menu();
+            }
+        }
+    });
+    action
+}
+
+/// "edited" label next to the timestamp. Returns true when clicked.
+fn show_edited_marker(ui: &mut egui::Ui, msg: &MessageItem) -> bool {
+    if !msg.edited {
+        return false;
+    }
+    ui.add(
+        egui::Label::new(
+            egui::RichText::new("edited")
+                .size(10.0)
+                .italics()
+                .color(Color32::from_white_alpha(160)),
+        )
+        .sense(Sense::click()),
+    )
+    .on_hover_text("Show edit history")
+    .clicked()
+}
+
+/// Time left before a disappearing message is deleted, next to the timestamp
+fn show_expiry_countdown(ui: &mut egui::Ui, msg: &MessageItem) {
+    let Some(expires_at) = msg.expires_at else {
+        return;
+    };
+    let remaining = (expires_at - Utc::now()).num_seconds().max(0);
+    let text = match remaining {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s if s < 7 * 86400 => format!("{}d", s / 86400),
+        s => format!("{}w", s / (7 * 86400)),
+    };
+    ui.label(
+        egui::RichText::new(format!("⏱ {}", text))
+            .size(10.0)
+            .color(Color32::from_white_alpha(160)),
+    )
+    .on_hover_text(format!(
+        "Disappears at {}",
+        expires_at.with_timezone(&Local).format("%b %d, %H:%M:%S")
+    ));
+    // Keep the countdown ticking
+    ui.ctx().request_repaint_after(std::time::Duration::from_secs(1));
+}
+
+/// Voice recording state machine
+enum VoiceState {
+    Idle,
+    Recording {
+        start_time: Instant,
+        recorder: VoiceRecorder,
+    },
+    Recorded {
         path: PathBuf,
         duration_secs: u32,
     },
 }
 
 fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str) {
-    static mut MESSAGE_INPUT: String = String::new();
     static mut EMOJI_PICKER: Option<EmojiPicker> = None;
+    static mut STICKER_PICKER: Option<StickerPicker> = None;
+    /// Whether the emoji popup shows the sticker tab
+    static mut SHOWING_STICKERS: bool = false;
     static mut PENDING_ATTACHMENT: Option<PathBuf> = None;
     static mut FILE_PICKER_OPEN: bool = false;
     static mut VOICE_STATE: Option<VoiceState> = None;
 
+    let typing = unsafe { &raw mut OUTGOING_TYPING };
+    let typing = unsafe { &mut *typing };
+    let now = Instant::now();
+    if typing.conversation_id().is_some_and(|id| id != conversation_id) {
+        send_typing_update(app, typing.stop());
+    }
+    send_typing_update(app, typing.tick(now));
+    if let Some(remaining) = typing.idle_remaining(now) {
+        ui.ctx().request_repaint_after(remaining);
+    }
+
     let voice_state = unsafe { &raw mut VOICE_STATE };
     let voice_state = unsafe { &mut *voice_state };
     if voice_state.is_none() {
         *voice_state = Some(VoiceState::Idle);
     }
 
+    let replying = unsafe { &raw mut REPLYING_TO };
+    let replying = unsafe { &mut *replying };
+    if replying.as_ref().is_some_and(|r| r.conversation_id != conversation_id) {
+        *replying = None;
+    }
+    if let Some(reply) = replying.as_ref() {
+        let mut cancel = false;
+        ui.horizontal(|ui| {
+            ui.add_space(8.0);
+            ui.label(
+                egui::RichText::new(format!("↩ Replying to {}", reply.author_name))
+                    .size(12.0)
+                    .color(SignalColors::SIGNAL_BLUE),
+            );
+            ui.add(
+                egui::Label::new(
+                    egui::RichText::new(&reply.text)
+                        .size(12.0)
+                        .color(SignalColors::TEXT_SECONDARY),
+                )
+                .truncate(),
+            );
+            cancel = ui.small_button("✕").on_hover_text("Cancel reply").clicked();
+        });
+        if cancel {
+            *replying = None;
+        }
+    }
+
     // Check if we're in an active recording state
     let is_recording = matches!(voice_state, Some(VoiceState::Recording { .. }));
     let is_recorded = matches!(voice_state, Some(VoiceState::Recorded { .. }));
@@ -877,7 +1928,7 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
 
                 // Send
                 if ui.button("➤").on_hover_text("Send voice message").clicked() {
-                    send_attachment_message(app, conversation_id, &path);
+                    send_attachment_message(app, conversation_id, &path, None, true);
                     *voice_state = Some(VoiceState::Idle);
                 }
             });
@@ -924,7 +1975,8 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
             overlay_emoji_on_textedit(ui, &output, input);
             let _response = output.response;
             if ui.button("➤").on_hover_text("Send").clicked() {
-                send_attachment_message(app, conversation_id, &path_clone);
+                let caption = Some(input.trim().to_string()).filter(|c| !c.is_empty());
+                send_attachment_message(app, conversation_id, &path_clone, caption, false);
                 *pending = None;
                 input.clear();
             }
@@ -933,6 +1985,29 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
         return;
     }
 
+    let editing = unsafe { &raw mut EDITING_MESSAGE };
+    let editing = unsafe { &mut *editing };
+    if editing.as_ref().is_some_and(|(conv_id, _)| conv_id != conversation_id) {
+        *editing = None;
+        let input = unsafe { &raw mut MESSAGE_INPUT };
+        unsafe { (*input).clear() };
+    }
+    if editing.is_some() {
+        ui.horizontal(|ui| {
+            ui.add_space(8.0);
+            ui.label(
+                egui::RichText::new("✏ Editing message")
+                    .size(12.0)
+                    .color(SignalColors::SIGNAL_BLUE),
+            );
+            if ui.small_button("✕").on_hover_text("Cancel editing").clicked() {
+                *editing = None;
+                let input = unsafe { &raw mut MESSAGE_INPUT };
+                unsafe { (*input).clear() };
+            }
+        });
+    }
+
     // Normal message input bar
     ui.horizontal(|ui| {
         ui.add_space(8.0);
@@ -971,6 +2046,8 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
             });
         }
 
+        show_disappearing_timer_menu(app, ui, conversation_id);
+
         let input = unsafe { &raw mut MESSAGE_INPUT };
         let input = unsafe { &mut *input };
         let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
@@ -984,10 +2061,20 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
         overlay_emoji_on_textedit(ui, &output, input);
         let response = output.response;
 
+        if response.changed() {
+            if input.is_empty() {
+                send_typing_update(app, typing.stop());
+            } else {
+                for update in typing.input_changed(conversation_id, Instant::now()) {
+                    send_typing_update(app, Some(update));
+                }
+            }
+        }
+
         // Emoji button — toggle popup using egui's memory-based popup state.
         // Use a fixed Id so it matches between the inner horizontal ui and the outer ui.
         let emoji_popup_id = egui::Id::new("emoji_picker_popup");
-        if ui.button("😀").on_hover_text("Emoji").clicked() {
+        if ui.button("😀").on_hover_text("Emoji and stickers").clicked() {
             ui.memory_mut(|mem| mem.toggle_popup(emoji_popup_id));
         }
 
@@ -1011,9 +2098,14 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
                 || (response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)));
 
             if should_send {
+                // The message itself clears the indicator on the other side
+                typing.reset();
                 let text = input.clone();
                 input.clear();
-                send_message(app, conversation_id, &text);
+                match editing.take() {
+                    Some((_, message_id)) => edit_sent_message(app, &message_id, &text),
+                    None => send_message(app, conversation_id, &text),
+                }
             }
         }
 
@@ -1030,6 +2122,10 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
         if picker.is_none() {
             *picker = Some(EmojiPicker::new());
         }
+        let sticker_picker = unsafe { &raw mut STICKER_PICKER };
+        let sticker_picker = unsafe { &mut *sticker_picker }.get_or_insert_with(StickerPicker::new);
+        let showing_stickers = unsafe { &raw mut SHOWING_STICKERS };
+        let showing_stickers = unsafe { &mut *showing_stickers };
 
         // Position above the input bar, clamped so it stays on screen
         let popup_y = (ui.min_rect().top() - 330.0).max(8.0);
@@ -1042,7 +2138,18 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
                     .show(ui, |ui| {
                         ui.set_width(320.0);
                         ui.set_height(300.0);
-                        if let Some(ref mut ep) = picker {
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(showing_stickers, false, "Emoji");
+                            ui.selectable_value(showing_stickers, true, "Stickers");
+                        });
+                        ui.separator();
+
+                        if *showing_stickers {
+                            if let Some(sticker) = sticker_picker.show(ui, app.storage()) {
+                                send_sticker(app, conversation_id, sticker);
+                                ui.memory_mut(|mem| mem.close_popup());
+                            }
+                        } else if let Some(ref mut ep) = picker {
                             if let Some(emoji) = ep.show(ui) {
                                 let input = unsafe { &raw mut MESSAGE_INPUT };
                                 let input = unsafe { &mut *input };
@@ -1054,7 +2161,7 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
             });
 
         // Close on Escape only. The popup also closes when:
-        // - an emoji is selected (above)
+        // - an emoji or sticker is selected (above)
         // - the emoji button is clicked again (toggle_popup)
         // We intentionally avoid clicked_elsewhere() because it fires on the
         // same frame the button opens the popup, causing instant dismissal.
@@ -1064,35 +2171,121 @@ fn show_message_input(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str)
     }
 }
 
+/// ⏱ button in the input bar to pick the conversation's disappearing message timer
+fn show_disappearing_timer_menu(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str) {
+    let current = unsafe { *(&raw const CACHED_DISAPPEARING_TIMER) };
+    let is_group = unsafe { *(&raw const CACHED_IS_GROUP) };
+    let color = if current > 0 {
+        SignalColors::SIGNAL_BLUE
+    } else {
+        SignalColors::TEXT_SECONDARY
+    };
+    let label = egui::RichText::new("⏱").color(color);
+
+    if is_group {
+        ui.add_enabled(false, egui::Button::new(label)).on_disabled_hover_text(format!(
+            "Disappearing messages: {}. The timer is set by the group.",
+            format_expiration_timer(current)
+        ));
+        return;
+    }
+
+    ui.menu_button(label, |ui| {
+        ui.label(
+            egui::RichText::new("Disappearing messages")
+                .size(12.0)
+                .color(SignalColors::TEXT_SECONDARY),
+        );
+        for seconds in EXPIRATION_TIMER_OPTIONS {
+            if ui.radio(current == seconds, format_expiration_timer(seconds)).clicked() {
+                if seconds != current {
+                    set_disappearing_timer(app, conversation_id, seconds);
+                }
+                ui.close_menu();
+            }
+        }
+    })
+    .response
+    .on_hover_text(format!("Disappearing messages: {}", format_expiration_timer(current)));
+}
+
+/// Take the pending reply for `conversation_id` as the quote of a new message
+fn take_reply_quote(app: &SignalApp, conversation_id: &str) -> Option<StorageQuote> {
+    let replying = unsafe { &raw mut REPLYING_TO };
+    let reply = unsafe { (*replying).take() }.filter(|r| r.conversation_id == conversation_id)?;
+
+    let db = app.storage().database()?;
+    let quoted = MessageRepository::new(&*db).get(&reply.message_id);
+    if quoted.is_none() {
+        tracing::warn!("Quoted message {} no longer exists, sending without quote", reply.message_id);
+    }
+    quoted.map(|m| m.to_quote())
+}
+
 fn send_message(app: &SignalApp, conversation_id: &str, text: &str) {
-    use crate::signal::messages::{Content, Message, MessageDirection, MessageStatus};
+    send_content(
+        app,
+        conversation_id,
+        StorageContent::Text {
+            body: text.to_string(),
+            mentions: Vec::new(),
+        },
+    );
+}
+
+fn send_sticker(app: &SignalApp, conversation_id: &str, sticker: PickedSticker) {
+    send_content(
+        app,
+        conversation_id,
+        StorageContent::Sticker {
+            pack_id: sticker.pack_id,
+            pack_key: sticker.pack_key,
+            sticker_id: sticker.sticker_id,
+            emoji: sticker.emoji,
+        },
+    );
+}
+
+/// Save an outgoing message, replying to the message being replied to, and
+/// queue it for sending
+fn send_content(app: &SignalApp, conversation_id: &str, content: StorageContent) {
+    use crate::signal::messages::{Message, MessageDirection, MessageStatus};
     use crate::storage::messages::MessageRepository;
     use crate::storage::conversations::ConversationRepository;
 
+    let quote = take_reply_quote(app, conversation_id);
     let Some(db) = app.storage().database() else {
         tracing::warn!("No database available, cannot send message");
         return;
     };
 
+    // Show the conversation's latest messages so the new one is visible
+    clear_focused_message();
+
     let my_id = app.storage().get_phone_number().unwrap_or_else(|| "me".to_string());
+    let expires_in_seconds = ConversationRepository::new(&*db)
+        .get(conversation_id)
+        .map(|c| c.disappearing_messages_timer)
+        .filter(|t| *t > 0);
+    let sent_at = Utc::now();
+    let timestamp = sent_at.timestamp_millis() as u64;
     let message = Message {
         id: uuid::Uuid::new_v4().to_string(),
         conversation_id: conversation_id.to_string(),
         sender: my_id,
         direction: MessageDirection::Outgoing,
         status: MessageStatus::Sending,
-        content: Content::Text {
-            body: text.to_string(),
-            mentions: Vec::new(),
-        },
-        sent_at: Utc::now(),
+        content,
+        sent_at,
         server_timestamp: None,
         delivered_at: None,
         read_at: None,
-        quote: None,
+        quote,
         reactions: Vec::new(),
-        expires_in_seconds: None,
+        expires_in_seconds,
         expires_at: None,
+        signal_timestamp: Some(timestamp),
+        edited_at: None,
     };
 
     let msg_repo = MessageRepository::new(&*db);
@@ -1103,31 +2296,212 @@ fn send_message(app: &SignalApp, conversation_id: &str, text: &str) {
 
     let conv_repo = ConversationRepository::new(&*db);
     if let Some(mut conv) = conv_repo.get(conversation_id) {
-        conv.update_last_message(text, message.sent_at);
+        conv.update_last_message(&message.preview_text(), message.sent_at);
         let _ = conv_repo.save(&conv);
     }
-    
+    drop(db);
+
+    queue_message(app, &message.id, conversation_id, false);
+}
+
+/// Hand a saved outgoing message to the outbox, which sends it once we're
+/// connected
+fn queue_message(app: &SignalApp, message_id: &str, conversation_id: &str, voice_note: bool) {
+    use crate::signal::manager::SignalManager;
+
+    match SignalManager::queue_message(app.storage(), message_id, conversation_id, voice_note) {
+        Ok(()) => tracing::info!("Queued message {} for sending", message_id),
+        Err(e) => {
+            tracing::error!("Failed to queue message {}: {}", message_id, e);
+            if let Some(db) = app.storage().database() {
+                let _ = MessageRepository::new(&*db).update_status(message_id, StorageStatus::Failed);
+            }
+        }
+    }
     invalidate_messages_cache();
     super::chat_list::invalidate_conversations_cache();
+}
+
+fn retry_failed_message(app: &mut SignalApp, message_id: &str) {
+    use crate::signal::manager::SignalManager;
+
+    if let Err(e) = SignalManager::retry_message(app.storage(), message_id) {
+        tracing::error!("Failed to retry message {}: {}", message_id, e);
+        app.set_error(e.to_string());
+    }
+    invalidate_messages_cache();
+}
 
-    // Get conversation type from database to determine if it's a group
-    let is_group = conv_repo.get(conversation_id)
-        .map(|conv| conv.conversation_type == crate::storage::conversations::ConversationType::Group)
-        .unwrap_or(false);
+fn discard_failed_message(app: &mut SignalApp, message_id: &str) {
+    use crate::signal::manager::SignalManager;
 
+    if let Err(e) = SignalManager::discard_message(app.storage(), message_id) {
+        tracing::error!("Failed to delete message {}: {}", message_id, e);
+        app.set_error(e.to_string());
+    }
+    invalidate_messages_cache();
+    super::chat_list::invalidate_conversations_cache();
+}
+
+/// Send a typing started/stopped message in the background
+fn send_typing_update(app: &SignalApp, update: Option<TypingUpdate>) {
+    let Some(update) = update else {
+        return;
+    };
     let storage = app.storage().clone();
-    let conversation_id = conversation_id.to_string();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for sending");
+
+        rt.block_on(async move {
+            use crate::signal::manager::SignalManager;
+
+            if let Err(e) = SignalManager::send_typing(&storage, &update.conversation_id, update.started).await {
+                tracing::debug!("Failed to send typing indicator: {}", e);
+            }
+        });
+    });
+}
+
+/// Send an edit of one of our messages and update the local copy once it went out
+fn edit_sent_message(app: &SignalApp, message_id: &str, text: &str) {
+    let storage = app.storage().clone();
+    let message_id = message_id.to_string();
     let text = text.to_string();
-    let text_for_log = text.clone();
 
-    // Validate conversation ID format before attempting to send
-    if is_group && !is_valid_base64_group_id(&conversation_id) {
-        tracing::error!(
-            "Invalid group ID format: {}. Group IDs must be 32-byte base64 encoded master keys.",
-            conversation_id
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for sending");
+
+        rt.block_on(async move {
+            use crate::signal::manager::SignalManager;
+
+            match SignalManager::edit_message(&storage, &message_id, &text).await {
+                Ok(()) => {
+                    tracing::info!("Edited message {}", message_id);
+                    invalidate_messages_cache();
+                    super::chat_list::invalidate_conversations_cache();
+                    crate::app::request_repaint();
+                }
+                Err(e) => tracing::error!("Failed to edit message {}: {}", message_id, e),
+            }
+        });
+    });
+}
+
+/// Bar shown in place of the input while a conversation is a message
+/// request. Returns the user's answer.
+fn show_message_request(ui: &mut egui::Ui, name: &str, is_blocked: bool) -> Option<MessageRequestAction> {
+    let mut action = None;
+    ui.add_space(8.0);
+    ui.vertical_centered(|ui| {
+        let text = if is_blocked {
+            format!("You blocked {}. Accept to unblock them and reply.", name)
+        } else {
+            format!(
+                "{} wants to message you. They won't know you've seen their messages until you accept.",
+                name
+            )
+        };
+        ui.label(egui::RichText::new(text).size(13.0).color(SignalColors::TEXT_SECONDARY));
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            let button = |text: &str| egui::Button::new(text).min_size(Vec2::new(96.0, 32.0));
+            if !is_blocked && ui.add(button("Block")).clicked() {
+                action = Some(MessageRequestAction::Block);
+            }
+            if ui.add(button("Delete")).clicked() {
+                action = Some(MessageRequestAction::Delete);
+            }
+            if ui.add(button("Accept").fill(SignalColors::SIGNAL_BLUE)).clicked() {
+                action = Some(MessageRequestAction::Accept);
+            }
+        });
+    });
+    ui.add_space(8.0);
+    action
+}
+
+/// Shown in place of the input in a conversation we blocked
+fn show_blocked_notice(app: &SignalApp, ui: &mut egui::Ui, conversation_id: &str) {
+    ui.add_space(8.0);
+    ui.vertical_centered(|ui| {
+        let text = if unsafe { *(&raw const CACHED_IS_GROUP) } {
+            "You blocked this group. You won't receive its messages."
+        } else {
+            "You blocked this person. You won't receive their messages."
+        };
+        ui.label(egui::RichText::new(text).size(13.0).color(SignalColors::TEXT_SECONDARY));
+        ui.add_space(8.0);
+        if ui.add(egui::Button::new("Unblock").min_size(Vec2::new(96.0, 32.0))).clicked() {
+            set_conversation_blocked(app, conversation_id, false);
+        }
+    });
+    ui.add_space(8.0);
+}
+
+/// Shown in place of the input when the safety number of a verified contact,
+/// or of a verified member of a group, changed, until the user looks at it or
+/// sends anyway
+fn show_identity_changed(app: &SignalApp, ui: &mut egui::Ui, uuid: &str, name: &str) {
+    ui.add_space(8.0);
+    ui.vertical_centered(|ui| {
+        let text = format!(
+            "Your safety number with {} has changed. This could mean someone is trying to intercept \
+             your communication, or that they reinstalled Signal.",
+            name
         );
-        return;
-    }
+        ui.label(egui::RichText::new(text).size(13.0).color(SignalColors::TEXT_SECONDARY));
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            let button = |text: &str| egui::Button::new(text).min_size(Vec2::new(96.0, 32.0));
+            if ui.add(button("View safety number")).clicked() {
+                super::safety_number::open(uuid, name);
+            }
+            if ui.add(button("Send anyway")).clicked() {
+                match crate::signal::manager::SignalManager::accept_identity_change(app.storage(), uuid) {
+                    Ok(()) => invalidate_messages_cache(),
+                    Err(e) => tracing::error!("Failed to accept safety number of {}: {}", uuid, e),
+                }
+            }
+        });
+    });
+    ui.add_space(8.0);
+}
+
+/// Answer a message request and sync the answer to our other devices
+fn answer_message_request(app: &SignalApp, conversation_id: &str, action: MessageRequestAction) {
+    let storage = app.storage().clone();
+    let conversation_id = conversation_id.to_string();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for sending");
+
+        rt.block_on(async move {
+            use crate::signal::manager::SignalManager;
+
+            if let Err(e) = SignalManager::respond_to_message_request(&storage, &conversation_id, action).await {
+                tracing::error!("Failed to answer message request from {}: {}", conversation_id, e);
+            }
+            invalidate_messages_cache();
+            super::chat_list::invalidate_conversations_cache();
+            crate::app::request_repaint();
+        });
+    });
+}
+
+/// Block or unblock a conversation's contact or group
+fn set_conversation_blocked(app: &SignalApp, conversation_id: &str, blocked: bool) {
+    let storage = app.storage().clone();
+    let conversation_id = conversation_id.to_string();
 
     std::thread::spawn(move || {
         let rt = tokio::runtime::Builder::new_current_thread()
@@ -1138,35 +2512,121 @@ fn send_message(app: &SignalApp, conversation_id: &str, text: &str) {
         rt.block_on(async move {
             use crate::signal::manager::SignalManager;
 
-            if is_group {
-                match SignalManager::send_group_message_static(&storage, &conversation_id, &text).await {
-                    Ok(()) => tracing::info!("Group message sent"),
-                    Err(e) => tracing::error!("Failed to send group message: {}", e),
+            if let Err(e) = SignalManager::set_blocked(&storage, &conversation_id, blocked).await {
+                tracing::error!("Failed to sync block of {}: {}", conversation_id, e);
+            }
+            invalidate_messages_cache();
+            super::chat_list::invalidate_conversations_cache();
+            crate::app::request_repaint();
+        });
+    });
+}
+
+/// Change a conversation's disappearing message timer and tell the other side
+fn set_disappearing_timer(app: &SignalApp, conversation_id: &str, seconds: u32) {
+    let storage = app.storage().clone();
+    let conversation_id = conversation_id.to_string();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for sending");
+
+        rt.block_on(async move {
+            use crate::signal::manager::SignalManager;
+
+            match SignalManager::set_expiration_timer(&storage, &conversation_id, seconds).await {
+                Ok(()) => {
+                    invalidate_messages_cache();
+                    super::chat_list::invalidate_conversations_cache();
+                    crate::app::request_repaint();
                 }
-            } else {
-                let recipient_uuid = extract_uuid_from_service_id(&conversation_id);
-                match SignalManager::send_message_static(&storage, &recipient_uuid, &text).await {
-                    Ok(()) => tracing::info!("Message sent to {}", recipient_uuid),
-                    Err(e) => tracing::error!("Failed to send message: {}", e),
+                Err(e) => tracing::error!(
+                    "Failed to set disappearing message timer for {}: {}",
+                    conversation_id,
+                    e
+                ),
+            }
+        });
+    });
+}
+
+/// Send a reaction (or remove ours) and apply it locally once sent
+fn react_to_message(app: &SignalApp, message_id: &str, emoji: &str, remove: bool) {
+    let storage = app.storage().clone();
+    let message_id = message_id.to_string();
+    let emoji = emoji.to_string();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for sending");
+
+        rt.block_on(async move {
+            use crate::signal::manager::SignalManager;
+
+            match SignalManager::send_reaction(&storage, &message_id, &emoji, remove).await {
+                Ok(()) => {
+                    invalidate_messages_cache();
+                    crate::app::request_repaint();
                 }
+                Err(e) => tracing::error!("Failed to react to message {}: {}", message_id, e),
             }
         });
     });
+}
+
+/// Delete one of our messages for everyone and tombstone the local copy
+fn delete_sent_message(app: &SignalApp, message_id: &str) {
+    let storage = app.storage().clone();
+    let message_id = message_id.to_string();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for sending");
+
+        rt.block_on(async move {
+            use crate::signal::manager::SignalManager;
 
-    tracing::info!("Queued message for sending: {}", text_for_log);
+            match SignalManager::delete_for_everyone(&storage, &message_id).await {
+                Ok(()) => {
+                    invalidate_messages_cache();
+                    super::chat_list::invalidate_conversations_cache();
+                    crate::app::request_repaint();
+                }
+                Err(e) => tracing::error!("Failed to delete message {}: {}", message_id, e),
+            }
+        });
+    });
 }
 
-/// Send an attachment message (file, image, audio, video) by copying to attachments dir
-fn send_attachment_message(app: &SignalApp, conversation_id: &str, file_path: &std::path::Path) {
+/// Send an attachment message (file, image, audio, video).
+///
+/// The file is copied to the attachments dir and saved locally as `Sending`, then
+/// handed to the send loop which uploads it to the CDN and delivers the pointer.
+fn send_attachment_message(
+    app: &SignalApp,
+    conversation_id: &str,
+    file_path: &std::path::Path,
+    caption: Option<String>,
+    voice_note: bool,
+) {
     use crate::signal::messages::{Content, Message, MessageDirection, MessageStatus};
     use crate::storage::conversations::ConversationRepository;
     use crate::storage::messages::MessageRepository;
 
+    let quote = take_reply_quote(app, conversation_id);
     let Some(db) = app.storage().database() else {
         tracing::warn!("No database available, cannot send attachment");
         return;
     };
 
+    clear_focused_message();
+
     let my_id = app
         .storage()
         .get_phone_number()
@@ -1204,14 +2664,23 @@ fn send_attachment_message(app: &SignalApp, conversation_id: &str, file_path: &s
         .to_string();
 
     // Build appropriate Content variant based on MIME type
+    let (width, height) = if content_type.starts_with("image/") {
+        std::fs::read(&dest_path)
+            .ok()
+            .and_then(|data| crate::signal::attachments::image_utils::get_dimensions(&data))
+            .unwrap_or((0, 0))
+    } else {
+        (0, 0)
+    };
+
     let content = if content_type.starts_with("image/") {
         Content::Image {
             attachment_id: dest_filename.clone(),
             content_type,
-            width: 0,
-            height: 0,
+            width,
+            height,
             size: file_size,
-            caption: None,
+            caption: caption.clone(),
             blurhash: None,
         }
     } else if content_type.starts_with("video/") {
@@ -1222,16 +2691,19 @@ fn send_attachment_message(app: &SignalApp, conversation_id: &str, file_path: &s
             height: 0,
             duration_ms: 0,
             size: file_size,
-            caption: None,
+            caption: caption.clone(),
             thumbnail_id: None,
         }
     } else if content_type.starts_with("audio/") {
+        let details = std::fs::read(&dest_path)
+            .ok()
+            .and_then(|data| crate::signal::attachments::voice::analyze(&data));
         Content::Audio {
             attachment_id: dest_filename.clone(),
             content_type,
-            duration_ms: 0,
+            duration_ms: details.as_ref().map_or(0, |(duration_ms, _)| *duration_ms),
             size: file_size,
-            waveform: None,
+            waveform: details.map(|(_, waveform)| waveform),
         }
     } else {
         Content::File {
@@ -1250,6 +2722,12 @@ fn send_attachment_message(app: &SignalApp, conversation_id: &str, file_path: &s
         format!("[File: {}]", original_filename)
     };
 
+    let expires_in_seconds = ConversationRepository::new(&*db)
+        .get(conversation_id)
+        .map(|c| c.disappearing_messages_timer)
+        .filter(|t| *t > 0);
+    let sent_at = Utc::now();
+    let timestamp = sent_at.timestamp_millis() as u64;
     let message = Message {
         id: uuid::Uuid::new_v4().to_string(),
         conversation_id: conversation_id.to_string(),
@@ -1257,14 +2735,16 @@ fn send_attachment_message(app: &SignalApp, conversation_id: &str, file_path: &s
         direction: MessageDirection::Outgoing,
         status: MessageStatus::Sending,
         content,
-        sent_at: Utc::now(),
+        sent_at,
         server_timestamp: None,
         delivered_at: None,
         read_at: None,
-        quote: None,
+        quote,
         reactions: Vec::new(),
-        expires_in_seconds: None,
+        expires_in_seconds,
         expires_at: None,
+        signal_timestamp: Some(timestamp),
+        edited_at: None,
     };
 
     let msg_repo = MessageRepository::new(&*db);
@@ -1278,15 +2758,10 @@ fn send_attachment_message(app: &SignalApp, conversation_id: &str, file_path: &s
         conv.update_last_message(&preview_text, message.sent_at);
         let _ = conv_repo.save(&conv);
     }
+    drop(db);
 
-    invalidate_messages_cache();
-    super::chat_list::invalidate_conversations_cache();
-
-    tracing::info!(
-        "Attachment message saved: {} ({}). Signal CDN upload is future work.",
-        dest_filename,
-        preview_text
-    );
+    tracing::info!("Sending attachment {} ({})", dest_filename, preview_text);
+    queue_message(app, &message.id, conversation_id, voice_note);
 }
 
 fn content_type_is_image(content: &crate::signal::messages::Content) -> bool {
@@ -1297,20 +2772,6 @@ fn content_type_is_audio(content: &crate::signal::messages::Content) -> bool {
     matches!(content, crate::signal::messages::Content::Audio { .. })
 }
 
-/// Validate if a conversation ID is a valid base64-encoded group ID
-/// Group master keys are 32 bytes when decoded from base64
-fn is_valid_base64_group_id(id: &str) -> bool {
-    use base64::Engine;
-
-    // Try to decode as base64
-    if let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(id) {
-        // Group master keys should be exactly 32 bytes
-        bytes.len() == 32
-    } else {
-        false
-    }
-}
-
 /// Format file size for display
 fn format_file_size(bytes: u64) -> String {
     const KB: u64 = 1024;
@@ -1328,22 +2789,6 @@ fn format_file_size(bytes: u64) -> String {
     }
 }
 
-fn extract_uuid_from_service_id(service_id: &str) -> String {
-    if service_id.starts_with("<ACI:") && service_id.ends_with('>') {
-        service_id[5..service_id.len() - 1].to_string()
-    } else if service_id.starts_with("<PNI:") && service_id.ends_with('>') {
-        service_id[5..service_id.len() - 1].to_string()
-    } else {
-        service_id.to_string()
-    }
-}
-
-fn format_duration(secs: u32) -> String {
-    let mins = secs / 60;
-    let secs = secs % 60;
-    format!("{}:{:02}", mins, secs)
-}
-
 /// Get placeholder messages for UI demonstration
 fn get_placeholder_messages() -> Vec<MessageItem> {
     vec![
@@ -1354,7 +2799,13 @@ fn get_placeholder_messages() -> Vec<MessageItem> {
             timestamp: Utc::now() - chrono::Duration::hours(2),
             status: MessageStatus::Read,
             sender_name: None,
-            reply_to: None,
+            quote: None,
+            attachment: None,
+            edited: false,
+            editable: false,
+            deletable: false,
+            reactable: false,
+            expires_at: None,
             reactions: vec![],
         },
         MessageItem {
@@ -1364,9 +2815,15 @@ fn get_placeholder_messages() -> Vec<MessageItem> {
             timestamp: Utc::now() - chrono::Duration::hours(1) - chrono::Duration::minutes(55),
             status: MessageStatus::Read,
             sender_name: None,
-            reply_to: None,
+            quote: None,
+            attachment: None,
+            edited: false,
+            editable: false,
+            deletable: false,
+            reactable: false,
+            expires_at: None,
             reactions: vec![
-                Reaction { emoji: "👍".to_string(), count: 1, from_me: false },
+                Reaction { emoji: "👍".to_string(), count: 1, from_me: false, senders: vec!["Alice".to_string()] },
             ],
         },
         MessageItem {
@@ -1376,7 +2833,13 @@ fn get_placeholder_messages() -> Vec<MessageItem> {
             timestamp: Utc::now() - chrono::Duration::hours(1) - chrono::Duration::minutes(50),
             status: MessageStatus::Read,
             sender_name: None,
-            reply_to: None,
+            quote: None,
+            attachment: None,
+            edited: false,
+            editable: false,
+            deletable: false,
+            reactable: false,
+            expires_at: None,
             reactions: vec![],
         },
         MessageItem {
@@ -1386,7 +2849,13 @@ fn get_placeholder_messages() -> Vec<MessageItem> {
             timestamp: Utc::now() - chrono::Duration::hours(1) - chrono::Duration::minutes(45),
             status: MessageStatus::Read,
             sender_name: None,
-            reply_to: None,
+            quote: None,
+            attachment: None,
+            edited: false,
+            editable: false,
+            deletable: false,
+            reactable: false,
+            expires_at: None,
             reactions: vec![],
         },
         MessageItem {
@@ -1399,9 +2868,15 @@ fn get_placeholder_messages() -> Vec<MessageItem> {
             timestamp: Utc::now() - chrono::Duration::minutes(30),
             status: MessageStatus::Read,
             sender_name: None,
-            reply_to: None,
+            quote: None,
+            attachment: None,
+            edited: false,
+            editable: false,
+            deletable: false,
+            reactable: false,
+            expires_at: None,
             reactions: vec![
-                Reaction { emoji: "❤️".to_string(), count: 1, from_me: true },
+                Reaction { emoji: "❤️".to_string(), count: 1, from_me: true, senders: vec!["You".to_string()] },
             ],
         },
         MessageItem {
@@ -1411,17 +2886,29 @@ fn get_placeholder_messages() -> Vec<MessageItem> {
             timestamp: Utc::now() - chrono::Duration::minutes(25),
             status: MessageStatus::Delivered,
             sender_name: None,
-            reply_to: None,
+            quote: None,
+            attachment: None,
+            edited: false,
+            editable: false,
+            deletable: false,
+            reactable: false,
+            expires_at: None,
             reactions: vec![],
         },
         MessageItem {
             id: "7".to_string(),
             direction: MessageDirection::Received,
-            content: MessageContent::Voice { duration_secs: 15 },
+            content: MessageContent::Voice { path: PathBuf::new(), duration_ms: 15_000, waveform: None },
             timestamp: Utc::now() - chrono::Duration::minutes(5),
             status: MessageStatus::Read,
             sender_name: None,
-            reply_to: None,
+            quote: None,
+            attachment: None,
+            edited: false,
+            editable: false,
+            deletable: false,
+            reactable: false,
+            expires_at: None,
             reactions: vec![],
         },
     ]
@@ -0,0 +1,145 @@
+//! Group details - members, roles and group settings
+
+use crate::app::SignalApp;
+use crate::signal::groups::Group;
+use crate::signal::manager::SignalManager;
+use crate::storage::contacts::ContactRepository;
+use crate::storage::groups::GroupRepository;
+use crate::ui::components::emoji_text::show_emoji_text;
+use crate::ui::theme::SignalColors;
+
+/// Group shown in the details window, by conversation ID
+static mut OPEN_GROUP: Option<String> = None;
+static mut CACHED_GROUP: Option<GroupDetails> = None;
+
+struct GroupDetails {
+    group: Group,
+    /// (uuid, display name) of each member, in the group's order
+    member_names: Vec<(String, String)>,
+    pending_names: Vec<(String, String)>,
+}
+
+/// Show the details window for a group conversation
+pub fn open(conversation_id: &str) {
+    let open = unsafe { &raw mut OPEN_GROUP };
+    unsafe { *open = Some(conversation_id.to_string()) };
+    invalidate_group_cache();
+}
+
+/// Reload the group on the next frame, e.g. after its state changed
+pub fn invalidate_group_cache() {
+    let cached = unsafe { &raw mut CACHED_GROUP };
+    unsafe { *cached = None };
+}
+
+fn load_group(app: &SignalApp, group_id: &str) -> Option<GroupDetails> {
+    let db = app.storage().database()?;
+    let group = GroupRepository::new(&db).get(group_id)?;
+    let contacts = ContactRepository::new(&db);
+    let self_aci = SignalManager::self_aci();
+
+    let name = |uuid: &str| {
+        if self_aci.as_deref() == Some(uuid) {
+            "You".to_string()
+        } else {
+            contacts
+                .get_by_uuid(uuid)
+                .map(|c| c.display_name().to_string())
+                .unwrap_or_else(|| uuid.to_string())
+        }
+    };
+
+    let member_names = group.members.iter().map(|m| (m.uuid.clone(), name(&m.uuid))).collect();
+    let pending_names = group
+        .pending_members
+        .iter()
+        .map(|m| (m.uuid.clone(), name(&m.uuid)))
+        .collect();
+
+    Some(GroupDetails {
+        group,
+        member_names,
+        pending_names,
+    })
+}
+
+pub fn show(app: &SignalApp, ctx: &egui::Context) {
+    let open_group = unsafe { &raw mut OPEN_GROUP };
+    let Some(group_id) = (unsafe { (*open_group).clone() }) else {
+        return;
+    };
+
+    let cached = unsafe { &raw mut CACHED_GROUP };
+    let cached = unsafe { &mut *cached };
+    if !cached.as_ref().is_some_and(|d| d.group.id == group_id) {
+        *cached = load_group(app, &group_id);
+    }
+
+    let mut open = true;
+    egui::Window::new("Group details")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .default_width(360.0)
+        .show(ctx, |ui| {
+            let Some(details) = cached.as_ref() else {
+                ui.label(
+                    egui::RichText::new("Group details haven't been fetched yet.")
+                        .color(SignalColors::TEXT_SECONDARY),
+                );
+                return;
+            };
+            show_details(ui, details);
+        });
+
+    if !open {
+        unsafe { *open_group = None };
+        *cached = None;
+    }
+}
+
+/// Read-only: presage can't submit group changes yet, so the group is only
+/// ever changed from another client and refetched here
+fn show_details(ui: &mut egui::Ui, details: &GroupDetails) {
+    let group = &details.group;
+
+    ui.heading(&group.name);
+    if let Some(description) = &group.description {
+        ui.label(egui::RichText::new(description).color(SignalColors::TEXT_SECONDARY));
+    }
+
+    ui.add_space(12.0);
+    ui.label(egui::RichText::new(format!("{} members", group.member_count())).strong());
+    ui.add_space(4.0);
+
+    egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+        for (uuid, name) in &details.member_names {
+            ui.horizontal(|ui| {
+                show_emoji_text(ui, name, SignalColors::TEXT_PRIMARY);
+                if group.is_admin(uuid) {
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(
+                            egui::RichText::new("Admin")
+                                .size(11.0)
+                                .color(SignalColors::TEXT_SECONDARY),
+                        );
+                    });
+                }
+            });
+        }
+
+        if !details.pending_names.is_empty() {
+            ui.add_space(8.0);
+            ui.label(egui::RichText::new("Invited").strong().color(SignalColors::TEXT_SECONDARY));
+            for (_, name) in &details.pending_names {
+                show_emoji_text(ui, name, SignalColors::TEXT_SECONDARY);
+            }
+        }
+    });
+
+    if group.left {
+        ui.add_space(12.0);
+        ui.separator();
+        ui.label(egui::RichText::new("You are no longer a member of this group.").color(SignalColors::TEXT_SECONDARY));
+    }
+}
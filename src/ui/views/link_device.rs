@@ -222,10 +222,10 @@ fn draw_error_state(ui: &egui::Ui, rect: Rect) {
 }
 
 /// Render a QR code from data
-pub fn render_qr_code(data: &str) -> Option<egui::ColorImage> {
+pub fn render_qr_code(data: impl AsRef<[u8]>) -> Option<egui::ColorImage> {
     use qrcode::QrCode;
 
-    let code = QrCode::new(data.as_bytes()).ok()?;
+    let code = QrCode::new(data.as_ref()).ok()?;
     let modules = code.to_colors();
     let size = (modules.len() as f64).sqrt() as usize;
 
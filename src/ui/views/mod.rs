@@ -3,8 +3,10 @@
 pub mod chat_list;
 pub mod chat_view;
 pub mod encryption_setup;
+pub mod group_details;
 pub mod link_device;
 pub mod main_view;
+pub mod safety_number;
 pub mod settings;
 pub mod unlock_database;
 
@@ -0,0 +1,274 @@
+//! Safety number - compare and verify a contact's identity key
+
+use crate::app::SignalApp;
+use crate::signal::manager::SignalManager;
+use crate::signal::safety::SafetyNumber;
+use crate::storage::contacts::ContactRepository;
+use crate::ui::theme::SignalColors;
+use egui::{Color32, Vec2};
+use parking_lot::Mutex;
+
+/// Contact shown in the window, as (uuid, display name)
+static mut OPEN_CONTACT: Option<(String, String)> = None;
+static mut CACHED_NUMBER: Option<LoadedNumber> = None;
+static mut COMPARE_INPUT: String = String::new();
+
+/// Outcome of the last comparison, set by the scan thread: whether the
+/// numbers matched, or why the image couldn't be read
+static COMPARE_RESULT: Mutex<Option<Result<bool, String>>> = Mutex::new(None);
+static SCANNING: Mutex<bool> = Mutex::new(false);
+/// Error from the last verify/unverify, set by the thread that synced it
+static VERIFY_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+struct LoadedNumber {
+    uuid: String,
+    number: Result<SafetyNumber, String>,
+    qr_code: Option<egui::TextureHandle>,
+    verified: bool,
+}
+
+/// Show the safety number window for a contact
+pub fn open(uuid: &str, name: &str) {
+    let open = unsafe { &raw mut OPEN_CONTACT };
+    unsafe { *open = Some((uuid.to_string(), name.to_string())) };
+    invalidate_cache();
+    let input = unsafe { &raw mut COMPARE_INPUT };
+    unsafe { (*input).clear() };
+    *COMPARE_RESULT.lock() = None;
+    *VERIFY_ERROR.lock() = None;
+}
+
+/// Reload the safety number and verification on the next frame
+pub fn invalidate_cache() {
+    let cached = unsafe { &raw mut CACHED_NUMBER };
+    unsafe { *cached = None };
+}
+
+fn load_number(app: &SignalApp, ctx: &egui::Context, uuid: &str) -> LoadedNumber {
+    let number = SignalManager::safety_number(app.storage(), uuid).map_err(|e| e.to_string());
+    let qr_code = number
+        .as_ref()
+        .ok()
+        .and_then(|n| crate::ui::views::link_device::render_qr_code(&n.scannable))
+        .map(|image| ctx.load_texture(format!("safety_number_{}", uuid), image, egui::TextureOptions::NEAREST));
+    let verified = app
+        .storage()
+        .database()
+        .and_then(|db| ContactRepository::new(&db).get_by_uuid(uuid))
+        .is_some_and(|c| c.is_verified());
+
+    LoadedNumber {
+        uuid: uuid.to_string(),
+        number,
+        qr_code,
+        verified,
+    }
+}
+
+pub fn show(app: &SignalApp, ctx: &egui::Context) {
+    let open_contact = unsafe { &raw mut OPEN_CONTACT };
+    let Some((uuid, name)) = (unsafe { (*open_contact).clone() }) else {
+        return;
+    };
+
+    let cached = unsafe { &raw mut CACHED_NUMBER };
+    let cached = unsafe { &mut *cached };
+    if !cached.as_ref().is_some_and(|n| n.uuid == uuid) {
+        *cached = Some(load_number(app, ctx, &uuid));
+    }
+
+    let mut open = true;
+    egui::Window::new("Safety number")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .default_width(340.0)
+        .show(ctx, |ui| {
+            let Some(loaded) = cached.as_ref() else {
+                return;
+            };
+            match &loaded.number {
+                Ok(number) => show_number(app, ui, loaded, number, &name),
+                Err(e) => {
+                    ui.label(
+                        egui::RichText::new(format!("The safety number isn't available: {}", e))
+                            .color(SignalColors::TEXT_SECONDARY),
+                    );
+                }
+            }
+        });
+
+    if !open {
+        unsafe { *open_contact = None };
+        *cached = None;
+    }
+}
+
+fn show_number(app: &SignalApp, ui: &mut egui::Ui, loaded: &LoadedNumber, number: &SafetyNumber, name: &str) {
+    ui.label(
+        egui::RichText::new(format!(
+            "To verify end-to-end encryption with {}, compare the numbers below with their device, or scan \
+             the code on their phone.",
+            name
+        ))
+        .size(13.0)
+        .color(SignalColors::TEXT_SECONDARY),
+    );
+    ui.add_space(8.0);
+
+    if let Some(texture) = &loaded.qr_code {
+        ui.vertical_centered(|ui| {
+            ui.add(egui::Image::new(egui::load::SizedTexture::new(texture.id(), Vec2::splat(180.0))));
+        });
+        ui.add_space(8.0);
+    }
+
+    ui.vertical_centered(|ui| {
+        egui::Grid::new("safety_number_digits").spacing(Vec2::new(16.0, 6.0)).show(ui, |ui| {
+            for (i, group) in number.groups().iter().enumerate() {
+                ui.label(egui::RichText::new(*group).monospace().size(16.0));
+                if i % 4 == 3 {
+                    ui.end_row();
+                }
+            }
+        });
+    });
+
+    ui.add_space(12.0);
+    ui.separator();
+    show_compare(ui, number);
+
+    ui.add_space(12.0);
+    ui.separator();
+    if let Some(error) = VERIFY_ERROR.lock().as_ref() {
+        ui.colored_label(Color32::RED, error);
+        ui.add_space(4.0);
+    }
+    ui.horizontal(|ui| {
+        if loaded.verified {
+            ui.label(egui::RichText::new("🛡 Verified").color(SignalColors::SIGNAL_BLUE));
+            if ui.button("Clear verification").clicked() {
+                set_verified(app, &loaded.uuid, false);
+            }
+        } else if ui
+            .add(egui::Button::new("Mark as verified").fill(SignalColors::SIGNAL_BLUE))
+            .clicked()
+        {
+            set_verified(app, &loaded.uuid, true);
+        }
+    });
+}
+
+/// Compare against digits pasted from the contact, or a picture of their QR
+/// code
+fn show_compare(ui: &mut egui::Ui, number: &SafetyNumber) {
+    let input = unsafe { &raw mut COMPARE_INPUT };
+    let input = unsafe { &mut *input };
+
+    ui.label(egui::RichText::new("Compare").strong());
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(input)
+                .hint_text("Paste their safety number")
+                .desired_width(200.0),
+        );
+        if ui.add_enabled(!input.trim().is_empty(), egui::Button::new("Compare")).clicked() {
+            *COMPARE_RESULT.lock() = Some(Ok(number.matches_digits(input)));
+        }
+    });
+
+    let scanning = *SCANNING.lock();
+    if ui
+        .add_enabled(!scanning, egui::Button::new("Scan code from image…"))
+        .clicked()
+    {
+        scan_image(ui.ctx().clone(), number.clone());
+    }
+
+    match COMPARE_RESULT.lock().as_ref() {
+        Some(Ok(true)) => {
+            ui.colored_label(SignalColors::SIGNAL_BLUE, "✔ The safety numbers match.");
+        }
+        Some(Ok(false)) => {
+            ui.colored_label(
+                Color32::RED,
+                "✖ The safety numbers don't match. Make sure you're comparing with the right contact.",
+            );
+        }
+        Some(Err(e)) => {
+            ui.colored_label(Color32::RED, e);
+        }
+        None => {}
+    }
+}
+
+/// Pick a picture of the contact's QR code and compare it in the background
+fn scan_image(ctx: egui::Context, number: SafetyNumber) {
+    *SCANNING.lock() = true;
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for file dialog");
+
+        let file = rt.block_on(
+            rfd::AsyncFileDialog::new()
+                .set_title("Choose a picture of their safety number code")
+                .add_filter("Images", &["png", "jpg", "jpeg", "webp"])
+                .pick_file(),
+        );
+        if let Some(file) = file {
+            let result = decode_qr_code(file.path()).and_then(|scanned| {
+                number
+                    .matches_scanned(&scanned)
+                    .map_err(|e| format!("This code can't be compared: {}", e))
+            });
+            *COMPARE_RESULT.lock() = Some(result);
+        }
+        *SCANNING.lock() = false;
+        ctx.request_repaint();
+    });
+}
+
+/// Raw contents of the first QR code found in an image
+fn decode_qr_code(path: &std::path::Path) -> Result<Vec<u8>, String> {
+    let image = image::open(path).map_err(|e| format!("Couldn't open the image: {}", e))?.to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        image.width() as usize,
+        image.height() as usize,
+        |x, y| image.get_pixel(x as u32, y as u32).0[0],
+    );
+
+    for grid in prepared.detect_grids() {
+        let mut contents = Vec::new();
+        if grid.decode_to(&mut contents).is_ok() {
+            return Ok(contents);
+        }
+    }
+    Err("No code found in the image".to_string())
+}
+
+/// Verify or unverify in the background; our other devices follow through a
+/// sync message
+fn set_verified(app: &SignalApp, uuid: &str, verified: bool) {
+    let storage = app.storage().clone();
+    let uuid = uuid.to_string();
+    *VERIFY_ERROR.lock() = None;
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for verification");
+
+        rt.block_on(async move {
+            if let Err(e) = SignalManager::set_verified(&storage, &uuid, verified).await {
+                tracing::error!("Failed to sync verification of {}: {}", uuid, e);
+                *VERIFY_ERROR.lock() = Some(e.to_string());
+            }
+            invalidate_cache();
+            crate::ui::views::chat_view::invalidate_messages_cache();
+            crate::app::request_repaint();
+        });
+    });
+}
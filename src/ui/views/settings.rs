@@ -1,11 +1,20 @@
 //! Settings view
 
 use crate::app::SignalApp;
+use crate::services::{ServiceHealth, ServiceStatus};
+use crate::signal::sync::SyncRequestKind;
+use crate::signal::SignalManager;
 use crate::storage::contacts::ContactRepository;
 use crate::storage::conversations::{ConversationRepository, ConversationType};
+use crate::storage::groups::GroupRepository;
+use crate::storage::settings::SettingsRepository;
+use crate::ui::components::emoji_text::show_emoji_text;
 use crate::ui::theme::SignalColors;
+use chrono::Local;
 use egui::{Color32, Vec2};
 
+static mut SELECTED_CATEGORY: SettingsCategory = SettingsCategory::Profile;
+
 /// Settings categories
 #[derive(Debug, Clone, PartialEq)]
 pub enum SettingsCategory {
@@ -108,9 +117,17 @@ pub fn show(app: &mut SignalApp, ctx: &egui::Context) {
         });
 
     // Main content area
-    let profile = load_profile_info(app);
-    egui::CentralPanel::default().show(ctx, |ui| {
-        show_profile_settings(ui, &profile);
+    let selected = unsafe { &*(&raw const SELECTED_CATEGORY) }.clone();
+    egui::CentralPanel::default().show(ctx, |ui| match selected {
+        SettingsCategory::Profile => show_profile_settings(ui, &load_profile_info(app)),
+        SettingsCategory::Privacy => show_privacy_settings(app, ui),
+        SettingsCategory::Notifications => show_notification_settings(ui),
+        SettingsCategory::Appearance => show_appearance_settings(ui),
+        SettingsCategory::LinkedDevices => show_linked_devices(app, ui),
+        SettingsCategory::Advanced => show_diagnostics(app, ui),
+        SettingsCategory::ChatsAndMedia | SettingsCategory::Help => {
+            ui.label(egui::RichText::new("Not yet available").color(SignalColors::TEXT_SECONDARY));
+        }
     });
 
     if go_back {
@@ -130,13 +147,15 @@ fn show_settings_sidebar(ui: &mut egui::Ui) {
         ("❓", "Help", SettingsCategory::Help),
     ];
 
-    for (icon, label, _category) in &categories {
+    let selected = unsafe { &mut *(&raw mut SELECTED_CATEGORY) };
+    for (icon, label, category) in categories {
         let button = ui.add(
             egui::Button::new(format!("{} {}", icon, label))
+                .selected(*selected == category)
                 .min_size(Vec2::new(180.0, 36.0))
         );
         if button.clicked() {
-            // Set selected category
+            *selected = category;
         }
     }
 }
@@ -215,13 +234,20 @@ fn show_profile_settings(ui: &mut egui::Ui, profile: &ProfileInfo) {
     });
 }
 
-fn show_privacy_settings(ui: &mut egui::Ui) {
+fn show_privacy_settings(app: &SignalApp, ui: &mut egui::Ui) {
     ui.heading("Privacy");
     ui.add_space(16.0);
 
+    let Some(db) = app.storage().database() else {
+        ui.label(egui::RichText::new("Database locked").color(SignalColors::TEXT_SECONDARY));
+        return;
+    };
+    let mut settings_repo = SettingsRepository::new(&db);
+    let settings = settings_repo.get_mut();
+    let mut changed = false;
+
     // Read receipts
-    let mut read_receipts = true;
-    ui.checkbox(&mut read_receipts, "Read Receipts");
+    changed |= ui.checkbox(&mut settings.read_receipts, "Read Receipts").changed();
     ui.label(
         egui::RichText::new("If turned off, you won't be able to see read receipts from others.")
             .size(12.0)
@@ -231,8 +257,7 @@ fn show_privacy_settings(ui: &mut egui::Ui) {
     ui.add_space(16.0);
 
     // Typing indicators
-    let mut typing_indicators = true;
-    ui.checkbox(&mut typing_indicators, "Typing Indicators");
+    changed |= ui.checkbox(&mut settings.typing_indicators, "Typing Indicators").changed();
     ui.label(
         egui::RichText::new("If turned off, you won't be able to see typing indicators from others.")
             .size(12.0)
@@ -241,6 +266,22 @@ fn show_privacy_settings(ui: &mut egui::Ui) {
 
     ui.add_space(16.0);
 
+    // Link previews
+    changed |= ui.checkbox(&mut settings.link_previews, "Generate Link Previews").changed();
+    ui.label(
+        egui::RichText::new("Retrieve link previews directly from websites for messages you send.")
+            .size(12.0)
+            .color(SignalColors::TEXT_SECONDARY)
+    );
+
+    if changed {
+        if let Err(e) = settings_repo.save() {
+            tracing::error!("Failed to save privacy settings: {}", e);
+        }
+    }
+
+    ui.add_space(16.0);
+
     // Screen lock
     let mut screen_lock = false;
     ui.checkbox(&mut screen_lock, "Screen Lock");
@@ -254,13 +295,70 @@ fn show_privacy_settings(ui: &mut egui::Ui) {
     ui.separator();
     ui.add_space(16.0);
 
-    ui.label(egui::RichText::new("Blocked Contacts").strong());
+    ui.label(egui::RichText::new("Blocked").strong());
     ui.add_space(8.0);
-    if ui.button("Manage Blocked Contacts").clicked() {
-        // Open blocked contacts
+    show_blocked_list(app, ui);
+}
+
+/// Blocked contacts and groups, each with a button to unblock
+fn show_blocked_list(app: &SignalApp, ui: &mut egui::Ui) {
+    let blocked: Vec<(String, String)> = match app.storage().database() {
+        Some(db) => {
+            let contacts = ContactRepository::new(&db)
+                .list_blocked()
+                .into_iter()
+                .map(|c| (c.uuid.clone(), c.display_name().to_string()));
+            let groups = GroupRepository::new(&db)
+                .list()
+                .into_iter()
+                .filter(|g| g.blocked)
+                .map(|g| (g.id, g.name));
+            contacts.chain(groups).collect()
+        }
+        None => Vec::new(),
+    };
+
+    if blocked.is_empty() {
+        ui.label(
+            egui::RichText::new("You haven't blocked anyone.")
+                .size(12.0)
+                .color(SignalColors::TEXT_SECONDARY)
+        );
+        return;
+    }
+
+    for (id, name) in blocked {
+        ui.horizontal(|ui| {
+            show_emoji_text(ui, &name, SignalColors::TEXT_PRIMARY);
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Unblock").clicked() {
+                    unblock(app, &id);
+                }
+            });
+        });
     }
 }
 
+fn unblock(app: &SignalApp, conversation_id: &str) {
+    let storage = app.storage().clone();
+    let conversation_id = conversation_id.to_string();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for unblocking");
+
+        rt.block_on(async move {
+            if let Err(e) = SignalManager::set_blocked(&storage, &conversation_id, false).await {
+                tracing::error!("Failed to sync unblock of {}: {}", conversation_id, e);
+            }
+            crate::ui::views::chat_list::invalidate_conversations_cache();
+            crate::app::request_repaint();
+        });
+    });
+}
+
 fn show_notification_settings(ui: &mut egui::Ui) {
     ui.heading("Notifications");
     ui.add_space(16.0);
@@ -323,10 +421,13 @@ fn show_appearance_settings(ui: &mut egui::Ui) {
     ui.add(egui::Slider::new(&mut font_size, 12.0..=20.0).text("px"));
 }
 
-fn show_linked_devices(ui: &mut egui::Ui) {
+fn show_linked_devices(app: &SignalApp, ui: &mut egui::Ui) {
     ui.heading("Linked Devices");
     ui.add_space(16.0);
 
+    show_primary_sync(app, ui);
+    ui.add_space(24.0);
+
     ui.label("This device:");
     ui.add_space(8.0);
 
@@ -384,3 +485,132 @@ fn show_linked_devices(ui: &mut egui::Ui) {
         // Show QR code for linking
     }
 }
+
+/// "Sync now" and what the primary device sent back so far
+fn show_primary_sync(app: &SignalApp, ui: &mut egui::Ui) {
+    let progress = app.sync_progress();
+    let now = chrono::Utc::now();
+    let running = progress.is_running(now);
+
+    ui.horizontal(|ui| {
+        ui.vertical(|ui| {
+            ui.label(egui::RichText::new("Sync with primary device").strong());
+            ui.label(
+                egui::RichText::new("Request contacts, blocked list and settings from your phone.")
+                    .size(12.0)
+                    .color(SignalColors::TEXT_SECONDARY),
+            );
+        });
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            if ui.add_enabled(!running, egui::Button::new("Sync now")).clicked() {
+                app.request_sync();
+            }
+            if running {
+                ui.spinner();
+            }
+        });
+    });
+
+    if progress.requested_at.is_none() {
+        return;
+    }
+    ui.add_space(8.0);
+
+    if let Some(error) = &progress.error {
+        ui.colored_label(Color32::RED, format!("Sync failed: {}", error));
+        return;
+    }
+
+    let timed_out = progress.timed_out(now);
+    for kind in SyncRequestKind::ALL {
+        let (icon, status, color) = if progress.received.contains(&kind) {
+            ("✔", "Received", Color32::GREEN)
+        } else if !progress.pending.contains(&kind) {
+            continue;
+        } else if timed_out {
+            ("✖", "No response", SignalColors::TEXT_TERTIARY)
+        } else {
+            ("…", "Waiting", SignalColors::TEXT_SECONDARY)
+        };
+        ui.horizontal(|ui| {
+            ui.colored_label(color, icon);
+            ui.label(kind.label());
+            ui.label(egui::RichText::new(status).size(12.0).color(color));
+        });
+    }
+
+    if running {
+        ui.ctx().request_repaint_after(std::time::Duration::from_secs(1));
+    }
+}
+
+/// Background services and the outcome of their last run
+fn show_diagnostics(app: &SignalApp, ui: &mut egui::Ui) {
+    ui.heading("Diagnostics");
+    ui.add_space(16.0);
+
+    ui.label(egui::RichText::new("Background Services").strong());
+    ui.add_space(8.0);
+
+    for health in app.service_health() {
+        show_service_health(ui, &health);
+        ui.add_space(8.0);
+    }
+
+    // Keep the service status live
+    ui.ctx().request_repaint_after(std::time::Duration::from_secs(1));
+}
+
+fn show_service_health(ui: &mut egui::Ui, health: &ServiceHealth) {
+    let (color, status) = match &health.status {
+        ServiceStatus::Running => (Color32::GREEN, "Running".to_string()),
+        ServiceStatus::Restarting => (Color32::YELLOW, "Restarting".to_string()),
+        ServiceStatus::Stopped => (Color32::GRAY, "Stopped".to_string()),
+        ServiceStatus::Failed(_) => (Color32::RED, "Failed".to_string()),
+    };
+    let format_time = |at: &chrono::DateTime<chrono::Utc>| at.with_timezone(&Local).format("%H:%M:%S").to_string();
+
+    egui::Frame::none()
+        .fill(SignalColors::DARK_SURFACE)
+        .rounding(egui::Rounding::same(8.0))
+        .inner_margin(egui::Margin::same(12.0))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.horizontal(|ui| {
+                ui.colored_label(color, "●");
+                ui.label(egui::RichText::new(health.name).strong());
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(egui::RichText::new(status).color(color));
+                });
+            });
+
+            let detail = |ui: &mut egui::Ui, text: String, color: Color32| {
+                ui.label(egui::RichText::new(text).size(12.0).color(color));
+            };
+
+            match (&health.last_run, &health.last_result) {
+                (Some(at), Some(Ok(summary))) => detail(
+                    ui,
+                    format!("Last run {}: {}", format_time(at), summary),
+                    SignalColors::TEXT_SECONDARY,
+                ),
+                (Some(at), Some(Err(error))) => detail(
+                    ui,
+                    format!("Last run {} failed: {}", format_time(at), error),
+                    Color32::RED,
+                ),
+                _ => detail(ui, "Not run yet".to_string(), SignalColors::TEXT_TERTIARY),
+            }
+
+            if let Some(started_at) = &health.started_at {
+                detail(
+                    ui,
+                    format!("Started {}, {} restarts", format_time(started_at), health.restarts),
+                    SignalColors::TEXT_TERTIARY,
+                );
+            }
+            if let Some(crash) = &health.last_crash {
+                detail(ui, format!("Last crash: {}", crash), SignalColors::TEXT_TERTIARY);
+            }
+        });
+}
@@ -2,4 +2,6 @@
 
 pub mod emoji_picker;
 pub mod search_bar;
+pub mod sticker;
+pub mod voice_player;
 pub mod voice_recorder;
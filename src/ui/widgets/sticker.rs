@@ -0,0 +1,432 @@
+//! Stickers - animated sticker images and the sticker picker
+//!
+//! Stickers are downloaded and decoded one at a time on a loader thread and
+//! turned into textures on the UI thread. Animated WebP and APNG stickers
+//! keep all their frames; anything else shows its first frame.
+
+use crate::signal::manager::SignalManager;
+use crate::signal::stickers;
+use crate::storage::stickers::{StickerPack, StickerRepository};
+use crate::storage::Storage;
+use crate::ui::theme::SignalColors;
+use egui::{Color32, ColorImage, Rounding, Sense, TextureHandle, TextureOptions, Vec2};
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, ImageFormat, RgbaImage};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Stickers are decoded at most this large; they're shown at 128px or less
+const MAX_STICKER_PIXELS: u32 = 256;
+/// Longest animation kept, so a huge sticker can't eat all memory
+const MAX_FRAMES: usize = 200;
+
+/// Sticker textures by (pack ID, sticker ID)
+static mut TEXTURES: Option<HashMap<(String, u32), StickerTexture>> = None;
+/// Installed packs shown in the picker
+static mut CACHED_PACKS: Option<Vec<StickerPack>> = None;
+
+/// Stickers waiting for the loader thread, and what it decoded
+static LOAD_QUEUE: Mutex<Vec<LoadRequest>> = Mutex::new(Vec::new());
+static LOADED: Mutex<Vec<((String, u32), Option<Vec<(ColorImage, Duration)>>)>> = Mutex::new(Vec::new());
+static LOADER_RUNNING: AtomicBool = AtomicBool::new(false);
+static PACKS_DIRTY: AtomicBool = AtomicBool::new(true);
+/// Set when packs changed, so stickers that failed to load are tried again
+static RETRY_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// A pack is being installed or removed
+static PACK_BUSY: Mutex<bool> = Mutex::new(false);
+/// Error from the last install or remove
+static PACK_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+enum StickerTexture {
+    Loading,
+    Failed,
+    /// Frames with how long each is shown; a still sticker has one
+    Frames(Vec<(TextureHandle, Duration)>),
+}
+
+struct LoadRequest {
+    storage: Arc<Storage>,
+    pack_id: String,
+    pack_key: String,
+    sticker_id: u32,
+}
+
+/// Reload installed packs on the next frame and try failed stickers again
+pub fn invalidate_packs_cache() {
+    PACKS_DIRTY.store(true, Ordering::SeqCst);
+    RETRY_FAILED.store(true, Ordering::SeqCst);
+}
+
+/// Show a sticker `size` points wide, downloading it if it isn't cached yet
+pub fn show_sticker(
+    ui: &mut egui::Ui,
+    storage: &Arc<Storage>,
+    pack_id: &str,
+    pack_key: &str,
+    sticker_id: u32,
+    emoji: Option<&str>,
+    size: f32,
+) -> egui::Response {
+    let textures = unsafe { &raw mut TEXTURES };
+    let textures = unsafe { &mut *textures }.get_or_insert_with(HashMap::new);
+    if RETRY_FAILED.swap(false, Ordering::SeqCst) {
+        textures.retain(|_, texture| !matches!(texture, StickerTexture::Failed));
+    }
+
+    for ((id, sticker), frames) in LOADED.lock().drain(..) {
+        let texture = match frames {
+            Some(frames) => StickerTexture::Frames(
+                frames
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (image, delay))| {
+                        let name = format!("sticker_{}_{}_{}", id, sticker, i);
+                        (ui.ctx().load_texture(name, image, TextureOptions::LINEAR), delay)
+                    })
+                    .collect(),
+            ),
+            None => StickerTexture::Failed,
+        };
+        textures.insert((id, sticker), texture);
+    }
+
+    let key = (pack_id.to_string(), sticker_id);
+    let texture = textures.entry(key).or_insert_with(|| {
+        request_load(LoadRequest {
+            storage: storage.clone(),
+            pack_id: pack_id.to_string(),
+            pack_key: pack_key.to_string(),
+            sticker_id,
+        });
+        StickerTexture::Loading
+    });
+
+    let (rect, response) = ui.allocate_exact_size(Vec2::splat(size), Sense::click());
+    match &*texture {
+        StickerTexture::Frames(frames) => {
+            let (texture, remaining) = current_frame(frames, ui.input(|i| i.time));
+            let fitted = egui::Rect::from_center_size(rect.center(), fit(texture.size_vec2(), size));
+            ui.painter().image(
+                texture.id(),
+                fitted,
+                egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                Color32::WHITE,
+            );
+            if let Some(remaining) = remaining {
+                ui.ctx().request_repaint_after(remaining);
+            }
+        }
+        state => {
+            ui.painter().rect_filled(rect, Rounding::same(8.0), Color32::from_white_alpha(12));
+            let label = match (emoji, matches!(state, StickerTexture::Failed)) {
+                (Some(emoji), _) => emoji,
+                (None, true) => "Sticker",
+                (None, false) => "…",
+            };
+            ui.painter().text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                label,
+                egui::FontId::proportional((size / 3.0).clamp(10.0, 32.0)),
+                SignalColors::TEXT_SECONDARY,
+            );
+        }
+    }
+
+    match emoji {
+        Some(emoji) => response.on_hover_text(emoji),
+        None => response,
+    }
+}
+
+/// The frame to show at `time` (seconds), and how long until the next one
+fn current_frame(frames: &[(TextureHandle, Duration)], time: f64) -> (&TextureHandle, Option<Duration>) {
+    let total: Duration = frames.iter().map(|(_, delay)| *delay).sum();
+    if frames.len() < 2 || total.is_zero() {
+        return (&frames[0].0, None);
+    }
+
+    let mut at = Duration::from_secs_f64(time % total.as_secs_f64());
+    for (texture, delay) in frames {
+        if at < *delay {
+            return (texture, Some(*delay - at));
+        }
+        at -= *delay;
+    }
+    (&frames[0].0, Some(frames[0].1))
+}
+
+/// Scale `image_size` to fit a `size` square, keeping its aspect ratio
+fn fit(image_size: Vec2, size: f32) -> Vec2 {
+    let scale = size / image_size.x.max(image_size.y).max(1.0);
+    image_size * scale
+}
+
+fn request_load(request: LoadRequest) {
+    LOAD_QUEUE.lock().push(request);
+    if LOADER_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    std::thread::spawn(|| {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for stickers");
+
+        loop {
+            let request = {
+                let mut queue = LOAD_QUEUE.lock();
+                if queue.is_empty() {
+                    LOADER_RUNNING.store(false, Ordering::SeqCst);
+                    return;
+                }
+                queue.remove(0)
+            };
+
+            let frames = rt
+                .block_on(stickers::download_sticker(
+                    request.storage.stickers_dir(),
+                    &request.pack_id,
+                    &request.pack_key,
+                    request.sticker_id,
+                ))
+                .map_err(|e| tracing::warn!("Failed to download sticker {}: {}", request.sticker_id, e))
+                .ok()
+                .and_then(|path| std::fs::read(path).ok())
+                .and_then(|data| decode_frames(&data));
+
+            LOADED.lock().push(((request.pack_id, request.sticker_id), frames));
+            crate::app::request_repaint();
+        }
+    });
+}
+
+/// Decode a sticker image, with all frames of an animated WebP or APNG
+fn decode_frames(data: &[u8]) -> Option<Vec<(ColorImage, Duration)>> {
+    let animation = match image::guess_format(data).ok()? {
+        ImageFormat::WebP => WebPDecoder::new(Cursor::new(data))
+            .ok()
+            .filter(|decoder| decoder.has_animation())
+            .and_then(|decoder| decoder.into_frames().take(MAX_FRAMES).collect::<Result<Vec<_>, _>>().ok()),
+        ImageFormat::Png => PngDecoder::new(Cursor::new(data))
+            .ok()
+            .filter(|decoder| decoder.is_apng().unwrap_or(false))
+            .and_then(|decoder| decoder.apng().ok())
+            .and_then(|apng| apng.into_frames().take(MAX_FRAMES).collect::<Result<Vec<_>, _>>().ok()),
+        _ => None,
+    };
+
+    match animation.filter(|frames| !frames.is_empty()) {
+        Some(frames) => Some(
+            frames
+                .into_iter()
+                .map(|frame| {
+                    let delay = Duration::from(frame.delay()).max(Duration::from_millis(20));
+                    (to_color_image(frame.into_buffer()), delay)
+                })
+                .collect(),
+        ),
+        None => {
+            let image = image::load_from_memory(data).ok()?.to_rgba8();
+            Some(vec![(to_color_image(image), Duration::ZERO)])
+        }
+    }
+}
+
+fn to_color_image(image: RgbaImage) -> ColorImage {
+    let image = if image.width().max(image.height()) > MAX_STICKER_PIXELS {
+        let scale = MAX_STICKER_PIXELS as f32 / image.width().max(image.height()) as f32;
+        image::imageops::resize(
+            &image,
+            ((image.width() as f32 * scale) as u32).max(1),
+            ((image.height() as f32 * scale) as u32).max(1),
+            image::imageops::FilterType::Triangle,
+        )
+    } else {
+        image
+    };
+    let size = [image.width() as usize, image.height() as usize];
+    ColorImage::from_rgba_unmultiplied(size, image.as_raw())
+}
+
+/// A sticker picked to send
+#[derive(Debug, Clone)]
+pub struct PickedSticker {
+    pub pack_id: String,
+    pub pack_key: String,
+    pub sticker_id: u32,
+    pub emoji: Option<String>,
+}
+
+/// Sticker picker: installed packs as tabs, and a field to install a pack
+/// from its signal.art link
+#[derive(Default)]
+pub struct StickerPicker {
+    selected_pack: usize,
+    pack_link: String,
+}
+
+impl StickerPicker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show the picker and return the sticker clicked, if any
+    pub fn show(&mut self, ui: &mut egui::Ui, storage: &Arc<Storage>) -> Option<PickedSticker> {
+        let packs = unsafe { &raw mut CACHED_PACKS };
+        let packs = unsafe { &mut *packs };
+        if PACKS_DIRTY.swap(false, Ordering::SeqCst) {
+            *packs = None;
+        }
+        let packs = packs.get_or_insert_with(|| {
+            storage
+                .database()
+                .map(|db| StickerRepository::new(&db).installed())
+                .unwrap_or_default()
+        });
+        let packs: &[StickerPack] = packs;
+        let mut picked = None;
+
+        if packs.is_empty() {
+            ui.add_space(8.0);
+            ui.label(
+                egui::RichText::new("No sticker packs installed. Paste a signal.art sticker link below to add one.")
+                    .color(SignalColors::TEXT_SECONDARY),
+            );
+        } else {
+            self.selected_pack = self.selected_pack.min(packs.len() - 1);
+
+            // Pack tabs, by cover sticker
+            egui::ScrollArea::horizontal()
+                .id_salt("sticker_pack_tabs")
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        for (i, pack) in packs.iter().enumerate() {
+                            let cover = pack
+                                .cover_sticker_id
+                                .or_else(|| pack.stickers.first().map(|s| s.sticker_id));
+                            let response = match cover {
+                                Some(cover) => show_sticker(ui, storage, &pack.pack_id, &pack.pack_key, cover, None, 28.0),
+                                None => ui.selectable_label(i == self.selected_pack, "📦"),
+                            };
+                            if i == self.selected_pack {
+                                ui.painter().rect_stroke(
+                                    response.rect.expand(2.0),
+                                    Rounding::same(4.0),
+                                    egui::Stroke::new(1.5, SignalColors::SIGNAL_BLUE),
+                                );
+                            }
+                            let title = pack.title.as_deref().unwrap_or("Sticker pack");
+                            let hover = match &pack.author {
+                                Some(author) => format!("{} by {}", title, author),
+                                None => title.to_string(),
+                            };
+                            if response.on_hover_text(hover).clicked() {
+                                self.selected_pack = i;
+                            }
+                        }
+                    });
+                });
+
+            ui.separator();
+
+            let pack = &packs[self.selected_pack];
+            egui::ScrollArea::vertical()
+                .max_height(150.0)
+                .id_salt("sticker_grid")
+                .show(ui, |ui| {
+                    if pack.stickers.is_empty() {
+                        ui.label(egui::RichText::new("Downloading pack…").color(SignalColors::TEXT_SECONDARY));
+                    }
+                    ui.horizontal_wrapped(|ui| {
+                        ui.spacing_mut().item_spacing = Vec2::splat(4.0);
+                        for sticker in &pack.stickers {
+                            let response = show_sticker(
+                                ui,
+                                storage,
+                                &pack.pack_id,
+                                &pack.pack_key,
+                                sticker.sticker_id,
+                                sticker.emoji.as_deref(),
+                                64.0,
+                            );
+                            if response.clicked() {
+                                picked = Some(PickedSticker {
+                                    pack_id: pack.pack_id.clone(),
+                                    pack_key: pack.pack_key.clone(),
+                                    sticker_id: sticker.sticker_id,
+                                    emoji: sticker.emoji.clone(),
+                                });
+                            }
+                        }
+                    });
+                });
+        }
+
+        ui.separator();
+        let busy = *PACK_BUSY.lock();
+        ui.horizontal(|ui| {
+            let input = egui::TextEdit::singleline(&mut self.pack_link)
+                .hint_text("https://signal.art/addstickers/#pack_id=…")
+                .desired_width(ui.available_width() - 70.0);
+            ui.add(input);
+
+            let link = stickers::parse_pack_link(&self.pack_link);
+            if ui.add_enabled(link.is_some() && !busy, egui::Button::new("Install")).clicked() {
+                if let Some((pack_id, pack_key)) = link {
+                    set_pack_installed(storage.clone(), pack_id, pack_key, true);
+                    self.pack_link.clear();
+                    self.selected_pack = packs.len();
+                }
+            }
+        });
+
+        if let Some(pack) = packs.get(self.selected_pack) {
+            if ui.add_enabled(!busy, egui::Button::new("Remove this pack").small()).clicked() {
+                set_pack_installed(storage.clone(), pack.pack_id.clone(), pack.pack_key.clone(), false);
+            }
+        }
+        if busy {
+            ui.label(egui::RichText::new("Updating sticker packs…").size(11.0).color(SignalColors::TEXT_SECONDARY));
+        }
+        if let Some(error) = PACK_ERROR.lock().as_ref() {
+            ui.label(egui::RichText::new(error).size(11.0).color(SignalColors::ERROR));
+        }
+
+        picked
+    }
+}
+
+fn set_pack_installed(storage: Arc<Storage>, pack_id: String, pack_key: String, installed: bool) {
+    *PACK_BUSY.lock() = true;
+    *PACK_ERROR.lock() = None;
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime for sticker packs");
+
+        rt.block_on(async move {
+            let result = if installed {
+                SignalManager::install_sticker_pack(&storage, &pack_id, &pack_key).await
+            } else {
+                SignalManager::remove_sticker_pack(&storage, &pack_id, &pack_key).await
+            };
+            if let Err(e) = result {
+                tracing::error!("Failed to update sticker pack {}: {}", pack_id, e);
+                *PACK_ERROR.lock() = Some(e.to_string());
+            }
+            *PACK_BUSY.lock() = false;
+            invalidate_packs_cache();
+            crate::app::request_repaint();
+        });
+    });
+}
@@ -0,0 +1,371 @@
+//! Inline voice note player
+//!
+//! One note plays at a time. It is decoded on a background thread, then
+//! handed to a rodio sink; seeking starts a new sink at the chosen sample.
+//! The position is tracked from the clock rather than read back from the
+//! sink, so it stays right across pauses and speed changes.
+
+use crate::signal::attachments::voice::{self, DecodedAudio};
+use crate::ui::theme::SignalColors;
+use egui::{Color32, Rounding, Sense, Vec2};
+use parking_lot::Mutex;
+use rodio::buffer::SamplesBuffer;
+use rodio::{OutputStream, OutputStreamHandle, Sink};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Playback speeds the speed button cycles through
+const SPEEDS: [f32; 3] = [1.0, 1.5, 2.0];
+const BUTTON_SIZE: f32 = 32.0;
+const WAVEFORM_SIZE: Vec2 = Vec2::new(150.0, 28.0);
+const BAR_WIDTH: f32 = 2.0;
+const BAR_GAP: f32 = 1.0;
+/// How often the position is redrawn while playing
+const REPAINT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The note being played, paused or just finished
+static mut PLAYBACK: Option<Playback> = None;
+/// Note being decoded, with where to start playing it
+static mut LOADING: Option<(String, Duration)> = None;
+/// Note that couldn't be decoded or played
+static mut FAILED: Option<String> = None;
+/// Note being dragged on, with the fraction of its waveform under the pointer
+static mut SCRUBBING: Option<(String, f32)> = None;
+/// Note that played to its end since the last `take_finished`
+static mut FINISHED: Option<String> = None;
+/// Index into `SPEEDS`, kept for the whole session
+static mut SPEED: usize = 0;
+
+/// What decoder threads returned: (message ID, audio)
+static DECODED: Mutex<Vec<(String, Option<DecodedAudio>)>> = Mutex::new(Vec::new());
+
+struct Playback {
+    message_id: String,
+    audio: DecodedAudio,
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    sink: Option<Sink>,
+    /// Position when playback last started, paused or changed speed
+    offset: Duration,
+    /// When playback last started, while playing
+    started: Option<Instant>,
+}
+
+impl Playback {
+    fn duration(&self) -> Duration {
+        Duration::from_millis(self.audio.duration_ms())
+    }
+
+    fn position(&self) -> Duration {
+        let played = self.started.map_or(Duration::ZERO, |started| started.elapsed().mul_f32(speed()));
+        (self.offset + played).min(self.duration())
+    }
+
+    fn is_playing(&self) -> bool {
+        self.started.is_some()
+    }
+
+    fn play_from(&mut self, at: Duration) -> Result<(), String> {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        if self.output.is_none() {
+            self.output = Some(OutputStream::try_default().map_err(|e| e.to_string())?);
+        }
+        let (_, handle) = self.output.as_ref().expect("output stream was just opened");
+        let sink = Sink::try_new(handle).map_err(|e| e.to_string())?;
+
+        let at = at.min(self.duration());
+        let start = ((at.as_secs_f64() * self.audio.sample_rate as f64) as usize).min(self.audio.samples.len());
+        sink.set_speed(speed());
+        sink.append(SamplesBuffer::new(1, self.audio.sample_rate, self.audio.samples[start..].to_vec()));
+
+        self.sink = Some(sink);
+        self.offset = at;
+        self.started = Some(Instant::now());
+        Ok(())
+    }
+
+    fn pause(&mut self) {
+        self.offset = self.position();
+        self.started = None;
+        if let Some(sink) = &self.sink {
+            sink.pause();
+        }
+    }
+
+    fn resume(&mut self) -> Result<(), String> {
+        match &self.sink {
+            Some(sink) if !sink.empty() => {
+                sink.play();
+                self.started = Some(Instant::now());
+                Ok(())
+            }
+            _ => self.play_from(self.offset),
+        }
+    }
+
+    /// Seek, staying paused if paused
+    fn seek(&mut self, at: Duration) -> Result<(), String> {
+        if self.is_playing() {
+            self.play_from(at)
+        } else {
+            if let Some(sink) = self.sink.take() {
+                sink.stop();
+            }
+            self.offset = at.min(self.duration());
+            Ok(())
+        }
+    }
+
+    /// Call before the speed changes, so time played so far counts at the
+    /// old speed
+    fn rebase(&mut self) {
+        self.offset = self.position();
+        if self.started.is_some() {
+            self.started = Some(Instant::now());
+        }
+    }
+}
+
+fn speed() -> f32 {
+    SPEEDS[unsafe { *(&raw const SPEED) } % SPEEDS.len()]
+}
+
+fn next_speed() {
+    if let Some(current) = playback().as_mut() {
+        current.rebase();
+    }
+    let index = unsafe { &mut *(&raw mut SPEED) };
+    *index = (*index + 1) % SPEEDS.len();
+    if let Some(sink) = playback().as_ref().and_then(|p| p.sink.as_ref()) {
+        sink.set_speed(speed());
+    }
+}
+
+fn playback() -> &'static mut Option<Playback> {
+    unsafe { &mut *(&raw mut PLAYBACK) }
+}
+
+fn fail(message_id: &str, error: &str) {
+    tracing::warn!("Can't play voice note {}: {}", message_id, error);
+    unsafe { *(&raw mut FAILED) = Some(message_id.to_string()) };
+}
+
+/// Start playing a voice note from `at`, decoding it first if needed
+fn play(message_id: &str, path: &Path, at: Duration) {
+    unsafe { *(&raw mut FAILED) = None };
+
+    if let Some(current) = playback().as_mut().filter(|p| p.message_id == message_id) {
+        if let Err(e) = current.play_from(at) {
+            fail(message_id, &e);
+        }
+        return;
+    }
+
+    if let Some(current) = playback().as_mut() {
+        current.pause();
+    }
+    unsafe { *(&raw mut LOADING) = Some((message_id.to_string(), at)) };
+
+    let message_id = message_id.to_string();
+    let path = path.to_path_buf();
+    std::thread::spawn(move || {
+        let audio = std::fs::read(&path)
+            .ok()
+            .and_then(|data| voice::decode(&data))
+            .filter(|audio| !audio.samples.is_empty() && audio.sample_rate > 0);
+        DECODED.lock().push((message_id, audio));
+        crate::app::request_repaint();
+    });
+}
+
+/// Start playing a voice note from the beginning
+pub fn play_note(message_id: &str, path: &Path) {
+    play(message_id, path, Duration::ZERO);
+}
+
+/// Pick up a decoded note and notice when the current one ends
+fn poll() {
+    let decoded: Vec<_> = DECODED.lock().drain(..).collect();
+    for (message_id, audio) in decoded {
+        let loading = unsafe { &mut *(&raw mut LOADING) };
+        // Ignore notes the user moved on from while they were decoding
+        if let Some((_, at)) = loading.take_if(|(id, _)| *id == message_id) {
+            match audio {
+                Some(audio) => {
+                    // Keep the output stream, opening one is slow on some systems
+                    let output = playback().take().and_then(|p| p.output);
+                    let mut current = Playback {
+                        message_id: message_id.clone(),
+                        audio,
+                        output,
+                        sink: None,
+                        offset: Duration::ZERO,
+                        started: None,
+                    };
+                    if let Err(e) = current.play_from(at) {
+                        fail(&message_id, &e);
+                    }
+                    *playback() = Some(current);
+                }
+                None => fail(&message_id, "audio could not be decoded"),
+            }
+        }
+    }
+
+    if let Some(current) = playback().as_mut() {
+        if current.is_playing() && current.sink.as_ref().is_none_or(Sink::empty) {
+            current.sink = None;
+            current.offset = Duration::ZERO;
+            current.started = None;
+            unsafe { *(&raw mut FINISHED) = Some(current.message_id.clone()) };
+        }
+    }
+}
+
+/// The note that just played to its end, for playing the next one
+pub fn take_finished() -> Option<String> {
+    poll();
+    unsafe { (*(&raw mut FINISHED)).take() }
+}
+
+/// Show the player for a voice note: play button, waveform seek bar, time
+/// and speed. `playable` is false until the audio was downloaded.
+pub fn show_voice_player(
+    ui: &mut egui::Ui,
+    message_id: &str,
+    path: &Path,
+    duration_ms: u64,
+    waveform: Option<&[u8]>,
+    playable: bool,
+) {
+    poll();
+
+    // (playing, position, duration) of this note if it's the current one
+    let current = playback()
+        .as_ref()
+        .filter(|p| p.message_id == message_id)
+        .map(|p| (p.is_playing(), p.position(), p.duration()));
+    let loading = unsafe { &*(&raw const LOADING) }.as_ref().is_some_and(|(id, _)| id == message_id);
+    let failed = unsafe { &*(&raw const FAILED) }.as_deref() == Some(message_id);
+    let playing = current.is_some_and(|(playing, _, _)| playing);
+    let duration = current.map_or(Duration::from_millis(duration_ms), |(_, _, duration)| duration);
+    let scrubbing = unsafe { &*(&raw const SCRUBBING) }
+        .as_ref()
+        .filter(|(id, _)| id == message_id)
+        .map(|(_, fraction)| *fraction);
+    let fraction = scrubbing.unwrap_or_else(|| match current {
+        Some((_, position, _)) if !duration.is_zero() => position.as_secs_f32() / duration.as_secs_f32(),
+        _ => 0.0,
+    });
+
+    ui.vertical(|ui| {
+        ui.horizontal(|ui| {
+            let icon = match (loading, playing) {
+                (true, _) => "…",
+                (false, true) => "⏸",
+                (false, false) => "▶",
+            };
+            let button = egui::Button::new(egui::RichText::new(icon).size(16.0).color(Color32::WHITE))
+                .fill(Color32::from_white_alpha(40))
+                .rounding(Rounding::same(BUTTON_SIZE / 2.0))
+                .min_size(Vec2::splat(BUTTON_SIZE));
+            if ui.add_enabled(playable && !loading, button).clicked() {
+                match playback().as_mut().filter(|p| p.message_id == message_id) {
+                    Some(p) if p.is_playing() => p.pause(),
+                    Some(p) => {
+                        if let Err(e) = p.resume() {
+                            fail(message_id, &e);
+                        }
+                    }
+                    None => play(message_id, path, Duration::ZERO),
+                }
+            }
+
+            let sense = if playable { Sense::click_and_drag() } else { Sense::hover() };
+            let (rect, response) = ui.allocate_exact_size(WAVEFORM_SIZE, sense);
+            paint_waveform(ui, rect, waveform, fraction);
+
+            let pointer_fraction = response
+                .interact_pointer_pos()
+                .map(|pos| ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0));
+            if response.dragged() {
+                if let Some(fraction) = pointer_fraction {
+                    unsafe { *(&raw mut SCRUBBING) = Some((message_id.to_string(), fraction)) };
+                }
+            }
+            if response.clicked() || response.drag_stopped() {
+                unsafe { *(&raw mut SCRUBBING) = None };
+                if let Some(fraction) = pointer_fraction.or(scrubbing) {
+                    let at = duration.mul_f32(fraction);
+                    match playback().as_mut().filter(|p| p.message_id == message_id) {
+                        Some(p) => {
+                            if let Err(e) = p.seek(at) {
+                                fail(message_id, &e);
+                            }
+                        }
+                        None => play(message_id, path, at),
+                    }
+                }
+            }
+
+            let speed_label = format!("{}x", speed());
+            let speed_button = egui::Button::new(egui::RichText::new(speed_label).size(11.0).color(Color32::WHITE))
+                .fill(Color32::from_white_alpha(30))
+                .rounding(Rounding::same(8.0));
+            if ui.add(speed_button).on_hover_text("Playback speed").clicked() {
+                next_speed();
+            }
+        });
+
+        let position = duration.mul_f32(fraction);
+        let time = if current.is_some() || scrubbing.is_some() {
+            format!("{} / {}", format_time(position), format_time(duration))
+        } else {
+            format_time(duration)
+        };
+        ui.horizontal(|ui| {
+            ui.label(
+                egui::RichText::new(time)
+                    .size(11.0)
+                    .color(Color32::from_rgba_unmultiplied(255, 255, 255, 200)),
+            );
+            if failed {
+                ui.label(egui::RichText::new("Can't play this voice message").size(11.0).color(SignalColors::ERROR));
+            }
+        });
+    });
+
+    if playing || loading {
+        ui.ctx().request_repaint_after(REPAINT_INTERVAL);
+    }
+}
+
+/// Amplitude bars, brighter up to `fraction` of the way through
+fn paint_waveform(ui: &egui::Ui, rect: egui::Rect, waveform: Option<&[u8]>, fraction: f32) {
+    let bars = ((rect.width() + BAR_GAP) / (BAR_WIDTH + BAR_GAP)) as usize;
+    // Until the note was decoded, show a flat line
+    let waveform = waveform.filter(|w| !w.is_empty()).unwrap_or(&[0]);
+    let played_x = rect.left() + rect.width() * fraction.clamp(0.0, 1.0);
+
+    for i in 0..bars {
+        let level = waveform[i * waveform.len() / bars] as f32 / 255.0;
+        let height = (rect.height() * level).max(BAR_WIDTH);
+        let x = rect.left() + i as f32 * (BAR_WIDTH + BAR_GAP);
+        let bar = egui::Rect::from_center_size(
+            egui::pos2(x + BAR_WIDTH / 2.0, rect.center().y),
+            Vec2::new(BAR_WIDTH, height),
+        );
+        let color = if x < played_x {
+            Color32::WHITE
+        } else {
+            Color32::from_white_alpha(100)
+        };
+        ui.painter().rect_filled(bar, Rounding::same(1.0), color);
+    }
+}
+
+fn format_time(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}